@@ -0,0 +1,23 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use wasm_fdw_whatsapp::bench;
+
+const PRODUCTS: usize = 10_000;
+
+fn mapping(c: &mut Criterion) {
+    let body = bench::catalog_body(PRODUCTS);
+    let rows = bench::parse_products(&body);
+
+    let mut group = c.benchmark_group("products");
+    group.throughput(Throughput::Elements(PRODUCTS as u64));
+    group.bench_function("parse_10k", |b| {
+        b.iter(|| bench::parse_products(black_box(&body)))
+    });
+    group.bench_function("map_10k", |b| {
+        b.iter(|| bench::map_products(black_box(&rows), bench::PRODUCT_COLUMNS))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, mapping);
+criterion_main!(benches);
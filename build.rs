@@ -0,0 +1,220 @@
+// Generate the column mappings of the objects from `schema/objects.json`.
+// Each column maps the source field of the same name (or `field`, if set)
+// with the extractor of its type, and gets tests derived from that type.
+// The columns are also listed with their Postgres type in `OBJECT_COLUMNS`,
+// and the `url_list` columns with their field in `URL_LIST_COLUMNS`.
+//
+// Also embed the git commit the component is built from as `GIT_HASH`.
+
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use serde_json::Value as JsonValue;
+
+const SCHEMA: &str = "schema/objects.json";
+
+// Extractor, sample source value, expected cell pattern, and a source
+// value of the wrong type, of each column type
+fn column_type(ty: &str) -> (&'static str, &'static str, &'static str, &'static str) {
+    match ty {
+        "string" => ("as_string", r#""sample""#, r#"Some(Cell::String(v)) if v == "sample""#, "1"),
+        "bool" => ("as_bool", "true", "Some(Cell::Bool(true))", r#""true""#),
+        "i64" => ("as_i64", "42", "Some(Cell::I64(42))", r#""42""#),
+        "timestamptz" => (
+            "as_timestamptz",
+            "1_700_000_000_000_000i64",
+            "Some(Cell::Timestamptz(1_700_000_000_000_000))",
+            r#""2023-11-14""#,
+        ),
+        "url_list" => (
+            "as_url_list",
+            r#"[{"url": "https://a"}, {"url": "https://b"}]"#,
+            r#"Some(Cell::String(v)) if v == "https://a, https://b""#,
+            r#""https://a""#,
+        ),
+        "json" => (
+            "as_json",
+            r#"{"a": [1]}"#,
+            r##"Some(Cell::Json(v)) if v == r#"{"a":[1]}"#"##,
+            "null",
+        ),
+        _ => panic!("{}: unknown column type '{}'", SCHEMA, ty),
+    }
+}
+
+// Postgres type of the columns of each type
+fn pg_type(ty: &str) -> &'static str {
+    match ty {
+        "string" | "url_list" => "text",
+        "bool" => "boolean",
+        "i64" => "bigint",
+        "timestamptz" => "timestamptz",
+        "json" => "jsonb",
+        _ => panic!("{}: unknown column type '{}'", SCHEMA, ty),
+    }
+}
+
+// Short hash of the current commit, suffixed with `-dirty` if the work
+// tree has changes, or `unknown` outside a git checkout
+fn git_hash() -> String {
+    let git = |args: &[&str]| {
+        Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|out| out.status.success())
+            .map(|out| String::from_utf8_lossy(&out.stdout).trim().to_owned())
+    };
+
+    match git(&["rev-parse", "--short=12", "HEAD"]) {
+        Some(hash) if !hash.is_empty() => {
+            let dirty = git(&["status", "--porcelain", "--untracked-files=no"])
+                .is_some_and(|status| !status.is_empty());
+            if dirty {
+                format!("{}-dirty", hash)
+            } else {
+                hash
+            }
+        }
+        _ => "unknown".to_owned(),
+    }
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", SCHEMA);
+    for path in [".git/HEAD", ".git/index"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={}", path);
+        }
+    }
+    println!("cargo:rustc-env=GIT_HASH={}", git_hash());
+
+    let schema: JsonValue = serde_json::from_str(&fs::read_to_string(SCHEMA).expect("cannot read schema"))
+        .unwrap_or_else(|e| panic!("{}: {}", SCHEMA, e));
+    let objects = schema.as_object().expect("schema is not an object");
+
+    let mut code = String::from("// Generated by build.rs from schema/objects.json, do not edit\n");
+    let mut tests = String::new();
+    let mut object_columns = String::new();
+    let mut url_list_columns = String::new();
+
+    for (object, def) in objects {
+        let mapper = def["mapper"].as_str().expect("missing mapper");
+        let cfg = match def["feature"].as_str() {
+            Some(feature) => format!("#[cfg(feature = \"{}\")]\n", feature),
+            None => String::new(),
+        };
+        let columns = def["columns"].as_array().expect("missing columns");
+
+        let mut arms = String::new();
+        let mut names = String::new();
+        let mut pg_types = String::new();
+        for column in columns {
+            let name = column["name"].as_str().expect("missing column name");
+            let field = column["field"].as_str().unwrap_or(name);
+            let ty = column["type"].as_str().expect("missing column type");
+            let (extractor, sample, expected, mistyped) = column_type(ty);
+            write!(pg_types, "({:?}, {:?}), ", name, pg_type(ty)).unwrap();
+            if ty == "url_list" {
+                writeln!(
+                    url_list_columns,
+                    "    {}({:?}, {:?}, {:?}),",
+                    cfg.replace('\n', "\n    "),
+                    object,
+                    name,
+                    field
+                )
+                .unwrap();
+            }
+
+            writeln!(arms, "        {:?} => {}(src_row, {:?}),", name, extractor, field).unwrap();
+            write!(names, "{:?}, ", name).unwrap();
+
+            write!(
+                tests,
+                r#"
+    {cfg}#[test]
+    fn {object}_{test_name}() {{
+        let row = serde_json::json!({{ {field:?}: {sample} }});
+        assert!(matches!({mapper}(&row, {name:?}).unwrap(), {expected}));
+        let row = serde_json::json!({{ {field:?}: {mistyped} }});
+        assert!({mapper}(&row, {name:?}).unwrap().is_none());
+        assert!({mapper}(&serde_json::json!({{}}), {name:?}).unwrap().is_none());
+    }}
+"#,
+                cfg = cfg.replace('\n', "\n    "),
+                test_name = name.to_lowercase(),
+            )
+            .unwrap();
+        }
+
+        write!(
+            code,
+            r#"
+// Map a source row field of the {object} object to the cell of the target column
+{cfg}pub(crate) fn {mapper}(src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {{
+    let cell = match tgt_col_name {{
+{arms}        _ => return Err(unsupported_column({object:?}, tgt_col_name, &[{names}])),
+    }};
+    Ok(cell)
+}}
+
+// Columns of the {object} object, for tests and benchmarks
+{cfg}#[cfg(any(test, feature = "bench"))]
+#[cfg_attr(not(test), allow(dead_code))]
+pub(crate) const {const_name}_COLUMNS: &[&str] = &[{names}];
+"#,
+            const_name = object.to_uppercase(),
+            names = names.trim_end_matches(", "),
+        )
+        .unwrap();
+
+        write!(
+            tests,
+            r#"
+    {cfg}#[test]
+    fn {object}_unknown_column_is_an_error() {{
+        assert!({mapper}(&serde_json::json!({{}}), "unknown_column").is_err());
+    }}
+"#,
+            cfg = cfg.replace('\n', "\n    "),
+        )
+        .unwrap();
+
+        writeln!(
+            object_columns,
+            "    {}({:?}, &[{}]),",
+            cfg.replace('\n', "\n    "),
+            object,
+            pg_types.trim_end_matches(", ")
+        )
+        .unwrap();
+    }
+
+    write!(
+        code,
+        "\n// Columns of each object with their Postgres type\npub(crate) const OBJECT_COLUMNS: &[(&str, &[(&str, &str)])] = &[\n{}];\n",
+        object_columns
+    )
+    .unwrap();
+
+    write!(
+        code,
+        "\n// Columns of type url_list of each object, with their source field\npub(crate) const URL_LIST_COLUMNS: &[(&str, &str, &str)] = &[\n{}];\n",
+        url_list_columns
+    )
+    .unwrap();
+
+    write!(
+        code,
+        "\n#[cfg(test)]\nmod generated_tests {{\n    use super::*;\n{}}}\n",
+        tests
+    )
+    .unwrap();
+
+    let out = Path::new(&env::var("OUT_DIR").unwrap()).join("columns.rs");
+    fs::write(out, code).expect("cannot write generated mappings");
+}
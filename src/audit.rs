@@ -0,0 +1,181 @@
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+
+use crate::bindings::supabase::wrappers::types::{FdwError, FdwResult};
+use crate::log::{self, info};
+
+// Write operations kept for the `audit_log` object, the oldest being
+// dropped first
+const AUDIT_LOG_SIZE: usize = 100;
+
+// Fields of a write response holding the id the provider gave the written
+// row, by provider: Cloud API and 360dialog messages, Commerce batches,
+// Vonage, Twilio, and plain ids
+const PROVIDER_ID_POINTERS: &[&str] = &[
+    "/messages/0/id",
+    "/handles/0",
+    "/message_uuid",
+    "/sid",
+    "/id",
+    "/data/id",
+    "/message_id",
+];
+
+// Fields of a write response holding the status of a sent message, by
+// provider: Cloud API `accepted` or `held_for_quality_assessment`, and the
+// queue status of the others, e.g. Twilio `queued`
+const STATUS_POINTERS: &[&str] = &["/messages/0/message_status", "/status"];
+
+// Statuses of a message the provider accepted but then dropped from its
+// queue, e.g. Twilio `undelivered`
+const REJECTED_STATUSES: &[&str] = &["failed", "undelivered", "rejected"];
+
+thread_local! {
+    // Like the stats, the audit log is kept across the FDW lifecycles of
+    // the Wasm instance
+    static AUDIT_LOG: RefCell<VecDeque<JsonValue>> = const { RefCell::new(VecDeque::new()) };
+}
+
+// Record a write sent to the provider, or buffered by it when it made no
+// request, and report it at the info level. `response` is the provider
+// response to the write, if any, and `status` the status of a sent message
+// confirmed after the write, otherwise read from the response.
+pub(crate) fn record(
+    now_secs: i64,
+    object: &str,
+    operation: &str,
+    rowid: Option<&JsonValue>,
+    result: &FdwResult,
+    response: Option<&JsonValue>,
+    status: Option<&str>,
+) {
+    let rowid = rowid.map(|rowid| match rowid {
+        JsonValue::String(s) => s.clone(),
+        other => other.to_string(),
+    });
+    let provider_id = response.and_then(provider_id);
+    let status = status.map(|status| status.to_owned()).or_else(|| {
+        STATUS_POINTERS
+            .iter()
+            .find_map(|pointer| response?.pointer(pointer)?.as_str().map(|s| s.to_owned()))
+    });
+    let outcome = match (result, response) {
+        (Err(_), _) => "failed",
+        (Ok(()), Some(_)) => "sent",
+        (Ok(()), None) => "buffered",
+    };
+
+    info!(
+        "audit",
+        "{} on '{}'{}: {}{}",
+        operation,
+        object,
+        rowid.as_ref().map(|id| format!(" rowid {}", id)).unwrap_or_default(),
+        outcome,
+        provider_id.as_ref().map(|id| format!(", provider id {}", id)).unwrap_or_default()
+    );
+
+    let entry = serde_json::json!({
+        "at": now_secs * 1_000_000,
+        "correlation_id": log::correlation_id(),
+        "object": object,
+        "operation": operation,
+        "rowid": rowid,
+        "outcome": outcome,
+        "provider_id": provider_id,
+        "status": status,
+        "error": result.as_ref().err(),
+    });
+    AUDIT_LOG.with(|audit_log| {
+        let mut audit_log = audit_log.borrow_mut();
+        if audit_log.len() == AUDIT_LOG_SIZE {
+            audit_log.pop_front();
+        }
+        audit_log.push_back(entry);
+    });
+}
+
+// The recorded operations, most recent first
+pub(crate) fn rows() -> Vec<JsonValue> {
+    AUDIT_LOG.with(|audit_log| audit_log.borrow().iter().rev().cloned().collect())
+}
+
+// Id the provider gave a written row, as text
+pub(crate) fn provider_id(response: &JsonValue) -> Option<String> {
+    PROVIDER_ID_POINTERS
+        .iter()
+        .find_map(|pointer| match response.pointer(pointer)? {
+            JsonValue::String(s) if !s.is_empty() => Some(s.clone()),
+            JsonValue::Number(n) => Some(n.to_string()),
+            _ => None,
+        })
+}
+
+// Status of a sent message looked up after the send, failing if the
+// provider rejected it from its queue
+pub(crate) fn confirmed_status(id: &str, message: &JsonValue) -> Result<Option<String>, FdwError> {
+    let Some(status) = message.get("status").and_then(|v| v.as_str()) else {
+        return Ok(None);
+    };
+    if REJECTED_STATUSES.contains(&status) {
+        let reason = message
+            .get("error_message")
+            .and_then(|v| v.as_str())
+            .map(|reason| format!(": {}", reason))
+            .unwrap_or_default();
+        return Err(format!("Message '{}' was sent but rejected with status '{}'{}", id, status, reason));
+    }
+    Ok(Some(status.to_owned()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_the_provider_id_of_each_api() {
+        for (response, expected) in [
+            (serde_json::json!({"messages": [{"id": "wamid.HBgL"}]}), Some("wamid.HBgL")),
+            (serde_json::json!({"message_uuid": "aaaa-bbbb"}), Some("aaaa-bbbb")),
+            (serde_json::json!({"sid": "SM123"}), Some("SM123")),
+            (serde_json::json!({"id": 42}), Some("42")),
+            (serde_json::json!({"result": true}), None),
+        ] {
+            assert_eq!(provider_id(&response).as_deref(), expected);
+        }
+    }
+
+    #[test]
+    fn rejected_statuses_fail_the_send() {
+        let message = serde_json::json!({"status": "queued"});
+        assert_eq!(confirmed_status("SM1", &message).unwrap().as_deref(), Some("queued"));
+        assert_eq!(confirmed_status("SM1", &serde_json::json!({})).unwrap(), None);
+
+        let message = serde_json::json!({"status": "undelivered", "error_message": "Unreachable destination"});
+        assert_eq!(
+            confirmed_status("SM1", &message).unwrap_err(),
+            "Message 'SM1' was sent but rejected with status 'undelivered': Unreachable destination"
+        );
+    }
+
+    #[test]
+    fn keeps_the_most_recent_operations() {
+        let rowid = serde_json::json!("SKU-1");
+        record(1_700_000_000, "products", "delete", Some(&rowid), &Err("Not found".to_owned()), None, None);
+        for i in 0..AUDIT_LOG_SIZE {
+            let response = serde_json::json!({"messages": [{"id": format!("wamid.{}", i), "message_status": "accepted"}]});
+            record(1_700_000_001, "messages", "insert", None, &Ok(()), Some(&response), None);
+        }
+        record(1_700_000_002, "products", "update", Some(&rowid), &Ok(()), None, None);
+
+        let rows = rows();
+        assert_eq!(rows.len(), AUDIT_LOG_SIZE);
+        assert_eq!(rows[0]["outcome"], "buffered");
+        assert_eq!(rows[0]["rowid"], "SKU-1");
+        assert_eq!(rows[1]["provider_id"], format!("wamid.{}", AUDIT_LOG_SIZE - 1));
+        assert_eq!(rows[1]["status"], "accepted");
+        assert!(rows[0]["status"].is_null());
+        assert!(rows.iter().all(|row| row["outcome"] != "failed"));
+    }
+}
@@ -0,0 +1,42 @@
+use serde_json::Value as JsonValue;
+
+use crate::mapping;
+use crate::providers::two_chat;
+
+// All the columns of the products object
+pub const PRODUCT_COLUMNS: &[&str] = mapping::PRODUCTS_COLUMNS;
+
+// A catalog response body with `count` products, alternating full and
+// sparse products like the test fixture
+pub fn catalog_body(count: usize) -> String {
+    let fixture: JsonValue = serde_json::from_str(include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/catalog_products.json"
+    )))
+    .expect("invalid fixture");
+    let templates = fixture["products"].as_array().expect("no fixture products");
+
+    let products: Vec<JsonValue> = (0..count)
+        .map(|i| {
+            let mut product = templates[i % templates.len()].clone();
+            product["id"] = JsonValue::String(format!("{}", 7_000_000_000u64 + i as u64));
+            product
+        })
+        .collect();
+    serde_json::json!({ "success": true, "products": products }).to_string()
+}
+
+// Parse a catalog response body into source rows, as begin_scan does
+pub fn parse_products(body: &str) -> Vec<JsonValue> {
+    let resp_json: JsonValue = serde_json::from_str(body).expect("invalid catalog body");
+    two_chat::parse_products(&resp_json).expect("invalid catalog")
+}
+
+// Map every column of every row, as iter_scan does, returning the number
+// of non-null cells
+pub fn map_products(rows: &[JsonValue], columns: &[&str]) -> usize {
+    rows.iter()
+        .flat_map(|row| columns.iter().map(move |col| mapping::product_cell(row, col)))
+        .filter(|cell| matches!(cell, Ok(Some(_))))
+        .count()
+}
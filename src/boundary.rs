@@ -0,0 +1,88 @@
+use std::any::Any;
+use std::cell::Cell;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::bindings::supabase::wrappers::types::FdwError;
+
+thread_local! {
+    // Guest entrypoint currently running, for panic reports
+    static ENTRYPOINT: Cell<&'static str> = const { Cell::new("unknown") };
+}
+
+// Run a guest entrypoint, turning a panic into an error naming the
+// entrypoint instead of an opaque trap.
+//
+// Wasm guests abort on panic, so the panic can't be caught there: a panic
+// hook reports the error to the host before the instance traps. Builds
+// that unwind, like native tests, catch the panic and return the error.
+pub(crate) fn guard<R>(
+    entrypoint: &'static str,
+    f: impl FnOnce() -> Result<R, FdwError>,
+) -> Result<R, FdwError> {
+    #[cfg(target_arch = "wasm32")]
+    install_hook();
+
+    ENTRYPOINT.with(|current| current.set(entrypoint));
+    panic::catch_unwind(AssertUnwindSafe(f))
+        .unwrap_or_else(|payload| Err(describe(entrypoint, payload_message(payload.as_ref()), None)))
+}
+
+fn describe(entrypoint: &str, msg: &str, location: Option<String>) -> FdwError {
+    let mut err = format!(
+        "Internal error: WhatsApp FDW panicked in {}: {}",
+        entrypoint, msg
+    );
+    if let Some(location) = location {
+        err.push_str(&format!(" at {}", location));
+    }
+    err
+}
+
+fn payload_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
+
+#[cfg(target_arch = "wasm32")]
+fn install_hook() {
+    use crate::bindings::supabase::wrappers::utils;
+    use std::sync::Once;
+
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        panic::set_hook(Box::new(|info| {
+            let entrypoint = ENTRYPOINT.with(|current| current.get());
+            let location = info
+                .location()
+                .map(|l| format!("{}:{}", l.file(), l.line()));
+            utils::report_error(&describe(entrypoint, payload_message(info.payload()), location));
+        }));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passes_results_through() {
+        assert_eq!(guard("begin_scan", || Ok::<_, FdwError>(7)), Ok(7));
+        assert_eq!(guard("begin_scan", || Err::<(), _>("failed".to_owned())), Err("failed".to_owned()));
+    }
+
+    #[test]
+    fn turns_panics_into_errors() {
+        let err = guard("iter_scan", || -> Result<(), FdwError> {
+            let rows: Vec<u32> = Vec::new();
+            panic!("index {} out of range", rows.len() + 3)
+        })
+        .unwrap_err();
+        assert_eq!(
+            err,
+            "Internal error: WhatsApp FDW panicked in iter_scan: index 3 out of range"
+        );
+    }
+}
@@ -0,0 +1,1297 @@
+use serde_json::Value as JsonValue;
+
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::clock::{Clock, HostClock};
+use crate::log::{self, debug, info};
+use crate::model::{Envelope, MetaError};
+use crate::pagination;
+use crate::stats;
+use crate::timestamp::Period;
+
+#[cfg(any(test, feature = "fault-injection"))]
+mod fault;
+mod middleware;
+mod transport;
+
+#[cfg(any(test, feature = "fault-injection"))]
+use fault::FaultInjector;
+use middleware::{
+    Auth, Cache, CreditMeter, DebugDump, Middleware, Next, RateLimiter, Recorder, Retry, Timing,
+};
+#[cfg(test)]
+pub(crate) use transport::mock;
+use transport::{HostTransport, Transport};
+
+// Maximum length, in characters, of an error message built from a provider response
+const MAX_ERROR_LEN: usize = 512;
+
+// OAuth credentials and the current access token, for providers using
+// expiring bearer tokens instead of a static API key
+#[derive(Debug, Default)]
+pub(crate) struct OAuth {
+    pub(crate) token_url: String,
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+    pub(crate) refresh_token: String,
+    pub(crate) access_token: String,
+    // seconds since Unix epoch
+    pub(crate) expires_at: i64,
+}
+
+// A level of remaining capacity, as a number of requests or a percentage
+// of the limit
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Threshold {
+    Count(i64),
+    Percent(i64),
+}
+
+impl Default for Threshold {
+    fn default() -> Self {
+        Threshold::Percent(10)
+    }
+}
+
+// Writes sent again after a transport failure, with the `retry_writes`
+// option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum WriteRetry {
+    // No write is retried
+    Never,
+    // Idempotent writes only, PUT and DELETE
+    WithIdempotencyOnly,
+}
+
+impl WriteRetry {
+    pub(crate) const CHOICES: &'static [(&'static str, WriteRetry)] = &[
+        ("never", WriteRetry::Never),
+        ("with_idempotency_only", WriteRetry::WithIdempotencyOnly),
+    ];
+}
+
+// Requests retried after a transport failure or a 5xx response, with the
+// `retry_reads` and `retry_writes` options, and how often and after which
+// delay they are retried
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct RetryPolicy {
+    pub(crate) reads: bool,
+    pub(crate) writes: WriteRetry,
+    pub(crate) max_retries: i64,
+    // Delay before the first retry in milliseconds, doubled for each of the
+    // following ones
+    pub(crate) backoff_ms: i64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            reads: true,
+            writes: WriteRetry::WithIdempotencyOnly,
+            max_retries: 3,
+            backoff_ms: 500,
+        }
+    }
+}
+
+// Rate limit state reported by the provider in response headers
+#[derive(Debug, Default)]
+pub(crate) struct RateLimit {
+    pub(crate) limit: Option<i64>,
+    pub(crate) remaining: Option<i64>,
+    // seconds since Unix epoch
+    pub(crate) reset_at: Option<i64>,
+    // Remaining capacity below which a warning is reported
+    pub(crate) warn_below: Threshold,
+    // Whether the warning was reported for the current window
+    warned: bool,
+}
+
+impl RateLimit {
+    // Whether the remaining capacity is below the warning threshold. A
+    // percentage needs the limit to be known.
+    fn is_low(&self) -> bool {
+        let Some(remaining) = self.remaining else { return false };
+        match (self.warn_below, self.limit) {
+            (Threshold::Count(count), _) => remaining < count,
+            (Threshold::Percent(percent), Some(limit)) => remaining * 100 < limit * percent,
+            (Threshold::Percent(_), None) => false,
+        }
+    }
+
+    // A warning naming the window, the first time the remaining capacity
+    // drops below the threshold in a window. The warning is re-armed once
+    // capacity is back above the threshold, i.e. in the next window.
+    pub(crate) fn low_capacity_warning(&mut self, clock: &dyn Clock) -> Option<String> {
+        if !self.is_low() {
+            self.warned = false;
+            return None;
+        }
+        if self.warned {
+            return None;
+        }
+        self.warned = true;
+
+        let mut msg = format!(
+            "API rate limit nearly exhausted: {} requests remaining",
+            self.remaining.unwrap_or_default()
+        );
+        if let Some(limit) = self.limit {
+            msg.push_str(&format!(" of {}", limit));
+        }
+        match self.reset_at {
+            Some(reset_at) => msg.push_str(&format!(
+                " in the window resetting in {}s",
+                (reset_at - clock.now_secs()).max(0)
+            )),
+            None => msg.push_str(" in the current window"),
+        }
+        msg.push_str(", throttle the jobs using this server to avoid failing requests");
+        Some(msg)
+    }
+
+    // Update the state from the rate limit headers of a response, if any
+    pub(crate) fn update(&mut self, headers: &[(String, String)], clock: &dyn Clock) {
+        let parse = |name: &str| header_value(headers, name).and_then(|v| v.trim().parse::<i64>().ok());
+
+        if let Some(limit) = parse("x-ratelimit-limit") {
+            self.limit = Some(limit);
+        }
+        if let Some(remaining) = parse("x-ratelimit-remaining") {
+            self.remaining = Some(remaining);
+        }
+        if let Some(reset) = parse("x-ratelimit-reset") {
+            // Providers send either an epoch timestamp or a delay in seconds
+            self.reset_at = Some(if reset > 1_000_000_000 {
+                reset
+            } else {
+                clock.now_secs() + reset
+            });
+        }
+    }
+}
+
+// Headers of the providers reporting the credits a request cost
+const CREDIT_COST_HEADERS: &[&str] = &["x-credits-used", "x-credits-cost", "x-request-cost"];
+
+// API credits consumed, for providers billing requests against a credit
+// balance reported in response headers
+#[derive(Debug, Default)]
+pub(crate) struct Credits {
+    // Balance reported by the last response
+    pub(crate) remaining: Option<i64>,
+    // Credits consumed by the current statement
+    pub(crate) statement_used: i64,
+}
+
+impl Credits {
+    // Count the credits consumed by a response, from its cost header or
+    // else from the decrease of the remaining balance. A balance going up
+    // is a top-up, not a consumption.
+    pub(crate) fn update(&mut self, headers: &[(String, String)]) -> i64 {
+        let parse = |name: &str| header_value(headers, name).and_then(|v| v.trim().parse::<i64>().ok());
+        let remaining = parse("x-credits-remaining");
+
+        let used = match CREDIT_COST_HEADERS.iter().find_map(|name| parse(name)) {
+            Some(cost) => cost.max(0),
+            None => match (self.remaining, remaining) {
+                (Some(before), Some(after)) => (before - after).max(0),
+                _ => 0,
+            },
+        };
+        if remaining.is_some() {
+            self.remaining = remaining;
+        }
+        self.statement_used += used;
+        stats::record(|stats| {
+            stats.credits_used += used;
+            if remaining.is_some() {
+                stats.credits_remaining = remaining;
+            }
+        });
+        used
+    }
+}
+
+// How the API key is sent, which differs between providers
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) enum KeyScheme {
+    // `X-User-API-Key` header, used by 2Chat
+    #[default]
+    UserApiKey,
+    // `Authorization: Bearer` header
+    Bearer,
+    // `Authorization: Basic` header, the API key being `user:password`
+    Basic,
+    // A provider specific header, e.g. `D360-API-KEY`
+    Header(&'static str),
+    // A header whose value is rendered from a template, `{api_key}` being
+    // replaced by the API key, e.g. `Token {api_key}`
+    Template { name: String, value: String },
+}
+
+impl KeyScheme {
+    // Header carrying the API key
+    pub(crate) fn header(&self, api_key: &str) -> (String, String) {
+        match self {
+            KeyScheme::UserApiKey => ("X-User-API-Key".to_owned(), api_key.to_owned()),
+            KeyScheme::Bearer => ("authorization".to_owned(), format!("Bearer {}", api_key)),
+            KeyScheme::Basic => (
+                "authorization".to_owned(),
+                format!("Basic {}", base64_encode(api_key.as_bytes())),
+            ),
+            KeyScheme::Header(name) => (name.to_string(), api_key.to_owned()),
+            KeyScheme::Template { name, value } => (name.clone(), value.replace("{api_key}", api_key)),
+        }
+    }
+}
+
+// Credentials and telemetry of the client, shared by the middlewares
+#[derive(Debug)]
+pub(crate) struct ClientState {
+    pub(crate) api_key: String,
+    pub(crate) key_scheme: KeyScheme,
+    pub(crate) oauth: Option<OAuth>,
+    pub(crate) rate_limit: RateLimit,
+    pub(crate) credits: Credits,
+    pub(crate) retry: RetryPolicy,
+    // Object of the current statement, for the per-object latency
+    pub(crate) object: String,
+    // Time range of the current scan, for the objects the provider filters
+    // by period
+    pub(crate) period: Period,
+    // Values of the equality quals of the current scan the provider
+    // applies, by column
+    pub(crate) equals: Vec<(String, String)>,
+    // Whether paged reads ask for the total count of the rows, to tell when
+    // they changed between pages, with the `consistency 'strict'` option
+    pub(crate) count_totals: bool,
+    // Response to the last successful write, for the audit log
+    pub(crate) last_write: Option<JsonValue>,
+    pub(crate) clock: Box<dyn Clock>,
+}
+
+impl Default for ClientState {
+    fn default() -> Self {
+        Self {
+            api_key: String::default(),
+            key_scheme: KeyScheme::default(),
+            oauth: None,
+            rate_limit: RateLimit::default(),
+            credits: Credits::default(),
+            retry: RetryPolicy::default(),
+            object: String::default(),
+            period: Period::default(),
+            equals: Vec::new(),
+            count_totals: false,
+            last_write: None,
+            clock: Box::new(HostClock),
+        }
+    }
+}
+
+impl ClientState {
+    // Value the current scan requires of a column, if pushed down
+    pub(crate) fn equal(&self, column: &str) -> Option<&str> {
+        self.equals
+            .iter()
+            .find(|(name, _)| name == column)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // Request of a new access token to the OAuth token endpoint, using the
+    // refresh token grant if a refresh token is set and the client
+    // credentials grant otherwise
+    pub(crate) fn token_request(&self) -> Result<http::Request, FdwError> {
+        let oauth = self.oauth.as_ref().ok_or("OAuth is not configured")?;
+
+        let mut body = if oauth.refresh_token.is_empty() {
+            "grant_type=client_credentials".to_owned()
+        } else {
+            format!(
+                "grant_type=refresh_token&refresh_token={}",
+                url_encode(&oauth.refresh_token)
+            )
+        };
+        body.push_str(&format!(
+            "&client_id={}&client_secret={}",
+            url_encode(&oauth.client_id),
+            url_encode(&oauth.client_secret)
+        ));
+
+        Ok(http::Request {
+            method: http::Method::Post,
+            url: oauth.token_url.clone(),
+            headers: vec![
+                ("user-agent".to_owned(), "WhatsApp Catalog FDW".to_owned()),
+                (
+                    "content-type".to_owned(),
+                    "application/x-www-form-urlencoded".to_owned(),
+                ),
+            ],
+            body,
+        })
+    }
+
+    // Keep the access token of a token endpoint response
+    pub(crate) fn store_token(&mut self, resp: &http::Response) -> FdwResult {
+        if resp.status_code >= 400 {
+            return Err(self.sanitize_error(&format!(
+                "OAuth token refresh failed with status {}: {}",
+                resp.status_code, resp.body
+            )));
+        }
+        let resp_json: JsonValue = serde_json::from_str(&resp.body)
+            .map_err(|e| self.sanitize_error(&format!("{}: {}", e, resp.body)))?;
+
+        let access_token = resp_json
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or("Cannot get 'access_token' from OAuth token response")?
+            .to_owned();
+        let expires_in = resp_json
+            .get("expires_in")
+            .and_then(|v| v.as_i64())
+            .unwrap_or(3600);
+
+        let oauth = self.oauth.as_mut().ok_or("OAuth is not configured")?;
+        oauth.access_token = access_token;
+        oauth.expires_at = self.clock.now_secs().saturating_add(expires_in);
+        // Some providers rotate the refresh token on every use
+        if let Some(refresh_token) = resp_json.get("refresh_token").and_then(|v| v.as_str()) {
+            oauth.refresh_token = refresh_token.to_owned();
+        }
+        self.register_secrets();
+
+        Ok(())
+    }
+
+    // Credentials of the client
+    fn secrets(&self) -> Vec<&str> {
+        let mut secrets = vec![self.api_key.as_str()];
+        if let Some(oauth) = &self.oauth {
+            secrets.extend([
+                oauth.client_secret.as_str(),
+                oauth.refresh_token.as_str(),
+                oauth.access_token.as_str(),
+            ]);
+        }
+        secrets.retain(|s| !s.is_empty());
+        secrets
+    }
+
+    // Have the credentials of the client redacted from log messages
+    pub(crate) fn register_secrets(&self) {
+        for secret in self.secrets() {
+            log::add_secret(secret);
+        }
+    }
+
+    // Replace the credentials of the client appearing in a message
+    pub(crate) fn redact_secrets(&self, msg: &str) -> String {
+        let mut msg = msg.to_owned();
+        for secret in self.secrets() {
+            msg = msg.replace(secret, "***");
+        }
+        msg
+    }
+
+    // Make a provider message safe to use as a Postgres error: secrets are
+    // redacted, control characters removed and the length is bounded
+    pub(crate) fn sanitize_error(&self, msg: &str) -> String {
+        let msg = self.redact_secrets(msg);
+        let mut sanitized: String = msg
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .take(MAX_ERROR_LEN)
+            .collect();
+        if msg.chars().count() > MAX_ERROR_LEN {
+            sanitized.push_str("...");
+        }
+        sanitized
+    }
+}
+
+// HTTP client for the provider API. Requests go through a pipeline of
+// middlewares handling the cross-cutting concerns, outermost first.
+#[derive(Debug)]
+pub(crate) struct Client {
+    pub(crate) base_url: String,
+    pub(crate) envelope: Envelope,
+    pub(crate) state: ClientState,
+    middlewares: Vec<Box<dyn Middleware>>,
+    transport: Box<dyn Transport>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::with_transport(Box::new(HostTransport))
+    }
+}
+
+impl Client {
+    // Create a client sending requests through the given transport
+    pub(crate) fn with_transport(transport: Box<dyn Transport>) -> Self {
+        Self {
+            base_url: String::default(),
+            envelope: Envelope::default(),
+            state: ClientState::default(),
+            middlewares: vec![
+                Box::new(RateLimiter),
+                Box::new(CreditMeter),
+                Box::new(Retry),
+                Box::new(Auth),
+            ],
+            transport,
+        }
+    }
+
+    // Cache the responses of GET requests for the given number of seconds
+    pub(crate) fn enable_cache(&mut self, ttl_secs: i64) {
+        self.middlewares.insert(0, Box::new(Cache::new(ttl_secs)));
+    }
+
+    // Report each request and a snippet of its response
+    pub(crate) fn enable_debug_dump(&mut self) {
+        self.middlewares.push(Box::new(DebugDump));
+    }
+
+    // Time the requests, each attempt on its own, reporting those taking
+    // longer than the threshold. This goes last, so that waits for the rate
+    // limit and simulated faults are not timed.
+    pub(crate) fn enable_timing(&mut self, slow_threshold_secs: i64) {
+        self.middlewares.push(Box::new(Timing { slow_threshold_secs }));
+    }
+
+    // Report every request/response exchange as a fixture line
+    pub(crate) fn enable_recording(&mut self) {
+        self.middlewares.push(Box::new(Recorder));
+    }
+
+    // Fail requests with the simulated faults of the spec, right before
+    // they reach the transport
+    #[cfg(any(test, feature = "fault-injection"))]
+    pub(crate) fn enable_fault_injection(&mut self, spec: &str) -> FdwResult {
+        self.middlewares.push(Box::new(FaultInjector::parse(spec)?));
+        Ok(())
+    }
+
+    // Make a GET request to the API
+    pub(crate) fn get(&mut self, url: String) -> Result<http::Response, FdwError> {
+        self.send(http::Method::Get, url, String::default())
+    }
+
+    // Make a GET request to the API and parse the successful JSON response
+    pub(crate) fn get_json(&mut self, url: String) -> Result<JsonValue, FdwError> {
+        let resp = self.get(url)?;
+        self.parse_json(&resp)
+    }
+
+    // Make a GET request for a page after the first of a list. Its cursor is
+    // the only parameter differing from the request of the page before, so
+    // a `400` response, unless it relays another Meta error than an invalid
+    // parameter, rejects the cursor, e.g. as the list changed since.
+    pub(crate) fn get_next_page_json(&mut self, url: String) -> Result<JsonValue, FdwError> {
+        let resp = self.get(url)?;
+        self.parse_json(&resp).map_err(|err| {
+            let meta_error = serde_json::from_str(&resp.body).ok().and_then(|body| MetaError::find(&body));
+            match resp.status_code == 400 && meta_error.is_none_or(|e| e.is_invalid_parameter()) {
+                true => pagination::cursor_rejected(&err),
+                false => err,
+            }
+        })
+    }
+
+    // Make a GET request for a single resource, `None` if it doesn't exist
+    pub(crate) fn find_json(&mut self, url: String) -> Result<Option<JsonValue>, FdwError> {
+        let resp = self.get(url)?;
+        if resp.status_code == 404 {
+            return Ok(None);
+        }
+        self.parse_json(&resp).map(Some)
+    }
+
+    // Download a file outside the provider API, e.g. a product feed. The
+    // request skips the middlewares, so that the provider credentials are
+    // never sent to another host.
+    pub(crate) fn download(&mut self, url: String) -> Result<String, FdwError> {
+        debug!("client", "GET {}", url);
+        let req = http::Request {
+            method: http::Method::Get,
+            url: url.clone(),
+            headers: vec![("user-agent".to_owned(), "WhatsApp Catalog FDW".to_owned())],
+            body: String::default(),
+        };
+        let resp = self.transport.send(&req)?;
+        if resp.status_code >= 400 {
+            return Err(format!("Cannot download '{}': HTTP status {}", url, resp.status_code));
+        }
+        Ok(resp.body)
+    }
+
+    // Make a request with a JSON body and parse the successful JSON response
+    pub(crate) fn send_json(
+        &mut self,
+        method: http::Method,
+        url: String,
+        body: &JsonValue,
+    ) -> Result<JsonValue, FdwError> {
+        let resp = self.send(method, url, body.to_string())?;
+        let resp_json = self.parse_json(&resp)?;
+        self.state.last_write = Some(resp_json.clone());
+        Ok(resp_json)
+    }
+
+    // Make a request with a JSON body and parse the successful JSON
+    // response, `None` if the provider refuses the body as too large
+    pub(crate) fn try_send_json(
+        &mut self,
+        method: http::Method,
+        url: String,
+        body: &JsonValue,
+    ) -> Result<Option<JsonValue>, FdwError> {
+        let resp = self.send(method, url, body.to_string())?;
+        if resp.status_code == 413 {
+            return Ok(None);
+        }
+        let resp_json = self.parse_json(&resp)?;
+        self.state.last_write = Some(resp_json.clone());
+        Ok(Some(resp_json))
+    }
+
+    // Make a DELETE request without a body and parse the successful JSON
+    // response
+    pub(crate) fn delete(&mut self, url: String) -> Result<JsonValue, FdwError> {
+        let resp = self.send(http::Method::Delete, url, String::new())?;
+        let resp_json = self.parse_json(&resp)?;
+        self.state.last_write = Some(resp_json.clone());
+        Ok(resp_json)
+    }
+
+    // Parse a JSON response, failing if the provider reports an error. An
+    // empty body, e.g. of a `204 No Content` response, parses as null.
+    fn parse_json(&self, resp: &http::Response) -> Result<JsonValue, FdwError> {
+        let resp_json: JsonValue = match resp.body.trim() {
+            "" => JsonValue::Null,
+            body => serde_json::from_str(body).map_err(|e| {
+                stats::record_error("invalid_response");
+                self.sanitize_error(&format!("{}: {}", e, resp.body))
+            })?,
+        };
+        self.envelope
+            .check(resp.status_code, &resp_json, &resp.body)
+            .map_err(|e| {
+                // Error statuses are already counted with the response
+                if stats::status_error_kind(resp.status_code).is_none() {
+                    stats::record_error("provider");
+                }
+                self.sanitize_error(&e)
+            })?;
+        Ok(resp_json)
+    }
+
+    // Make a request with a form body and parse the successful JSON response
+    pub(crate) fn send_form(
+        &mut self,
+        method: http::Method,
+        url: String,
+        form: &[(&str, &str)],
+    ) -> Result<JsonValue, FdwError> {
+        let body: Vec<String> = form
+            .iter()
+            .map(|(k, v)| format!("{}={}", url_encode(k), url_encode(v)))
+            .collect();
+        let resp = self.request(method, url, body.join("&"), "application/x-www-form-urlencoded")?;
+        let resp_json = self.parse_json(&resp)?;
+        self.state.last_write = Some(resp_json.clone());
+        Ok(resp_json)
+    }
+
+    // Make a request to the API through the middleware pipeline
+    pub(crate) fn send(
+        &mut self,
+        method: http::Method,
+        url: String,
+        body: String,
+    ) -> Result<http::Response, FdwError> {
+        self.request(method, url, body, "application/json")
+    }
+
+    fn request(
+        &mut self,
+        method: http::Method,
+        url: String,
+        body: String,
+        content_type: &str,
+    ) -> Result<http::Response, FdwError> {
+        let mut headers: Vec<(String, String)> =
+            vec![("user-agent".to_owned(), "WhatsApp Catalog FDW".to_owned())];
+        if !body.is_empty() {
+            headers.push(("content-type".to_owned(), content_type.to_owned()));
+        }
+        if let Some(id) = log::correlation_id() {
+            headers.push(("x-request-id".to_owned(), id));
+        }
+        debug!("client", "{} {}", method_name(&method), url);
+        let req = http::Request {
+            method,
+            url,
+            headers,
+            body,
+        };
+
+        let mut next = Next::new(&mut self.middlewares, self.transport.as_mut());
+        let result = next.run(&mut self.state, req);
+        match &result {
+            Ok(resp) => {
+                if let Some(kind) = stats::status_error_kind(resp.status_code) {
+                    stats::record_error(kind);
+                }
+            }
+            Err(_) => stats::record_error("network"),
+        }
+        result.map_err(|e| self.state.sanitize_error(&e))
+    }
+
+    // Report the remaining rate limit capacity, if the provider sent it
+    pub(crate) fn report_rate_limit(&self) {
+        let Some(remaining) = self.state.rate_limit.remaining else {
+            return;
+        };
+
+        let mut msg = format!("API rate limit: {} requests remaining", remaining);
+        if let Some(limit) = self.state.rate_limit.limit {
+            msg.push_str(&format!(" of {}", limit));
+        }
+        if let Some(reset_at) = self.state.rate_limit.reset_at {
+            msg.push_str(&format!(
+                ", resets in {}s",
+                (reset_at - self.state.clock.now_secs()).max(0)
+            ));
+        }
+        info!("client", "{}", msg);
+    }
+
+    // Report the API credits consumed by the current statement, if the
+    // provider reports them
+    pub(crate) fn report_credits(&self) {
+        let credits = &self.state.credits;
+        if credits.statement_used == 0 {
+            return;
+        }
+        let mut msg = format!("API credits used: {}", credits.statement_used);
+        if let Some(remaining) = credits.remaining {
+            msg.push_str(&format!(", {} remaining", remaining));
+        }
+        info!("client", "{}", msg);
+    }
+
+    // Make a provider message safe to use as a Postgres error
+    pub(crate) fn sanitize_error(&self, msg: &str) -> String {
+        self.state.sanitize_error(msg)
+    }
+}
+
+// Find a header value by case-insensitive name
+pub(crate) fn header_value<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+// Name of an HTTP method, as used in fixtures
+pub(crate) fn method_name(method: &http::Method) -> &'static str {
+    match method {
+        http::Method::Get => "GET",
+        http::Method::Post => "POST",
+        http::Method::Put => "PUT",
+        http::Method::Patch => "PATCH",
+        http::Method::Delete => "DELETE",
+    }
+}
+
+// Percent-encode a string for use in a URL query or form body
+pub(crate) fn url_encode(s: &str) -> String {
+    let mut encoded = String::with_capacity(s.len());
+    for b in s.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    encoded
+}
+
+// Normalize a URL given in an option: surrounding whitespace, duplicate
+// slashes in the path and, without a query, trailing slashes are removed,
+// e.g. `https://api.example.com//v1/` becomes `https://api.example.com/v1`
+pub(crate) fn normalize_url(url: &str) -> String {
+    let url = url.trim();
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest)) => (Some(scheme), rest),
+        None => (None, url),
+    };
+    let (path, query) = match rest.find(['?', '#']) {
+        Some(at) => rest.split_at(at),
+        None => (rest, ""),
+    };
+
+    let mut normalized = scheme.map(|scheme| format!("{}://", scheme)).unwrap_or_default();
+    for c in path.chars() {
+        if c != '/' || !normalized.ends_with('/') || normalized.ends_with("://") {
+            normalized.push(c);
+        }
+    }
+    if query.is_empty() {
+        normalized.truncate(normalized.trim_end_matches('/').len());
+    }
+    normalized.push_str(query);
+    normalized
+}
+
+// URL of a path relative to a base URL, with a single slash between them
+pub(crate) fn join_url(base_url: &str, path: &str) -> String {
+    match path.trim_start_matches('/') {
+        "" => base_url.to_owned(),
+        path => format!("{}/{}", base_url.trim_end_matches('/'), path),
+    }
+}
+
+// A phone number without the spaces and punctuation it is often written
+// with, e.g. `+491701234567` for `+49 (170) 123-4567`
+pub(crate) fn normalize_number(number: &str) -> String {
+    number
+        .chars()
+        .filter(|c| !c.is_whitespace() && !"-()./".contains(*c))
+        .collect()
+}
+
+// Standard base64 encoding, with padding
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::mock::{response, MockTransport};
+    use super::*;
+    use crate::clock::mock::FakeClock;
+    use crate::state::FdwState;
+
+    type Requests = Rc<RefCell<Vec<http::Request>>>;
+
+    fn client(responses: Vec<http::HttpResult>) -> (Client, Requests) {
+        let transport = MockTransport::new(responses);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.base_url = "https://api.example.com".to_owned();
+        client.state.api_key = "secret-key".to_owned();
+        (client, requests)
+    }
+
+    #[test]
+    fn normalizes_urls_and_numbers() {
+        assert_eq!(normalize_url(" https://api.example.com//v1/ "), "https://api.example.com/v1");
+        assert_eq!(normalize_url("https://api.example.com/"), "https://api.example.com");
+        assert_eq!(
+            normalize_url("https://cdn.example.com//feeds/?v=1//2"),
+            "https://cdn.example.com/feeds/?v=1//2"
+        );
+        assert_eq!(join_url("https://api.example.com", "/messages"), "https://api.example.com/messages");
+        assert_eq!(join_url("https://api.example.com", "messages"), "https://api.example.com/messages");
+        assert_eq!(join_url("https://api.example.com", ""), "https://api.example.com");
+        assert_eq!(normalize_number("+49 (170) 123-4567"), "+491701234567");
+    }
+
+    #[test]
+    fn sends_api_key_header() {
+        let (mut client, requests) = client(vec![response(200, r#"{"success": true}"#)]);
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            header_value(&requests[0].headers, "x-user-api-key"),
+            Some("secret-key")
+        );
+    }
+
+    #[test]
+    fn sends_the_correlation_id() {
+        let (mut client, requests) = client(vec![response(200, r#"{"success": true}"#)]);
+        let id = log::start_trace(1_700_000_000);
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        assert_eq!(
+            header_value(&requests.borrow()[0].headers, "x-request-id"),
+            Some(id.as_str())
+        );
+    }
+
+    #[test]
+    fn encodes_basic_credentials() {
+        for (input, expected) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("AC1:tok", "QUMxOnRvaw==")] {
+            assert_eq!(base64_encode(input.as_bytes()), expected);
+        }
+    }
+
+    #[test]
+    fn retries_reads_after_transport_failure() {
+        let (mut client, requests) = client(vec![
+            Err("connection reset".to_owned()),
+            response(200, r#"{"success": true}"#),
+        ]);
+        let clock = FakeClock::at(1_700_000_000);
+        client.state.clock = Box::new(clock.clone());
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_ok());
+        assert_eq!(requests.borrow().len(), 2);
+        assert_eq!(*clock.sleeps.borrow(), vec![500]);
+    }
+
+    #[test]
+    fn retries_server_errors_with_exponential_backoff() {
+        // A server error doesn't tell whether a message was sent
+        let (mut sender, requests) = client(vec![response(500, "oops"), response(200, "{}")]);
+        sender.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        let resp = sender.send(http::Method::Post, "https://api.example.com/send".to_owned(), "{}".to_owned());
+        assert_eq!(resp.unwrap().status_code, 500);
+        assert_eq!(requests.borrow().len(), 1);
+
+        let (mut client, requests) = client(vec![
+            response(503, "unavailable"),
+            response(502, "bad gateway"),
+            response(200, r#"{"success": true}"#),
+            response(500, r#"{"success": false, "message": "attempt 1"}"#),
+            response(500, r#"{"success": false, "message": "attempt 2"}"#),
+            response(500, r#"{"success": false, "message": "attempt 3"}"#),
+            response(500, r#"{"success": false, "message": "attempt 4"}"#),
+        ]);
+        let clock = FakeClock::at(1_700_000_000);
+        client.state.clock = Box::new(clock.clone());
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_ok());
+        assert_eq!(*clock.sleeps.borrow(), vec![500, 1000]);
+
+        // The last response is returned once the retries are exhausted
+        clock.sleeps.borrow_mut().clear();
+        let err = client.get_json("https://api.example.com/x".to_owned()).unwrap_err();
+        assert!(err.contains("attempt 4"), "{}", err);
+        assert_eq!(requests.borrow().len(), 7);
+        assert_eq!(*clock.sleeps.borrow(), vec![500, 1000, 2000]);
+    }
+
+    #[test]
+    fn retries_rate_limited_requests_after_the_requested_delay() {
+        let rate_limited = |retry_after: &str| {
+            let mut resp = response(429, "too many requests")?;
+            resp.headers = vec![("Retry-After".to_owned(), retry_after.to_owned())];
+            Ok(resp)
+        };
+        let (mut client, requests) = client(vec![
+            rate_limited("2"),
+            rate_limited("Tue, 14 Nov 2023 22:13:30 GMT"),
+            response(200, r#"{"success": true}"#),
+            rate_limited("3600"),
+            rate_limited("40"),
+            rate_limited("30"),
+        ]);
+        let clock = FakeClock::at(1_700_000_000);
+        client.state.clock = Box::new(clock.clone());
+
+        // A rate limited request wasn't processed, so even a send is retried
+        let send = |client: &mut Client| {
+            let resp = client.send(http::Method::Post, "https://api.example.com/send".to_owned(), "{}".to_owned());
+            resp.unwrap().status_code
+        };
+        assert_eq!(send(&mut client), 200);
+        assert_eq!(*clock.sleeps.borrow(), vec![2000, 8000]);
+
+        // A delay over the longest wait fails the request instead
+        assert_eq!(send(&mut client), 429);
+        assert_eq!(requests.borrow().len(), 4);
+        assert_eq!(clock.sleeps.borrow().len(), 2);
+
+        // So do delays adding up past it
+        assert_eq!(send(&mut client), 429);
+        assert_eq!(requests.borrow().len(), 6);
+        assert_eq!(*clock.sleeps.borrow(), vec![2000, 8000, 40_000]);
+    }
+
+    #[test]
+    fn max_retries_bounds_the_attempts() {
+        let (mut client, requests) = client(vec![
+            Err("connection reset".to_owned()),
+            Err("connection reset".to_owned()),
+            response(200, r#"{"success": true}"#),
+        ]);
+        let clock = FakeClock::at(1_700_000_000);
+        client.state.clock = Box::new(clock.clone());
+        client.state.retry = RetryPolicy {
+            max_retries: 1,
+            backoff_ms: 0,
+            ..RetryPolicy::default()
+        };
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_err());
+        assert_eq!(requests.borrow().len(), 2);
+        assert!(clock.sleeps.borrow().is_empty());
+    }
+
+    #[test]
+    fn does_not_retry_non_idempotent_writes() {
+        let (mut client, requests) = client(vec![
+            Err("connection reset".to_owned()),
+            response(200, r#"{"success": true}"#),
+        ]);
+        let result = client.send(
+            http::Method::Post,
+            "https://api.example.com/send".to_owned(),
+            "{}".to_owned(),
+        );
+        assert!(result.is_err());
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn retry_options_narrow_the_retried_requests() {
+        let policy = RetryPolicy {
+            reads: false,
+            writes: WriteRetry::Never,
+            ..RetryPolicy::default()
+        };
+
+        // Rate limited requests aren't retried either
+        let (mut limited, requests) = client(vec![
+            response(429, r#"{"success": false, "message": "slow down"}"#),
+            response(429, "too many requests"),
+            response(200, r#"{"success": true}"#),
+        ]);
+        limited.state.retry = policy;
+        assert!(limited.get_json("https://api.example.com/x".to_owned()).is_err());
+        let resp = limited.send(http::Method::Post, "https://api.example.com/send".to_owned(), "{}".to_owned());
+        assert_eq!(resp.unwrap().status_code, 429);
+        assert_eq!(requests.borrow().len(), 2);
+
+        let (mut client, requests) = client(vec![
+            Err("connection reset".to_owned()),
+            Err("connection reset".to_owned()),
+            response(200, r#"{"success": true}"#),
+        ]);
+        client.state.retry = policy;
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_err());
+        let result = client.send(http::Method::Put, "https://api.example.com/x".to_owned(), "{}".to_owned());
+        assert!(result.is_err());
+        assert_eq!(requests.borrow().len(), 2);
+    }
+
+    #[test]
+    fn unsuccessful_response_is_sanitized() {
+        let body = format!(
+            r#"{{"success": false, "message": "bad key secret-key\n{}"}}"#,
+            "x".repeat(1000)
+        );
+        let (mut client, _) = client(vec![response(400, &body)]);
+        let err = client
+            .get_json("https://api.example.com/x".to_owned())
+            .unwrap_err();
+        assert!(!err.contains("secret-key"));
+        assert!(!err.chars().any(|c| c.is_control()));
+        assert!(err.chars().count() <= MAX_ERROR_LEN + 3);
+    }
+
+    #[test]
+    fn describes_relayed_meta_errors() {
+        let body = r#"{"success": false, "error": {"message": "(#132001) Template name does not exist", "code": 132001, "fbtrace_id": "AbC123"}}"#;
+        let (mut client, _) = client(vec![response(400, body)]);
+        let err = client
+            .get_json("https://api.example.com/x".to_owned())
+            .unwrap_err();
+        assert!(err.contains("Meta error 132001"));
+        assert!(err.contains("template does not exist"));
+        assert!(err.contains("[fbtrace_id: AbC123]"));
+    }
+
+    fn rate_limited(remaining: i64, reset: i64) -> http::HttpResult {
+        let mut resp = response(200, r#"{"success": true}"#)?;
+        resp.headers = vec![
+            ("x-ratelimit-remaining".to_owned(), remaining.to_string()),
+            ("x-ratelimit-reset".to_owned(), reset.to_string()),
+        ];
+        Ok(resp)
+    }
+
+    #[test]
+    fn counts_credits_from_cost_and_balance_headers() {
+        let with_headers = |headers: &[(&str, &str)]| {
+            let mut resp = response(200, r#"{"success": true}"#)?;
+            resp.headers = headers.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+            Ok(resp)
+        };
+        let (mut client, _) = client(vec![
+            with_headers(&[("x-credits-remaining", "100")]),
+            with_headers(&[("x-credits-remaining", "97")]),
+            with_headers(&[("x-credits-remaining", "95"), ("x-credits-used", "1")]),
+            with_headers(&[("x-credits-remaining", "500")]),
+        ]);
+        client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        client.enable_cache(60);
+        for path in ["a", "b", "c", "a", "d"] {
+            client.get_json(format!("https://api.example.com/{}", path)).unwrap();
+        }
+
+        // The cached response is not counted again
+        assert_eq!(client.state.credits.statement_used, 4);
+        assert_eq!(client.state.credits.remaining, Some(500));
+        let stats = stats::snapshot();
+        assert_eq!((stats.credits_used, stats.credits_remaining), (4, Some(500)));
+    }
+
+    #[test]
+    fn warns_once_per_window_when_capacity_runs_low() {
+        let headers = |remaining: i64| {
+            vec![
+                ("x-ratelimit-limit".to_owned(), "100".to_owned()),
+                ("x-ratelimit-remaining".to_owned(), remaining.to_string()),
+                ("x-ratelimit-reset".to_owned(), "30".to_owned()),
+            ]
+        };
+        let clock = FakeClock::at(1_700_000_000);
+        let mut rate_limit = RateLimit::default();
+        let mut warnings = Vec::new();
+        for remaining in [50, 9, 8, 100, 5] {
+            rate_limit.update(&headers(remaining), &clock);
+            warnings.extend(rate_limit.low_capacity_warning(&clock));
+        }
+        assert_eq!(warnings.len(), 2);
+        assert_eq!(
+            warnings[0],
+            "API rate limit nearly exhausted: 9 requests remaining of 100 in the window resetting in 30s, throttle the jobs using this server to avoid failing requests"
+        );
+
+        rate_limit.warn_below = Threshold::Count(3);
+        rate_limit.update(&headers(4), &clock);
+        assert!(rate_limit.low_capacity_warning(&clock).is_none());
+    }
+
+    #[test]
+    fn cached_responses_expire_after_ttl() {
+        let clock = FakeClock::at(1_700_000_000);
+        let (mut client, requests) = client(vec![
+            response(200, r#"{"success": true}"#),
+            response(200, r#"{"success": true}"#),
+        ]);
+        client.state.clock = Box::new(clock.clone());
+        client.enable_cache(60);
+
+        let url = "https://api.example.com/x";
+        client.get_json(url.to_owned()).unwrap();
+        clock.advance(59);
+        client.get_json(url.to_owned()).unwrap();
+        assert_eq!(requests.borrow().len(), 1);
+
+        clock.advance(1);
+        client.get_json(url.to_owned()).unwrap();
+        assert_eq!(requests.borrow().len(), 2);
+    }
+
+    #[test]
+    fn writes_drop_the_cached_responses_of_their_object() {
+        // Each statement runs in a new FDW lifecycle, with a new client
+        let statement = |object: &str, responses: Vec<http::HttpResult>| {
+            FdwState::reset();
+            let (mut client, requests) = client(responses);
+            client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+            client.state.object = object.to_owned();
+            client.enable_cache(60);
+            (client, requests)
+        };
+        let products = "https://api.example.com/products";
+        let templates = "https://api.example.com/templates";
+
+        let (mut scan, _) = statement(
+            "products",
+            vec![
+                response(200, r#"{"success": true, "products": [{"id": "p1", "price": "10.00"}]}"#),
+                response(200, r#"{"success": true, "templates": [{"name": "sale"}]}"#),
+            ],
+        );
+        scan.get_json(products.to_owned()).unwrap();
+        scan.state.object = "templates".to_owned();
+        scan.get_json(templates.to_owned()).unwrap();
+
+        // A following statement reads the cached responses
+        let (mut scan, requests) = statement("products", vec![]);
+        assert_eq!(scan.get_json(products.to_owned()).unwrap()["products"][0]["price"], "10.00");
+        assert!(requests.borrow().is_empty());
+
+        let (mut update, _) = statement("products", vec![response(200, r#"{"success": true}"#)]);
+        let resp = update.send(
+            http::Method::Put,
+            "https://api.example.com/products/p1".to_owned(),
+            r#"{"price": "12.00"}"#.to_owned(),
+        );
+        assert!(resp.is_ok());
+
+        // The scan after the write sees the change instead of the cached
+        // page, the cached responses of other objects being kept
+        let (mut scan, requests) = statement(
+            "products",
+            vec![response(200, r#"{"success": true, "products": [{"id": "p1", "price": "12.00"}]}"#)],
+        );
+        assert_eq!(scan.get_json(products.to_owned()).unwrap()["products"][0]["price"], "12.00");
+        scan.state.object = "templates".to_owned();
+        scan.get_json(templates.to_owned()).unwrap();
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn refreshes_a_rejected_access_token() {
+        let (mut client, requests) = client(vec![
+            response(401, r#"{"success": false, "message": "token expired"}"#),
+            response(200, r#"{"access_token": "new-token", "expires_in": 7200, "refresh_token": "rotated"}"#),
+            response(200, r#"{"success": true}"#),
+        ]);
+        client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        client.state.oauth = Some(OAuth {
+            token_url: "https://auth.example.com/token".to_owned(),
+            client_id: "client".to_owned(),
+            client_secret: "client-secret".to_owned(),
+            refresh_token: "refresh".to_owned(),
+            access_token: "old-token".to_owned(),
+            expires_at: 1_700_003_600,
+        });
+        client.enable_recording();
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_ok());
+
+        // The token request goes through the transport like any other
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(header_value(&requests[0].headers, "authorization"), Some("Bearer old-token"));
+        assert_eq!(requests[1].url, "https://auth.example.com/token");
+        assert_eq!(
+            requests[1].body,
+            "grant_type=refresh_token&refresh_token=refresh&client_id=client&client_secret=client-secret"
+        );
+        assert_eq!(header_value(&requests[2].headers, "authorization"), Some("Bearer new-token"));
+        let oauth = client.state.oauth.as_ref().unwrap();
+        assert_eq!((oauth.refresh_token.as_str(), oauth.expires_at), ("rotated", 1_700_007_200));
+        // A hostile lifetime can't overflow the expiry
+        let resp = response(200, r#"{"access_token": "t", "expires_in": 9223372036854775807}"#).unwrap();
+        client.state.store_token(&resp).unwrap();
+        assert_eq!(client.state.oauth.as_ref().unwrap().expires_at, i64::MAX);
+    }
+
+    #[test]
+    fn redacts_the_credentials_of_responses() {
+        assert_eq!(
+            middleware::redact_credentials(r#"{"access_token": "new-token", "expires_in": 7200}"#),
+            r#"{"access_token":"***","expires_in":7200}"#
+        );
+        assert_eq!(middleware::redact_credentials(r#"{"data": []}"#), r#"{"data": []}"#);
+        assert_eq!(middleware::redact_credentials("not json"), "not json");
+    }
+
+    #[test]
+    fn tells_rejected_cursors_from_other_page_failures() {
+        let meta_error = |code: i64| {
+            response(400, &format!(r#"{{"error": {{"message": "Invalid cursor", "code": {}}}}}"#, code))
+        };
+        let (mut client, _) = client(vec![
+            meta_error(100),
+            response(400, r#"{"success": false, "message": "cursor expired"}"#),
+            meta_error(190),
+            response(401, r#"{"success": false, "message": "Invalid cursor"}"#),
+            response(500, r#"{"success": false, "message": "cursor store unavailable"}"#),
+        ]);
+        client.state.retry.max_retries = 0;
+        let mut page = || client.get_next_page_json("https://api.example.com/x?after=QVFI".to_owned()).unwrap_err();
+        assert!(page().starts_with("Cursor rejected: "));
+        assert!(page().starts_with("Cursor rejected: "));
+
+        // Other failures are not, whatever their message
+        for _ in 0..3 {
+            let err = page();
+            assert!(!err.starts_with("Cursor rejected: "), "{}", err);
+        }
+    }
+
+    #[test]
+    fn debug_snippets_are_single_line_and_bounded() {
+        assert_eq!(middleware::snippet("{\"data\":\n[]}"), "{\"data\": []}");
+        let long = "x".repeat(400);
+        let snippet = middleware::snippet(&long);
+        assert!(snippet.starts_with(&"x".repeat(300)));
+        assert!(snippet.ends_with("x... (400 characters)"), "{}", snippet);
+    }
+
+    #[test]
+    fn times_requests_and_reports_slow_ones() {
+        let clock = FakeClock::at(1_700_000_000);
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"success": true}"#),
+            response(200, r#"{"success": true}"#),
+        ])
+        .with_latency(clock.clone(), 3);
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(clock.clone());
+        client.state.object = "contacts".to_owned();
+        client.enable_timing(2);
+
+        client.get_json("https://api.example.com/x?page=2".to_owned()).unwrap();
+        assert_eq!(stats::snapshot().slow_requests, 1);
+
+        client.middlewares.pop();
+        client.enable_timing(5);
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        let stats = stats::snapshot();
+        assert_eq!(stats.slow_requests, 1);
+        assert_eq!(stats.latency["contacts"][&3], 2);
+    }
+
+    #[test]
+    fn waits_for_exhausted_rate_limit_window() {
+        let clock = FakeClock::at(1_700_000_000);
+        let (mut client, requests) = client(vec![
+            rate_limited(0, 30),
+            response(200, r#"{"success": true}"#),
+        ]);
+        client.state.clock = Box::new(clock.clone());
+
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        client.get_json("https://api.example.com/y".to_owned()).unwrap();
+        assert_eq!(*clock.sleeps.borrow(), vec![30_000]);
+        assert_eq!(requests.borrow().len(), 2);
+    }
+
+    #[test]
+    fn distant_rate_limit_reset_is_an_error() {
+        let clock = FakeClock::at(1_700_000_000);
+        let (mut client, requests) = client(vec![rate_limited(0, 1_700_000_300)]);
+        client.state.clock = Box::new(clock.clone());
+
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        let err = client
+            .get_json("https://api.example.com/y".to_owned())
+            .unwrap_err();
+        assert_eq!(err, "API rate limit exhausted, resets in 300s");
+        assert!(clock.sleeps.borrow().is_empty());
+        assert_eq!(requests.borrow().len(), 1);
+    }
+}
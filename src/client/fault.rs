@@ -0,0 +1,187 @@
+use super::{ClientState, Middleware, Next};
+use crate::bindings::supabase::wrappers::{http, types::FdwError};
+
+// A simulated failure of a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FaultKind {
+    RateLimited,
+    ServerError,
+    Timeout,
+    Parse,
+}
+
+impl FaultKind {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "429" => Some(Self::RateLimited),
+            "500" => Some(Self::ServerError),
+            "timeout" => Some(Self::Timeout),
+            "parse" => Some(Self::Parse),
+            _ => None,
+        }
+    }
+
+    fn result(&self, req: &http::Request) -> http::HttpResult {
+        let response = |status_code: u16, body: &str| http::Response {
+            url: req.url.clone(),
+            status_code,
+            headers: vec![],
+            body: body.to_owned(),
+        };
+        match self {
+            Self::RateLimited => Ok(response(
+                429,
+                r#"{"success": false, "message": "Injected fault: too many requests"}"#,
+            )),
+            Self::ServerError => Ok(response(
+                500,
+                r#"{"success": false, "message": "Injected fault: internal server error"}"#,
+            )),
+            Self::Timeout => Err("Injected fault: request timed out".to_owned()),
+            Self::Parse => Ok(response(200, "<html>Injected fault: malformed body")),
+        }
+    }
+}
+
+// A fault and the request it applies to, every request if unset
+#[derive(Debug, PartialEq)]
+struct Fault {
+    kind: FaultKind,
+    at: Option<usize>,
+}
+
+// Fail selected requests with simulated faults instead of sending them, so
+// the retry and partial failure paths can be exercised end-to-end. Faults
+// are given as a comma-separated list of `kind` or `kind@n`, where kind is
+// one of `429`, `500`, `timeout` or `parse` and n is the 1-based number of
+// the request reaching the transport, e.g. `timeout@1,500@3`.
+#[derive(Debug)]
+pub(crate) struct FaultInjector {
+    faults: Vec<Fault>,
+    requests: usize,
+}
+
+impl FaultInjector {
+    pub(crate) fn parse(spec: &str) -> Result<Self, FdwError> {
+        let faults = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| {
+                let (kind, at) = match s.split_once('@') {
+                    Some((kind, at)) => (kind, Some(at)),
+                    None => (s, None),
+                };
+                let kind = FaultKind::parse(kind);
+                let at = at.map(|at| at.parse::<usize>().ok().filter(|at| *at > 0));
+                match (kind, at) {
+                    (Some(kind), None) => Ok(Fault { kind, at: None }),
+                    (Some(kind), Some(Some(at))) => Ok(Fault { kind, at: Some(at) }),
+                    _ => Err(format!(
+                        "Invalid inject_faults '{}', expected kind or kind@n with kind one of 429, 500, timeout, parse",
+                        s
+                    )),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            faults,
+            requests: 0,
+        })
+    }
+}
+
+impl Middleware for FaultInjector {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        self.requests += 1;
+        let fault = self
+            .faults
+            .iter()
+            .find(|f| f.at.is_none_or(|at| at == self.requests));
+        match fault {
+            Some(fault) => fault.kind.result(&req),
+            None => next.run(state, req),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::client::mock::{response, MockTransport};
+    use crate::client::Client;
+    use crate::clock::mock::FakeClock;
+
+    fn client(faults: &str, responses: Vec<http::HttpResult>) -> (Client, Rc<RefCell<Vec<http::Request>>>) {
+        let transport = MockTransport::new(responses);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        client.enable_fault_injection(faults).unwrap();
+        (client, requests)
+    }
+
+    #[test]
+    fn parses_fault_spec() {
+        let injector = FaultInjector::parse("timeout@1, 500@3,parse").unwrap();
+        assert_eq!(
+            injector.faults,
+            vec![
+                Fault { kind: FaultKind::Timeout, at: Some(1) },
+                Fault { kind: FaultKind::ServerError, at: Some(3) },
+                Fault { kind: FaultKind::Parse, at: None },
+            ]
+        );
+        for spec in ["503", "timeout@0", "timeout@x", "@2"] {
+            assert!(FaultInjector::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn injected_timeout_is_retried() {
+        let (mut client, requests) = client("timeout@1", vec![response(200, r#"{"success": true}"#)]);
+        assert!(client.get_json("https://api.example.com/x".to_owned()).is_ok());
+        // The first attempt never reached the transport
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[test]
+    fn injected_faults_fail_requests() {
+        for (faults, expected) in [
+            ("429", "too many requests"),
+            ("500", "internal server error"),
+            ("parse", "malformed body"),
+        ] {
+            let (mut client, requests) = client(faults, vec![]);
+            let err = client
+                .get_json("https://api.example.com/x".to_owned())
+                .unwrap_err();
+            assert!(err.contains(expected), "{}: {}", faults, err);
+            assert!(requests.borrow().is_empty());
+        }
+    }
+
+    #[test]
+    fn injected_write_fault_is_not_retried() {
+        let (mut client, requests) = client("timeout@2", vec![response(200, "{}"), response(200, "{}")]);
+        let mut send = || {
+            client.send(
+                http::Method::Post,
+                "https://api.example.com/send".to_owned(),
+                "{}".to_owned(),
+            )
+        };
+        assert!(send().is_ok());
+        assert!(send().is_err());
+        assert!(send().is_ok());
+        assert_eq!(requests.borrow().len(), 2);
+    }
+}
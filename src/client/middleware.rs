@@ -0,0 +1,536 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{method_name, ClientState, RetryPolicy, Transport, WriteRetry};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::log::{info, notice, warning};
+use crate::stats;
+use crate::timestamp;
+
+// Seconds before expiry at which an access token is proactively refreshed
+const TOKEN_REFRESH_MARGIN_SECS: i64 = 60;
+
+// Longest request body or response snippet, in characters, reported by
+// the debug dump
+const DEBUG_SNIPPET_LEN: usize = 300;
+
+// Fields of a response body holding a credential
+const CREDENTIAL_FIELDS: &[&str] = &["access_token", "refresh_token"];
+
+// Longest wait, in seconds, for an exhausted rate limit window to reset
+const MAX_RATE_LIMIT_WAIT_SECS: i64 = 60;
+
+// Longest total wait, in milliseconds, between the retries of a request,
+// past which the last response or error is returned
+const MAX_RETRY_WAIT_MS: u64 = 60_000;
+
+// A step of the request pipeline. A middleware can adjust the request,
+// short-circuit it, or hand it to the rest of the pipeline with `next`.
+pub(crate) trait Middleware: fmt::Debug {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError>;
+}
+
+// The rest of the pipeline after the current middleware, ending with the
+// transport
+pub(crate) struct Next<'a> {
+    middlewares: &'a mut [Box<dyn Middleware>],
+    transport: &'a mut dyn Transport,
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middlewares: &'a mut [Box<dyn Middleware>],
+        transport: &'a mut dyn Transport,
+    ) -> Self {
+        Self {
+            middlewares,
+            transport,
+        }
+    }
+
+    // Run the request through the rest of the pipeline. This can be
+    // called more than once, e.g. to retry a request.
+    pub(crate) fn run(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+    ) -> Result<http::Response, FdwError> {
+        match self.middlewares.split_first_mut() {
+            Some((middleware, rest)) => {
+                middleware.handle(state, req, &mut Next::new(rest, &mut *self.transport))
+            }
+            None => {
+                stats::record(|stats| stats.api_calls += 1);
+                self.transport.send(&req)
+            }
+        }
+    }
+}
+
+// Inject the authentication header, refreshing the OAuth access token when
+// it is about to expire or has been rejected by the API
+#[derive(Debug)]
+pub(crate) struct Auth;
+
+impl Auth {
+    // Obtain a new access token through the rest of the pipeline, so that
+    // the token request is dumped, timed and recorded like any other, and
+    // retried as a rate limited write would be
+    fn refresh(state: &mut ClientState, next: &mut Next<'_>) -> FdwResult {
+        let req = state.token_request()?;
+        let resp = Retry.handle(state, req, next).map_err(|e| state.sanitize_error(&e))?;
+        state.store_token(&resp)
+    }
+
+    fn authorize(state: &ClientState, req: &http::Request) -> http::Request {
+        let mut req = req.clone();
+        match &state.oauth {
+            Some(oauth) => req.headers.push((
+                "authorization".to_owned(),
+                format!("Bearer {}", oauth.access_token),
+            )),
+            None => req.headers.push(state.key_scheme.header(&state.api_key)),
+        }
+        req
+    }
+}
+
+impl Middleware for Auth {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        if let Some(oauth) = &state.oauth {
+            if oauth.access_token.is_empty()
+                || state.clock.now_secs() >= oauth.expires_at - TOKEN_REFRESH_MARGIN_SECS
+            {
+                Self::refresh(state, next)?;
+            }
+        }
+
+        let resp = next.run(state, Self::authorize(state, &req))?;
+        if resp.status_code == 401 && state.oauth.is_some() {
+            // The token may have been revoked or rotated early, refresh and
+            // retry once. The request was rejected before being processed,
+            // so this is safe for writes too.
+            Self::refresh(state, next)?;
+            return next.run(state, Self::authorize(state, &req));
+        }
+
+        Ok(resp)
+    }
+}
+
+// Retry a request after a transient failure, as the retry policy allows,
+// waiting twice as long before each new attempt
+#[derive(Debug)]
+pub(crate) struct Retry;
+
+impl Retry {
+    // Whether a request can be sent again after a failure without risking a
+    // duplicate side effect, e.g. a message being sent twice. Reads and
+    // idempotent writes are safe unless the policy excludes them, POST and
+    // PATCH (message sends, product creation) never are.
+    fn is_retry_safe(policy: RetryPolicy, method: &http::Method) -> bool {
+        match method {
+            http::Method::Get => policy.reads,
+            http::Method::Put | http::Method::Delete => policy.writes == WriteRetry::WithIdempotencyOnly,
+            http::Method::Post | http::Method::Patch => false,
+        }
+    }
+
+    // Whether a rate limited request can be sent again. The provider rejected
+    // it before processing it, so any write can, unless the policy retries
+    // no write at all.
+    fn is_rate_limit_retry_allowed(policy: RetryPolicy, method: &http::Method) -> bool {
+        match method {
+            http::Method::Get => policy.reads,
+            _ => policy.writes != WriteRetry::Never,
+        }
+    }
+
+    // Seconds to wait before sending a rate limited request again, from the
+    // `Retry-After` header given as seconds or as an HTTP date
+    fn retry_after(state: &ClientState, resp: &http::Response) -> Option<i64> {
+        let (_, value) = resp
+            .headers
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case("retry-after"))?;
+        let value = value.trim();
+        let secs = match value.parse::<i64>() {
+            Ok(secs) => secs,
+            Err(_) => timestamp::parse_rfc2822(value)? / 1_000_000 - state.clock.now_secs(),
+        };
+        Some(secs.max(0))
+    }
+
+    // Milliseconds to wait before the given retry, counting from 0
+    fn backoff_ms(policy: RetryPolicy, retry: i64) -> u64 {
+        (policy.backoff_ms as u64).saturating_mul(1 << retry.min(20))
+    }
+}
+
+impl Middleware for Retry {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        let retry_safe = Self::is_retry_safe(state.retry, &req.method);
+        let rate_limit_retry = Self::is_rate_limit_retry_allowed(state.retry, &req.method);
+        let mut retries = 0;
+        let mut waited_ms: u64 = 0;
+        loop {
+            let result = next.run(state, req.clone());
+            if retries >= state.retry.max_retries {
+                return result;
+            }
+            let (reason, wait_ms) = match &result {
+                // A rate limited request was rejected before being processed,
+                // so it's sent again after the delay asked by the provider
+                Ok(resp) if resp.status_code == 429 && rate_limit_retry => match Self::retry_after(state, resp) {
+                    Some(secs) => ("HTTP 429".to_owned(), (secs as u64).saturating_mul(1000)),
+                    None => ("HTTP 429".to_owned(), Self::backoff_ms(state.retry, retries)),
+                },
+                // A transport failure or a server error doesn't tell whether
+                // the provider processed the request, so only idempotent
+                // requests are retried
+                Ok(resp) if resp.status_code >= 500 && retry_safe => {
+                    (format!("HTTP {}", resp.status_code), Self::backoff_ms(state.retry, retries))
+                }
+                Err(err) if retry_safe => (err.clone(), Self::backoff_ms(state.retry, retries)),
+                _ => return result,
+            };
+            waited_ms = waited_ms.saturating_add(wait_ms);
+            if waited_ms > MAX_RETRY_WAIT_MS {
+                return result;
+            }
+
+            retries += 1;
+            stats::record(|stats| stats.retries += 1);
+            notice!(
+                "client",
+                "Retrying {} request after {} in {}ms, attempt {} of {}",
+                method_name(&req.method),
+                state.redact_secrets(&reason),
+                wait_ms,
+                retries,
+                state.retry.max_retries
+            );
+            if wait_ms > 0 {
+                state.clock.sleep_ms(wait_ms);
+            }
+        }
+    }
+}
+
+// Track the rate limit headers of responses, and wait for the window to
+// reset before sending a request once the limit is exhausted
+#[derive(Debug)]
+pub(crate) struct RateLimiter;
+
+impl Middleware for RateLimiter {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        if let (Some(0), Some(reset_at)) = (state.rate_limit.remaining, state.rate_limit.reset_at) {
+            let wait_secs = reset_at - state.clock.now_secs();
+            if wait_secs > MAX_RATE_LIMIT_WAIT_SECS {
+                return Err(format!(
+                    "API rate limit exhausted, resets in {}s",
+                    wait_secs
+                ));
+            }
+            if wait_secs > 0 {
+                warning!(
+                    "client",
+                    "API rate limit exhausted, waiting {}s for the window to reset",
+                    wait_secs
+                );
+                state.clock.sleep_ms(wait_secs as u64 * 1000);
+            }
+        }
+
+        let resp = next.run(state, req)?;
+        state.rate_limit.update(&resp.headers, state.clock.as_ref());
+        if let Some(msg) = state.rate_limit.low_capacity_warning(state.clock.as_ref()) {
+            warning!("client", "{}", msg);
+        }
+        Ok(resp)
+    }
+}
+
+// Count the API credits consumed by the responses, inside the cache so
+// that cached responses are free
+#[derive(Debug)]
+pub(crate) struct CreditMeter;
+
+impl Middleware for CreditMeter {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        let resp = next.run(state, req)?;
+        state.credits.update(&resp.headers);
+        Ok(resp)
+    }
+}
+
+// A cached response, with the object of the scan that read it
+#[derive(Debug)]
+struct CacheEntry {
+    object: String,
+    // seconds since Unix epoch
+    expires_at: i64,
+    resp: http::Response,
+}
+
+thread_local! {
+    // Cached responses by credential and URL. Like the stats, they are kept
+    // across the FDW lifecycles of the Wasm instance, so that they serve
+    // the scans of the following statements.
+    static CACHE: RefCell<HashMap<(String, String), CacheEntry>> = RefCell::new(HashMap::new());
+}
+
+// Cache successful responses of GET requests by URL for a fixed time,
+// until a write to their object is sent
+#[derive(Debug)]
+pub(crate) struct Cache {
+    ttl_secs: i64,
+}
+
+impl Cache {
+    pub(crate) fn new(ttl_secs: i64) -> Self {
+        Self { ttl_secs }
+    }
+
+    // Credential the cached responses are read with, so that servers of
+    // different accounts don't share them
+    fn credential(state: &ClientState) -> String {
+        match &state.oauth {
+            Some(oauth) => oauth.client_id.clone(),
+            None => state.api_key.clone(),
+        }
+    }
+
+    // Drop the cached responses of an object, and those of the same
+    // resources read by other objects, e.g. the products read by
+    // `product_images`
+    fn invalidate(object: &str) {
+        let path = |url: &str| url.split('?').next().unwrap_or_default().to_owned();
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            let paths: Vec<String> = cache
+                .iter()
+                .filter(|(_, entry)| entry.object == object)
+                .map(|((_, url), _)| path(url))
+                .collect();
+            cache.retain(|(_, url), entry| entry.object != object && !paths.contains(&path(url)));
+        });
+    }
+}
+
+impl Middleware for Cache {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        if !matches!(req.method, http::Method::Get) {
+            // A write can change the cached pages of its object, e.g. an
+            // updated product shows in the products list, so they are dropped
+            // for the next scan to read the change. This is whatever the
+            // outcome, as a failed write may still have been applied.
+            let result = next.run(state, req);
+            Self::invalidate(&state.object);
+            return result;
+        }
+
+        let now = state.clock.now_secs();
+        let key = (Self::credential(state), req.url.clone());
+        let cached = CACHE.with(|cache| {
+            let cache = cache.borrow();
+            let entry = cache.get(&key)?;
+            (now < entry.expires_at).then(|| entry.resp.clone())
+        });
+        if let Some(resp) = cached {
+            stats::record(|stats| stats.cache_hits += 1);
+            return Ok(resp);
+        }
+
+        let resp = next.run(state, req)?;
+        if (200..300).contains(&resp.status_code) {
+            let entry = CacheEntry {
+                object: state.object.clone(),
+                expires_at: now + self.ttl_secs,
+                resp: resp.clone(),
+            };
+            CACHE.with(|cache| cache.borrow_mut().insert(key, entry));
+        }
+        Ok(resp)
+    }
+}
+
+// Report each request with its method, URL and body, and a snippet of its
+// response, to see what the API returned when columns come back null.
+// Credentials are redacted before the snippets are cut, so that no part of
+// a secret survives.
+#[derive(Debug)]
+pub(crate) struct DebugDump;
+
+impl Middleware for DebugDump {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        let mut request = format!("{} {}", method_name(&req.method), state.redact_secrets(&req.url));
+        if !req.body.is_empty() {
+            request.push_str(&format!(" body: {}", snippet(&state.redact_secrets(&req.body))));
+        }
+        info!("debug", "request: {}", request);
+
+        let result = next.run(state, req);
+        match &result {
+            Ok(resp) => info!(
+                "debug",
+                "response: {} {}",
+                resp.status_code,
+                snippet(&state.redact_secrets(&redact_credentials(&resp.body)))
+            ),
+            Err(err) => info!("debug", "response: error {}", snippet(&state.redact_secrets(err))),
+        }
+        result
+    }
+}
+
+// Redact the credentials a response body holds, e.g. the new access token
+// of a token endpoint, which is only registered as a secret once the
+// response reaches the client
+pub(crate) fn redact_credentials(body: &str) -> String {
+    let Ok(mut json) = serde_json::from_str::<serde_json::Value>(body) else {
+        return body.to_owned();
+    };
+    let Some(fields) = json.as_object_mut() else {
+        return body.to_owned();
+    };
+    let mut redacted = false;
+    for name in CREDENTIAL_FIELDS {
+        if let Some(value) = fields.get_mut(*name) {
+            *value = "***".into();
+            redacted = true;
+        }
+    }
+    match redacted {
+        true => json.to_string(),
+        false => body.to_owned(),
+    }
+}
+
+// A single-line text of at most `DEBUG_SNIPPET_LEN` characters, noting the
+// full length of a longer text
+pub(crate) fn snippet(text: &str) -> String {
+    let mut snippet: String = text
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .take(DEBUG_SNIPPET_LEN)
+        .collect();
+    let len = text.chars().count();
+    if len > DEBUG_SNIPPET_LEN {
+        snippet.push_str(&format!("... ({} characters)", len));
+    }
+    snippet
+}
+
+// Time API requests for the per-object latency, and report those taking
+// longer than a threshold, 0 disabling the reports. The host clock has a
+// resolution of one second.
+#[derive(Debug)]
+pub(crate) struct Timing {
+    pub(crate) slow_threshold_secs: i64,
+}
+
+impl Middleware for Timing {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        let started_at = state.clock.now_secs();
+        let method = method_name(&req.method);
+        // The endpoint without its query, which may be long or carry ids
+        let endpoint = req.url.split('?').next().unwrap_or_default().to_owned();
+
+        let result = next.run(state, req);
+        let elapsed = state.clock.now_secs() - started_at;
+        stats::record_latency(&state.object, elapsed);
+        if self.slow_threshold_secs > 0 && elapsed > self.slow_threshold_secs {
+            stats::record(|stats| stats.slow_requests += 1);
+            notice!(
+                "client",
+                "Slow API request: {} {} took {}s, over the {}s threshold",
+                method,
+                endpoint,
+                elapsed,
+                self.slow_threshold_secs
+            );
+        }
+        result
+    }
+}
+
+// Report each exchange with the API as a fixture line, the logger redacts
+// the client credentials and the credentials of responses are redacted. The lines can be replayed in tests to catch
+// provider API changes.
+#[derive(Debug)]
+pub(crate) struct Recorder;
+
+impl Middleware for Recorder {
+    fn handle(
+        &mut self,
+        state: &mut ClientState,
+        req: http::Request,
+        next: &mut Next<'_>,
+    ) -> Result<http::Response, FdwError> {
+        let request = serde_json::json!({
+            "method": method_name(&req.method),
+            "url": req.url,
+            "body": req.body,
+        });
+
+        let result = next.run(state, req);
+        let response = match &result {
+            Ok(resp) => serde_json::json!({
+                "status_code": resp.status_code,
+                "headers": resp.headers,
+                "body": redact_credentials(&resp.body),
+            }),
+            Err(err) => serde_json::json!({ "error": err }),
+        };
+
+        let fixture = serde_json::json!({ "request": request, "response": response });
+        info!("recorder", "fixture: {}", fixture);
+
+        result
+    }
+}
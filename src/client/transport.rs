@@ -0,0 +1,130 @@
+use std::fmt;
+
+use crate::bindings::supabase::wrappers::http;
+
+// Sends requests to the API at the end of the middleware pipeline. The
+// host HTTP functions are only available inside a Wasm host, this lets
+// the request logic run natively in tests.
+pub(crate) trait Transport: fmt::Debug {
+    fn send(&mut self, req: &http::Request) -> http::HttpResult;
+}
+
+// Transport using the HTTP functions of the Wasm host
+#[derive(Debug)]
+pub(crate) struct HostTransport;
+
+impl Transport for HostTransport {
+    fn send(&mut self, req: &http::Request) -> http::HttpResult {
+        match req.method {
+            http::Method::Get => http::get(req),
+            http::Method::Post => http::post(req),
+            http::Method::Put => http::put(req),
+            http::Method::Patch => http::patch(req),
+            http::Method::Delete => http::delete(req),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::rc::Rc;
+
+    use serde_json::Value as JsonValue;
+
+    use super::Transport;
+    use crate::bindings::supabase::wrappers::http;
+    use crate::client::method_name;
+    use crate::clock::mock::FakeClock;
+
+    // Transport replaying canned responses in order, and recording the
+    // requests it was given
+    #[derive(Debug, Default)]
+    pub(crate) struct MockTransport {
+        responses: VecDeque<http::HttpResult>,
+        pub(crate) requests: Rc<RefCell<Vec<http::Request>>>,
+        // Clock advanced by the given seconds on each request
+        latency: Option<(FakeClock, i64)>,
+    }
+
+    impl MockTransport {
+        pub(crate) fn new(responses: Vec<http::HttpResult>) -> Self {
+            Self {
+                responses: responses.into(),
+                requests: Rc::default(),
+                latency: None,
+            }
+        }
+
+        // Simulate requests taking `secs` seconds on the clock
+        pub(crate) fn with_latency(mut self, clock: FakeClock, secs: i64) -> Self {
+            self.latency = Some((clock, secs));
+            self
+        }
+    }
+
+    impl Transport for MockTransport {
+        fn send(&mut self, req: &http::Request) -> http::HttpResult {
+            if let Some((clock, secs)) = &self.latency {
+                clock.advance(*secs);
+            }
+            self.requests.borrow_mut().push(req.clone());
+            self.responses
+                .pop_front()
+                .unwrap_or_else(|| Err("no more canned responses".to_owned()))
+        }
+    }
+
+    // A canned response with a status code and body
+    pub(crate) fn response(status_code: u16, body: &str) -> http::HttpResult {
+        Ok(http::Response {
+            url: String::default(),
+            status_code,
+            headers: Vec::new(),
+            body: body.to_owned(),
+        })
+    }
+
+    // Transport answering requests from fixture lines captured with the
+    // `record_fixtures` option, matching on method and URL
+    #[derive(Debug)]
+    pub(crate) struct ReplayTransport {
+        fixtures: Vec<JsonValue>,
+    }
+
+    impl ReplayTransport {
+        pub(crate) fn new(fixture_lines: &str) -> Self {
+            let fixtures = fixture_lines
+                .lines()
+                .map(|line| line.split_once("fixture:").map_or(line, |(_, f)| f).trim())
+                .filter(|line| !line.is_empty())
+                .map(|line| serde_json::from_str(line).expect("invalid fixture line"))
+                .collect();
+            Self { fixtures }
+        }
+    }
+
+    impl Transport for ReplayTransport {
+        fn send(&mut self, req: &http::Request) -> http::HttpResult {
+            let fixture = self
+                .fixtures
+                .iter()
+                .find(|f| {
+                    f["request"]["method"] == method_name(&req.method) && f["request"]["url"] == req.url
+                })
+                .ok_or_else(|| format!("no fixture for {} {}", method_name(&req.method), req.url))?;
+
+            let resp = &fixture["response"];
+            if let Some(err) = resp["error"].as_str() {
+                return Err(err.to_owned());
+            }
+            Ok(http::Response {
+                url: req.url.clone(),
+                status_code: resp["status_code"].as_u64().unwrap_or(200) as u16,
+                headers: serde_json::from_value(resp["headers"].clone()).unwrap_or_default(),
+                body: resp["body"].as_str().unwrap_or_default().to_owned(),
+            })
+        }
+    }
+}
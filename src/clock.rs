@@ -0,0 +1,65 @@
+use std::fmt;
+
+use crate::bindings::supabase::wrappers::time;
+
+// Source of time for the time-dependent behaviors (cache expiry, rate
+// limit waits, token refresh), so they can be tested without real sleeps
+pub(crate) trait Clock: fmt::Debug {
+    // seconds since Unix epoch
+    fn now_secs(&self) -> i64;
+
+    fn sleep_ms(&self, ms: u64);
+}
+
+// Clock of the Wasm host
+#[derive(Debug)]
+pub(crate) struct HostClock;
+
+impl Clock for HostClock {
+    fn now_secs(&self) -> i64 {
+        time::epoch_secs()
+    }
+
+    fn sleep_ms(&self, ms: u64) {
+        time::sleep(ms);
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::cell::{Cell, RefCell};
+    use std::rc::Rc;
+
+    use super::Clock;
+
+    // Deterministic clock whose sleeps advance the time instantly and are
+    // recorded. Clones share the same time.
+    #[derive(Debug, Clone, Default)]
+    pub(crate) struct FakeClock {
+        now: Rc<Cell<i64>>,
+        pub(crate) sleeps: Rc<RefCell<Vec<u64>>>,
+    }
+
+    impl FakeClock {
+        pub(crate) fn at(now: i64) -> Self {
+            let clock = Self::default();
+            clock.now.set(now);
+            clock
+        }
+
+        pub(crate) fn advance(&self, secs: i64) {
+            self.now.set(self.now.get() + secs);
+        }
+    }
+
+    impl Clock for FakeClock {
+        fn now_secs(&self) -> i64 {
+            self.now.get()
+        }
+
+        fn sleep_ms(&self, ms: u64) {
+            self.sleeps.borrow_mut().push(ms);
+            self.advance((ms / 1000) as i64);
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use crate::bindings::supabase::wrappers::{
+    types::{FdwError, Options},
+    utils,
+};
+use crate::client::{normalize_number, normalize_url, OAuth, RetryPolicy, Threshold, WriteRetry};
+use crate::log::Level;
+use crate::quiet_hours::{self, Policy, QuietHours};
+use crate::timestamp;
+
+// Source of raw option values, the host options or a map in tests
+pub(crate) trait OptionSource {
+    fn get(&self, key: &str) -> Option<String>;
+}
+
+impl OptionSource for Options {
+    fn get(&self, key: &str) -> Option<String> {
+        Options::get(self, key)
+    }
+}
+
+#[cfg(test)]
+impl OptionSource for std::collections::HashMap<&str, &str> {
+    fn get(&self, key: &str) -> Option<String> {
+        std::collections::HashMap::get(self, key).map(|v| v.to_string())
+    }
+}
+
+// Options of one source of a union server, given with the source name as
+// prefix, e.g. `old.api_key`
+pub(crate) struct Scoped<'a> {
+    source: &'a dyn OptionSource,
+    prefix: String,
+}
+
+impl OptionSource for Scoped<'_> {
+    fn get(&self, key: &str) -> Option<String> {
+        self.source.get(&format!("{}.{}", self.prefix, key))
+    }
+}
+
+// Typed accessors over server or table options. An empty value is treated
+// as unset, and invalid values fail with a uniform message naming the
+// option and the expected format.
+pub(crate) struct Opts<'a> {
+    source: &'a dyn OptionSource,
+}
+
+impl<'a> Opts<'a> {
+    pub(crate) fn new(source: &'a dyn OptionSource) -> Self {
+        Self { source }
+    }
+
+    pub(crate) fn string(&self, key: &str) -> Option<String> {
+        self.source.get(key).filter(|v| !v.is_empty())
+    }
+
+    pub(crate) fn string_or(&self, key: &str, default: &str) -> String {
+        self.string(key).unwrap_or_else(|| default.to_owned())
+    }
+
+    // A credential, given as is in `key` or stored in Supabase Vault, the
+    // `{key}_id` option naming the Vault secret
+    pub(crate) fn secret(&self, key: &str) -> Result<Option<String>, FdwError> {
+        if let Some(value) = self.string(key) {
+            return Ok(Some(value));
+        }
+        let id_key = format!("{}_id", key);
+        match self.string(&id_key) {
+            Some(secret_id) => utils::get_vault_secret(&secret_id)
+                .filter(|v| !v.is_empty())
+                .map(Some)
+                .ok_or_else(|| format!("Cannot read Vault secret '{}' of option '{}'", secret_id, id_key)),
+            None => Ok(None),
+        }
+    }
+
+    // A required credential, see `secret`
+    pub(crate) fn require_secret(&self, key: &str) -> Result<String, FdwError> {
+        self.secret(key)?
+            .ok_or_else(|| format!("Missing required option '{}' or '{}_id'", key, key))
+    }
+
+    // A comma separated list, e.g. `old, new`, without empty items
+    pub(crate) fn list(&self, key: &str) -> Option<Vec<String>> {
+        let value = self.string(key)?;
+        Some(
+            value
+                .split(',')
+                .map(|item| item.trim().to_owned())
+                .filter(|item| !item.is_empty())
+                .collect(),
+        )
+    }
+
+    // The options of a source of a union server
+    pub(crate) fn scoped(&self, name: &str) -> Scoped<'a> {
+        Scoped {
+            source: self.source,
+            prefix: name.to_owned(),
+        }
+    }
+
+    // A URL, normalized, see `normalize_url`
+    pub(crate) fn url(&self, key: &str) -> Option<String> {
+        self.string(key).map(|url| normalize_url(&url))
+    }
+
+    pub(crate) fn url_or(&self, key: &str, default: &str) -> String {
+        self.url(key).unwrap_or_else(|| default.to_owned())
+    }
+
+    // A phone number, without spaces and punctuation, see `normalize_number`
+    pub(crate) fn phone_number(&self, key: &str) -> Option<String> {
+        self.string(key).map(|number| normalize_number(&number))
+    }
+
+    pub(crate) fn require_phone_number(&self, key: &str) -> Result<String, FdwError> {
+        self.require(key).map(|number| normalize_number(&number))
+    }
+
+    pub(crate) fn require(&self, key: &str) -> Result<String, FdwError> {
+        self.string(key)
+            .ok_or_else(|| format!("Missing required option '{}'", key))
+    }
+
+    // Parse an option with the given parser, describing the expected format
+    // on failure
+    fn parse<T>(
+        &self,
+        key: &str,
+        default: T,
+        expected: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<T, FdwError> {
+        match self.string(key) {
+            None => Ok(default),
+            Some(value) => parse(value.trim()).ok_or_else(|| {
+                format!(
+                    "Invalid value '{}' for option '{}', expected {}",
+                    value, key, expected
+                )
+            }),
+        }
+    }
+
+    pub(crate) fn bool(&self, key: &str, default: bool) -> Result<bool, FdwError> {
+        self.parse(key, default, "true or false", |v| {
+            match v.to_ascii_lowercase().as_str() {
+                "true" | "on" | "yes" | "1" => Some(true),
+                "false" | "off" | "no" | "0" => Some(false),
+                _ => None,
+            }
+        })
+    }
+
+    // A duration in seconds, either plain seconds or suffixed with s, m, h
+    // or d, e.g. `90`, `15m`
+    pub(crate) fn duration_secs(&self, key: &str, default: i64) -> Result<i64, FdwError> {
+        self.parse(key, default, "a duration such as 30, 30s, 5m, 1h or 1d", |v| {
+            let (num, unit) = match v.char_indices().last() {
+                Some((i, c)) if c.is_ascii_alphabetic() => (&v[..i], &v[i..]),
+                _ => (v, "s"),
+            };
+            let multiplier = match unit {
+                "s" => 1,
+                "m" => 60,
+                "h" => 3600,
+                "d" => 86400,
+                _ => return None,
+            };
+            num.trim()
+                .parse::<i64>()
+                .ok()
+                .filter(|n| *n >= 0)
+                .and_then(|n| n.checked_mul(multiplier))
+        })
+    }
+
+    // A non-negative number, e.g. `20`
+    pub(crate) fn count(&self, key: &str, default: i64) -> Result<i64, FdwError> {
+        self.parse(key, default, "a non-negative number", |v| v.parse::<i64>().ok().filter(|n| *n >= 0))
+    }
+
+    // A point in time in RFC 3339, e.g. `2024-06-01T00:00:00Z`, as
+    // microseconds since Unix epoch
+    pub(crate) fn timestamp(&self, key: &str) -> Result<Option<i64>, FdwError> {
+        self.parse(key, None, "a timestamp such as 2024-06-01T00:00:00Z", |v| {
+            timestamp::parse_rfc3339(v).map(Some)
+        })
+    }
+
+    // A number of requests, or a percentage of the limit suffixed with %,
+    // e.g. `50` or `10%`
+    pub(crate) fn threshold(&self, key: &str, default: Threshold) -> Result<Threshold, FdwError> {
+        self.parse(key, default, "a number of requests or a percentage such as 50 or 10%", |v| {
+            match v.strip_suffix('%') {
+                Some(percent) => percent
+                    .trim()
+                    .parse::<i64>()
+                    .ok()
+                    .filter(|n| (0..=100).contains(n))
+                    .map(Threshold::Percent),
+                None => v.parse::<i64>().ok().filter(|n| *n >= 0).map(Threshold::Count),
+            }
+        })
+    }
+
+    // A local time range, e.g. `22:00-08:00`, as minutes since midnight
+    pub(crate) fn time_range(&self, key: &str) -> Result<Option<(i64, i64)>, FdwError> {
+        self.parse(key, None, "a time range such as 22:00-08:00", |v| {
+            quiet_hours::parse_range(v).map(Some)
+        })
+    }
+
+    // A UTC offset, e.g. `UTC` or `+02:00`, as seconds east of UTC
+    pub(crate) fn utc_offset(&self, key: &str) -> Result<i64, FdwError> {
+        self.parse(key, 0, "a UTC offset such as UTC, +02:00 or -05:30", quiet_hours::parse_utc_offset)
+    }
+
+    // One of a fixed set of named values
+    pub(crate) fn choice<T: Copy>(
+        &self,
+        key: &str,
+        default: T,
+        choices: &[(&str, T)],
+    ) -> Result<T, FdwError> {
+        let names: Vec<&str> = choices.iter().map(|(name, _)| *name).collect();
+        self.parse(key, default, &format!("one of {}", names.join(", ")), |v| {
+            choices
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(v))
+                .map(|(_, value)| *value)
+        })
+    }
+}
+
+// Foreign server options shared by all providers, parsed once at init.
+// The options of the selected provider are parsed by the provider.
+#[derive(Debug, Default)]
+pub(crate) struct ServerConfig {
+    pub(crate) oauth: Option<OAuth>,
+    // seconds, 0 disables caching
+    pub(crate) cache_ttl: i64,
+    pub(crate) log_level: Level,
+    pub(crate) record_fixtures: bool,
+    // Report each request and a snippet of its response
+    pub(crate) debug: bool,
+    // Report how each scan is run, as Postgres can't explain it
+    pub(crate) explain: bool,
+    // seconds an API request may take before a notice, 0 disables it
+    pub(crate) slow_request_threshold: i64,
+    // Remaining rate limit capacity below which a warning is reported
+    pub(crate) rate_limit_warning: Threshold,
+    // Requests retried after a transport failure
+    pub(crate) retry: RetryPolicy,
+    // Local time range during which messages are not sent
+    pub(crate) quiet_hours: Option<QuietHours>,
+    // Messages sent to a contact per 24 hours, 0 disables the cap
+    pub(crate) max_messages_per_contact_per_day: i64,
+    #[cfg(feature = "fault-injection")]
+    pub(crate) inject_faults: Option<String>,
+}
+
+impl ServerConfig {
+    pub(crate) fn parse(opts: &Opts) -> Result<Self, FdwError> {
+        // Use OAuth bearer tokens instead of the API key if a token endpoint is set
+        let oauth = match opts.url("token_url") {
+            Some(token_url) => {
+                // A supplied access token is used until the API rejects it
+                let access_token = opts.string_or("access_token", "");
+                let expires_at = if access_token.is_empty() { 0 } else { i64::MAX };
+                Some(OAuth {
+                    token_url,
+                    client_id: opts.require("client_id")?,
+                    client_secret: opts.require("client_secret")?,
+                    refresh_token: opts.string_or("refresh_token", ""),
+                    access_token,
+                    expires_at,
+                })
+            }
+            None => None,
+        };
+
+        // Holding rows back until the quiet hours end would need an object
+        // scheduling messages, which no provider offers
+        if opts.string("quiet_hours_policy").is_some_and(|v| v.trim().eq_ignore_ascii_case("queue")) {
+            return Err("Server option quiet_hours_policy 'queue' is not supported, as no provider schedules messages to be sent later. Use 'fail' and run the insert again after the quiet hours".to_owned());
+        }
+        // The host's HTTP interface blocks until each response, so requests
+        // can only be sent one at a time
+        let max_concurrency = opts.parse("max_concurrency", 1, "a positive number", |v| {
+            v.parse::<i64>().ok().filter(|n| *n > 0)
+        })?;
+        if max_concurrency > 1 {
+            return Err(format!(
+                "Server option max_concurrency '{}' is not supported, as the Wasm host sends one request at a time. Use '1' or leave it unset",
+                max_concurrency
+            ));
+        }
+        let quiet_hours = match opts.time_range("quiet_hours")? {
+            Some((start, end)) => Some(QuietHours {
+                start,
+                end,
+                utc_offset: opts.utc_offset("timezone")?,
+                policy: opts.choice("quiet_hours_policy", Policy::Fail, Policy::CHOICES)?,
+            }),
+            None => None,
+        };
+
+        Ok(Self {
+            oauth,
+            cache_ttl: opts.duration_secs("cache_ttl", 0)?,
+            log_level: opts.choice("log_level", Level::Info, Level::CHOICES)?,
+            record_fixtures: opts.bool("record_fixtures", false)?,
+            debug: opts.bool("debug", false)?,
+            explain: opts.bool("explain", false)?,
+            slow_request_threshold: opts.duration_secs("slow_request_threshold", 5)?,
+            rate_limit_warning: opts.threshold("rate_limit_warning", Threshold::Percent(10))?,
+            retry: RetryPolicy {
+                reads: opts.bool("retry_reads", true)?,
+                writes: opts.choice("retry_writes", WriteRetry::WithIdempotencyOnly, WriteRetry::CHOICES)?,
+                max_retries: opts.count("max_retries", 3)?,
+                backoff_ms: opts.count("retry_backoff_ms", 500)?,
+            },
+            quiet_hours,
+            max_messages_per_contact_per_day: opts.count("max_messages_per_contact_per_day", 0)?,
+            #[cfg(feature = "fault-injection")]
+            inject_faults: opts.string("inject_faults"),
+        })
+    }
+}
+
+// Table options only meaningful for some objects, with those objects
+const OBJECT_OPTIONS: &[(&str, &[&str])] = &[
+    ("feed_url", &["product_feed", "catalog_diff"]),
+    ("webhook_url", &["webhook_bridge"]),
+    ("verify_token", &["webhook_bridge"]),
+    ("sample_objects", &["discover"]),
+    ("server_name", &["ddl"]),
+    ("per_number", &["ddl"]),
+    ("sends_per_second", &["campaign_sends"]),
+    ("progress_every", &["campaign_sends"]),
+    ("sla_threshold", &["sla"]),
+    ("as_of", &["products"]),
+    ("consistency", &["products"]),
+    ("confirm_sends", &["messages"]),
+];
+
+// Foreign table options, parsed at the start of each scan or modify
+#[derive(Debug, Default)]
+pub(crate) struct TableConfig {
+    pub(crate) object: String,
+    // URL of the feed file read by the `product_feed` object
+    pub(crate) feed_url: Option<String>,
+    // Callback URL registered by the `webhook_bridge` object, e.g. of a
+    // Supabase Edge Function
+    pub(crate) webhook_url: Option<String>,
+    // Token Meta sends back to verify the callback URL
+    pub(crate) verify_token: Option<String>,
+    // Column identifying updated and deleted rows
+    pub(crate) rowid_column: Option<String>,
+    // Sources of a union server read by the table, all by default
+    pub(crate) sources: Option<Vec<String>>,
+    // Objects sampled by the `discover` object
+    pub(crate) sample_objects: Option<Vec<String>>,
+    // Server named by the statements of the `ddl` object
+    pub(crate) server_name: Option<String>,
+    // Whether the `ddl` object creates the tables in a schema per number
+    pub(crate) per_number: bool,
+    // Whether columns the object doesn't declare are read from the row
+    // field of the same name, with the `columns 'auto'` option
+    pub(crate) auto_columns: bool,
+    // Throughput of the `campaign_sends` object, 0 sending without waits
+    pub(crate) sends_per_second: i64,
+    // Rows sent by a campaign between progress notices, 0 disables them
+    pub(crate) progress_every: i64,
+    // seconds a chat may wait for a reply before the `sla` object reports
+    // a breach
+    pub(crate) sla_threshold: i64,
+    // Capture time of the `snapshot_at` column of products, microseconds
+    // since Unix epoch, the start of each scan if unset
+    pub(crate) as_of: Option<i64>,
+    // Whether a scan reads all the pages of the products before returning
+    // any, as of a single version of the catalog, with the
+    // `consistency 'strict'` option
+    pub(crate) strict_consistency: bool,
+    // Whether each sent message is looked up after the send, failing the
+    // insert if the provider rejected it from its queue
+    pub(crate) confirm_sends: bool,
+}
+
+impl TableConfig {
+    pub(crate) fn parse(opts: &Opts) -> Result<Self, FdwError> {
+        let table = Self {
+            object: opts.string_or("object", "products"),
+            feed_url: opts.url("feed_url"),
+            webhook_url: opts.url("webhook_url"),
+            verify_token: opts.string("verify_token"),
+            rowid_column: opts.string("rowid_column"),
+            sources: opts.list("sources"),
+            sample_objects: opts.list("sample_objects"),
+            server_name: opts.string("server_name"),
+            per_number: opts.bool("per_number", false)?,
+            auto_columns: opts.choice("columns", false, &[("strict", false), ("auto", true)])?,
+            sends_per_second: opts.count("sends_per_second", 0)?,
+            progress_every: opts.count("progress_every", 100)?,
+            sla_threshold: opts.duration_secs("sla_threshold", 3600)?,
+            as_of: opts.timestamp("as_of")?,
+            strict_consistency: opts.choice("consistency", false, &[("best_effort", false), ("strict", true)])?,
+            confirm_sends: opts.bool("confirm_sends", false)?,
+        };
+        for (option, objects) in OBJECT_OPTIONS {
+            if opts.string(option).is_some() && !objects.contains(&table.object.as_str()) {
+                return Err(format!(
+                    "Table option '{}' only applies to object '{}', not to '{}'",
+                    option,
+                    objects.join("' or '"),
+                    table.object
+                ));
+            }
+        }
+        Ok(table)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn server(options: &[(&'static str, &'static str)]) -> Result<ServerConfig, FdwError> {
+        let mut source: HashMap<&str, &str> = HashMap::from([
+            ("phone_number", "+4915123456789"),
+            ("from_number", "+4915198765432"),
+            ("api_key", "key"),
+        ]);
+        source.extend(options.iter().copied());
+        ServerConfig::parse(&Opts::new(&source))
+    }
+
+    #[test]
+    fn parses_server_defaults() {
+        let config = server(&[]).unwrap();
+        assert_eq!(config.cache_ttl, 0);
+        assert_eq!(config.log_level, Level::Info);
+        assert!(!config.record_fixtures);
+        assert!(!config.debug);
+        assert_eq!(config.slow_request_threshold, 5);
+        assert_eq!(config.retry, RetryPolicy::default());
+        assert!(config.quiet_hours.is_none());
+        assert_eq!(config.max_messages_per_contact_per_day, 0);
+        assert!(config.oauth.is_none());
+    }
+
+    #[test]
+    fn parses_typed_server_options() {
+        let config = server(&[
+            ("cache_ttl", "5m"),
+            ("log_level", "DEBUG"),
+            ("record_fixtures", "on"),
+            ("slow_request_threshold", "0"),
+            ("rate_limit_warning", "50"),
+            ("retry_reads", "false"),
+            ("retry_writes", "never"),
+            ("max_retries", "5"),
+            ("retry_backoff_ms", "0"),
+            ("quiet_hours", "21:30-08:00"),
+            ("timezone", "+02:00"),
+            ("quiet_hours_policy", "warn"),
+        ])
+        .unwrap();
+        assert_eq!(config.cache_ttl, 300);
+        assert_eq!(config.slow_request_threshold, 0);
+        assert_eq!(config.rate_limit_warning, Threshold::Count(50));
+        assert!(!config.retry.reads);
+        assert_eq!(config.retry.writes, WriteRetry::Never);
+        assert_eq!(config.retry.max_retries, 5);
+        assert_eq!(config.retry.backoff_ms, 0);
+        assert_eq!(
+            config.quiet_hours,
+            Some(QuietHours {
+                start: 1290,
+                end: 480,
+                utc_offset: 7200,
+                policy: Policy::Warn,
+            })
+        );
+        assert_eq!(config.log_level, Level::Debug);
+        assert!(config.record_fixtures);
+    }
+
+    #[test]
+    fn invalid_values_name_the_option() {
+        for (key, value, expected) in [
+            ("cache_ttl", "soon", "a duration such as 30, 30s, 5m, 1h or 1d"),
+            ("cache_ttl", "-5", "a duration such as 30, 30s, 5m, 1h or 1d"),
+            ("log_level", "trace", "one of debug, info, notice, warning"),
+            ("record_fixtures", "maybe", "true or false"),
+            ("rate_limit_warning", "120%", "a number of requests or a percentage such as 50 or 10%"),
+            ("max_concurrency", "0", "a positive number"),
+            ("max_retries", "-1", "a non-negative number"),
+        ] {
+            assert_eq!(
+                server(&[(key, value)]).unwrap_err(),
+                format!("Invalid value '{}' for option '{}', expected {}", value, key, expected)
+            );
+        }
+
+        let err = server(&[("quiet_hours", "22:00-08:00"), ("quiet_hours_policy", "queue")]).unwrap_err();
+        assert!(err.starts_with("Server option quiet_hours_policy 'queue' is not supported"), "{}", err);
+        assert!(server(&[("max_concurrency", "1")]).is_ok());
+        let err = server(&[("max_concurrency", "4")]).unwrap_err();
+        assert!(err.starts_with("Server option max_concurrency '4' is not supported"), "{}", err);
+    }
+
+    #[test]
+    fn oauth_requires_client_credentials() {
+        let err = server(&[("token_url", "https://auth.example.com/token")]).unwrap_err();
+        assert_eq!(err, "Missing required option 'client_id'");
+
+        let config = server(&[
+            ("api_key", ""),
+            ("token_url", "https://auth.example.com/token"),
+            ("client_id", "id"),
+            ("client_secret", "secret"),
+        ])
+        .unwrap();
+        assert_eq!(config.oauth.unwrap().expires_at, 0);
+    }
+
+    #[test]
+    fn secrets_are_given_as_is_or_by_vault_id() {
+        let source = HashMap::from([("app_secret", "secret"), ("api_key", "")]);
+        let opts = Opts::new(&source);
+        assert_eq!(opts.require_secret("app_secret").unwrap(), "secret");
+        assert_eq!(
+            opts.require_secret("api_key").unwrap_err(),
+            "Missing required option 'api_key' or 'api_key_id'"
+        );
+    }
+
+    #[test]
+    fn feed_url_only_applies_to_product_feed() {
+        let table = |options: &[(&'static str, &'static str)]| {
+            let source: HashMap<&str, &str> = options.iter().copied().collect();
+            TableConfig::parse(&Opts::new(&source))
+        };
+        let feed = table(&[("object", "product_feed"), ("feed_url", "https://shop.example.com/feed.csv")]).unwrap();
+        assert_eq!(feed.feed_url.as_deref(), Some("https://shop.example.com/feed.csv"));
+
+        let err = table(&[("feed_url", "https://shop.example.com/feed.csv")]).unwrap_err();
+        assert_eq!(err, "Table option 'feed_url' only applies to object 'product_feed' or 'catalog_diff', not to 'products'");
+        assert!(table(&[("object", "health"), ("verify_token", "s3cret")]).is_err());
+        assert!(table(&[("object", "messages"), ("confirm_sends", "true")]).unwrap().confirm_sends);
+        assert!(table(&[("object", "campaign_sends"), ("confirm_sends", "true")]).is_err());
+        assert!(table(&[("consistency", "strict")]).unwrap().strict_consistency);
+        assert!(!table(&[("consistency", "best_effort")]).unwrap().strict_consistency);
+        assert!(table(&[("object", "messages"), ("consistency", "strict")]).is_err());
+    }
+
+    #[test]
+    fn as_of_is_a_timestamp() {
+        let source = HashMap::from([("as_of", "2024-06-01T00:00:00Z")]);
+        let table = TableConfig::parse(&Opts::new(&source)).unwrap();
+        assert_eq!(table.as_of, Some(1_717_200_000_000_000));
+
+        let source = HashMap::from([("as_of", "yesterday")]);
+        let err = TableConfig::parse(&Opts::new(&source)).unwrap_err();
+        assert_eq!(
+            err,
+            "Invalid value 'yesterday' for option 'as_of', expected a timestamp such as 2024-06-01T00:00:00Z"
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::bindings::supabase::wrappers::types::{Cell, Context, Value};
+use crate::objects::ObjectCapabilities;
+use crate::timestamp::Period;
+
+// Likely reason for an object to have no rows at all, besides a
+// misconfiguration
+const EMPTY_OBJECT_CAUSES: &[(&str, &str)] = &[
+    ("products", "no catalog is published for the number, or it has no products"),
+    ("messages", "the number sent and received no messages recently"),
+    ("templates", "the account has no message templates"),
+    ("contacts", "the account has no contacts yet"),
+    ("phone_numbers", "the account has no registered numbers"),
+    ("webhook_bridge", "no webhook is registered"),
+];
+
+// How a scan is run: which quals the provider applies and which Postgres
+// evaluates on the fetched rows, along with the sorts and limit, which are
+// always applied by Postgres. The host has no explain hook, so this is
+// reported when the scan begins with the `explain` option.
+#[derive(Debug, Default)]
+pub(crate) struct ScanPlan {
+    pub(crate) object: String,
+    pub(crate) pagination: &'static str,
+    pub(crate) pushed_quals: Vec<String>,
+    pub(crate) local_quals: Vec<String>,
+    pub(crate) sorts: Vec<String>,
+    pub(crate) limit: Option<String>,
+    // Time range pushed down with the quals
+    pub(crate) period: Period,
+    // Values of the equality quals pushed down, by column
+    pub(crate) equals: Vec<(String, String)>,
+}
+
+impl ScanPlan {
+    // Plan of the scan of an object with the quals, sorts and limit of the
+    // query
+    pub(crate) fn new(ctx: &Context, caps: &ObjectCapabilities) -> Self {
+        let (pushed_quals, local_quals): (Vec<_>, Vec<_>) = ctx
+            .get_quals()
+            .into_iter()
+            .partition(|qual| caps.quals.contains(&qual.field().as_str()));
+        let mut period = Period::default();
+        let mut equals = Vec::new();
+        for qual in pushed_quals.iter().filter(|qual| !qual.use_or()) {
+            period.add_qual(&qual.field(), &qual.operator(), &qual.value());
+            if let Some(value) = equal_value(&qual.operator(), &qual.value()) {
+                equals.push((qual.field(), value));
+            }
+        }
+        Self {
+            object: caps.name.to_owned(),
+            pagination: caps.pagination.as_str(),
+            pushed_quals: pushed_quals.iter().map(|qual| qual.deparse()).collect(),
+            local_quals: local_quals.iter().map(|qual| qual.deparse()).collect(),
+            sorts: ctx.get_sorts().iter().map(|sort| sort.deparse()).collect(),
+            limit: ctx.get_limit().map(|limit| limit.deparse()),
+            period,
+            equals,
+        }
+    }
+
+    // One line describing the plan
+    pub(crate) fn describe(&self) -> String {
+        let list = |items: &[String]| match items {
+            [] => "none".to_owned(),
+            items => items.join(", "),
+        };
+        let mut msg = format!(
+            "Scan of object '{}' with {} pagination, quals pushed down: {}, evaluated by Postgres: {}",
+            self.object,
+            self.pagination,
+            list(&self.pushed_quals),
+            list(&self.local_quals)
+        );
+        if !self.sorts.is_empty() {
+            msg.push_str(&format!(", sort by Postgres: {}", self.sorts.join(", ")));
+        }
+        if let Some(limit) = &self.limit {
+            msg.push_str(&format!(", {} by Postgres after fetching all rows", limit));
+        }
+        msg
+    }
+
+    // Likely causes of the scan returning no rows, with the options to
+    // check: `identity_options` are the server options naming the number
+    // or account, e.g. `from_number`
+    pub(crate) fn empty_result_causes(&self, identity_options: &[String]) -> Vec<String> {
+        let mut causes: Vec<String> = EMPTY_OBJECT_CAUSES
+            .iter()
+            .filter(|(object, _)| *object == self.object)
+            .map(|(_, cause)| cause.to_string())
+            .collect();
+        if !identity_options.is_empty() {
+            causes.push(format!(
+                "the server options name another number or account, check {}",
+                identity_options.join(", ")
+            ));
+        }
+        if !self.pushed_quals.is_empty() {
+            causes.push(format!(
+                "the provider filtered out every row with {}",
+                self.pushed_quals.join(" and ")
+            ));
+        }
+        causes
+    }
+}
+
+// Value of an equality qual on a text or integer column, as text
+fn equal_value(operator: &str, value: &Value) -> Option<String> {
+    match (operator, value) {
+        ("=", Value::Cell(Cell::String(s))) => Some(s.clone()),
+        ("=", Value::Cell(Cell::I64(n))) => Some(n.to_string()),
+        ("=", Value::Cell(Cell::I32(n))) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn describes_what_is_pushed_down() {
+        let plan = ScanPlan {
+            object: "messages".to_owned(),
+            pagination: "cursor",
+            pushed_quals: vec!["status = 'read'".to_owned()],
+            local_quals: Vec::new(),
+            sorts: vec!["sent_at desc".to_owned()],
+            limit: Some("limit 10".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(
+            plan.describe(),
+            "Scan of object 'messages' with cursor pagination, quals pushed down: status = 'read', evaluated by Postgres: none, sort by Postgres: sent_at desc, limit 10 by Postgres after fetching all rows"
+        );
+        assert_eq!(
+            ScanPlan {
+                object: "contacts".to_owned(),
+                pagination: "no",
+                ..Default::default()
+            }
+            .describe(),
+            "Scan of object 'contacts' with no pagination, quals pushed down: none, evaluated by Postgres: none"
+        );
+    }
+
+    #[test]
+    fn suggests_causes_of_empty_results() {
+        let plan = ScanPlan {
+            object: "products".to_owned(),
+            pushed_quals: vec!["availability = 'in stock'".to_owned()],
+            ..Default::default()
+        };
+        assert_eq!(
+            plan.empty_result_causes(&["phone_number".to_owned(), "from_number".to_owned()]),
+            [
+                "no catalog is published for the number, or it has no products",
+                "the server options name another number or account, check phone_number, from_number",
+                "the provider filtered out every row with availability = 'in stock'",
+            ]
+        );
+        assert!(ScanPlan::default().empty_result_causes(&[]).is_empty());
+    }
+
+    #[test]
+    fn pushes_down_text_and_integer_equalities() {
+        let chat_id = Value::Cell(Cell::String("4915123456789@c.us".to_owned()));
+        assert_eq!(equal_value("=", &chat_id).as_deref(), Some("4915123456789@c.us"));
+        assert_eq!(equal_value("=", &Value::Cell(Cell::I64(42))).as_deref(), Some("42"));
+        assert_eq!(equal_value("<>", &chat_id), None);
+        assert_eq!(equal_value("=", &Value::Array(Vec::new())), None);
+    }
+}
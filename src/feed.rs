@@ -0,0 +1,289 @@
+// Parsing of Meta commerce product feed files, CSV (or TSV) and RSS/Atom
+// XML, into rows of their fields by name
+
+use serde_json::{Map, Value as JsonValue};
+
+use crate::bindings::supabase::wrappers::types::FdwError;
+
+// Feed fields holding a number, the others being strings
+const INTEGER_FIELDS: &[&str] = &["quantity_to_sell_on_facebook"];
+
+// Parse a feed file, XML if it starts with a tag and CSV otherwise
+pub(crate) fn parse(body: &str) -> Result<Vec<JsonValue>, FdwError> {
+    let body = body.trim_start_matches('\u{feff}').trim_start();
+    let items = if body.starts_with('<') {
+        parse_xml(body)?
+    } else {
+        parse_csv(body)?
+    };
+    Ok(items.into_iter().map(feed_row).collect())
+}
+
+// Convert the fields of a feed item to a row, numeric fields as numbers
+fn feed_row(mut item: Map<String, JsonValue>) -> JsonValue {
+    for field in INTEGER_FIELDS {
+        if let Some(value) = item.get_mut(*field) {
+            *value = value
+                .as_str()
+                .and_then(|v| v.trim().parse::<i64>().ok())
+                .map_or(JsonValue::Null, JsonValue::from);
+        }
+    }
+    JsonValue::Object(item)
+}
+
+// Parse a CSV feed, the first record naming the fields. A header with tabs
+// and no commas is a TSV feed.
+fn parse_csv(body: &str) -> Result<Vec<Map<String, JsonValue>>, FdwError> {
+    let first_line = body.lines().next().unwrap_or_default();
+    let delimiter = if first_line.contains('\t') && !first_line.contains(',') {
+        '\t'
+    } else {
+        ','
+    };
+
+    let mut records = csv_records(body, delimiter)?.into_iter();
+    let header: Vec<String> = match records.next() {
+        Some(header) => header.iter().map(|name| name.trim().to_lowercase()).collect(),
+        None => return Ok(Vec::new()),
+    };
+    Ok(records
+        .filter(|record| record.iter().any(|value| !value.is_empty()))
+        .map(|record| {
+            header
+                .iter()
+                .zip(record)
+                .filter(|(name, _)| !name.is_empty())
+                .map(|(name, value)| (name.clone(), JsonValue::String(value)))
+                .collect()
+        })
+        .collect())
+}
+
+// Split CSV text into records of fields, with RFC 4180 quoting: quoted
+// fields may hold delimiters, line breaks and `""` escaped quotes
+fn csv_records(body: &str, delimiter: char) -> Result<Vec<Vec<String>>, FdwError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut chars = body.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    field.push('"');
+                } else {
+                    quoted = false;
+                }
+            }
+            '"' if field.is_empty() => quoted = true,
+            '\n' if quoted => {
+                line += 1;
+                field.push(c);
+            }
+            c if quoted => field.push(c),
+            c if c == delimiter => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                line += 1;
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err(format!("Unterminated quoted field in product feed at line {}", line));
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+// Parse the `<item>` (RSS) or `<entry>` (Atom) elements of an XML feed.
+// Fields are the text children of an item, without their namespace prefix,
+// e.g. `<g:price>`; repeated fields such as `additional_image_link` are
+// joined with `, `, and nested elements such as `<g:shipping>` skipped.
+fn parse_xml(body: &str) -> Result<Vec<Map<String, JsonValue>>, FdwError> {
+    let mut items = Vec::new();
+    let mut rest = body;
+
+    while let Some((tag, start)) = next_item(rest) {
+        let content = &rest[start..];
+        let close = format!("</{}>", tag);
+        let end = content
+            .find(&close)
+            .ok_or_else(|| format!("Unterminated <{}> element in product feed", tag))?;
+        items.push(parse_item(&content[..end]));
+        rest = &content[end + close.len()..];
+    }
+    Ok(items)
+}
+
+// Find the next item element, returning its tag and the offset of its
+// content
+fn next_item(xml: &str) -> Option<(&'static str, usize)> {
+    ["item", "entry"]
+        .into_iter()
+        .filter_map(|tag| {
+            let mut from = 0;
+            while let Some(at) = xml[from..].find(&format!("<{}", tag)) {
+                let at = from + at;
+                let after = &xml[at + 1 + tag.len()..];
+                // Not a longer tag name, e.g. `<items>`
+                if after.starts_with(['>', ' ', '\t', '\r', '\n']) {
+                    return Some((tag, at + 1 + tag.len() + after.find('>')? + 1));
+                }
+                from = at + 1;
+            }
+            None
+        })
+        .min_by_key(|(_, start)| *start)
+}
+
+// Collect the text children of an item
+fn parse_item(content: &str) -> Map<String, JsonValue> {
+    let mut item = Map::new();
+    let mut rest = content;
+
+    while let Some(open) = rest.find('<') {
+        rest = &rest[open..];
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(tag_end) = rest.find('>') else { break };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+        if tag.ends_with('/') || tag.starts_with(['/', '?', '!']) {
+            continue;
+        }
+
+        let name = tag.split_whitespace().next().unwrap_or_default();
+        let close = format!("</{}>", name);
+        let Some(end) = rest.find(&close) else { break };
+        let inner = &rest[..end];
+        rest = &rest[end + close.len()..];
+
+        let text = match inner.trim().strip_prefix("<![CDATA[") {
+            Some(cdata) => cdata.strip_suffix("]]>").unwrap_or(cdata).to_owned(),
+            None if inner.contains('<') => continue,
+            None => decode_entities(inner.trim()),
+        };
+        let field = name.rsplit(':').next().unwrap_or(name).to_lowercase();
+        match item.get_mut(&field) {
+            Some(JsonValue::String(value)) => {
+                value.push_str(", ");
+                value.push_str(&text);
+            }
+            _ => {
+                item.insert(field, JsonValue::String(text));
+            }
+        }
+    }
+    item
+}
+
+// Decode the predefined and numeric XML entities
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        decoded.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let entity = rest.find(';').map(|end| &rest[1..end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => match entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X")) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok().and_then(char::from_u32),
+                None => entity
+                    .strip_prefix('#')
+                    .and_then(|dec| dec.parse().ok())
+                    .and_then(char::from_u32),
+            },
+        });
+        match (c, entity) {
+            (Some(c), Some(entity)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_csv_feeds() {
+        let rows = parse(concat!(
+            "\u{feff}id,title,description,price,quantity_to_sell_on_facebook\r\n",
+            "SKU-1,Mug,\"Large, blue\nmug\",12.00 EUR,7\r\n",
+            "\r\n",
+            "SKU-2,\"The \"\"Best\"\" Tee\",,20.00 EUR,n/a\r\n",
+        ))
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["description"], "Large, blue\nmug");
+        assert_eq!(rows[0]["quantity_to_sell_on_facebook"], 7);
+        assert_eq!(rows[1]["title"], "The \"Best\" Tee");
+        assert!(rows[1]["quantity_to_sell_on_facebook"].is_null());
+
+        let tsv = parse("id\ttitle\nSKU-1\tMug, blue\n").unwrap();
+        assert_eq!(tsv[0]["title"], "Mug, blue");
+
+        let err = parse("id,title\nSKU-1,\"Mug\n").unwrap_err();
+        assert_eq!(err, "Unterminated quoted field in product feed at line 3");
+    }
+
+    #[test]
+    fn parses_rss_feeds() {
+        let rows = parse(
+            r#"<?xml version="1.0"?>
+            <rss xmlns:g="http://base.google.com/ns/1.0" version="2.0">
+              <channel>
+                <title>Store &amp; Co</title>
+                <item>
+                  <g:id>SKU-1</g:id>
+                  <g:title><![CDATA[Mug <Large>]]></g:title>
+                  <g:description>Tom &amp; Jerry&#39;s mug</g:description>
+                  <g:image_link>https://a</g:image_link>
+                  <g:additional_image_link>https://b</g:additional_image_link>
+                  <g:additional_image_link>https://c</g:additional_image_link>
+                  <g:shipping><g:country>DE</g:country></g:shipping>
+                  <!-- <g:brand>Hidden</g:brand> -->
+                </item>
+                <item><g:id>SKU-2</g:id></item>
+              </channel>
+            </rss>"#,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["id"], "SKU-1");
+        assert_eq!(rows[0]["title"], "Mug <Large>");
+        assert_eq!(rows[0]["description"], "Tom & Jerry's mug");
+        assert_eq!(rows[0]["additional_image_link"], "https://b, https://c");
+        assert!(rows[0].get("shipping").is_none());
+        assert!(rows[0].get("brand").is_none());
+        assert_eq!(rows[1]["id"], "SKU-2");
+
+        assert!(parse("<feed><entry><id>1</id>").is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use crate::bindings::supabase::wrappers::types::FdwResult;
+use crate::client::normalize_number;
+use crate::log;
+
+// Objects whose inserted rows send a message to their `to` column
+pub(crate) const CAPPED_OBJECTS: &[&str] = &["messages", "campaign_sends"];
+
+// Window of the cap, a day
+const WINDOW_SECS: i64 = 86400;
+
+thread_local! {
+    // Times of the messages sent to each contact within the window, in
+    // seconds since Unix epoch. Like the stats, they are kept across the
+    // FDW lifecycles of the Wasm instance.
+    static SENDS: RefCell<HashMap<String, VecDeque<i64>>> = RefCell::new(HashMap::new());
+}
+
+// Check that a message may be sent to `to`, fewer than `max` messages
+// having been sent to the contact in the last 24 hours
+pub(crate) fn check(max: i64, to: &str, now_secs: i64) -> FdwResult {
+    let sent = SENDS.with(|sends| {
+        let mut sends = sends.borrow_mut();
+        let Some(times) = sends.get_mut(&normalize_number(to)) else {
+            return 0;
+        };
+        while times.front().is_some_and(|at| *at <= now_secs - WINDOW_SECS) {
+            times.pop_front();
+        }
+        times.len() as i64
+    });
+    if sent >= max {
+        return Err(format!(
+            "Not sending to {}: {} message(s) were sent to this contact in the last 24 hours, the limit of the server option max_messages_per_contact_per_day",
+            log::mask_number(to),
+            sent
+        ));
+    }
+    Ok(())
+}
+
+// Record a message sent to `to`
+pub(crate) fn record(to: &str, now_secs: i64) {
+    SENDS.with(|sends| {
+        sends
+            .borrow_mut()
+            .entry(normalize_number(to))
+            .or_default()
+            .push_back(now_secs)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_sends_above_the_daily_cap() {
+        let now = 1_700_000_000;
+        for at in [now, now + 60] {
+            check(2, "+49 151 23456789", at).unwrap();
+            record("+49 151 23456789", at);
+        }
+        let err = check(2, "+4915123456789", now + 120).unwrap_err();
+        assert!(
+            err.contains("2 message(s) were sent to this contact in the last 24 hours"),
+            "{}",
+            err
+        );
+        assert!(!err.contains("+4915123456789"), "{}", err);
+        // Other contacts have their own count
+        check(2, "+4915198765432", now + 120).unwrap();
+        // The first send leaves the window a day later
+        check(2, "+4915123456789", now + WINDOW_SECS).unwrap();
+    }
+}
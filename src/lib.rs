@@ -1,213 +1,343 @@
+// Helpers shared by the object families are partly unused in builds
+// leaving some families out
+#![cfg_attr(
+    not(all(
+        feature = "commerce",
+        feature = "messages",
+        feature = "groups",
+        feature = "analytics"
+    )),
+    allow(dead_code, unused_imports)
+)]
+
 #[allow(warnings)]
 mod bindings;
-use serde_json::Value as JsonValue;
+// Entry points for the native benchmarks in `benches/`
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod bench;
+mod audit;
+mod boundary;
+mod client;
+mod clock;
+mod config;
+mod explain;
+#[cfg(feature = "commerce")]
+mod feed;
+mod frequency_cap;
+mod log;
+mod mapping;
+mod model;
+mod objects;
+mod pagination;
+mod providers;
+mod quiet_hours;
+mod state;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod timestamp;
 
 use bindings::{
     exports::supabase::wrappers::routines::Guest,
-    supabase::wrappers::{
-        http, time,
-        types::{Cell, Context, FdwError, FdwResult, OptionsType, Row, TypeOid},
-        utils,
-    },
+    supabase::wrappers::types::{Cell, Context, FdwError, FdwResult, OptionsType, Row, TypeOid},
 };
+use config::{Opts, ServerConfig, TableConfig};
+use explain::ScanPlan;
+use log::{debug, info, notice};
+use objects::Operation;
+use providers::WriteRow;
+use state::{FdwState, Phase, WriteReport};
 
-#[derive(Debug, Default)]
-struct ExampleFdw {
-    base_url: String,
-    phone_number: String,
-    from_number: String,
-    api_key: String,
-    src_rows: Vec<JsonValue>,
-    src_idx: usize,
-}
+// Version of the FDW, and the git commit it was built from
+pub(crate) const FDW_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub(crate) const GIT_HASH: &str = env!("GIT_HASH");
 
-// Pointer for the static FDW instance
-static mut INSTANCE: *mut ExampleFdw = std::ptr::null_mut::<ExampleFdw>();
-
-impl ExampleFdw {
-    // Initialize FDW instance
-    fn init_instance() {
-        let instance = Self::default();
-        unsafe {
-            INSTANCE = Box::leak(Box::new(instance));
-        }
-    }
-
-    fn this_mut() -> &'static mut Self {
-        unsafe { &mut (*INSTANCE) }
-    }
-}
+struct ExampleFdw;
 
 impl Guest for ExampleFdw {
     fn host_version_requirement() -> String {
         // Semver expression for Wasm FDW host version requirement
         // Ref: https://docs.rs/semver/latest/semver/enum.Op.html
-        "^0.1.0".to_string()
+        //
+        // The component only imports the 0.1.0 interfaces, which 0.2 hosts
+        // keep providing, so both host releases can load it
+        ">=0.1.0, <0.3.0".to_string()
     }
 
     fn init(ctx: &Context) -> FdwResult {
-        Self::init_instance();
-        let this = Self::this_mut();
-
-        // Retrieve API options from foreign server options
-        let opts = ctx.get_options(OptionsType::Server);
-        // Fetch required options
-        this.phone_number = opts.require_or("phone_number", "")?;
-        this.from_number = opts.require_or("from_number", "")?;
-        this.api_key = opts.require_or("api_key", "")?;
-
-        // Validate that all required options are provided
-        if this.phone_number.is_empty() || this.from_number.is_empty() || this.api_key.is_empty() {
-            return Err("Missing required options: phone_number, from_number, api_key".to_string());
-        }
-
-        // Set the base URL for WhatsApp Catalog API
-        this.base_url = "https://api.p.2chat.io/open/whatsapp/catalog/products".to_string();
-
-        Ok(())
+        FdwState::reset();
+        FdwState::with("init", |this| {
+            // Retrieve API options from foreign server options
+            let opts = ctx.get_options(OptionsType::Server);
+            this.config = ServerConfig::parse(&Opts::new(&opts))?;
+            log::init(this.config.log_level);
+            stats::start(this.client.state.clock.now_secs());
+            info!("init", "WhatsApp FDW {} (commit {})", FDW_VERSION, GIT_HASH);
+
+            // Point the client at the API of the selected provider
+            let provider = providers::parse(&Opts::new(&opts))?;
+            provider.connect(&mut this.client);
+            this.client.state.oauth = this.config.oauth.take();
+            this.client.state.register_secrets();
+            info!("init", "{}", providers::startup_report(provider.as_ref(), &this.client.base_url));
+            this.provider = Some(provider);
+
+            this.client.state.rate_limit.warn_below = this.config.rate_limit_warning;
+            this.client.state.retry = this.config.retry;
+
+            // Optionally cache API responses across scans
+            if this.config.cache_ttl > 0 {
+                this.client.enable_cache(this.config.cache_ttl);
+            }
+
+            // Debug option dumping API exchanges as replayable fixtures
+            if this.config.record_fixtures {
+                this.client.enable_recording();
+            }
+
+            // Debug option reporting each request and its response
+            if this.config.debug {
+                this.client.enable_debug_dump();
+            }
+
+            // Hidden option simulating API failures, only in test builds
+            #[cfg(feature = "fault-injection")]
+            if let Some(spec) = &this.config.inject_faults {
+                this.client.enable_fault_injection(spec)?;
+            }
+
+            // Time each API request, after the other middlewares
+            this.client.enable_timing(this.config.slow_request_threshold);
+
+            this.transition("init", Phase::Uninitialized, Phase::Ready)
+        })
     }
 
     fn begin_scan(ctx: &Context) -> FdwResult {
-        let this = Self::this_mut();
-
-        // Construct the request URL with phone_number and from_number
-        let url = format!(
-            "{}/{}?from_number={}",
-            this.base_url,
-            this.phone_number,
-            this.from_number
-        );
-
-        // Set up request headers
-        let headers: Vec<(String, String)> = vec![
-            ("user-agent".to_owned(), "WhatsApp Catalog FDW".to_owned()),
-            ("X-User-API-Key".to_owned(), this.api_key.clone()),
-        ];
-
-        // Make a GET request to the WhatsApp Catalog API
-        let req = http::Request {
-            method: http::Method::Get,
-            url,
-            headers,
-            body: String::default(),
-        };
-        let resp = http::get(&req)?;
-        let resp_json: JsonValue = serde_json::from_str(&resp.body).map_err(|e| e.to_string())?;
-
-        // Check if the API request was successful
-        if !resp_json.get("success").and_then(|v| v.as_bool()).unwrap_or(false) {
-            return Err("API request was not successful".to_owned());
-        }
-
-        // Extract the 'products' array from the response
-        this.src_rows = resp_json
-            .pointer("/products")
-            .ok_or("Cannot get 'products' from response")?
-            .as_array()
-            .ok_or("'products' is not an array")?
-            .to_owned();
-
-        // Log the number of products retrieved (visible in psql)
-        utils::report_info(&format!(
-            "Retrieved {} products from WhatsApp Catalog API",
-            this.src_rows.len()
-        ));
-
-        Ok(())
+        FdwState::with("begin_scan", |this| {
+            this.transition("begin_scan", Phase::Ready, Phase::Scanning)?;
+            log::start_trace(this.client.state.clock.now_secs());
+            this.client.state.credits.statement_used = 0;
+
+            // Retrieve the object to scan from foreign table options
+            let opts = ctx.get_options(OptionsType::Table);
+            this.open_table(TableConfig::parse(&Opts::new(&opts))?)?;
+
+            // Quals the provider doesn't apply, sorts and limits are
+            // evaluated by Postgres on all the fetched rows
+            let plan = ScanPlan::new(ctx, this.capabilities()?);
+            if this.config.explain {
+                info!("explain", "{}", plan.describe());
+            } else {
+                for qual in &plan.local_quals {
+                    debug!("scan", "Qual {} is evaluated by Postgres", qual);
+                }
+            }
+
+            stats::record(|stats| stats.scans += 1);
+            this.client.state.period = plan.period;
+            this.client.state.equals = plan.equals.clone();
+            this.src_rows = this.fetch()?;
+            let rows = this.src_rows.len() as u64;
+            stats::record(|stats| stats.rows_scanned += rows);
+
+            // Log the number of rows retrieved (visible in psql), with the
+            // likely causes of an empty result unless the API exchanges are
+            // already reported
+            if this.src_rows.is_empty() {
+                let provider = this.provider()?;
+                let mut msg = format!(
+                    "No rows found for object '{}' from provider '{}'",
+                    this.object,
+                    provider.name()
+                );
+                let identity_options: Vec<String> = provider.identities().into_iter().map(|(name, _)| name).collect();
+                let causes = plan.empty_result_causes(&identity_options);
+                // Objects the FDW serves itself, such as the audit log, are
+                // often empty
+                let served_by_provider = provider.capabilities(&this.object).is_ok();
+                if !this.config.debug && served_by_provider && !causes.is_empty() {
+                    msg.push_str(&format!(
+                        ". Likely causes: {}. Set the server option debug 'true' to see the API responses",
+                        causes.join("; ")
+                    ));
+                }
+                notice!("scan", "{}", msg);
+            }
+            let more = match this.pager.as_ref().is_some_and(|pager| !pager.is_done()) {
+                true => ", more pages being read as the scan proceeds",
+                false => "",
+            };
+            info!(
+                "scan",
+                "Retrieved {} rows for object '{}' from WhatsApp API{}",
+                this.src_rows.len(),
+                this.object,
+                more
+            );
+            this.client.report_rate_limit();
+            this.client.report_credits();
+
+            // Warn about selected columns that never appear in the response
+            this.report_missing_columns(ctx);
+
+            Ok(())
+        })
     }
 
     fn iter_scan(ctx: &Context, row: &Row) -> Result<Option<u32>, FdwError> {
-        let this = Self::this_mut();
-
-        // If all products have been processed, end the scan
-        if this.src_idx >= this.src_rows.len() {
-            return Ok(None);
-        }
-
-        // Get the current product
-        let src_row = &this.src_rows[this.src_idx];
-
-        // Map each column to the corresponding product field
-        for tgt_col in ctx.get_columns() {
-            let tgt_col_name = tgt_col.name();
-            let cell = match tgt_col_name {
-                "id" => src_row.get("id").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "retailer_id" => src_row.get("retailer_id").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "name" => src_row.get("name").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "description" => src_row.get("description").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "url" => src_row.get("url").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "currency" => src_row.get("currency").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "price" => src_row.get("price").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "is_hidden" => src_row.get("is_hidden").and_then(|v| v.as_bool()).map(|v| Cell::Bool(v)),
-                "max_available" => src_row.get("max_available").and_then(|v| v.as_i64()).map(|v| Cell::I64(v)),
-                "availability" => src_row.get("availability").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "checkmark" => src_row.get("checkmark").and_then(|v| v.as_bool()).map(|v| Cell::Bool(v)),
-                "whatsapp_product_can_appeal" => src_row.get("whatsapp_product_can_appeal").and_then(|v| v.as_bool()).map(|v| Cell::Bool(v)),
-                "is_approved" => src_row.get("is_approved").and_then(|v| v.as_bool()).map(|v| Cell::Bool(v)),
-                "approval_status" => src_row.get("approval_status").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "signedShimmedUrl" => src_row.get("signedShimmedUrl").and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned())),
-                "images" => {
-                    // Concatenate all image URLs into a single string
-                    if let Some(images) = src_row.get("images").and_then(|v| v.as_array()) {
-                        let urls: Vec<String> = images
-                            .iter()
-                            .filter_map(|img| img.get("url").and_then(|u| u.as_str()).map(|s| s.to_owned()))
-                            .collect();
-                        Some(Cell::String(urls.join(", ")))
-                    } else {
-                        None
-                    }
-                },
-                _ => {
-                    // Unsupported column
-                    return Err(format!(
-                        "Column '{}' is not supported by the WhatsApp Catalog FDW",
-                        tgt_col_name
-                    ).into());
+        FdwState::with("iter_scan", |this| {
+            this.transition("iter_scan", Phase::Scanning, Phase::Scanning)?;
+
+            // Once the source rows have been processed, read the next page
+            // of an object paged lazily, the scan ending after the last page
+            if this.src_idx >= this.src_rows.len() {
+                this.src_rows = this.next_page()?;
+                this.src_idx = 0;
+                let rows = this.src_rows.len() as u64;
+                stats::record(|stats| stats.rows_scanned += rows);
+                if this.src_rows.is_empty() {
+                    return Ok(None);
                 }
-            };
-
-            // Push the cell value to the target row
-            row.push(cell.as_ref());
-        }
-
-        // Move to the next product
-        this.src_idx += 1;
-
-        // Indicate that a row has been processed
-        Ok(Some(0))
+            }
+
+            // Get the current source row
+            let object = objects::lookup(&this.object)?;
+            let src_row = &this.src_rows[this.src_idx];
+
+            // Map each column to the corresponding source field
+            for tgt_col in ctx.get_columns() {
+                let name = tgt_col.name();
+                let type_oid = tgt_col.type_oid();
+                // Lists of URLs declared as jsonb read the source array
+                let list = match type_oid {
+                    TypeOid::Json => mapping::url_list_array(&this.object, src_row, &name),
+                    _ => None,
+                };
+                let cell = match list.map(Ok).unwrap_or_else(|| object.map_cell(src_row, &name)) {
+                    // Union tables add the source of the rows to any object
+                    Err(_) if name == mapping::SOURCE_COLUMN => mapping::source_cell(src_row),
+                    // Tables in `columns 'auto'` mode read any other field
+                    Err(_) if this.table.auto_columns => mapping::auto_cell(src_row, &name, type_oid),
+                    cell => cell?,
+                };
+                // Cells convert to the declared type of the column, e.g. a
+                // price to numeric
+                let cell = cell.and_then(|cell| mapping::coerce_cell(cell, type_oid));
+
+                // Push the cell value to the target row
+                row.push(cell.as_ref());
+            }
+
+            // Move to the next source row
+            this.src_idx += 1;
+
+            // Indicate that a row has been processed
+            Ok(Some(0))
+        })
     }
 
     fn re_scan(_ctx: &Context) -> FdwResult {
-        Err("Re-scan on foreign table is not supported".to_owned())
+        FdwState::with("re_scan", |this| {
+            this.transition("re_scan", Phase::Scanning, Phase::Scanning)?;
+            Err("Re-scan on foreign table is not supported".to_owned())
+        })
     }
 
     fn end_scan(_ctx: &Context) -> FdwResult {
-        let this = Self::this_mut();
-        this.src_rows.clear();
-        this.src_idx = 0;
-        Ok(())
+        FdwState::with("end_scan", |this| {
+            this.transition("end_scan", Phase::Scanning, Phase::Ready)?;
+            this.src_rows.clear();
+            this.src_idx = 0;
+            this.pager = None;
+            Ok(())
+        })
     }
 
-    fn begin_modify(_ctx: &Context) -> FdwResult {
-        Err("Modify operations on foreign table are not supported".to_owned())
+    fn begin_modify(ctx: &Context) -> FdwResult {
+        FdwState::with("begin_modify", |this| {
+            this.transition("begin_modify", Phase::Ready, Phase::Modifying)?;
+            log::start_trace(this.client.state.clock.now_secs());
+            this.client.state.credits.statement_used = 0;
+
+            let opts = ctx.get_options(OptionsType::Table);
+            this.open_table(TableConfig::parse(&Opts::new(&opts))?)?;
+            this.write_report = WriteReport::default();
+            #[cfg(feature = "messages")]
+            this.campaigns.begin();
+
+            let caps = this.capabilities()?;
+            if !caps.is_writable() {
+                return Err(format!(
+                    "Modify operations are not supported on object '{}', supported operations: {}",
+                    caps.name,
+                    caps.operations().join(", ")
+                ));
+            }
+
+            Ok(())
+        })
     }
 
-    fn insert(_ctx: &Context, _row: &Row) -> FdwResult {
-        Ok(())
+    fn insert(_ctx: &Context, row: &Row) -> FdwResult {
+        FdwState::with("insert", |this| {
+            this.transition("insert", Phase::Modifying, Phase::Modifying)?;
+
+            let result = this.write_row(WriteRow {
+                operation: Operation::Insert,
+                rowid: None,
+                columns: mapping::row_to_json(row.cols(), row.cells()),
+            });
+            this.write_report.record(result)
+        })
     }
 
-    fn update(_ctx: &Context, _rowid: Cell, _row: &Row) -> FdwResult {
-        Ok(())
+    fn update(_ctx: &Context, rowid: Cell, row: &Row) -> FdwResult {
+        FdwState::with("update", |this| {
+            this.transition("update", Phase::Modifying, Phase::Modifying)?;
+
+            let result = this.write_row(WriteRow {
+                operation: Operation::Update,
+                rowid: Some(mapping::cell_to_json(&rowid)),
+                columns: mapping::row_to_json(row.cols(), row.cells()),
+            });
+            this.write_report.record(result)
+        })
     }
 
-    fn delete(_ctx: &Context, _rowid: Cell) -> FdwResult {
-        Ok(())
+    fn delete(_ctx: &Context, rowid: Cell) -> FdwResult {
+        FdwState::with("delete", |this| {
+            this.transition("delete", Phase::Modifying, Phase::Modifying)?;
+
+            let result = this.write_row(WriteRow {
+                operation: Operation::Delete,
+                rowid: Some(mapping::cell_to_json(&rowid)),
+                columns: serde_json::Value::Null,
+            });
+            this.write_report.record(result)
+        })
     }
 
     fn end_modify(_ctx: &Context) -> FdwResult {
-        Ok(())
+        FdwState::with("end_modify", |this| {
+            this.transition("end_modify", Phase::Modifying, Phase::Ready)?;
+            this.end_modify()?;
+            #[cfg(feature = "messages")]
+            this.campaigns.end();
+
+            if this.write_report.sent > 0 {
+                info!(
+                    "modify",
+                    "Sent {} row(s) to object '{}'",
+                    this.write_report.sent,
+                    this.object
+                );
+            }
+            this.client.report_credits();
+            Ok(())
+        })
     }
 }
 
-bindings::export!(ExampleFdw with_types_in bindings);
\ No newline at end of file
+bindings::export!(ExampleFdw with_types_in bindings);
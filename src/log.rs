@@ -0,0 +1,244 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+
+use crate::bindings::supabase::wrappers::types::FdwError;
+#[cfg(not(test))]
+use crate::bindings::supabase::wrappers::utils;
+
+// Severity of a log message, in increasing order
+#[derive(Debug, Default, Clone, Copy, PartialEq, PartialOrd)]
+pub(crate) enum Level {
+    Debug,
+    #[default]
+    Info,
+    Notice,
+    Warning,
+}
+
+impl Level {
+    // Names of the levels, as given in the `log_level` option
+    pub(crate) const CHOICES: &'static [(&'static str, Level)] = &[
+        ("debug", Level::Debug),
+        ("info", Level::Info),
+        ("notice", Level::Notice),
+        ("warning", Level::Warning),
+    ];
+}
+
+// Minimum level of the messages reported to the host, the secrets
+// redacted from every message, and the correlation id of the current
+// statement
+#[derive(Debug, Default)]
+struct Logger {
+    level: Level,
+    secrets: Vec<String>,
+    correlation_id: Option<String>,
+}
+
+impl Logger {
+    // Render a message, or None if it is below the minimum level
+    fn render(&self, level: Level, tag: &str, msg: fmt::Arguments) -> Option<String> {
+        if level < self.level {
+            return None;
+        }
+        let mut msg = match &self.correlation_id {
+            Some(id) => format!("[{} {}] {}", tag, id, msg),
+            None => format!("[{}] {}", tag, msg),
+        };
+        for secret in &self.secrets {
+            msg = msg.replace(secret.as_str(), "***");
+        }
+        Some(msg)
+    }
+}
+
+thread_local! {
+    static LOGGER: RefCell<Logger> = RefCell::new(Logger::default());
+    // Correlation ids generated by the instance, not reset at init so that
+    // ids stay unique within the instance
+    static TRACES: Cell<u32> = const { Cell::new(0) };
+}
+
+// Reset the logger, at the start of the FDW lifecycle
+pub(crate) fn init(level: Level) {
+    LOGGER.with(|logger| {
+        *logger.borrow_mut() = Logger {
+            level,
+            secrets: Vec::new(),
+            correlation_id: None,
+        }
+    });
+}
+
+// Redact a secret from all the messages logged from now on
+pub(crate) fn add_secret(secret: &str) {
+    if secret.is_empty() {
+        return;
+    }
+    LOGGER.with(|logger| {
+        let secrets = &mut logger.borrow_mut().secrets;
+        if !secrets.iter().any(|s| s == secret) {
+            secrets.push(secret.to_owned());
+        }
+    });
+}
+
+// Mask a phone number for the logs, keeping its first and last three
+// digits, e.g. `+491*******789`. Short numbers are masked entirely.
+pub(crate) fn mask_number(number: &str) -> String {
+    let digits = number.chars().filter(|c| c.is_ascii_digit()).count();
+    let mut seen = 0;
+    number
+        .chars()
+        .map(|c| {
+            if !c.is_ascii_digit() {
+                return c;
+            }
+            seen += 1;
+            if digits > 6 && (seen <= 3 || seen > digits - 3) {
+                c
+            } else {
+                '*'
+            }
+        })
+        .collect()
+}
+
+// Start a scan or modify statement under a new correlation id, made of
+// the current time and a sequence number, e.g. `wa-6553f100-0001`. The id
+// is sent to the provider as the `X-Request-Id` header, and added to every
+// log message and error of the statement.
+pub(crate) fn start_trace(now_secs: i64) -> String {
+    let seq = TRACES.with(|traces| {
+        traces.set(traces.get().wrapping_add(1));
+        traces.get()
+    });
+    let id = format!("wa-{:08x}-{:04x}", now_secs, seq);
+    LOGGER.with(|logger| logger.borrow_mut().correlation_id = Some(id.clone()));
+    id
+}
+
+// Correlation id of the current statement, if any
+pub(crate) fn correlation_id() -> Option<String> {
+    LOGGER.with(|logger| logger.borrow().correlation_id.clone())
+}
+
+// Add the correlation id of the current statement to an error
+pub(crate) fn trace_error(err: FdwError) -> FdwError {
+    match correlation_id() {
+        Some(id) => format!("{} (correlation id {})", err, id),
+        None => err,
+    }
+}
+
+// Report a message to the host, tagged with the module it comes from.
+// Use the `debug!`, `info!`, `notice!` and `warning!` macros instead.
+pub(crate) fn log(level: Level, tag: &str, msg: fmt::Arguments) {
+    let Some(msg) = LOGGER.with(|logger| logger.borrow().render(level, tag, msg)) else {
+        return;
+    };
+    // Tests run natively, without the host to report to
+    #[cfg(test)]
+    eprintln!("{}", msg);
+    #[cfg(not(test))]
+    match level {
+        Level::Debug | Level::Info => utils::report_info(&msg),
+        Level::Notice => utils::report_notice(&msg),
+        Level::Warning => utils::report_warning(&msg),
+    }
+}
+
+macro_rules! debug {
+    ($tag:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Debug, $tag, format_args!($($arg)+))
+    };
+}
+
+macro_rules! info {
+    ($tag:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Info, $tag, format_args!($($arg)+))
+    };
+}
+
+macro_rules! notice {
+    ($tag:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Notice, $tag, format_args!($($arg)+))
+    };
+}
+
+macro_rules! warning {
+    ($tag:expr, $($arg:tt)+) => {
+        $crate::log::log($crate::log::Level::Warning, $tag, format_args!($($arg)+))
+    };
+}
+
+pub(crate) use {debug, info, notice, warning};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_tagged_messages_above_level() {
+        let logger = Logger {
+            level: Level::Notice,
+            ..Default::default()
+        };
+        assert_eq!(logger.render(Level::Info, "scan", format_args!("rows: {}", 3)), None);
+        assert_eq!(
+            logger.render(Level::Warning, "scan", format_args!("rows: {}", 3)).as_deref(),
+            Some("[scan] rows: 3")
+        );
+    }
+
+    #[test]
+    fn redacts_secrets() {
+        let logger = Logger {
+            level: Level::Debug,
+            secrets: vec!["secret-key".to_owned()],
+            correlation_id: None,
+        };
+        assert_eq!(
+            logger
+                .render(Level::Debug, "client", format_args!("GET /x?key={}", "secret-key"))
+                .as_deref(),
+            Some("[client] GET /x?key=***")
+        );
+    }
+
+    #[test]
+    fn masks_phone_numbers() {
+        assert_eq!(mask_number("+4915123456789"), "+491*******789");
+        assert_eq!(mask_number("whatsapp:+14155238886"), "whatsapp:+141*****886");
+        assert_eq!(mask_number("12345"), "*****");
+    }
+
+    #[test]
+    fn tags_messages_and_errors_with_the_correlation_id() {
+        let first = start_trace(0x6553_f100);
+        let second = start_trace(0x6553_f100);
+        assert_eq!(first, "wa-6553f100-0001");
+        assert_eq!(second, "wa-6553f100-0002");
+        assert_eq!(correlation_id().as_deref(), Some("wa-6553f100-0002"));
+        assert_eq!(
+            trace_error("Cannot get 'results'".to_owned()),
+            "Cannot get 'results' (correlation id wa-6553f100-0002)"
+        );
+
+        let logger = Logger {
+            correlation_id: Some(second),
+            ..Default::default()
+        };
+        assert_eq!(
+            logger.render(Level::Info, "scan", format_args!("rows: {}", 3)).as_deref(),
+            Some("[scan wa-6553f100-0002] rows: 3")
+        );
+    }
+
+    #[test]
+    fn orders_levels_by_severity() {
+        let names: Vec<&str> = Level::CHOICES.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["debug", "info", "notice", "warning"]);
+        assert!(Level::CHOICES.windows(2).all(|w| w[0].1 < w[1].1));
+    }
+}
@@ -0,0 +1,656 @@
+use serde_json::Value as JsonValue;
+
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError, TypeOid};
+use crate::timestamp;
+
+// Column mappings generated from schema/objects.json by build.rs. Adding a
+// column of an existing type is a schema edit.
+include!(concat!(env!("OUT_DIR"), "/columns.rs"));
+
+// Column naming the source of each row of a union table, on any object
+pub(crate) const SOURCE_COLUMN: &str = "source";
+
+// The source of a row, for objects without a column of that name
+pub(crate) fn source_cell(src_row: &JsonValue) -> Option<Cell> {
+    as_string(src_row, SOURCE_COLUMN)
+}
+
+fn unsupported_column(object: &str, tgt_col_name: &str, columns: &[&str]) -> FdwError {
+    format!(
+        "Column '{}' is not supported by object '{}', supported columns: {}",
+        tgt_col_name,
+        object,
+        columns.join(", ")
+    )
+}
+
+fn as_string(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    src_row.get(field).and_then(|v| v.as_str()).map(|v| Cell::String(v.to_owned()))
+}
+
+fn as_bool(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    src_row.get(field).and_then(|v| v.as_bool()).map(Cell::Bool)
+}
+
+fn as_i64(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    src_row.get(field).and_then(|v| v.as_i64()).map(Cell::I64)
+}
+
+// microseconds since Unix epoch
+fn as_timestamptz(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    src_row.get(field).and_then(|v| v.as_i64()).map(Cell::Timestamptz)
+}
+
+// Concatenate the URLs of an array of objects into a single string
+fn as_url_list(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    let items = src_row.get(field).and_then(|v| v.as_array())?;
+    let urls: Vec<&str> = items
+        .iter()
+        .filter_map(|item| item.get("url").and_then(|u| u.as_str()))
+        .collect();
+    Some(Cell::String(urls.join(", ")))
+}
+
+// Cell of a `url_list` column declared as jsonb: the array of the source
+// row as is, keeping what the provider tells of each image besides its
+// URL, e.g. its id or dimensions. None for the columns of other types.
+pub(crate) fn url_list_array(object: &str, src_row: &JsonValue, tgt_col_name: &str) -> Option<Option<Cell>> {
+    let (_, _, field) = URL_LIST_COLUMNS
+        .iter()
+        .find(|(list_object, column, _)| *list_object == object && *column == tgt_col_name)?;
+    Some(as_json(src_row, field))
+}
+
+// URLs of a written `url_list` column, a comma-separated list as scanned
+// into text columns, or an array of URLs or of objects with a `url`, as
+// scanned into jsonb columns
+pub(crate) fn url_list(value: &JsonValue) -> Vec<String> {
+    match value {
+        JsonValue::String(s) => s
+            .split(',')
+            .map(str::trim)
+            .filter(|url| !url.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        JsonValue::Array(items) => items
+            .iter()
+            .filter_map(|item| item.get("url").unwrap_or(item).as_str())
+            .map(str::to_owned)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// Pass a JSON value through as is, only null being absent
+fn as_json(src_row: &JsonValue, field: &str) -> Option<Cell> {
+    src_row
+        .get(field)
+        .filter(|v| !v.is_null())
+        .map(|v| Cell::Json(v.to_string()))
+}
+
+// Convert a row of a modify statement to a JSON object of its columns, the
+// shape the providers build their requests from. Dates and timestamps are
+// kept as numbers, in the unit of their cell.
+pub(crate) fn row_to_json(cols: Vec<String>, cells: Vec<Option<Cell>>) -> JsonValue {
+    let row = cols
+        .into_iter()
+        .zip(cells)
+        .map(|(col, cell)| (col, cell.as_ref().map_or(JsonValue::Null, cell_to_json)))
+        .collect();
+    JsonValue::Object(row)
+}
+
+// Convert a cell to JSON, e.g. the rowid of an updated row
+pub(crate) fn cell_to_json(cell: &Cell) -> JsonValue {
+    match cell {
+        Cell::Bool(v) => (*v).into(),
+        Cell::I8(v) => (*v).into(),
+        Cell::I16(v) => (*v).into(),
+        Cell::I32(v) => (*v).into(),
+        Cell::I64(v) | Cell::Date(v) | Cell::Timestamp(v) | Cell::Timestamptz(v) => (*v).into(),
+        Cell::F32(v) => (*v as f64).into(),
+        Cell::F64(v) | Cell::Numeric(v) => (*v).into(),
+        Cell::String(v) => v.as_str().into(),
+        // A JSON column is passed as its value, e.g. template components
+        Cell::Json(v) => serde_json::from_str(v).unwrap_or_else(|_| v.as_str().into()),
+    }
+}
+
+// Field of a source row named like a column, as is, in camelCase or in
+// snake_case, e.g. `phoneNumber` for the column `phone_number`
+pub(crate) fn field<'a>(src_row: &'a JsonValue, name: &str) -> Option<&'a JsonValue> {
+    [name.to_owned(), camel_case(name), snake_case(name)]
+        .iter()
+        .find_map(|key| src_row.get(key))
+        .filter(|v| !v.is_null())
+}
+
+fn camel_case(name: &str) -> String {
+    let mut camel = String::new();
+    let mut upper = false;
+    for c in name.chars() {
+        match c {
+            '_' if !camel.is_empty() => upper = true,
+            c if upper => {
+                camel.push(c.to_ascii_uppercase());
+                upper = false;
+            }
+            c => camel.push(c),
+        }
+    }
+    camel
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+// Parse a price into its amount in hundredths and its ISO currency code,
+// e.g. `1.234,50 EUR` into `(123450, Some("EUR"))`. The last `.` or `,`
+// followed by one or two digits is the decimal separator, the others
+// group thousands.
+pub(crate) fn parse_price(price: &str) -> Option<(i64, Option<String>)> {
+    let currency = price
+        .split(|c: char| !c.is_ascii_alphabetic())
+        .find(|word| word.len() == 3 && word.chars().all(|c| c.is_ascii_uppercase()))
+        .map(str::to_owned);
+
+    let number: String = price
+        .chars()
+        .skip_while(|c| !c.is_ascii_digit())
+        .take_while(|c| c.is_ascii_digit() || *c == '.' || *c == ',')
+        .collect();
+    let (whole, fraction) = match number.rfind(['.', ',']) {
+        Some(at) if (1..=2).contains(&(number.len() - at - 1)) => (&number[..at], &number[at + 1..]),
+        _ => (number.as_str(), ""),
+    };
+    let whole: String = whole.chars().filter(|c| c.is_ascii_digit()).collect();
+    let whole: i64 = whole.parse().ok()?;
+    let fraction: i64 = format!("{:0<2}", fraction).parse().ok()?;
+    Some((whole.checked_mul(100)?.checked_add(fraction)?, currency))
+}
+
+// Map a field the object doesn't declare, for tables with the `columns
+// 'auto'` option, to a cell of the column type, or None if it is absent
+// or doesn't convert
+pub(crate) fn auto_cell(src_row: &JsonValue, name: &str, type_oid: TypeOid) -> Option<Cell> {
+    convert(field(src_row, name)?, type_oid)
+}
+
+// Convert a mapped cell to the type the column is declared with, e.g. a
+// price to `numeric` or a timestamp to `text`, None if it doesn't convert.
+// Cells of the column type are kept as is.
+pub(crate) fn coerce_cell(cell: Cell, type_oid: TypeOid) -> Option<Cell> {
+    match (cell, type_oid) {
+        (cell @ Cell::Bool(_), TypeOid::Bool)
+        | (cell @ Cell::I8(_), TypeOid::I8)
+        | (cell @ Cell::I16(_), TypeOid::I16)
+        | (cell @ Cell::I32(_), TypeOid::I32)
+        | (cell @ Cell::I64(_), TypeOid::I64)
+        | (cell @ Cell::F32(_), TypeOid::F32)
+        | (cell @ Cell::F64(_), TypeOid::F64)
+        | (cell @ Cell::Numeric(_), TypeOid::Numeric)
+        | (cell @ Cell::String(_), TypeOid::String)
+        | (cell @ Cell::Date(_), TypeOid::Date)
+        | (cell @ Cell::Timestamp(_), TypeOid::Timestamp)
+        | (cell @ Cell::Timestamptz(_), TypeOid::Timestamptz)
+        | (cell @ Cell::Json(_), TypeOid::Json) => Some(cell),
+        // Timestamps are microseconds with or without a zone, and dates
+        // seconds, since Unix epoch
+        (Cell::Timestamptz(t), TypeOid::Timestamp) => Some(Cell::Timestamp(t)),
+        (Cell::Timestamp(t), TypeOid::Timestamptz) => Some(Cell::Timestamptz(t)),
+        (Cell::Timestamp(t) | Cell::Timestamptz(t), TypeOid::Date) => {
+            Some(Cell::Date(t.div_euclid(86_400_000_000) * 86400))
+        }
+        (Cell::Timestamp(t) | Cell::Timestamptz(t), TypeOid::String) => {
+            Some(Cell::String(timestamp::format_rfc3339(t)))
+        }
+        (Cell::Date(d), TypeOid::Timestamp) => d.checked_mul(1_000_000).map(Cell::Timestamp),
+        (Cell::Date(d), TypeOid::Timestamptz) => d.checked_mul(1_000_000).map(Cell::Timestamptz),
+        (Cell::Date(d), TypeOid::String) => {
+            let timestamp = timestamp::format_rfc3339(d.checked_mul(1_000_000)?);
+            timestamp.split_once('T').map(|(date, _)| Cell::String(date.to_owned()))
+        }
+        // Other values convert as their JSON value would in `auto` mode
+        (cell, type_oid) => convert(&cell_to_json(&cell), type_oid),
+    }
+}
+
+// Convert a JSON value to a cell of a column type, or None if it doesn't
+// convert. Prices are read as their amount, e.g. `€12.00` as 12. Numeric
+// timestamps are read as seconds, milliseconds or microseconds since Unix
+// epoch depending on their magnitude.
+fn convert(value: &JsonValue, type_oid: TypeOid) -> Option<Cell> {
+    let number = || match value {
+        JsonValue::String(s) => s
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .or_else(|| parse_price(s).map(|(hundredths, _)| hundredths as f64 / 100.0)),
+        v => v.as_f64(),
+    };
+    let integer = || match value {
+        JsonValue::String(s) => s.trim().parse::<i64>().ok(),
+        v => v.as_i64(),
+    };
+    let micros = || match value {
+        JsonValue::String(s) => timestamp::parse_rfc3339(s)
+            .or_else(|| timestamp::parse_rfc3339(&format!("{}T00:00:00Z", s.trim())))
+            .or_else(|| timestamp::parse_rfc2822(s)),
+        v => v.as_i64().map(|n| match n.abs() {
+            0..=99_999_999_999 => n * 1_000_000,
+            100_000_000_000..=99_999_999_999_999 => n * 1_000,
+            _ => n,
+        }),
+    };
+
+    match type_oid {
+        TypeOid::Bool => match value {
+            JsonValue::Bool(b) => Some(Cell::Bool(*b)),
+            JsonValue::String(s) if s.eq_ignore_ascii_case("true") => Some(Cell::Bool(true)),
+            JsonValue::String(s) if s.eq_ignore_ascii_case("false") => Some(Cell::Bool(false)),
+            _ => None,
+        },
+        TypeOid::I8 => integer().and_then(|n| n.try_into().ok()).map(Cell::I8),
+        TypeOid::I16 => integer().and_then(|n| n.try_into().ok()).map(Cell::I16),
+        TypeOid::I32 => integer().and_then(|n| n.try_into().ok()).map(Cell::I32),
+        TypeOid::I64 => integer().map(Cell::I64),
+        TypeOid::F32 => number().map(|n| Cell::F32(n as f32)),
+        TypeOid::F64 => number().map(Cell::F64),
+        TypeOid::Numeric => number().map(Cell::Numeric),
+        TypeOid::String => match value {
+            JsonValue::String(s) => Some(Cell::String(s.clone())),
+            v => Some(Cell::String(v.to_string())),
+        },
+        TypeOid::Date => micros().map(|t| Cell::Date(t.div_euclid(1_000_000))),
+        TypeOid::Timestamp => micros().map(Cell::Timestamp),
+        TypeOid::Timestamptz => micros().map(Cell::Timestamptz),
+        TypeOid::Json => Some(Cell::Json(value.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Rng;
+
+    #[cfg(feature = "commerce")]
+    mod products {
+        use super::*;
+
+        fn products() -> Vec<JsonValue> {
+            let resp_json: JsonValue = serde_json::from_str(include_str!(concat!(
+                env!("CARGO_MANIFEST_DIR"),
+                "/tests/fixtures/catalog_products.json"
+            )))
+            .unwrap();
+            resp_json["products"].as_array().unwrap().to_owned()
+        }
+
+        fn string_cell(src_row: &JsonValue, col: &str) -> Option<String> {
+            match product_cell(src_row, col).unwrap() {
+                Some(Cell::String(s)) => Some(s),
+                None => None,
+                other => panic!("unexpected cell for '{}': {:?}", col, other),
+            }
+        }
+
+        fn bool_cell(src_row: &JsonValue, col: &str) -> Option<bool> {
+            match product_cell(src_row, col).unwrap() {
+                Some(Cell::Bool(b)) => Some(b),
+                None => None,
+                other => panic!("unexpected cell for '{}': {:?}", col, other),
+            }
+        }
+
+        #[test]
+        fn maps_string_columns() {
+            let product = &products()[0];
+            let expected = [
+                ("id", "7418529630"),
+                ("retailer_id", "SKU-001"),
+                ("name", "Espresso Beans 1kg"),
+                ("description", "Single origin, medium roast"),
+                ("url", "https://shop.example.com/espresso"),
+                ("currency", "EUR"),
+                ("price", "24.90"),
+                ("availability", "in stock"),
+                ("approval_status", "APPROVED"),
+                ("signedShimmedUrl", "https://wa.me/p/7418529630/4915123456789"),
+            ];
+            for (col, value) in expected {
+                assert_eq!(string_cell(product, col).as_deref(), Some(value), "{}", col);
+            }
+        }
+
+        #[test]
+        fn maps_bool_columns() {
+            let product = &products()[0];
+            let expected = [
+                ("is_hidden", false),
+                ("checkmark", true),
+                ("whatsapp_product_can_appeal", false),
+                ("is_approved", true),
+            ];
+            for (col, value) in expected {
+                assert_eq!(bool_cell(product, col), Some(value), "{}", col);
+            }
+        }
+
+        #[test]
+        fn maps_max_available() {
+            let product = &products()[0];
+            assert!(matches!(
+                product_cell(product, "max_available").unwrap(),
+                Some(Cell::I64(120))
+            ));
+        }
+
+        #[test]
+        fn joins_image_urls() {
+            let product = &products()[0];
+            assert_eq!(
+                string_cell(product, "images").as_deref(),
+                Some("https://cdn.example.com/espresso-1.jpg, https://cdn.example.com/espresso-2.jpg")
+            );
+        }
+
+        #[test]
+        fn reads_image_arrays_into_jsonb_columns() {
+            let product = &products()[0];
+            let Some(Some(Cell::Json(images))) = url_list_array("products", product, "images") else {
+                panic!("images are not read as JSON");
+            };
+            let images: JsonValue = serde_json::from_str(&images).unwrap();
+            assert_eq!(images, product["images"]);
+            assert!(url_list_array("products", product, "name").is_none());
+            assert!(matches!(url_list_array("products", &products()[1], "images"), Some(None)));
+
+            // Written images are read back from either column type
+            let urls = ["https://a".to_owned(), "https://b".to_owned()];
+            assert_eq!(url_list(&serde_json::json!("https://a, https://b")), urls);
+            assert_eq!(url_list(&serde_json::json!([{"id": "1", "url": "https://a"}, "https://b"])), urls);
+        }
+
+        #[test]
+        fn missing_fields_are_null() {
+            let product = &products()[1];
+            for col in ["retailer_id", "price", "images", "max_available", "is_hidden"] {
+                assert!(product_cell(product, col).unwrap().is_none(), "{}", col);
+            }
+        }
+
+        #[test]
+        fn unsupported_column_is_an_error() {
+            let product = &products()[0];
+            let err = product_cell(product, "retailor_id").unwrap_err();
+            assert!(
+                err.starts_with("Column 'retailor_id' is not supported by object 'products', supported columns: id, "),
+                "{}",
+                err
+            );
+            assert!(err.contains("retailer_id"), "{}", err);
+        }
+
+        #[test]
+        fn mistyped_fields_are_null() {
+            let product = serde_json::json!({
+                "id": 7418529630u64,
+                "price": 24.9,
+                "is_hidden": "false",
+                "max_available": "120",
+                "images": {"url": "https://cdn.example.com/a.jpg"},
+            });
+            for col in ["id", "price", "is_hidden", "max_available", "images"] {
+                assert!(product_cell(&product, col).unwrap().is_none(), "{}", col);
+            }
+            assert!(product_cell(&serde_json::json!({"max_available": u64::MAX}), "max_available")
+                .unwrap()
+                .is_none());
+        }
+    }
+
+    #[test]
+    fn maps_health_columns() {
+        let health = serde_json::json!({
+            "api_reachable": true,
+            "auth_valid": false,
+            "session_status": "C",
+            "remaining_credits": 42,
+            "rate_limit_remaining": null,
+            "rate_limit_reset_at": null,
+            "checked_at": 1_700_000_000_000_000i64,
+        });
+        assert!(matches!(health_cell(&health, "api_reachable").unwrap(), Some(Cell::Bool(true))));
+        assert!(matches!(health_cell(&health, "auth_valid").unwrap(), Some(Cell::Bool(false))));
+        assert!(matches!(health_cell(&health, "session_status").unwrap(), Some(Cell::String(s)) if s == "C"));
+        assert!(matches!(health_cell(&health, "remaining_credits").unwrap(), Some(Cell::I64(42))));
+        assert!(health_cell(&health, "rate_limit_remaining").unwrap().is_none());
+        assert!(matches!(
+            health_cell(&health, "checked_at").unwrap(),
+            Some(Cell::Timestamptz(1_700_000_000_000_000))
+        ));
+        assert!(health_cell(&health, "unknown").is_err());
+    }
+
+    #[test]
+    fn parses_prices_in_common_formats() {
+        assert_eq!(parse_price("12.00 EUR"), Some((1200, Some("EUR".to_owned()))));
+        assert_eq!(parse_price("€12"), Some((1200, None)));
+        assert_eq!(parse_price("$1,234.5"), Some((123_450, None)));
+        assert_eq!(parse_price("1.234,50 EUR"), Some((123_450, Some("EUR".to_owned()))));
+        assert_eq!(parse_price("free"), None);
+    }
+
+    #[test]
+    fn coerces_cells_to_the_column_type() {
+        let price = || Cell::String("€24.90".to_owned());
+        assert!(matches!(coerce_cell(price(), TypeOid::Numeric), Some(Cell::Numeric(v)) if v == 24.9));
+        assert!(matches!(coerce_cell(price(), TypeOid::F64), Some(Cell::F64(v)) if v == 24.9));
+        assert!(matches!(coerce_cell(price(), TypeOid::String), Some(Cell::String(v)) if v == "€24.90"));
+        assert!(coerce_cell(Cell::String("free".to_owned()), TypeOid::Numeric).is_none());
+
+        let updated_at = || Cell::Timestamptz(1_438_287_151_500_000);
+        assert!(matches!(
+            coerce_cell(updated_at(), TypeOid::Timestamp),
+            Some(Cell::Timestamp(1_438_287_151_500_000))
+        ));
+        assert!(matches!(coerce_cell(updated_at(), TypeOid::Date), Some(Cell::Date(1_438_214_400))));
+        assert!(matches!(
+            coerce_cell(updated_at(), TypeOid::String),
+            Some(Cell::String(v)) if v == "2015-07-30T20:12:31.5Z"
+        ));
+        assert!(matches!(
+            coerce_cell(Cell::String("2015-07-30T20:12:31Z".to_owned()), TypeOid::Timestamptz),
+            Some(Cell::Timestamptz(1_438_287_151_000_000))
+        ));
+        assert!(matches!(
+            coerce_cell(Cell::Date(1_438_214_400), TypeOid::String),
+            Some(Cell::String(v)) if v == "2015-07-30"
+        ));
+        assert!(matches!(
+            coerce_cell(Cell::Date(253_402_300_800), TypeOid::String),
+            Some(Cell::String(v)) if v == "10000-01-01"
+        ));
+        assert!(coerce_cell(Cell::Date(i64::MAX), TypeOid::String).is_none());
+        assert!(coerce_cell(Cell::Date(i64::MAX), TypeOid::Timestamptz).is_none());
+
+        assert!(matches!(coerce_cell(Cell::I64(120), TypeOid::I32), Some(Cell::I32(120))));
+        assert!(matches!(coerce_cell(Cell::I64(120), TypeOid::Numeric), Some(Cell::Numeric(v)) if v == 120.0));
+        assert!(matches!(coerce_cell(Cell::I64(120), TypeOid::String), Some(Cell::String(v)) if v == "120"));
+        assert!(matches!(coerce_cell(Cell::Bool(true), TypeOid::String), Some(Cell::String(v)) if v == "true"));
+        assert!(matches!(
+            coerce_cell(Cell::Json(r#"{"a":1}"#.to_owned()), TypeOid::String),
+            Some(Cell::String(v)) if v == r#"{"a":1}"#
+        ));
+    }
+
+    #[test]
+    fn converts_modify_rows_to_json() {
+        let row = row_to_json(
+            vec!["to".to_owned(), "body".to_owned(), "components".to_owned(), "priority".to_owned()],
+            vec![
+                Some(Cell::String("+4915123456789".to_owned())),
+                None,
+                Some(Cell::Json(r#"[{"type": "body"}]"#.to_owned())),
+                Some(Cell::I32(2)),
+            ],
+        );
+        assert_eq!(
+            row,
+            serde_json::json!({
+                "to": "+4915123456789",
+                "body": null,
+                "components": [{"type": "body"}],
+                "priority": 2,
+            })
+        );
+    }
+
+    // Generate an arbitrary JSON value, biased towards the keys the mapper
+    // looks up so that every column sees values of every type
+    fn arbitrary_json(rng: &mut Rng, keys: &[&str], depth: u32) -> JsonValue {
+        let kind = if depth == 0 { rng.below(6) } else { rng.below(8) };
+        match kind {
+            0 => JsonValue::Null,
+            1 => JsonValue::Bool(rng.below(2) == 1),
+            2 => match rng.below(4) {
+                0 => serde_json::json!(i64::MIN),
+                1 => serde_json::json!(u64::MAX),
+                2 => serde_json::json!(rng.next() as i64),
+                _ => serde_json::json!(f64::from_bits(rng.next() & !(0x7ff << 52)) * 1e10),
+            },
+            3 => JsonValue::String(String::new()),
+            4 => JsonValue::String(
+                (0..rng.below(16))
+                    .map(|_| char::from_u32(rng.below(0x2fff) as u32).unwrap_or('\u{fffd}'))
+                    .collect(),
+            ),
+            5 => JsonValue::String(keys[rng.below(keys.len() as u64) as usize].to_owned()),
+            6 => (0..rng.below(4))
+                .map(|_| arbitrary_json(rng, keys, depth - 1))
+                .collect(),
+            _ => {
+                let mut object = serde_json::Map::new();
+                for _ in 0..rng.below(keys.len() as u64 + 2) {
+                    let key = match rng.below(5) {
+                        0 => "url".to_owned(),
+                        1 => format!("k{}", rng.below(10)),
+                        _ => keys[rng.below(keys.len() as u64) as usize].to_owned(),
+                    };
+                    object.insert(key, arbitrary_json(rng, keys, depth - 1));
+                }
+                JsonValue::Object(object)
+            }
+        }
+    }
+
+    #[test]
+    fn auto_columns_resolve_fields_by_name() {
+        let row = serde_json::json!({
+            "phoneNumber": "+4915123456789",
+            "unread_count": "3",
+            "lastSeen": 1_700_000_000,
+            "updatedAt": "2024-01-02T03:04:05Z",
+            "isBusiness": true,
+            "labels": ["vip"],
+            "score": null,
+        });
+        assert!(matches!(auto_cell(&row, "phone_number", TypeOid::String), Some(Cell::String(v)) if v == "+4915123456789"));
+        assert!(matches!(auto_cell(&row, "unreadCount", TypeOid::I32), Some(Cell::I32(3))));
+        assert!(matches!(
+            auto_cell(&row, "last_seen", TypeOid::Timestamptz),
+            Some(Cell::Timestamptz(1_700_000_000_000_000))
+        ));
+        assert!(matches!(
+            auto_cell(&row, "updated_at", TypeOid::Timestamptz),
+            Some(Cell::Timestamptz(1_704_164_645_000_000))
+        ));
+        assert!(matches!(auto_cell(&row, "is_business", TypeOid::Bool), Some(Cell::Bool(true))));
+        assert!(matches!(auto_cell(&row, "labels", TypeOid::Json), Some(Cell::Json(v)) if v == r#"["vip"]"#));
+        assert!(matches!(auto_cell(&row, "labels", TypeOid::String), Some(Cell::String(v)) if v == r#"["vip"]"#));
+
+        // Absent, null and unconvertible fields are null
+        assert!(auto_cell(&row, "score", TypeOid::F64).is_none());
+        assert!(auto_cell(&row, "new_field", TypeOid::String).is_none());
+        assert!(auto_cell(&row, "labels", TypeOid::I64).is_none());
+    }
+
+    #[test]
+    fn arbitrary_json_never_panics() {
+        type Mapper = fn(&JsonValue, &str) -> Result<Option<Cell>, FdwError>;
+        let objects: &[(&[&str], Mapper)] = &[
+            #[cfg(feature = "commerce")]
+            (PRODUCTS_COLUMNS, product_cell),
+            #[cfg(feature = "commerce")]
+            (PRODUCT_FEED_COLUMNS, product_feed_cell),
+            #[cfg(feature = "commerce")]
+            (CATALOG_DIFF_COLUMNS, catalog_diff_cell),
+            #[cfg(feature = "commerce")]
+            (PRODUCT_IMAGES_COLUMNS, product_image_cell),
+            #[cfg(feature = "commerce")]
+            (PRODUCT_DUPLICATES_COLUMNS, product_duplicate_cell),
+            #[cfg(feature = "commerce")]
+            (IMPORT_PRODUCTS_COLUMNS, import_product_cell),
+            #[cfg(feature = "messages")]
+            (MESSAGES_COLUMNS, message_cell),
+            #[cfg(feature = "messages")]
+            (TEMPLATES_COLUMNS, template_cell),
+            #[cfg(feature = "messages")]
+            (BUTTON_REPLIES_COLUMNS, button_reply_cell),
+            #[cfg(feature = "messages")]
+            (FLOW_RESPONSES_COLUMNS, flow_response_cell),
+            #[cfg(feature = "groups")]
+            (GROUPS_COLUMNS, group_cell),
+            #[cfg(feature = "groups")]
+            (GROUP_STATS_COLUMNS, group_stats_cell),
+            #[cfg(feature = "messages")]
+            (UNREAD_COUNTS_COLUMNS, unread_count_cell),
+            #[cfg(feature = "messages")]
+            (BROADCASTS_COLUMNS, broadcast_cell),
+            #[cfg(feature = "messages")]
+            (BROADCAST_SENDS_COLUMNS, broadcast_send_cell),
+            #[cfg(feature = "messages")]
+            (CAMPAIGN_SENDS_COLUMNS, campaign_send_cell),
+            #[cfg(feature = "messages")]
+            (EXPORT_MESSAGES_COLUMNS, export_message_cell),
+            #[cfg(feature = "messages")]
+            (NOTES_COLUMNS, note_cell),
+            #[cfg(feature = "messages")]
+            (SLA_COLUMNS, sla_cell),
+            (CONTACTS_COLUMNS, contact_cell),
+            (CONSENTS_COLUMNS, consent_cell),
+            (PHONE_NUMBERS_COLUMNS, phone_number_cell),
+            (BUSINESS_PROFILE_COLUMNS, business_profile_cell),
+            (ACCESS_TOKEN_COLUMNS, access_token_cell),
+            (WEBHOOK_BRIDGE_COLUMNS, webhook_bridge_cell),
+            (HEALTH_COLUMNS, health_cell),
+            (FDW_STATS_COLUMNS, fdw_stats_cell),
+            (AUDIT_LOG_COLUMNS, audit_log_cell),
+            (DISCOVER_COLUMNS, discover_cell),
+            (COLUMNS_COLUMNS, columns_cell),
+            (DDL_COLUMNS, ddl_cell),
+        ];
+        for seed in 1..=2000u64 {
+            let mut rng = Rng::new(seed);
+            for (columns, mapper) in objects {
+                let row = arbitrary_json(&mut rng, columns, 3);
+                for col in *columns {
+                    assert!(mapper(&row, col).is_ok(), "seed {} column {}", seed, col);
+                }
+            }
+        }
+    }
+}
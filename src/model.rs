@@ -0,0 +1,202 @@
+use serde_json::Value as JsonValue;
+use std::fmt;
+
+// Shape of the successful and failed responses of a provider API
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum Envelope {
+    // 2Chat responses, carrying a `success` flag
+    #[default]
+    Success,
+    // Graph API responses, failed ones carrying an `error` object
+    Graph,
+    // Plain REST responses, failed ones having an error status code
+    Status,
+    // WATI responses, carrying a `result` flag, `true` or `"success"`
+    ResultFlag,
+    // Next-gen 2Chat responses, failed ones having an error status code and
+    // an `error` object, e.g. `{"code": "invalid_api_key", "message": ...}`
+    TwoChatNext,
+    // 2Chat responses of either generation, told apart by the `success`
+    // flag of the current one
+    TwoChatAny,
+}
+
+impl Envelope {
+    // Check that a response is successful, describing the provider error
+    // if the request failed
+    pub(crate) fn check(&self, status_code: u16, resp_json: &JsonValue, body: &str) -> Result<(), String> {
+        let ok = match self {
+            Envelope::Success => resp_json.get("success").and_then(|v| v.as_bool()).unwrap_or(false),
+            Envelope::Graph => status_code < 400 && resp_json.get("error").is_none(),
+            Envelope::Status => status_code < 400,
+            Envelope::TwoChatNext => status_code < 400 && resp_json.get("error").is_none(),
+            Envelope::TwoChatAny if resp_json.get("success").is_some() => {
+                return Envelope::Success.check(status_code, resp_json, body)
+            }
+            Envelope::TwoChatAny => return Envelope::TwoChatNext.check(status_code, resp_json, body),
+            Envelope::ResultFlag => {
+                status_code < 400
+                    && match resp_json.get("result") {
+                        Some(JsonValue::Bool(ok)) => *ok,
+                        Some(JsonValue::String(result)) => result == "success",
+                        _ => true,
+                    }
+            }
+        };
+        if ok {
+            return Ok(());
+        }
+
+        let reason = MetaError::find(resp_json)
+            .map(|err| err.to_string())
+            .or_else(|| match self {
+                Envelope::TwoChatNext => next_gen_error(resp_json),
+                _ => None,
+            })
+            .unwrap_or_else(|| body.to_owned());
+        Err(format!("API request was not successful: {}", reason))
+    }
+}
+
+// Describe a next-gen 2Chat error object as its message and code
+fn next_gen_error(resp_json: &JsonValue) -> Option<String> {
+    let err = resp_json.get("error")?;
+    let message = err.get("message").and_then(|v| v.as_str())?;
+    Some(match err.get("code").and_then(|v| v.as_str()) {
+        Some(code) => format!("{} ({})", message, code),
+        None => message.to_owned(),
+    })
+}
+
+// A Meta Graph API error object, as relayed by the provider, e.g.
+// `{"error": {"code": 100, "error_subcode": 2388093, ...}}`
+#[derive(Debug)]
+pub(crate) struct MetaError {
+    code: i64,
+    subcode: Option<i64>,
+    message: String,
+    fbtrace_id: Option<String>,
+}
+
+impl MetaError {
+    // Find a Meta error object anywhere in a response
+    pub(crate) fn find(value: &JsonValue) -> Option<Self> {
+        let err = find_error_object(value)?;
+        Some(Self {
+            code: err.get("code").and_then(|v| v.as_i64())?,
+            subcode: err.get("error_subcode").and_then(|v| v.as_i64()),
+            message: err
+                .get("error_user_msg")
+                .or_else(|| err.get("message"))
+                .and_then(|v| v.as_str())
+                .unwrap_or("unknown error")
+                .to_owned(),
+            fbtrace_id: err
+                .get("fbtrace_id")
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_owned()),
+        })
+    }
+
+    // Whether a parameter of the request was invalid, e.g. a paging cursor
+    // Meta no longer accepts
+    pub(crate) fn is_invalid_parameter(&self) -> bool {
+        self.code == 100
+    }
+
+    // Explanation of well-known Meta error codes and subcodes
+    fn hint(&self) -> Option<&'static str> {
+        let hint = match (self.code, self.subcode) {
+            (190, Some(463)) => "access token has expired",
+            (190, Some(467)) => "access token is invalid",
+            (190, _) => "access token is invalid or has expired",
+            (4, _) | (80007, _) => "API request limit reached",
+            (10, _) | (200, _) => "permission denied",
+            (100, _) => "invalid parameter",
+            (368, _) => "temporarily blocked for policy violations",
+            (130429, _) => "Cloud API throughput limit reached",
+            (131026, _) => "message undeliverable",
+            (131047, _) => "more than 24 hours have passed since the customer last replied",
+            (131051, _) => "unsupported message type",
+            (131056, _) => "too many messages sent to the same recipient",
+            (132000, _) => "number of template parameters does not match",
+            (132001, _) => "template does not exist",
+            (132005, _) => "translated template text is too long",
+            (132007, _) => "template content violates a WhatsApp policy",
+            (132012, _) => "template parameter format mismatch",
+            (133010, _) => "phone number is not registered",
+            _ => return None,
+        };
+        Some(hint)
+    }
+}
+
+impl fmt::Display for MetaError {
+    // Describe the error with its codes and trace id, so that template and
+    // catalog rejections can be triaged
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Meta error {}", self.code)?;
+        if let Some(subcode) = self.subcode {
+            write!(f, " (subcode {})", subcode)?;
+        }
+        if let Some(hint) = self.hint() {
+            write!(f, ", {}", hint)?;
+        }
+        write!(f, ": {}", self.message)?;
+        if let Some(trace_id) = &self.fbtrace_id {
+            write!(f, " [fbtrace_id: {}]", trace_id)?;
+        }
+        Ok(())
+    }
+}
+
+// Find the first `error` object carrying a `code`, searching depth-first
+fn find_error_object(value: &JsonValue) -> Option<&JsonValue> {
+    match value {
+        JsonValue::Object(map) => {
+            if let Some(err) = map.get("error").filter(|e| e.get("code").is_some()) {
+                return Some(err);
+            }
+            map.values().find_map(find_error_object)
+        }
+        JsonValue::Array(items) => items.iter().find_map(find_error_object),
+        _ => None,
+    }
+}
+
+// Time of a row of the messages object, microseconds since Unix epoch
+pub(crate) fn sent_at(row: &JsonValue) -> Option<i64> {
+    row.get("sent_at").and_then(|v| v.as_i64())
+}
+
+// Forwarding count from which WhatsApp labels a message as forwarded many
+// times
+const FREQUENTLY_FORWARDED: i64 = 5;
+
+// Add the forwarding metadata of a WhatsApp message to its row, as relayed
+// by the provider: the `forwarded` and `frequently_forwarded` flags of the
+// Cloud API context, or the forwarding score of WhatsApp Web gateways,
+// along with the channel a message was forwarded from. WhatsApp doesn't
+// disclose the original sender of other forwards.
+pub(crate) fn add_forwarding(row: &mut JsonValue, message: &JsonValue) {
+    let find = |pointers: &[&str]| {
+        pointers
+            .iter()
+            .find_map(|pointer| message.pointer(pointer).filter(|v| !v.is_null()))
+    };
+    let forward_count =
+        find(&["/forwardingScore", "/forwarding_score", "/context/forwarding_score"]).and_then(|v| v.as_i64());
+    let frequently_forwarded = find(&["/context/frequently_forwarded", "/frequently_forwarded"])
+        .and_then(|v| v.as_bool())
+        .or(forward_count.map(|count| count >= FREQUENTLY_FORWARDED));
+    let is_forwarded = find(&["/context/forwarded", "/isForwarded", "/is_forwarded"])
+        .and_then(|v| v.as_bool())
+        .or(forward_count.map(|count| count > 0))
+        .or(frequently_forwarded.filter(|frequently| *frequently));
+    let forwarded_from = find(&["/forwardedNewsletterMessageInfo/newsletterName", "/forwarded_from"]);
+
+    row["is_forwarded"] = is_forwarded.into();
+    row["forward_count"] = forward_count.into();
+    row["frequently_forwarded"] = frequently_forwarded.into();
+    row["forwarded_from"] = forwarded_from.cloned().unwrap_or_default();
+}
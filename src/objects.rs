@@ -0,0 +1,374 @@
+use serde_json::Value as JsonValue;
+
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError, FdwResult};
+
+mod access_token;
+pub(crate) mod audit_log;
+#[cfg(feature = "messages")]
+pub(crate) mod broadcast_sends;
+#[cfg(feature = "messages")]
+pub(crate) mod broadcasts;
+mod business_profile;
+#[cfg(feature = "messages")]
+pub(crate) mod campaign_sends;
+#[cfg(feature = "messages")]
+pub(crate) mod button_replies;
+#[cfg(feature = "commerce")]
+pub(crate) mod catalog_diff;
+pub(crate) mod columns;
+pub(crate) mod consents;
+pub(crate) mod contacts;
+pub(crate) mod ddl;
+pub(crate) mod discover;
+#[cfg(feature = "messages")]
+pub(crate) mod export_messages;
+pub(crate) mod fdw_stats;
+#[cfg(feature = "messages")]
+pub(crate) mod flow_responses;
+#[cfg(feature = "groups")]
+pub(crate) mod group_stats;
+#[cfg(feature = "groups")]
+mod groups;
+pub(crate) mod health;
+#[cfg(feature = "commerce")]
+pub(crate) mod import_products;
+#[cfg(feature = "messages")]
+pub(crate) mod messages;
+#[cfg(feature = "messages")]
+pub(crate) mod notes;
+mod phone_numbers;
+#[cfg(feature = "commerce")]
+pub(crate) mod product_duplicates;
+#[cfg(feature = "commerce")]
+pub(crate) mod product_feed;
+#[cfg(feature = "commerce")]
+pub(crate) mod product_images;
+#[cfg(feature = "commerce")]
+pub(crate) mod products;
+#[cfg(feature = "messages")]
+pub(crate) mod sla;
+#[cfg(feature = "messages")]
+pub(crate) mod templates;
+#[cfg(feature = "messages")]
+pub(crate) mod unread_counts;
+pub(crate) mod webhook_bridge;
+
+// Operations the host can run against a foreign table
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Operation {
+    Scan,
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Operation {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Operation::Scan => "scan",
+            Operation::Insert => "insert",
+            Operation::Update => "update",
+            Operation::Delete => "delete",
+        }
+    }
+}
+
+// How a provider pages through the rows of an object
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Pagination {
+    // All rows come in a single response
+    None,
+    // An opaque cursor, e.g. the Graph API `after` cursor
+    Cursor,
+    // A row offset
+    Offset,
+    // A page number
+    PageNumber,
+    // A link to the next page
+    NextLink,
+}
+
+impl Pagination {
+    pub(crate) fn as_str(&self) -> &'static str {
+        match self {
+            Pagination::None => "no",
+            Pagination::Cursor => "cursor",
+            Pagination::Offset => "offset",
+            Pagination::PageNumber => "page number",
+            Pagination::NextLink => "next link",
+        }
+    }
+}
+
+// Capabilities of an object exposed by a provider, checked when a table
+// is opened so that unsupported combinations fail before any request
+#[derive(Debug)]
+pub(crate) struct ObjectCapabilities {
+    pub(crate) name: &'static str,
+    pub(crate) scan: bool,
+    pub(crate) insert: bool,
+    pub(crate) update: bool,
+    pub(crate) delete: bool,
+    // Column identifying updated and deleted rows, which must be the
+    // table's `rowid_column`
+    pub(crate) rowid: Option<&'static str>,
+    // Columns whose quals the provider applies itself, the others being
+    // evaluated by Postgres on the fetched rows
+    pub(crate) quals: &'static [&'static str],
+    pub(crate) pagination: Pagination,
+}
+
+impl ObjectCapabilities {
+    // An object that can only be scanned
+    pub(crate) const fn read_only(name: &'static str) -> Self {
+        Self {
+            name,
+            scan: true,
+            insert: false,
+            update: false,
+            delete: false,
+            rowid: None,
+            quals: &[],
+            pagination: Pagination::None,
+        }
+    }
+
+    // The same object, fetched with the given pagination
+    pub(crate) const fn paged(self, pagination: Pagination) -> Self {
+        Self { pagination, ..self }
+    }
+
+    // The same object, the provider applying the quals on the columns
+    pub(crate) const fn filtered(self, quals: &'static [&'static str]) -> Self {
+        Self { quals, ..self }
+    }
+
+    fn supports(&self, operation: Operation) -> bool {
+        match operation {
+            Operation::Scan => self.scan,
+            Operation::Insert => self.insert,
+            Operation::Update => self.update,
+            Operation::Delete => self.delete,
+        }
+    }
+
+    pub(crate) fn operations(&self) -> Vec<&'static str> {
+        [Operation::Scan, Operation::Insert, Operation::Update, Operation::Delete]
+            .into_iter()
+            .filter(|op| self.supports(*op))
+            .map(|op| op.as_str())
+            .collect()
+    }
+
+    pub(crate) fn is_writable(&self) -> bool {
+        self.insert || self.update || self.delete
+    }
+
+    // Fail with a precise message if the table's `rowid_column` is not the
+    // column identifying the rows of the object. Without it only inserts
+    // are possible, which fail later on update or delete.
+    pub(crate) fn check_rowid(&self, rowid_column: Option<&str>) -> FdwResult {
+        match (self.rowid, rowid_column) {
+            (Some(rowid), Some(column)) if (self.update || self.delete) && rowid != column => Err(format!(
+                "Object '{}' identifies rows by column '{}', set the table option rowid_column '{}' instead of '{}'",
+                self.name, rowid, rowid, column
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // Fail with a precise message if the operation is not supported
+    pub(crate) fn check(&self, operation: Operation) -> FdwResult {
+        if self.supports(operation) {
+            return Ok(());
+        }
+        Err(format!(
+            "Operation '{}' is not supported on object '{}', supported operations: {}",
+            operation.as_str(),
+            self.name,
+            self.operations().join(", ")
+        ))
+    }
+}
+
+// A WhatsApp resource exposed as a foreign table, selected by the `object`
+// table option. The object defines the columns of the table, the
+// providers fetch its rows. Adding a resource means implementing this
+// trait, registering the implementation in `OBJECTS`, and exposing it
+// from the providers serving it.
+pub(crate) trait Object {
+    // Name of the object, as given in the `object` option
+    fn name(&self) -> &'static str;
+
+    // Map a source row field to the cell of the target column
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError>;
+}
+
+// Registry of all the objects exposed by the FDW
+const OBJECTS: &[&dyn Object] = &[
+    #[cfg(feature = "commerce")]
+    &products::Products,
+    #[cfg(feature = "commerce")]
+    &product_feed::ProductFeed,
+    #[cfg(feature = "commerce")]
+    &catalog_diff::CatalogDiff,
+    #[cfg(feature = "commerce")]
+    &product_images::ProductImages,
+    #[cfg(feature = "commerce")]
+    &product_duplicates::ProductDuplicates,
+    #[cfg(feature = "commerce")]
+    &import_products::ImportProducts,
+    #[cfg(feature = "messages")]
+    &messages::Messages,
+    #[cfg(feature = "messages")]
+    &templates::Templates,
+    #[cfg(feature = "messages")]
+    &button_replies::ButtonReplies,
+    #[cfg(feature = "messages")]
+    &flow_responses::FlowResponses,
+    #[cfg(feature = "groups")]
+    &groups::Groups,
+    #[cfg(feature = "groups")]
+    &group_stats::GroupStats,
+    #[cfg(feature = "messages")]
+    &unread_counts::UnreadCounts,
+    #[cfg(feature = "messages")]
+    &broadcasts::Broadcasts,
+    #[cfg(feature = "messages")]
+    &broadcast_sends::BroadcastSends,
+    #[cfg(feature = "messages")]
+    &campaign_sends::CampaignSends,
+    #[cfg(feature = "messages")]
+    &export_messages::ExportMessages,
+    #[cfg(feature = "messages")]
+    &notes::Notes,
+    #[cfg(feature = "messages")]
+    &sla::Sla,
+    &contacts::Contacts,
+    &consents::Consents,
+    &phone_numbers::PhoneNumbers,
+    &business_profile::BusinessProfile,
+    &access_token::AccessToken,
+    &webhook_bridge::WebhookBridge,
+    &health::Health,
+    &fdw_stats::FdwStats,
+    &audit_log::AuditLog,
+    &discover::Discover,
+    &columns::Columns,
+    &ddl::Ddl,
+];
+
+// Objects of optional families, with the cargo feature enabling them
+const GATED_OBJECTS: &[(&str, &str)] = &[
+    ("products", "commerce"),
+    ("product_feed", "commerce"),
+    ("catalog_diff", "commerce"),
+    ("product_images", "commerce"),
+    ("product_duplicates", "commerce"),
+    ("import_products", "commerce"),
+    ("messages", "messages"),
+    ("templates", "messages"),
+    ("button_replies", "messages"),
+    ("flow_responses", "messages"),
+    ("groups", "groups"),
+    ("group_stats", "groups"),
+    ("unread_counts", "messages"),
+    ("broadcasts", "messages"),
+    ("broadcast_sends", "messages"),
+    ("campaign_sends", "messages"),
+    ("export_messages", "messages"),
+    ("notes", "messages"),
+    ("sla", "messages"),
+];
+
+// Cargo features the component was built with
+pub(crate) fn enabled_features() -> Vec<&'static str> {
+    [
+        ("commerce", cfg!(feature = "commerce")),
+        ("messages", cfg!(feature = "messages")),
+        ("groups", cfg!(feature = "groups")),
+        ("analytics", cfg!(feature = "analytics")),
+        ("fault-injection", cfg!(feature = "fault-injection")),
+        ("bench", cfg!(feature = "bench")),
+    ]
+    .into_iter()
+    .filter_map(|(feature, enabled)| enabled.then_some(feature))
+    .collect()
+}
+
+// Look up an object by name
+pub(crate) fn lookup(name: &str) -> Result<&'static dyn Object, FdwError> {
+    OBJECTS
+        .iter()
+        .copied()
+        .find(|object| object.name() == name)
+        .ok_or_else(|| match GATED_OBJECTS.iter().find(|(object, _)| *object == name) {
+            Some((_, feature)) => format!(
+                "Object '{}' is not available in this build, it requires the '{}' feature",
+                name, feature
+            ),
+            None => {
+                let names: Vec<&str> = OBJECTS.iter().map(|object| object.name()).collect();
+                format!(
+                    "Object '{}' is not supported by the WhatsApp Catalog FDW, supported objects: {}",
+                    name,
+                    names.join(", ")
+                )
+            }
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disabled_object_names_its_feature() {
+        let err = match lookup("products") {
+            Ok(_) => return,
+            Err(err) => err,
+        };
+        assert!(err.contains("'commerce' feature"), "{}", err);
+    }
+
+    #[test]
+    fn unknown_object_lists_the_supported_objects() {
+        let err = lookup("prodcuts").err().unwrap();
+        assert!(
+            err.starts_with("Object 'prodcuts' is not supported by the WhatsApp Catalog FDW, supported objects: "),
+            "{}",
+            err
+        );
+        assert!(err.contains("health, fdw_stats"), "{}", err);
+    }
+
+    #[test]
+    fn gated_objects_are_registered_when_enabled() {
+        for (name, feature) in GATED_OBJECTS {
+            if let Err(err) = lookup(name) {
+                assert!(err.contains(feature), "{}", err);
+            }
+        }
+    }
+
+    #[test]
+    fn rowid_column_must_identify_the_rows() {
+        let caps = ObjectCapabilities {
+            name: "products",
+            scan: true,
+            insert: true,
+            update: true,
+            delete: true,
+            rowid: Some("retailer_id"),
+            quals: &[],
+            pagination: Pagination::Cursor,
+        };
+        assert!(caps.check_rowid(Some("retailer_id")).is_ok());
+        assert!(caps.check_rowid(None).is_ok());
+        assert_eq!(
+            caps.check_rowid(Some("id")).unwrap_err(),
+            "Object 'products' identifies rows by column 'retailer_id', set the table option rowid_column 'retailer_id' instead of 'id'"
+        );
+        assert!(ObjectCapabilities::read_only("templates").check_rowid(Some("id")).is_ok());
+    }
+}
@@ -0,0 +1,19 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// A renewed long-lived access token of a Meta system user, exchanged on
+// each scan
+pub(crate) struct AccessToken;
+
+impl Object for AccessToken {
+    fn name(&self) -> &'static str {
+        "access_token"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::access_token_cell(src_row, tgt_col_name)
+    }
+}
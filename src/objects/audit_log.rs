@@ -0,0 +1,22 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// The audit log is kept by the FDW itself, so any provider serves it
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("audit_log");
+
+// The most recent write operations sent to the provider, as sent messages
+// can't be unsent
+pub(crate) struct AuditLog;
+
+impl Object for AuditLog {
+    fn name(&self) -> &'static str {
+        "audit_log"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::audit_log_cell(src_row, tgt_col_name)
+    }
+}
@@ -0,0 +1,42 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Inserting a row sends a message to a broadcast list, nothing being
+// scanned
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "broadcast_sends",
+    scan: false,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: &[],
+    pagination: Pagination::None,
+};
+
+// Messages sent to every member of a broadcast list
+pub(crate) struct BroadcastSends;
+
+impl Object for BroadcastSends {
+    fn name(&self) -> &'static str {
+        "broadcast_sends"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::broadcast_send_cell(src_row, tgt_col_name)
+    }
+}
+
+// The message row sent to a member of the list, the inserted row with the
+// member as recipient
+pub(crate) fn member_message(columns: &JsonValue, to: &str) -> JsonValue {
+    let mut message = columns.clone();
+    if let Some(fields) = message.as_object_mut() {
+        fields.remove("broadcast_id");
+        fields.insert("to".to_owned(), to.into());
+    }
+    message
+}
@@ -0,0 +1,71 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::normalize_number;
+use crate::mapping;
+
+// Broadcast lists are listed and created, their members being added at
+// creation
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "broadcasts",
+    scan: true,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: &[],
+    pagination: Pagination::Cursor,
+};
+
+// Broadcast lists of the account, the audiences of the messages inserted
+// into `broadcast_sends`
+pub(crate) struct Broadcasts;
+
+impl Object for Broadcasts {
+    fn name(&self) -> &'static str {
+        "broadcasts"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::broadcast_cell(src_row, tgt_col_name)
+    }
+}
+
+// Phone numbers of the `recipients` column of an inserted list: a JSON
+// array of numbers, or of objects with a `phone` or `to` field such as the
+// rows of `jsonb_agg`
+pub(crate) fn recipients(columns: &JsonValue) -> Result<Vec<String>, FdwError> {
+    let recipients = columns
+        .get("recipients")
+        .and_then(|v| v.as_array())
+        .ok_or("Column 'recipients' is required to create a broadcast list, as a JSON array of phone numbers")?;
+    recipients
+        .iter()
+        .map(|recipient| {
+            let number = match recipient {
+                JsonValue::Object(fields) => fields.get("phone").or(fields.get("to")).and_then(|v| v.as_str()),
+                other => other.as_str(),
+            };
+            number
+                .map(normalize_number)
+                .ok_or_else(|| format!("Invalid recipient {}, expected a phone number", recipient))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_numbers_and_rows_of_recipients() {
+        let columns = serde_json::json!({
+            "name": "VIP",
+            "recipients": ["+49 151 23456789", {"phone": "+4915100000000", "name": "Jane"}],
+        });
+        assert_eq!(recipients(&columns).unwrap(), ["+4915123456789", "+4915100000000"]);
+        assert!(recipients(&serde_json::json!({"recipients": [42]})).is_err());
+        assert!(recipients(&serde_json::json!({"name": "VIP"})).is_err());
+    }
+}
@@ -0,0 +1,18 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Business profile of the WhatsApp number, as a single row
+pub(crate) struct BusinessProfile;
+
+impl Object for BusinessProfile {
+    fn name(&self) -> &'static str {
+        "business_profile"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::business_profile_cell(src_row, tgt_col_name)
+    }
+}
@@ -0,0 +1,92 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Where the reply to an interactive message is found in an incoming
+// message, with the pointers to the id and title of the chosen option:
+// the Cloud API shapes of buttons, list rows and template quick replies,
+// as relayed by gateways, and the postback action of Bird
+const REPLY_SHAPES: &[(&str, &str, &str, &str)] = &[
+    ("button", "/interactive/button_reply", "/id", "/title"),
+    ("list", "/interactive/list_reply", "/id", "/title"),
+    ("quick_reply", "/button", "/payload", "/text"),
+    ("button", "/body/text/actions/0/postback", "/payload", "/text"),
+];
+
+// Pointers to the id of the message replied to
+const CONTEXT_POINTERS: &[&str] = &["/context/id", "/replyTo/id"];
+
+// Replies of contacts to interactive, list and button messages, one row per
+// chosen option
+pub(crate) struct ButtonReplies;
+
+impl Object for ButtonReplies {
+    fn name(&self) -> &'static str {
+        "button_replies"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::button_reply_cell(src_row, tgt_col_name)
+    }
+}
+
+// The button reply row of an incoming message, sent by `contact` at
+// `replied_at` in microseconds since Unix epoch, or None if the message
+// isn't a reply to an interactive message
+pub(crate) fn reply_row(
+    message_id: &JsonValue,
+    message: &JsonValue,
+    contact: Option<&str>,
+    replied_at: Option<i64>,
+) -> Option<JsonValue> {
+    let text = |value: &JsonValue, pointer: &str| value.pointer(pointer).and_then(|v| v.as_str()).map(str::to_owned);
+    let (reply_type, reply, button_id, button_title) = REPLY_SHAPES
+        .iter()
+        .find_map(|(reply_type, pointer, id, title)| Some((reply_type, message.pointer(pointer)?, id, title)))?;
+
+    Some(serde_json::json!({
+        "message_id": message_id,
+        "replied_message_id": CONTEXT_POINTERS.iter().find_map(|pointer| text(message, pointer)),
+        "reply_type": reply_type,
+        "button_id": text(reply, button_id),
+        "button_title": text(reply, button_title),
+        "contact": contact,
+        "replied_at": replied_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_chosen_option_of_each_shape() {
+        let list_reply = serde_json::json!({
+            "context": {"id": "wamid.OUT"},
+            "interactive": {"type": "list_reply", "list_reply": {"id": "size_m", "title": "Medium"}},
+        });
+        let row = reply_row(&"wamid.IN".into(), &list_reply, Some("+4915123456789"), Some(1)).unwrap();
+        assert_eq!(
+            row,
+            serde_json::json!({
+                "message_id": "wamid.IN",
+                "replied_message_id": "wamid.OUT",
+                "reply_type": "list",
+                "button_id": "size_m",
+                "button_title": "Medium",
+                "contact": "+4915123456789",
+                "replied_at": 1,
+            })
+        );
+
+        let quick_reply = serde_json::json!({"button": {"payload": "STOP", "text": "Unsubscribe"}});
+        let row = reply_row(&"wamid.IN2".into(), &quick_reply, None, None).unwrap();
+        assert_eq!(row["reply_type"], "quick_reply");
+        assert_eq!(row["button_id"], "STOP");
+
+        let text = serde_json::json!({"body": {"type": "text", "text": {"text": "Hi"}}});
+        assert!(reply_row(&"msg-1".into(), &text, None, None).is_none());
+    }
+}
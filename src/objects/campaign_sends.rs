@@ -0,0 +1,292 @@
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use super::{Object, ObjectCapabilities, Operation, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError, FdwResult};
+use crate::client::Client;
+use crate::config::TableConfig;
+use crate::log::{info, notice};
+use crate::mapping;
+use crate::providers::{Provider, WriteRow};
+
+// Campaigns are sent by the FDW itself, relaying each inserted row to the
+// provider's `messages` object. Scanning reads the cursor of each campaign
+// sent by the Wasm instance.
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "campaign_sends",
+    scan: true,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: &[],
+    pagination: Pagination::None,
+};
+
+// Columns of an inserted row that drive the campaign rather than the
+// message
+const CAMPAIGN_COLUMNS: &[&str] = &["campaign_id", "position", "sent", "last_sent_at"];
+
+// Bulk sends of a template to an audience, one inserted row per recipient
+pub(crate) struct CampaignSends;
+
+impl Object for CampaignSends {
+    fn name(&self) -> &'static str {
+        "campaign_sends"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::campaign_send_cell(src_row, tgt_col_name)
+    }
+}
+
+// How far a campaign got: the rows sent, and the `position` of the last
+// one, from which an interrupted insert resumes
+#[derive(Debug, Default)]
+struct Cursor {
+    sent: i64,
+    position: Option<i64>,
+    // microseconds since Unix epoch
+    last_sent_at: Option<i64>,
+}
+
+thread_local! {
+    // Cursors of the campaigns sent by the instance, by campaign id. Like
+    // the frequency cap, they are kept across the FDW lifecycles of the
+    // Wasm instance, so that rerunning an insert resumes after its cursor.
+    static CURSORS: RefCell<BTreeMap<String, Cursor>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+// Progress of the current insert statement into `campaign_sends`
+#[derive(Debug, Default)]
+pub(crate) struct Campaigns {
+    statement_sent: i64,
+    statement_skipped: i64,
+    statement_campaign: Option<String>,
+}
+
+impl Campaigns {
+    // Start the progress of a new insert statement
+    pub(crate) fn begin(&mut self) {
+        self.statement_sent = 0;
+        self.statement_skipped = 0;
+        self.statement_campaign = None;
+    }
+
+    // Whether an inserted row was sent by an earlier statement of its
+    // campaign, at or before the position of the campaign's cursor
+    pub(crate) fn is_sent(&self, columns: &JsonValue) -> bool {
+        let campaign_id = columns.get("campaign_id").and_then(|v| v.as_str());
+        let position = columns.get("position").and_then(|v| v.as_i64());
+        let sent_position = campaign_id.and_then(|id| CURSORS.with(|cursors| cursors.borrow().get(id)?.position));
+        matches!((position, sent_position), (Some(position), Some(sent)) if position <= sent)
+    }
+
+    // Send the message of an inserted row through the provider's `messages`
+    // object. Rows at or before the campaign's cursor position were sent by
+    // an earlier statement and are skipped, so that rerunning an
+    // interrupted insert resumes where it stopped.
+    pub(crate) fn send(
+        &mut self,
+        client: &mut Client,
+        provider: &mut dyn Provider,
+        table: &TableConfig,
+        columns: &JsonValue,
+    ) -> FdwResult {
+        let campaign_id = columns
+            .get("campaign_id")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'campaign_id' is required to send a campaign")?;
+        let position = columns.get("position").and_then(|v| v.as_i64());
+        self.statement_campaign = Some(campaign_id.to_owned());
+        if self.is_sent(columns) {
+            self.statement_skipped += 1;
+            return Ok(());
+        }
+        let (sent, sent_position) = CURSORS.with(|cursors| {
+            let cursors = cursors.borrow();
+            let cursor = cursors.get(campaign_id);
+            (cursor.map_or(0, |c| c.sent), cursor.and_then(|c| c.position))
+        });
+
+        // Space the sends to the configured throughput
+        if table.sends_per_second > 0 && self.statement_sent > 0 {
+            client.state.clock.sleep_ms((1000 / table.sends_per_second) as u64);
+        }
+
+        let mut message = columns.clone();
+        if let Some(fields) = message.as_object_mut() {
+            fields.retain(|name, _| !CAMPAIGN_COLUMNS.contains(&name.as_str()));
+        }
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: message,
+        };
+        provider.write_row(client, "messages", &row).map_err(|err| {
+            format!(
+                "Campaign '{}' stopped after {} row(s) sent{}, rerun the insert to resume after it: {}",
+                campaign_id,
+                sent,
+                sent_position
+                    .map(|p| format!(" up to position {}", p))
+                    .unwrap_or_default(),
+                err
+            )
+        })?;
+
+        let now = client.state.clock.now_secs() * 1_000_000;
+        let total_sent = CURSORS.with(|cursors| {
+            let mut cursors = cursors.borrow_mut();
+            let cursor = cursors.entry(campaign_id.to_owned()).or_default();
+            cursor.sent += 1;
+            cursor.position = position.or(cursor.position);
+            cursor.last_sent_at = Some(now);
+            cursor.sent
+        });
+        self.statement_sent += 1;
+        if table.progress_every > 0 && self.statement_sent % table.progress_every == 0 {
+            notice!(
+                "modify",
+                "Campaign '{}': {} row(s) sent by this statement, {} in total",
+                campaign_id,
+                self.statement_sent,
+                total_sent
+            );
+        }
+        Ok(())
+    }
+
+    // Report the outcome of the insert statement
+    pub(crate) fn end(&self) {
+        let Some(campaign_id) = &self.statement_campaign else {
+            return;
+        };
+        if self.statement_skipped > 0 {
+            info!(
+                "modify",
+                "Campaign '{}': skipped {} row(s) already sent, sent {}",
+                campaign_id,
+                self.statement_skipped,
+                self.statement_sent
+            );
+        }
+    }
+
+    // The cursor of each campaign, as rows
+    pub(crate) fn rows(&self) -> Vec<JsonValue> {
+        CURSORS.with(|cursors| {
+            cursors
+                .borrow()
+                .iter()
+                .map(|(campaign_id, cursor)| {
+                    serde_json::json!({
+                        "campaign_id": campaign_id,
+                        "position": cursor.position,
+                        "sent": cursor.sent,
+                        "last_sent_at": cursor.last_sent_at,
+                    })
+                })
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::mock::FakeClock;
+    use crate::state::FdwState;
+
+    // Provider recording the sent messages, failing on the given recipient
+    #[derive(Debug, Default)]
+    struct Recorder {
+        sent: Vec<JsonValue>,
+        failing: Option<&'static str>,
+    }
+
+    impl Provider for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn objects(&self) -> &[ObjectCapabilities] {
+            &[]
+        }
+
+        fn connect(&self, _client: &mut Client) {}
+
+        fn fetch(&self, _client: &mut Client, _object: &str) -> Result<Vec<JsonValue>, FdwError> {
+            Ok(Vec::new())
+        }
+
+        fn write_row(&mut self, _client: &mut Client, _object: &str, row: &WriteRow) -> FdwResult {
+            if row.columns["to"].as_str() == self.failing {
+                return Err("HTTP 400".to_owned());
+            }
+            self.sent.push(row.columns.clone());
+            Ok(())
+        }
+    }
+
+    fn row(position: i64, to: &str) -> JsonValue {
+        serde_json::json!({"campaign_id": "spring", "position": position, "to": to, "template_name": "sale"})
+    }
+
+    #[test]
+    fn throttles_sends_and_resumes_after_the_cursor() {
+        let clock = FakeClock::at(1_700_000_000);
+        let mut client = Client::default();
+        client.state.clock = Box::new(clock.clone());
+        let table = TableConfig {
+            sends_per_second: 4,
+            ..Default::default()
+        };
+        let mut provider = Recorder {
+            failing: Some("+3"),
+            ..Default::default()
+        };
+        let mut campaigns = Campaigns::default();
+
+        campaigns.begin();
+        for (position, to) in [(1, "+1"), (2, "+2")] {
+            campaigns
+                .send(&mut client, &mut provider, &table, &row(position, to))
+                .unwrap();
+        }
+        let err = campaigns
+            .send(&mut client, &mut provider, &table, &row(3, "+3"))
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Campaign 'spring' stopped after 2 row(s) sent up to position 2, rerun the insert to resume after it: HTTP 400"
+        );
+        assert_eq!(
+            provider.sent[0],
+            serde_json::json!({"to": "+1", "template_name": "sale"})
+        );
+        assert_eq!(*clock.sleeps.borrow(), [250, 250]);
+
+        // The rerun, in a new FDW lifecycle, skips the rows already sent
+        FdwState::reset();
+        provider.failing = None;
+        let mut campaigns = Campaigns::default();
+        campaigns.begin();
+        for (position, to) in [(1, "+1"), (2, "+2"), (3, "+3")] {
+            campaigns
+                .send(&mut client, &mut provider, &table, &row(position, to))
+                .unwrap();
+        }
+        assert_eq!(provider.sent.len(), 3);
+        assert_eq!(
+            campaigns.rows(),
+            [serde_json::json!({
+                "campaign_id": "spring",
+                "position": 3,
+                "sent": 3,
+                "last_sent_at": 1_700_000_000_000_000i64,
+            })]
+        );
+    }
+}
@@ -0,0 +1,139 @@
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+
+use super::{product_feed, Object, ObjectCapabilities, Operation};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::Client;
+use crate::mapping::{self, parse_price};
+use crate::providers::Provider;
+
+// The diff reads the products of the provider, so it is served by any
+// provider scanning products
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("catalog_diff");
+
+// Differences between the live catalog and a feed file, one row per product
+// missing from the catalog, extra in the catalog, or priced differently
+pub(crate) struct CatalogDiff;
+
+impl Object for CatalogDiff {
+    fn name(&self) -> &'static str {
+        "catalog_diff"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::catalog_diff_cell(src_row, tgt_col_name)
+    }
+}
+
+// Fetch the catalog products from the provider and the feed of the
+// `feed_url` table option, and compare them
+pub(crate) fn fetch(
+    client: &mut Client,
+    provider: &dyn Provider,
+    feed_url: Option<&str>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let feed_url = feed_url.ok_or("Object 'catalog_diff' requires the 'feed_url' table option")?;
+    provider.capabilities("products")?.check(Operation::Scan)?;
+    let catalog = provider.fetch(client, "products")?;
+    let feed = product_feed::fetch(client, Some(feed_url))?;
+    Ok(diff(&catalog, &feed))
+}
+
+// Compare catalog products to feed items by retailer id, the feed `id`.
+// Products of the feed come first, in feed order, then the extra ones in
+// catalog order.
+fn diff(catalog: &[JsonValue], feed: &[JsonValue]) -> Vec<JsonValue> {
+    let text = |row: &JsonValue, field: &str| row.get(field).and_then(|v| v.as_str()).map(str::to_owned);
+    let by_retailer_id: HashMap<String, &JsonValue> = catalog
+        .iter()
+        .filter_map(|product| Some((text(product, "retailer_id")?, product)))
+        .collect();
+
+    let feed_ids: HashSet<String> = feed.iter().filter_map(|item| text(item, "id")).collect();
+
+    let mut rows = Vec::new();
+    for item in feed {
+        let Some(id) = text(item, "id") else { continue };
+        let feed_price = text(item, "price");
+        let status = match by_retailer_id.get(&id) {
+            None => "missing",
+            Some(product) => {
+                let catalog_price = text(product, "price");
+                if same_price(catalog_price.as_deref(), text(product, "currency"), feed_price.as_deref()) {
+                    continue;
+                }
+                "price_mismatch"
+            }
+        };
+        let product = by_retailer_id.get(&id);
+        rows.push(serde_json::json!({
+            "retailer_id": id,
+            "status": status,
+            "product_id": product.and_then(|p| p.get("id")),
+            "name": text(item, "title").or_else(|| product.and_then(|p| text(p, "name"))),
+            "catalog_price": product.and_then(|p| p.get("price")),
+            "feed_price": feed_price,
+        }));
+    }
+
+    for product in catalog {
+        let Some(id) = text(product, "retailer_id") else { continue };
+        if feed_ids.contains(&id) {
+            continue;
+        }
+        rows.push(serde_json::json!({
+            "retailer_id": id,
+            "status": "extra",
+            "product_id": product.get("id"),
+            "name": product.get("name"),
+            "catalog_price": product.get("price"),
+            "feed_price": null,
+        }));
+    }
+    rows
+}
+
+// Whether a catalog price, e.g. `€12.00` or `12.00` with its currency, is
+// the feed price, e.g. `12.00 EUR`. Currencies are only compared when both
+// sides name one.
+fn same_price(catalog_price: Option<&str>, catalog_currency: Option<String>, feed_price: Option<&str>) -> bool {
+    let (Some(catalog), Some(feed)) = (catalog_price.and_then(parse_price), feed_price.and_then(parse_price)) else {
+        return catalog_price.is_none() && feed_price.is_none();
+    };
+    let catalog_currency = catalog.1.or(catalog_currency);
+    catalog.0 == feed.0
+        && match (catalog_currency, feed.1) {
+            (Some(a), Some(b)) => a.eq_ignore_ascii_case(&b),
+            _ => true,
+        }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diffs_catalog_against_feed() {
+        let catalog = vec![
+            serde_json::json!({"id": "1", "retailer_id": "SKU-1", "name": "Mug", "price": "€12.00", "currency": "EUR"}),
+            serde_json::json!({"id": "2", "retailer_id": "SKU-2", "name": "Tee", "price": "€20.00", "currency": "EUR"}),
+            serde_json::json!({"id": "3", "retailer_id": "SKU-3", "name": "Cap", "price": "€9.00", "currency": "EUR"}),
+        ];
+        let feed = vec![
+            serde_json::json!({"id": "SKU-1", "title": "Mug", "price": "12.00 EUR"}),
+            serde_json::json!({"id": "SKU-2", "title": "Tee", "price": "25.00 EUR", "sale_price": "22.00 EUR"}),
+            serde_json::json!({"id": "SKU-4", "title": "Bag", "price": "30.00 EUR"}),
+        ];
+        let rows = diff(&catalog, &feed);
+        let summary: Vec<(&str, &str)> = rows
+            .iter()
+            .map(|row| (row["retailer_id"].as_str().unwrap(), row["status"].as_str().unwrap()))
+            .collect();
+        assert_eq!(
+            summary,
+            [("SKU-2", "price_mismatch"), ("SKU-4", "missing"), ("SKU-3", "extra")]
+        );
+        assert_eq!(rows[0]["feed_price"], "25.00 EUR");
+        assert_eq!(rows[0]["product_id"], "2");
+    }
+}
@@ -0,0 +1,79 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping::{self, OBJECT_COLUMNS};
+
+// The column metadata is part of the FDW, so any provider serves it
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("columns");
+
+// Every column of the objects of this build, with what the provider
+// supports on it
+pub(crate) struct Columns;
+
+impl Object for Columns {
+    fn name(&self) -> &'static str {
+        "columns"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::columns_cell(src_row, tgt_col_name)
+    }
+}
+
+// One row per column of each object, with the capabilities of the object
+// on the provider, None for objects it doesn't serve
+pub(crate) fn fetch<'a>(capabilities: impl Fn(&str) -> Option<&'a ObjectCapabilities>) -> Vec<JsonValue> {
+    OBJECT_COLUMNS
+        .iter()
+        .flat_map(|(object, columns)| {
+            let caps = capabilities(object);
+            columns.iter().map(move |(column, pg_type)| {
+                serde_json::json!({
+                    "object": object,
+                    "column_name": column,
+                    "pg_type": pg_type,
+                    "served": caps.is_some(),
+                    "operations": caps.map(|caps| caps.operations().join(", ")),
+                    "writable": caps.is_some_and(|caps| caps.is_writable()),
+                    "pushdown": caps.is_some_and(|caps| caps.quals.contains(column)),
+                    "rowid": caps.is_some_and(|caps| caps.rowid == Some(*column)),
+                })
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_columns_with_the_provider_capabilities() {
+        let health = ObjectCapabilities::read_only("health");
+        let rows = fetch(|object| (object == "health").then_some(&health));
+        let row = |object: &str, column: &str| {
+            rows.iter()
+                .find(|row| row["object"] == object && row["column_name"] == column)
+                .unwrap()
+        };
+
+        assert_eq!(
+            row("health", "checked_at"),
+            &serde_json::json!({
+                "object": "health",
+                "column_name": "checked_at",
+                "pg_type": "timestamptz",
+                "served": true,
+                "operations": "scan",
+                "writable": false,
+                "pushdown": false,
+                "rowid": false,
+            })
+        );
+        assert_eq!(row("contacts", "attributes")["pg_type"], "jsonb");
+        assert_eq!(row("contacts", "attributes")["served"], false);
+        assert!(row("contacts", "attributes")["operations"].is_null());
+        assert!(rows.iter().any(|row| row["object"] == "columns"));
+    }
+}
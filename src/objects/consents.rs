@@ -0,0 +1,128 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Contact attribute recording the consent set through the FDW, which takes
+// precedence over the opt-in flag of the provider
+pub(crate) const CONSENT_ATTRIBUTE: &str = "marketing_consent";
+
+// Consents recorded by an update, the contacts who never opted in being
+// `not_opted_in`
+const RECORDED_CONSENTS: &[&str] = &["opted_in", "opted_out"];
+
+// Consents are read from the contacts, and updated by phone number
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "consents",
+    scan: true,
+    insert: false,
+    update: true,
+    delete: false,
+    rowid: Some("phone"),
+    quals: &[],
+    pagination: Pagination::PageNumber,
+};
+
+// Marketing consent of each contact of the WhatsApp number
+pub(crate) struct Consents;
+
+impl Object for Consents {
+    fn name(&self) -> &'static str {
+        "consents"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::consent_cell(src_row, tgt_col_name)
+    }
+}
+
+// The consent row of a row of the contacts object
+pub(crate) fn consent_row(contact: &JsonValue) -> JsonValue {
+    let recorded = contact
+        .pointer(&format!("/attributes/{}", CONSENT_ATTRIBUTE))
+        .and_then(|v| v.as_str())
+        .filter(|consent| RECORDED_CONSENTS.contains(consent));
+    let opted_in = contact.get("opted_in").and_then(|v| v.as_bool());
+    let consent = match (recorded, opted_in) {
+        (Some(consent), _) => Some(consent),
+        (None, Some(true)) => Some("opted_in"),
+        (None, Some(false)) => Some("not_opted_in"),
+        (None, None) => None,
+    };
+
+    serde_json::json!({
+        "contact_id": contact.get("id"),
+        "phone": contact.get("phone").filter(|v| !v.is_null()).or(contact.get("wa_id")),
+        "name": contact.get("name"),
+        "consent": consent,
+        "opted_in": opted_in,
+        "source": if recorded.is_some() { "attribute" } else { "provider" },
+    })
+}
+
+// The consent set by an updated row, which is all that can be updated
+pub(crate) fn updated_consent(columns: &JsonValue) -> Result<&str, FdwError> {
+    if let Some(column) = columns
+        .as_object()
+        .into_iter()
+        .flatten()
+        .find(|(name, value)| !matches!(name.as_str(), "consent" | "phone") && !value.is_null())
+        .map(|(name, _)| name)
+    {
+        return Err(format!(
+            "Column '{}' of object 'consents' can't be updated, only 'consent' can",
+            column
+        ));
+    }
+    match columns.get("consent").and_then(|v| v.as_str()) {
+        Some(consent) if RECORDED_CONSENTS.contains(&consent) => Ok(consent),
+        Some(consent) => Err(format!(
+            "Invalid consent '{}', expected one of {}",
+            consent,
+            RECORDED_CONSENTS.join(", ")
+        )),
+        None => Err("Column 'consent' is required to update a consent".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recorded_consent_overrides_the_provider_opt_in() {
+        let contact = serde_json::json!({
+            "id": "61f0",
+            "phone": "4915123456789",
+            "opted_in": true,
+            "attributes": {"marketing_consent": "opted_out"},
+        });
+        assert_eq!(
+            consent_row(&contact),
+            serde_json::json!({
+                "contact_id": "61f0",
+                "phone": "4915123456789",
+                "name": null,
+                "consent": "opted_out",
+                "opted_in": true,
+                "source": "attribute",
+            })
+        );
+        let contact = serde_json::json!({"id": "61f1", "wa_id": "4915100000000", "opted_in": false});
+        assert_eq!(consent_row(&contact)["consent"], "not_opted_in");
+        assert_eq!(consent_row(&contact)["phone"], "4915100000000");
+    }
+
+    #[test]
+    fn only_the_consent_can_be_updated() {
+        assert_eq!(
+            updated_consent(&serde_json::json!({"consent": "opted_out"})),
+            Ok("opted_out")
+        );
+        assert!(updated_consent(&serde_json::json!({"consent": "maybe"}))
+            .unwrap_err()
+            .ends_with("expected one of opted_in, opted_out"));
+        assert!(updated_consent(&serde_json::json!({"consent": "opted_out", "name": "Jane"})).is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Contacts of a contact book are added, and their attributes updated, by
+// phone number
+pub(crate) const WRITABLE_CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "contacts",
+    scan: true,
+    insert: true,
+    update: true,
+    delete: false,
+    rowid: Some("phone"),
+    quals: &[],
+    pagination: Pagination::PageNumber,
+};
+
+// Contacts of the WhatsApp number, with their custom attributes
+pub(crate) struct Contacts;
+
+impl Object for Contacts {
+    fn name(&self) -> &'static str {
+        "contacts"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::contact_cell(src_row, tgt_col_name)
+    }
+}
+
+// Display name and custom attributes of a written contact row. The other
+// columns are read-only and skipped, as an update passes them unchanged.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct ContactChange {
+    pub(crate) name: Option<String>,
+    // Attributes as name and text value pairs, the shape of contact book
+    // custom parameters
+    pub(crate) attributes: Vec<(String, String)>,
+}
+
+impl ContactChange {
+    pub(crate) fn of(columns: &JsonValue) -> Result<Self, FdwError> {
+        let name = columns.get("name").and_then(|v| v.as_str()).map(str::to_owned);
+        let attributes = match columns.get("attributes") {
+            None | Some(JsonValue::Null) => Vec::new(),
+            Some(JsonValue::Object(attributes)) => attributes
+                .iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(name, value)| {
+                    let value = match value {
+                        JsonValue::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (name.clone(), value)
+                })
+                .collect(),
+            Some(other) => {
+                return Err(format!(
+                    "Invalid attributes {}, expected a JSON object of attribute values by name",
+                    other
+                ))
+            }
+        };
+        Ok(Self { name, attributes })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_name_and_attributes_of_written_rows() {
+        let change = ContactChange::of(&serde_json::json!({
+            "name": "Jane Doe",
+            "status": "VALID",
+            "attributes": {"city": "Berlin", "orders": 3, "vip": true, "note": null},
+        }))
+        .unwrap();
+        assert_eq!(change.name.as_deref(), Some("Jane Doe"));
+        assert_eq!(
+            change.attributes,
+            [
+                ("city".to_owned(), "Berlin".to_owned()),
+                ("orders".to_owned(), "3".to_owned()),
+                ("vip".to_owned(), "true".to_owned()),
+            ]
+        );
+        assert!(ContactChange::of(&serde_json::json!({"attributes": ["vip"]})).is_err());
+    }
+}
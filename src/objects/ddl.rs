@@ -0,0 +1,239 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping::{self, OBJECT_COLUMNS};
+use crate::providers::Line;
+
+// The statements are generated by the FDW, so any provider serves them
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("ddl");
+
+// Server named in the statements by default, as in the README examples
+const DEFAULT_SERVER_NAME: &str = "whatsapp_server";
+
+// Table options an object needs, with a placeholder value to replace
+const REQUIRED_OPTIONS: &[(&str, &str, &str)] = &[
+    ("product_feed", "feed_url", "https://example.com/feed.csv"),
+    ("catalog_diff", "feed_url", "https://example.com/feed.csv"),
+    (
+        "webhook_bridge",
+        "webhook_url",
+        "https://<project>.supabase.co/functions/v1/whatsapp-webhook",
+    ),
+    ("discover", "sample_objects", "messages,contacts"),
+];
+
+// A `create foreign table` statement for each object the provider serves
+pub(crate) struct Ddl;
+
+impl Object for Ddl {
+    fn name(&self) -> &'static str {
+        "ddl"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::ddl_cell(src_row, tgt_col_name)
+    }
+}
+
+// One row per object served by the provider, with the statement creating
+// its foreign table on the server `server_name`. With `lines`, the tables
+// are created in a schema per number instead, reading the source of the
+// number on a server with several sources.
+pub(crate) fn fetch<'a>(
+    capabilities: impl Fn(&str) -> Option<&'a ObjectCapabilities>,
+    server_name: Option<&str>,
+    lines: Option<&[Line]>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let server_name = server_name.unwrap_or(DEFAULT_SERVER_NAME);
+    let served: Vec<_> = OBJECT_COLUMNS
+        .iter()
+        .filter_map(|(object, columns)| Some((capabilities(object)?, *columns)))
+        .collect();
+
+    let Some(lines) = lines else {
+        return Ok(served
+            .iter()
+            .map(|(caps, columns)| {
+                let table_name = format!("wa_{}", caps.name);
+                let create = statement(&table_name, server_name, caps, columns, None);
+                row(caps.name, &table_name, None, None, create)
+            })
+            .collect());
+    };
+    if lines.is_empty() {
+        return Err(
+            "Table option 'per_number' requires a provider sending from a number, e.g. set with 'from_number' or 'phone_number_id'"
+                .to_owned(),
+        );
+    }
+
+    let mut rows = Vec::new();
+    for line in lines {
+        let schema_name = format!("wa_{}", schema_suffix(&line.number));
+        for (caps, columns) in &served {
+            let table_name = format!("{}.{}", schema_name, caps.name);
+            let create = format!(
+                "create schema if not exists {};\n{}",
+                schema_name,
+                statement(&table_name, server_name, caps, columns, line.source.as_deref())
+            );
+            rows.push(row(
+                caps.name,
+                &table_name,
+                Some(&schema_name),
+                line.source.as_deref(),
+                create,
+            ));
+        }
+    }
+    Ok(rows)
+}
+
+fn row(
+    object: &str,
+    table_name: &str,
+    schema_name: Option<&str>,
+    source: Option<&str>,
+    statement: String,
+) -> JsonValue {
+    serde_json::json!({
+        "object": object,
+        "table_name": table_name,
+        "schema_name": schema_name,
+        "source": source,
+        "statement": statement,
+    })
+}
+
+// Name of the schema of a number, its letters and digits without the
+// punctuation of phone numbers, e.g. `4915123456789` for `+49 151
+// 2345-6789`, other characters becoming `_`
+fn schema_suffix(number: &str) -> String {
+    let mut suffix = String::new();
+    for c in number.chars().filter(|c| !" +-().".contains(*c)) {
+        if c.is_ascii_alphanumeric() {
+            suffix.push(c.to_ascii_lowercase());
+        } else if !suffix.is_empty() && !suffix.ends_with('_') {
+            suffix.push('_');
+        }
+    }
+    suffix.trim_end_matches('_').to_owned()
+}
+
+// Statement creating the foreign table of an object, with the columns of
+// the object and the options it needs, reading `source` if set
+fn statement(
+    table_name: &str,
+    server_name: &str,
+    caps: &ObjectCapabilities,
+    columns: &[(&str, &str)],
+    source: Option<&str>,
+) -> String {
+    let columns: Vec<String> = columns
+        .iter()
+        .map(|(column, pg_type)| format!("  {} {}", column, pg_type))
+        .collect();
+
+    let mut options = vec![format!("object '{}'", caps.name)];
+    if let (Some(rowid), true) = (caps.rowid, caps.update || caps.delete) {
+        options.push(format!("rowid_column '{}'", rowid));
+    }
+    options.extend(
+        REQUIRED_OPTIONS
+            .iter()
+            .filter(|(object, _, _)| *object == caps.name)
+            .map(|(_, option, placeholder)| format!("{} '{}'", option, placeholder)),
+    );
+    if let Some(source) = source {
+        options.push(format!("sources '{}'", source));
+    }
+
+    format!(
+        "create foreign table {} (\n{}\n)\n  server {}\n  options ({});",
+        table_name,
+        columns.join(",\n"),
+        server_name,
+        options.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn creates_tables_of_the_served_objects() {
+        let caps = ObjectCapabilities {
+            update: true,
+            rowid: Some("id"),
+            ..ObjectCapabilities::read_only("contacts")
+        };
+        assert_eq!(
+            statement("wa_contacts", "wa", &caps, &[("id", "text"), ("attributes", "jsonb")], None),
+            "create foreign table wa_contacts (\n  id text,\n  attributes jsonb\n)\n  server wa\n  options (object 'contacts', rowid_column 'id');"
+        );
+
+        let webhook_bridge = ObjectCapabilities::read_only("webhook_bridge");
+        let rows = fetch(
+            |object| (object == "webhook_bridge").then_some(&webhook_bridge),
+            None,
+            None,
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0]["table_name"], "wa_webhook_bridge");
+        let statement = rows[0]["statement"].as_str().unwrap();
+        assert!(
+            statement.starts_with("create foreign table wa_webhook_bridge (\n"),
+            "{}",
+            statement
+        );
+        assert!(
+            statement.ends_with(
+                "  server whatsapp_server\n  options (object 'webhook_bridge', webhook_url 'https://<project>.supabase.co/functions/v1/whatsapp-webhook');"
+            ),
+            "{}",
+            statement
+        );
+    }
+
+    #[test]
+    fn creates_a_schema_per_number() {
+        let health = ObjectCapabilities::read_only("health");
+        let lines = [
+            Line {
+                source: Some("berlin".to_owned()),
+                number: "+49 151 2345-6789".to_owned(),
+            },
+            Line {
+                source: Some("london".to_owned()),
+                number: "whatsapp:+447700900123".to_owned(),
+            },
+        ];
+        let rows = fetch(
+            |object| (object == "health").then_some(&health),
+            Some("wa"),
+            Some(&lines),
+        )
+        .unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["table_name"], "wa_4915123456789.health");
+        assert_eq!(rows[1]["schema_name"], "wa_whatsapp_447700900123");
+        let statement = rows[1]["statement"].as_str().unwrap();
+        assert!(
+            statement.starts_with(
+                "create schema if not exists wa_whatsapp_447700900123;\ncreate foreign table wa_whatsapp_447700900123.health (\n"
+            ),
+            "{}",
+            statement
+        );
+        assert!(
+            statement.ends_with("  server wa\n  options (object 'health', sources 'london');"),
+            "{}",
+            statement
+        );
+
+        assert!(fetch(|_| Some(&health), None, Some(&[])).is_err());
+    }
+}
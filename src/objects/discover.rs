@@ -0,0 +1,251 @@
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+
+use super::{Object, ObjectCapabilities, Operation};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::Client;
+use crate::mapping;
+use crate::providers::Provider;
+use crate::timestamp;
+
+// Discovery samples the objects of the provider, which serves them all
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("discover");
+
+// Rows of each object whose fields are inspected
+const SAMPLE_ROWS: usize = 100;
+
+// Columns of the objects named by the `sample_objects` table option,
+// inferred from the rows the provider returns for them
+pub(crate) struct Discover;
+
+impl Object for Discover {
+    fn name(&self) -> &'static str {
+        "discover"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::discover_cell(src_row, tgt_col_name)
+    }
+}
+
+// Fetch a sample of each object and describe the fields found in it
+pub(crate) fn fetch(
+    client: &mut Client,
+    provider: &dyn Provider,
+    sample_objects: Option<&[String]>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let sample_objects = sample_objects
+        .filter(|objects| !objects.is_empty())
+        .ok_or("Table option 'sample_objects' is required for object 'discover', e.g. 'messages,contacts'")?;
+
+    let mut rows = Vec::new();
+    for object in sample_objects {
+        provider.capabilities(object)?.check(Operation::Scan)?;
+        let sample = provider.fetch(client, object)?;
+        rows.extend(infer(object, &sample[..sample.len().min(SAMPLE_ROWS)]));
+    }
+    Ok(rows)
+}
+
+// Type of a JSON value, before merging the types seen in all rows
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    Bool,
+    Integer,
+    Float,
+    Timestamp,
+    Text,
+    Json,
+}
+
+impl Kind {
+    fn of(value: &JsonValue) -> Option<Self> {
+        match value {
+            JsonValue::Null => None,
+            JsonValue::Bool(_) => Some(Kind::Bool),
+            JsonValue::Number(n) if n.is_i64() || n.is_u64() => Some(Kind::Integer),
+            JsonValue::Number(_) => Some(Kind::Float),
+            JsonValue::String(s) if timestamp::parse_rfc3339(s).is_some() => Some(Kind::Timestamp),
+            JsonValue::String(_) => Some(Kind::Text),
+            JsonValue::Array(_) | JsonValue::Object(_) => Some(Kind::Json),
+        }
+    }
+
+    // Type holding the values of both kinds
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Kind::Integer, Kind::Float) | (Kind::Float, Kind::Integer) => Kind::Float,
+            (Kind::Timestamp, Kind::Text) | (Kind::Text, Kind::Timestamp) => Kind::Text,
+            _ => Kind::Json,
+        }
+    }
+
+    fn pg_type(self) -> &'static str {
+        match self {
+            Kind::Bool => "boolean",
+            Kind::Integer => "bigint",
+            Kind::Float => "double precision",
+            Kind::Timestamp => "timestamptz",
+            Kind::Text => "text",
+            Kind::Json => "jsonb",
+        }
+    }
+}
+
+// Postgres type of the cell a column is mapped to
+fn cell_pg_type(cell: &Cell) -> &'static str {
+    match cell {
+        Cell::Bool(_) => "boolean",
+        Cell::I8(_) | Cell::I16(_) => "smallint",
+        Cell::I32(_) => "integer",
+        Cell::I64(_) => "bigint",
+        Cell::F32(_) => "real",
+        Cell::F64(_) => "double precision",
+        Cell::Numeric(_) => "numeric",
+        Cell::Date(_) => "date",
+        Cell::Timestamp(_) => "timestamp",
+        Cell::Timestamptz(_) => "timestamptz",
+        Cell::Json(_) => "jsonb",
+        _ => "text",
+    }
+}
+
+// A field found in the sample, by JSON pointer into the rows
+#[derive(Debug)]
+struct Field {
+    kind: Option<Kind>,
+    // Type of the column the FDW maps the field to, for top-level fields
+    mapped: Option<&'static str>,
+    // Whether the object has a column of the field
+    known: bool,
+}
+
+// Describe the fields of the sampled rows of an object: every top-level
+// field, and the scalar and array fields nested in objects, the latter
+// being read from a `jsonb` column with the path. Fields the FDW maps to a
+// column of the object take the type of that column.
+fn infer(object: &str, sample: &[JsonValue]) -> Vec<JsonValue> {
+    let mapper = super::lookup(object).ok();
+    let mut fields: BTreeMap<String, Field> = BTreeMap::new();
+    for row in sample {
+        let Some(row) = row.as_object() else { continue };
+        for (name, value) in row {
+            // Unsupported columns are an error, absent values None
+            let cell = mapper.and_then(|mapper| mapper.map_cell(&serde_json::json!({ name: value }), name).ok());
+            let field = fields.entry(pointer(&[name])).or_insert(Field {
+                kind: None,
+                mapped: None,
+                known: cell.is_some(),
+            });
+            field.mapped = field.mapped.or(cell.flatten().as_ref().map(cell_pg_type));
+            add_kind(field, value);
+            if let Some(nested) = value.as_object() {
+                collect_nested(&mut fields, &mut vec![name.as_str()], nested);
+            }
+        }
+    }
+
+    fields
+        .into_iter()
+        .filter_map(|(path, field)| {
+            let pg_type = field.mapped.or(field.kind.map(Kind::pg_type))?;
+            let segments: Vec<String> = path[1..].split('/').map(unescape).collect();
+            Some(serde_json::json!({
+                "object": object,
+                "column_name": segments.join("_"),
+                "suggested_pg_type": pg_type,
+                "json_path": path,
+                "known": field.known,
+            }))
+        })
+        .collect()
+}
+
+// Collect the fields nested in an object, descending into objects
+fn collect_nested<'a>(
+    fields: &mut BTreeMap<String, Field>,
+    parents: &mut Vec<&'a str>,
+    object: &'a serde_json::Map<String, JsonValue>,
+) {
+    for (name, value) in object {
+        parents.push(name);
+        match value.as_object() {
+            Some(nested) => collect_nested(fields, parents, nested),
+            None => {
+                let field = fields.entry(pointer(parents)).or_insert(Field {
+                    kind: None,
+                    mapped: None,
+                    known: false,
+                });
+                add_kind(field, value);
+            }
+        }
+        parents.pop();
+    }
+}
+
+fn add_kind(field: &mut Field, value: &JsonValue) {
+    if let Some(kind) = Kind::of(value) {
+        field.kind = Some(field.kind.map_or(kind, |seen| seen.merge(kind)));
+    }
+}
+
+// JSON pointer of a field, e.g. `/attributes/conversation_id`
+fn pointer<S: AsRef<str>>(segments: &[S]) -> String {
+    segments
+        .iter()
+        .map(|segment| format!("/{}", segment.as_ref().replace('~', "~0").replace('/', "~1")))
+        .collect()
+}
+
+fn unescape(segment: &str) -> String {
+    segment.replace("~1", "/").replace("~0", "~")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn infers_types_and_paths_of_the_sampled_fields() {
+        let sample = [
+            serde_json::json!({
+                "id": "ct-1",
+                "name": "Jane",
+                "created_at": 1_700_000_000_000_000i64,
+                "attributes": {"conversation_id": "conv-1", "score": 3, "last_seen": "2024-01-02T03:04:05Z"},
+                "vip": true,
+            }),
+            serde_json::json!({
+                "id": "ct-2",
+                "name": null,
+                "attributes": {"score": 4.5, "last_seen": "yesterday", "tags": ["a"]},
+                "vip": "yes",
+            }),
+        ];
+        let rows = infer("contacts", &sample);
+        let row = |path: &str| rows.iter().find(|row| row["json_path"] == path).unwrap();
+
+        assert_eq!(
+            row("/attributes/conversation_id"),
+            &serde_json::json!({
+                "object": "contacts",
+                "column_name": "attributes_conversation_id",
+                "suggested_pg_type": "text",
+                "json_path": "/attributes/conversation_id",
+                "known": false,
+            })
+        );
+        assert_eq!(row("/attributes/score")["suggested_pg_type"], "double precision");
+        assert_eq!(row("/attributes/last_seen")["suggested_pg_type"], "text");
+        assert_eq!(row("/attributes/tags")["suggested_pg_type"], "jsonb");
+        assert_eq!(row("/vip")["suggested_pg_type"], "jsonb");
+
+        // Known columns take the type the FDW maps them to
+        assert_eq!(row("/created_at")["suggested_pg_type"], "timestamptz");
+        assert_eq!(row("/created_at")["known"], true);
+        assert_eq!(row("/attributes")["suggested_pg_type"], "jsonb");
+        assert_eq!(row("/name")["suggested_pg_type"], "text");
+    }
+}
@@ -0,0 +1,28 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::clock::Clock;
+use crate::mapping;
+use crate::stats;
+
+// The counters are kept by the FDW itself, so any provider serves them
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("fdw_stats");
+
+// Cumulative counters of the FDW instance, one row
+pub(crate) struct FdwStats;
+
+impl Object for FdwStats {
+    fn name(&self) -> &'static str {
+        "fdw_stats"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::fdw_stats_cell(src_row, tgt_col_name)
+    }
+}
+
+// The row of the current counters, without any request to the provider
+pub(crate) fn fetch(clock: &dyn Clock) -> Vec<JsonValue> {
+    vec![stats::snapshot().to_row(clock.now_secs())]
+}
@@ -0,0 +1,96 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Where the response to a flow is found in an incoming message: the Cloud
+// API `nfm_reply`, as relayed by gateways, at the top level or in the body
+const RESPONSE_POINTERS: &[&str] = &["/interactive/nfm_reply", "/body/interactive/nfm_reply"];
+
+// Pointers to the id of the flow message replied to
+const CONTEXT_POINTERS: &[&str] = &["/context/id", "/replyTo/id"];
+
+// Answers submitted by contacts through WhatsApp Flows, one row per
+// completed flow
+pub(crate) struct FlowResponses;
+
+impl Object for FlowResponses {
+    fn name(&self) -> &'static str {
+        "flow_responses"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::flow_response_cell(src_row, tgt_col_name)
+    }
+}
+
+// The flow response row of an incoming message, sent by `contact` at
+// `submitted_at` in microseconds since Unix epoch, or None if the message
+// doesn't complete a flow. The answers are the fields of the response
+// besides the `flow_token`, and WhatsApp doesn't send the flow id, which
+// is read from the answers or the token, e.g. as set by `flow_token
+// '<flow id>'` when the flow was sent.
+pub(crate) fn response_row(
+    message_id: &JsonValue,
+    message: &JsonValue,
+    contact: Option<&str>,
+    submitted_at: Option<i64>,
+) -> Option<JsonValue> {
+    let reply = RESPONSE_POINTERS.iter().find_map(|pointer| message.pointer(pointer))?;
+    // The response is JSON encoded as a string by the Cloud API
+    let mut answers = match reply.get("response_json")? {
+        JsonValue::String(s) => serde_json::from_str(s).ok()?,
+        other => other.clone(),
+    };
+    let flow_token = answers.as_object_mut().and_then(|answers| answers.remove("flow_token"));
+    let flow_id = answers.get("flow_id").cloned().or_else(|| flow_token.clone());
+
+    Some(serde_json::json!({
+        "message_id": message_id,
+        "replied_message_id": CONTEXT_POINTERS
+            .iter()
+            .find_map(|pointer| message.pointer(pointer).and_then(|v| v.as_str())),
+        "flow_id": flow_id,
+        "flow_token": flow_token,
+        "contact": contact,
+        "answers": answers,
+        "submitted_at": submitted_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_answers_of_a_completed_flow() {
+        let message = serde_json::json!({
+            "context": {"id": "wamid.OUT"},
+            "interactive": {
+                "type": "nfm_reply",
+                "nfm_reply": {
+                    "name": "flow",
+                    "body": "Sent",
+                    "response_json": r#"{"flow_token": "lead_form", "email": "jane@example.com", "budget": 500}"#,
+                },
+            },
+        });
+        let row = response_row(&"wamid.IN".into(), &message, Some("+4915123456789"), Some(1)).unwrap();
+        assert_eq!(
+            row,
+            serde_json::json!({
+                "message_id": "wamid.IN",
+                "replied_message_id": "wamid.OUT",
+                "flow_id": "lead_form",
+                "flow_token": "lead_form",
+                "contact": "+4915123456789",
+                "answers": {"email": "jane@example.com", "budget": 500},
+                "submitted_at": 1,
+            })
+        );
+
+        let text = serde_json::json!({"body": {"type": "text", "text": {"text": "Hi"}}});
+        assert!(response_row(&"msg-1".into(), &text, None, None).is_none());
+    }
+}
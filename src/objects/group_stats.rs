@@ -0,0 +1,92 @@
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+use crate::timestamp::Period;
+
+// Columns of the period of the statistics, whose quals the providers
+// apply while reading the group history
+pub(crate) const PERIOD_QUALS: &[&str] = &["since", "until"];
+
+// Message statistics of each WhatsApp group of the number over a period,
+// set with quals on `since` and `until`, the whole history by default
+pub(crate) struct GroupStats;
+
+impl Object for GroupStats {
+    fn name(&self) -> &'static str {
+        "group_stats"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::group_stats_cell(src_row, tgt_col_name)
+    }
+}
+
+// A message of a group history, sent by `sender` at `sent_at` in
+// microseconds since Unix epoch
+#[derive(Debug)]
+pub(crate) struct GroupMessage {
+    pub(crate) sender: Option<String>,
+    pub(crate) sent_at: Option<i64>,
+}
+
+// The statistics row of a group from its messages, those outside the
+// period being left out
+pub(crate) fn stats_row(
+    group_id: &str,
+    group_name: Option<&str>,
+    messages: &[GroupMessage],
+    period: Period,
+) -> JsonValue {
+    let in_period: Vec<&GroupMessage> = messages
+        .iter()
+        .filter(|message| message.sent_at.is_some_and(|at| period.contains(at)))
+        .collect();
+    let members: HashSet<&str> = in_period
+        .iter()
+        .filter_map(|message| message.sender.as_deref())
+        .collect();
+
+    serde_json::json!({
+        "group_id": group_id,
+        "group_name": group_name,
+        "since": period.since,
+        "until": period.until,
+        "message_count": in_period.len(),
+        "active_members": members.len(),
+        "last_activity_at": in_period.iter().filter_map(|message| message.sent_at).max(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_messages_and_senders_in_the_period() {
+        let message = |sender: &str, sent_at: i64| GroupMessage {
+            sender: Some(sender.to_owned()),
+            sent_at: Some(sent_at),
+        };
+        let messages = [message("+1", 5), message("+2", 4), message("+1", 3), message("+3", 1)];
+        let period = Period {
+            since: Some(2),
+            until: Some(4),
+        };
+        assert_eq!(
+            stats_row("G1", Some("Team"), &messages, period),
+            serde_json::json!({
+                "group_id": "G1",
+                "group_name": "Team",
+                "since": 2,
+                "until": 4,
+                "message_count": 2,
+                "active_members": 2,
+                "last_activity_at": 4,
+            })
+        );
+        assert_eq!(stats_row("G2", None, &[], Period::default())["message_count"], 0);
+    }
+}
@@ -0,0 +1,18 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// WhatsApp groups the number takes part in
+pub(crate) struct Groups;
+
+impl Object for Groups {
+    fn name(&self) -> &'static str {
+        "groups"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::group_cell(src_row, tgt_col_name)
+    }
+}
@@ -0,0 +1,64 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::{header_value, Client};
+use crate::mapping;
+
+// Health of the API connection, as a single row for monitoring
+pub(crate) struct Health;
+
+impl Object for Health {
+    fn name(&self) -> &'static str {
+        "health"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::health_cell(src_row, tgt_col_name)
+    }
+}
+
+// Probe the provider API with a GET of `url` and build a single health
+// row, reading the session state of the connected number from the probe
+// response with `session_status`. Failures are recorded in the row
+// instead of being returned, so that monitoring queries keep working
+// while the API is down.
+pub(crate) fn probe(
+    client: &mut Client,
+    url: String,
+    session_status: impl FnOnce(&JsonValue) -> Option<JsonValue>,
+) -> Vec<JsonValue> {
+    let mut health = serde_json::json!({
+        "api_reachable": false,
+        "auth_valid": false,
+        "session_status": null,
+        "remaining_credits": null,
+        "rate_limit_remaining": null,
+        "rate_limit_reset_at": null,
+        "checked_at": client.state.clock.now_secs() * 1_000_000,
+        "fdw_version": crate::FDW_VERSION,
+        "git_hash": crate::GIT_HASH,
+    });
+
+    if let Ok(resp) = client.get(url) {
+        health["api_reachable"] = true.into();
+        health["auth_valid"] = (resp.status_code != 401 && resp.status_code != 403).into();
+
+        let resp_json: JsonValue = serde_json::from_str(&resp.body).unwrap_or_default();
+        if let Some(status) = session_status(&resp_json) {
+            health["session_status"] = status;
+        }
+
+        // Remaining credits, when the account reports them
+        if let Some(credits) = header_value(&resp.headers, "x-credits-remaining")
+            .and_then(|v| v.trim().parse::<i64>().ok())
+        {
+            health["remaining_credits"] = credits.into();
+        }
+
+        health["rate_limit_remaining"] = client.state.rate_limit.remaining.into();
+        health["rate_limit_reset_at"] = client.state.rate_limit.reset_at.map(|t| t * 1_000_000).into();
+    }
+
+    vec![health]
+}
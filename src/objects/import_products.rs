@@ -0,0 +1,76 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Inserting a row imports a product into the catalog, nothing being
+// scanned
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "import_products",
+    scan: false,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: &[],
+    pagination: Pagination::None,
+};
+
+// Bulk loads of catalog products, each row carrying a whole product
+// document
+pub(crate) struct ImportProducts;
+
+impl Object for ImportProducts {
+    fn name(&self) -> &'static str {
+        "import_products"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::import_product_cell(src_row, tgt_col_name)
+    }
+}
+
+// The product document of an inserted row, a JSON object with the columns
+// of the products object. The `retailer_id` column, if set, takes
+// precedence over the one of the document, e.g. when loading documents
+// keyed by SKU.
+pub(crate) fn document(columns: &JsonValue) -> Result<JsonValue, FdwError> {
+    let mut product = match columns.get("product") {
+        Some(JsonValue::Object(product)) => product.clone(),
+        Some(JsonValue::Null) | None => return Err("Column 'product' is required to import a product".to_owned()),
+        Some(other) => return Err(format!("Invalid product document {}, expected a JSON object", other)),
+    };
+    if let Some(retailer_id) = columns.get("retailer_id").filter(|v| !v.is_null()) {
+        product.insert("retailer_id".to_owned(), retailer_id.clone());
+    }
+    if product.get("retailer_id").and_then(|v| v.as_str()).is_none() {
+        return Err(
+            "Column 'retailer_id' or a 'retailer_id' in the product document is required to import a product"
+                .to_owned(),
+        );
+    }
+    Ok(JsonValue::Object(product))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_the_product_document() {
+        let columns = serde_json::json!({"retailer_id": "SKU-2", "product": {"retailer_id": "SKU-1", "name": "Mug"}});
+        assert_eq!(
+            document(&columns).unwrap(),
+            serde_json::json!({"retailer_id": "SKU-2", "name": "Mug"})
+        );
+        let columns = serde_json::json!({"retailer_id": null, "product": {"retailer_id": "SKU-1"}});
+        assert_eq!(document(&columns).unwrap()["retailer_id"], "SKU-1");
+
+        assert!(document(&serde_json::json!({"product": {"name": "Mug"}})).is_err());
+        assert_eq!(
+            document(&serde_json::json!({"product": [1]})).unwrap_err(),
+            "Invalid product document [1], expected a JSON object"
+        );
+    }
+}
@@ -0,0 +1,22 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Column of the time range quals, the providers reading the message
+// history back to the start of the range only
+pub(crate) const TIME_QUALS: &[&str] = &["sent_at"];
+
+// Messages sent from or to the WhatsApp number
+pub(crate) struct Messages;
+
+impl Object for Messages {
+    fn name(&self) -> &'static str {
+        "messages"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::message_cell(src_row, tgt_col_name)
+    }
+}
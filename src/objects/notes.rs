@@ -0,0 +1,60 @@
+use serde_json::Value as JsonValue;
+
+use super::{unread_counts, Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+use crate::timestamp;
+
+// Notes are read per chat, all chats being read without a `chat_id` qual,
+// and added to a chat
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "notes",
+    scan: true,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: unread_counts::CHAT_QUALS,
+    pagination: Pagination::None,
+};
+
+// Internal notes agents attach to chats, not seen by the contact
+pub(crate) struct Notes;
+
+impl Object for Notes {
+    fn name(&self) -> &'static str {
+        "notes"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::note_cell(src_row, tgt_col_name)
+    }
+}
+
+// Convert a note of a chat to the shape of the notes object
+pub(crate) fn note_row(chat_id: &str, note: &JsonValue) -> JsonValue {
+    serde_json::json!({
+        "id": note.get("id").or(note.get("uuid")),
+        "chat_id": chat_id,
+        "text": note.get("text").or(note.get("note")),
+        "author": note.pointer("/author/name").or(note.get("author")).or(note.get("created_by")),
+        "created_at": note
+            .get("created_at")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    })
+}
+
+// The chat and text of an inserted note
+pub(crate) fn inserted_note(columns: &JsonValue) -> Result<(&str, &str), FdwError> {
+    let chat_id = columns
+        .get("chat_id")
+        .and_then(|v| v.as_str())
+        .ok_or("Column 'chat_id' is required to add a note")?;
+    let text = columns
+        .get("text")
+        .and_then(|v| v.as_str())
+        .filter(|text| !text.trim().is_empty())
+        .ok_or("Column 'text' is required to add a note")?;
+    Ok((chat_id, text))
+}
@@ -0,0 +1,18 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Phone numbers of the WhatsApp Business Account
+pub(crate) struct PhoneNumbers;
+
+impl Object for PhoneNumbers {
+    fn name(&self) -> &'static str {
+        "phone_numbers"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::phone_number_cell(src_row, tgt_col_name)
+    }
+}
@@ -0,0 +1,224 @@
+use serde_json::Value as JsonValue;
+use std::collections::{HashMap, HashSet};
+
+use super::{Object, ObjectCapabilities, Operation};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::Client;
+use crate::mapping;
+use crate::providers::Provider;
+
+// The duplicates are found among the products of the provider, so they are
+// served by any provider scanning products
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("product_duplicates");
+
+// Name similarity, in percent, from which two products are likely duplicates
+const MIN_SIMILARITY: i64 = 80;
+
+// Products sharing a name word are compared, unless the word is so common
+// in the catalog that it says nothing, e.g. `shirt` in a clothing shop
+const MAX_WORD_PRODUCTS: usize = 50;
+
+// Groups of catalog products that are likely duplicates of each other
+pub(crate) struct ProductDuplicates;
+
+impl Object for ProductDuplicates {
+    fn name(&self) -> &'static str {
+        "product_duplicates"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::product_duplicate_cell(src_row, tgt_col_name)
+    }
+}
+
+// Fetch the catalog products from the provider and group the likely
+// duplicates
+pub(crate) fn fetch(client: &mut Client, provider: &dyn Provider) -> Result<Vec<JsonValue>, FdwError> {
+    provider.capabilities("products")?.check(Operation::Scan)?;
+    let catalog = provider.fetch(client, "products")?;
+    Ok(duplicates(&catalog))
+}
+
+// Retailer id compared case-insensitively and without separators, e.g.
+// `SKU-001` and `sku_001` being the same
+fn retailer_key(product: &JsonValue) -> Option<String> {
+    let id = product.get("retailer_id")?.as_str()?;
+    let key: String = id
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect();
+    Some(key).filter(|key| !key.is_empty())
+}
+
+// Lowercase words of a product name, punctuation separating words
+fn name_words(product: &JsonValue) -> Vec<String> {
+    let name = product.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+    name.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// Similarity of two normalized names in percent, the Dice coefficient of
+// their character bigrams, so that word order, typos and plurals only lower
+// it slightly
+fn similarity(a: &str, b: &str) -> i64 {
+    let bigrams = |s: &str| {
+        let chars: Vec<char> = s.chars().collect();
+        let mut bigrams: Vec<(char, char)> = chars.windows(2).map(|w| (w[0], w[1])).collect();
+        bigrams.sort_unstable();
+        bigrams
+    };
+    let (a, b) = (bigrams(a), bigrams(b));
+    if a.is_empty() || b.is_empty() {
+        return if a == b { 100 } else { 0 };
+    }
+    // Count the shared bigrams of the sorted lists
+    let (mut i, mut j, mut shared) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    ((200 * shared) as f64 / (a.len() + b.len()) as f64).round() as i64
+}
+
+// Root of a product's group, the first product of the group in catalog
+// order
+fn root(groups: &mut [usize], mut i: usize) -> usize {
+    while groups[i] != i {
+        groups[i] = groups[groups[i]];
+        i = groups[i];
+    }
+    i
+}
+
+fn join(groups: &mut [usize], a: usize, b: usize) {
+    let (a, b) = (root(groups, a), root(groups, b));
+    groups[a.max(b)] = a.min(b);
+}
+
+// Group the products sharing a retailer id, or whose names are similar,
+// and return the groups of two or more products. Each group is led by its
+// first product in catalog order, the others being scored against it.
+fn duplicates(catalog: &[JsonValue]) -> Vec<JsonValue> {
+    let keys: Vec<Option<String>> = catalog.iter().map(retailer_key).collect();
+    let words: Vec<Vec<String>> = catalog.iter().map(name_words).collect();
+    let names: Vec<String> = words.iter().map(|words| words.join(" ")).collect();
+    let mut groups: Vec<usize> = (0..catalog.len()).collect();
+
+    let mut by_key: HashMap<&str, usize> = HashMap::new();
+    for (i, key) in keys.iter().enumerate() {
+        if let Some(key) = key {
+            let first = *by_key.entry(key).or_insert(i);
+            join(&mut groups, first, i);
+        }
+    }
+
+    // Only compare the names of products sharing a word
+    let mut by_word: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (i, words) in words.iter().enumerate() {
+        let unique: HashSet<&str> = words.iter().map(String::as_str).collect();
+        for word in unique {
+            by_word.entry(word).or_default().push(i);
+        }
+    }
+    let mut compared = HashSet::new();
+    for products in by_word.values().filter(|products| products.len() <= MAX_WORD_PRODUCTS) {
+        for (n, &a) in products.iter().enumerate() {
+            for &b in &products[n + 1..] {
+                let pair = (a.min(b), a.max(b));
+                if compared.insert(pair) && similarity(&names[a], &names[b]) >= MIN_SIMILARITY {
+                    join(&mut groups, a, b);
+                }
+            }
+        }
+    }
+
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..catalog.len() {
+        let first = root(&mut groups, i);
+        members.entry(first).or_default().push(i);
+    }
+    let mut firsts: Vec<usize> = members
+        .keys()
+        .copied()
+        .filter(|first| members[first].len() > 1)
+        .collect();
+    firsts.sort_unstable();
+
+    let mut rows = Vec::new();
+    for first in firsts {
+        for &i in &members[&first] {
+            let (similarity, reason) = if i == first {
+                (100, None)
+            } else if keys[i].is_some() && keys[i] == keys[first] {
+                (100, Some("retailer_id"))
+            } else {
+                (similarity(&names[i], &names[first]), Some("name"))
+            };
+            let product = &catalog[i];
+            rows.push(serde_json::json!({
+                "group_id": catalog[first].get("id"),
+                "product_id": product.get("id"),
+                "retailer_id": product.get("retailer_id"),
+                "name": product.get("name"),
+                "similarity": similarity,
+                "reason": reason,
+            }));
+        }
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_name_similarity() {
+        assert_eq!(similarity("ceramic mug", "ceramic mug"), 100);
+        assert!(similarity("ceramic mug", "ceramic mugs") >= MIN_SIMILARITY);
+        assert!(similarity("ceramic mug", "cotton tee") < MIN_SIMILARITY);
+        assert_eq!(similarity("", "mug"), 0);
+    }
+
+    #[test]
+    fn groups_likely_duplicates() {
+        let product = |id: &str, sku: &str, name: &str| serde_json::json!({"id": id, "retailer_id": sku, "name": name});
+        let catalog = vec![
+            product("1", "SKU-001", "Ceramic Mug"),
+            product("2", "TEE-1", "Cotton Tee"),
+            product("3", "sku_001", "Mug (imported)"),
+            product("4", "MUG-2", "Ceramic mugs"),
+            product("5", "CAP-1", "Baseball Cap"),
+        ];
+        let rows = duplicates(&catalog);
+        let summary: Vec<(&str, &str, i64, Option<&str>)> = rows
+            .iter()
+            .map(|row| {
+                (
+                    row["group_id"].as_str().unwrap(),
+                    row["product_id"].as_str().unwrap(),
+                    row["similarity"].as_i64().unwrap(),
+                    row["reason"].as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("1", "1", 100, None),
+                ("1", "3", 100, Some("retailer_id")),
+                ("1", "4", similarity("ceramic mugs", "ceramic mug"), Some("name")),
+            ]
+        );
+    }
+}
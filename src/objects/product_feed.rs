@@ -0,0 +1,64 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::Client;
+use crate::feed;
+use crate::mapping;
+
+// A product feed file is read from its URL rather than from the provider
+// API, so every provider serves it
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("product_feed");
+
+// Products of a Meta commerce feed file, CSV or XML, to be compared with
+// the live catalog
+pub(crate) struct ProductFeed;
+
+impl Object for ProductFeed {
+    fn name(&self) -> &'static str {
+        "product_feed"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::product_feed_cell(src_row, tgt_col_name)
+    }
+}
+
+// Download and parse the feed file of the `feed_url` table option
+pub(crate) fn fetch(client: &mut Client, feed_url: Option<&str>) -> Result<Vec<JsonValue>, FdwError> {
+    let url = feed_url.ok_or("Object 'product_feed' requires the 'feed_url' table option")?;
+    if !url.starts_with("https://") && !url.starts_with("http://") {
+        return Err(format!("Invalid value '{}' for option 'feed_url', expected an http(s) URL", url));
+    }
+    feed::parse(&client.download(url.to_owned())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    #[test]
+    fn downloads_feeds_without_credentials() {
+        let transport = MockTransport::new(vec![
+            response(200, "id,title\nSKU-1,Mug\n"),
+            response(404, "Not Found"),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.api_key = "secret".to_owned();
+
+        let url = "https://shop.example.com/feed.csv";
+        let rows = fetch(&mut client, Some(url)).unwrap();
+        assert_eq!(rows[0]["id"], "SKU-1");
+        let err = fetch(&mut client, Some(url)).unwrap_err();
+        assert_eq!(err, "Cannot download 'https://shop.example.com/feed.csv': HTTP status 404");
+        assert!(fetch(&mut client, None).is_err());
+        assert!(fetch(&mut client, Some("file:///etc/passwd")).is_err());
+
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(header_value(&requests[0].headers, "x-user-api-key"), None);
+    }
+}
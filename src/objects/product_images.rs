@@ -0,0 +1,155 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Images are added and removed one row at a time, the row id joining the
+// product's retailer id and the image URL
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "product_images",
+    scan: true,
+    insert: true,
+    update: false,
+    delete: true,
+    rowid: Some("id"),
+    quals: &[],
+    pagination: Pagination::Cursor,
+};
+
+// Images of the catalog products, the main image first
+pub(crate) struct ProductImages;
+
+impl Object for ProductImages {
+    fn name(&self) -> &'static str {
+        "product_images"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::product_image_cell(src_row, tgt_col_name)
+    }
+}
+
+// A change to the images of a product, applied to its current images when
+// the statement ends
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum ImageChange {
+    Add(String),
+    Remove(String),
+}
+
+impl ImageChange {
+    // The change of an inserted row, an image URL added to a product
+    pub(crate) fn added(columns: &JsonValue) -> Result<(String, Self), FdwError> {
+        let retailer_id = columns
+            .get("retailer_id")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'retailer_id' is required to add a product image")?;
+        // The catalog downloads images from their URL, it has no upload of
+        // the image bytes
+        let url = columns
+            .get("url")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'url' is required to add a product image, as the catalog reads images from URLs")?;
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Err(format!("Invalid image URL '{}', expected an http or https URL", url));
+        }
+        Ok((retailer_id.to_owned(), Self::Add(url.to_owned())))
+    }
+
+    // The change of a deleted row, identified by its `id`
+    pub(crate) fn removed(rowid: Option<&JsonValue>) -> Result<(String, Self), FdwError> {
+        let id = rowid
+            .and_then(|v| v.as_str())
+            .ok_or("Deleting product images requires the table option rowid_column 'id'")?;
+        let (retailer_id, url) = id
+            .split_once('|')
+            .ok_or_else(|| format!("Invalid product image id '{}', expected the retailer id and URL", id))?;
+        Ok((retailer_id.to_owned(), Self::Remove(url.to_owned())))
+    }
+}
+
+// Row id of an image
+fn image_id(retailer_id: &str, url: &str) -> String {
+    format!("{}|{}", retailer_id, url)
+}
+
+// The image URLs of a catalog product, the main image first
+pub(crate) fn image_urls(product: &JsonValue) -> Vec<String> {
+    let main = product.get("image_url").into_iter();
+    let additional = product
+        .get("additional_image_urls")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten();
+    main.chain(additional)
+        .filter_map(|url| url.as_str())
+        .map(|url| url.to_owned())
+        .collect()
+}
+
+// Rows of the images of a catalog product
+pub(crate) fn image_rows(product: &JsonValue) -> Vec<JsonValue> {
+    let retailer_id = product.get("retailer_id").and_then(|v| v.as_str()).unwrap_or_default();
+    image_urls(product)
+        .iter()
+        .enumerate()
+        .map(|(position, url)| {
+            serde_json::json!({
+                "id": image_id(retailer_id, url),
+                "product_id": product.get("id"),
+                "retailer_id": retailer_id,
+                "position": position,
+                "url": url,
+            })
+        })
+        .collect()
+}
+
+// Apply the changes of a statement to the images of a product, failing if
+// no image would be left, as the catalog requires a main image
+pub(crate) fn apply(
+    retailer_id: &str,
+    mut urls: Vec<String>,
+    changes: &[ImageChange],
+) -> Result<Vec<String>, FdwError> {
+    for change in changes {
+        match change {
+            ImageChange::Add(url) if !urls.contains(url) => urls.push(url.clone()),
+            ImageChange::Add(_) => {}
+            ImageChange::Remove(url) => urls.retain(|u| u != url),
+        }
+    }
+    if urls.is_empty() {
+        return Err(format!("Cannot remove the last image of product '{}'", retailer_id));
+    }
+    Ok(urls)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_added_and_removed_images() {
+        let product = serde_json::json!({
+            "id": "7418529630",
+            "retailer_id": "SKU-1",
+            "image_url": "https://a",
+            "additional_image_urls": ["https://b"],
+        });
+        let rows = image_rows(&product);
+        assert_eq!(rows[1]["id"], "SKU-1|https://b");
+        assert_eq!(rows[1]["position"], 1);
+
+        let (_, removed) = ImageChange::removed(Some(&rows[0]["id"])).unwrap();
+        let (_, added) = ImageChange::added(&serde_json::json!({"retailer_id": "SKU-1", "url": "https://c"})).unwrap();
+        let urls = apply("SKU-1", image_urls(&product), &[added, removed]).unwrap();
+        assert_eq!(urls, ["https://b", "https://c"]);
+
+        let remove_all = [ImageChange::Remove("https://a".to_owned())];
+        let err = apply("SKU-1", vec!["https://a".to_owned()], &remove_all).unwrap_err();
+        assert_eq!(err, "Cannot remove the last image of product 'SKU-1'");
+        assert!(ImageChange::added(&serde_json::json!({"retailer_id": "SKU-1", "url": "a.png"})).is_err());
+    }
+}
@@ -0,0 +1,29 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Catalog products of a WhatsApp number
+pub(crate) struct Products;
+
+impl Object for Products {
+    fn name(&self) -> &'static str {
+        "products"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::product_cell(src_row, tgt_col_name)
+    }
+}
+
+// Stamp the products of a scan with its capture time, microseconds since
+// Unix epoch, the same for every row so that the scan copies into a history
+// table as one snapshot
+pub(crate) fn stamp_snapshot(rows: &mut [JsonValue], snapshot_at: i64) {
+    for row in rows {
+        if let Some(fields) = row.as_object_mut() {
+            fields.insert("snapshot_at".to_owned(), snapshot_at.into());
+        }
+    }
+}
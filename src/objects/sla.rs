@@ -0,0 +1,139 @@
+use serde_json::Value as JsonValue;
+use std::collections::BTreeMap;
+
+use super::{Object, ObjectCapabilities, Operation};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::client::Client;
+use crate::mapping;
+use crate::providers::Provider;
+
+// The SLA reads the messages of the provider, so it is served by any
+// provider scanning messages
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities::read_only("sla");
+
+// Chats waiting for an agent reply, and whether they breach the response
+// time threshold
+pub(crate) struct Sla;
+
+impl Object for Sla {
+    fn name(&self) -> &'static str {
+        "sla"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::sla_cell(src_row, tgt_col_name)
+    }
+}
+
+// Last inbound and outbound message times of a chat, microseconds since
+// Unix epoch
+#[derive(Debug, Default)]
+struct ChatActivity {
+    last_inbound_at: Option<i64>,
+    last_reply_at: Option<i64>,
+}
+
+// Whether a message was received from the contact, e.g. `incoming` with
+// Bird or `inbound` with Twilio, the other directions being sent
+fn is_inbound(message: &JsonValue) -> bool {
+    matches!(
+        message.get("direction").and_then(|v| v.as_str()),
+        Some("incoming" | "inbound")
+    )
+}
+
+// Fetch all messages of the provider and keep the chats whose last inbound
+// message has no later reply, waiting for `threshold_secs` or longer being
+// a breach. The longest waiting chats come first.
+pub(crate) fn fetch(
+    client: &mut Client,
+    provider: &dyn Provider,
+    threshold_secs: i64,
+) -> Result<Vec<JsonValue>, FdwError> {
+    provider.capabilities("messages")?.check(Operation::Scan)?;
+    let messages = provider.fetch(client, "messages")?;
+    let now = client.state.clock.now_secs() * 1_000_000;
+    Ok(open_chats(&messages, now, threshold_secs))
+}
+
+fn open_chats(messages: &[JsonValue], now: i64, threshold_secs: i64) -> Vec<JsonValue> {
+    let mut chats: BTreeMap<&str, ChatActivity> = BTreeMap::new();
+    for message in messages {
+        let inbound = is_inbound(message);
+        let contact = message
+            .get(if inbound { "from" } else { "to" })
+            .and_then(|v| v.as_str());
+        let (Some(contact), Some(sent_at)) = (contact, message.get("sent_at").and_then(|v| v.as_i64())) else {
+            continue;
+        };
+        let chat = chats.entry(contact).or_default();
+        let last = if inbound {
+            &mut chat.last_inbound_at
+        } else {
+            &mut chat.last_reply_at
+        };
+        *last = Some(last.map_or(sent_at, |last| last.max(sent_at)));
+    }
+
+    let mut rows: Vec<(i64, JsonValue)> = chats
+        .into_iter()
+        .filter_map(|(chat_id, chat)| {
+            let last_inbound_at = chat.last_inbound_at?;
+            if chat.last_reply_at.is_some_and(|reply| reply >= last_inbound_at) {
+                return None;
+            }
+            let waiting_secs = (now - last_inbound_at).max(0) / 1_000_000;
+            let row = serde_json::json!({
+                "chat_id": chat_id,
+                "last_inbound_at": last_inbound_at,
+                "last_reply_at": chat.last_reply_at,
+                "waiting_secs": waiting_secs,
+                "threshold_secs": threshold_secs,
+                "breached": waiting_secs >= threshold_secs,
+            });
+            Some((waiting_secs, row))
+        })
+        .collect();
+    rows.sort_by(|(a, _), (b, _)| b.cmp(a));
+    rows.into_iter().map(|(_, row)| row).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_chats_waiting_for_a_reply() {
+        let hour = 3_600_000_000i64;
+        let message = |direction: &str, contact: &str, sent_at: i64| {
+            let (from, to) = if direction == "inbound" {
+                (contact, "+1")
+            } else {
+                ("+1", contact)
+            };
+            serde_json::json!({"direction": direction, "from": from, "to": to, "sent_at": sent_at})
+        };
+        let messages = [
+            // Answered
+            message("inbound", "+491", hour),
+            message("outbound-api", "+491", 2 * hour),
+            // Asked again after the reply, for 3 hours
+            message("inbound", "+492", hour),
+            message("outbound-api", "+492", 2 * hour),
+            message("inbound", "+492", 3 * hour),
+            // Never answered, for 30 minutes
+            message("inbound", "+493", 5 * hour + hour / 2),
+            // Outbound only
+            message("outbound-api", "+494", hour),
+        ];
+        let rows = open_chats(&messages, 6 * hour, 3600);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["chat_id"], "+492");
+        assert_eq!(rows[0]["waiting_secs"], 3 * 3600);
+        assert_eq!(rows[0]["last_reply_at"], 2 * hour);
+        assert_eq!(rows[0]["breached"], true);
+        assert_eq!(rows[1]["chat_id"], "+493");
+        assert!(rows[1]["last_reply_at"].is_null());
+        assert_eq!(rows[1]["breached"], false);
+    }
+}
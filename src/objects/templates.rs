@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError, FdwResult};
+use crate::mapping;
+
+// Message templates of the WhatsApp Business Account
+pub(crate) struct Templates;
+
+impl Object for Templates {
+    fn name(&self) -> &'static str {
+        "templates"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::template_cell(src_row, tgt_col_name)
+    }
+}
+
+// Seconds a template definition is reused to check the messages sending it
+pub(crate) const DEFINITION_TTL_SECS: i64 = 600;
+
+// Definitions of the templates sent, by name and language, with the time
+// they were read, `None` for templates the account doesn't have
+#[derive(Debug, Default)]
+pub(crate) struct Definitions {
+    entries: HashMap<(String, String), (i64, Option<JsonValue>)>,
+}
+
+impl Definitions {
+    // Check the Cloud API `template` object of a message against the
+    // definition of its template, read with `fetch` unless read recently,
+    // `fetch` returning the definitions of the templates of a name
+    pub(crate) fn check(
+        &mut self,
+        now_secs: i64,
+        template: &JsonValue,
+        fetch: impl FnOnce(&str) -> Result<Vec<JsonValue>, FdwError>,
+    ) -> FdwResult {
+        let name = template["name"].as_str().unwrap_or_default().to_owned();
+        let language = template["language"]["code"].as_str().unwrap_or_default().to_owned();
+        let key = (name.clone(), language.clone());
+        let definition = match self.entries.get(&key) {
+            Some((read_at, definition)) if now_secs - read_at < DEFINITION_TTL_SECS => definition.clone(),
+            _ => {
+                let definition = fetch(&name)?.into_iter().find(|definition| {
+                    definition["name"].as_str() == Some(&name) && definition["language"].as_str() == Some(&language)
+                });
+                self.entries.insert(key, (now_secs, definition.clone()));
+                definition
+            }
+        };
+        let definition = definition
+            .ok_or_else(|| format!("Template '{}' in language '{}' doesn't exist in the account", name, language))?;
+        check_variables(&definition, template.get("components"))
+    }
+}
+
+// Components of a template definition whose variables a message fills
+const VARIABLE_COMPONENTS: &[&str] = &["header", "body"];
+
+// Parameter types filling a text variable
+const TEXT_PARAMETER_TYPES: &[&str] = &["text", "currency", "date_time"];
+
+// Check the parameters of the `components` of a template message against
+// the variables of the template definition, as read from the `templates`
+// object: the number of variables of the header and the body, and the
+// parameter types, so that a mismatch fails before the message is sent
+pub(crate) fn check_variables(definition: &JsonValue, components: Option<&JsonValue>) -> Result<(), FdwError> {
+    let name = definition.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+    for component in VARIABLE_COMPONENTS {
+        let expected = variables(definition, component);
+        let parameters = parameters(components, component);
+        if parameters.len() != expected.len() {
+            return Err(format!(
+                "Template '{}' expects {} {} variable{}, got {}",
+                name,
+                expected.len(),
+                component,
+                if expected.len() == 1 { "" } else { "s" },
+                parameters.len()
+            ));
+        }
+        for (i, (kinds, parameter)) in expected.iter().zip(parameters).enumerate() {
+            let kind = parameter.get("type").and_then(|v| v.as_str()).unwrap_or_default();
+            if !kinds.contains(&kind) {
+                return Err(format!(
+                    "Template '{}' expects {} variable {} of type {}, got '{}'",
+                    name,
+                    component,
+                    i + 1,
+                    kinds.join(" or "),
+                    kind
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Parameter types accepted by each variable of a component of a template
+// definition: one per distinct `{{1}}` or `{{name}}` placeholder of its
+// text, or the media of a media header
+fn variables(definition: &JsonValue, component: &str) -> Vec<&'static [&'static str]> {
+    let Some(part) = definition
+        .get("components")
+        .and_then(|v| v.as_array())
+        .and_then(|parts| parts.iter().find(|part| is_type(part, component)))
+    else {
+        return Vec::new();
+    };
+    let format = part.get("format").and_then(|v| v.as_str()).unwrap_or("TEXT");
+    match format.to_ascii_lowercase().as_str() {
+        "text" => {
+            let text = part.get("text").and_then(|v| v.as_str()).unwrap_or_default();
+            vec![TEXT_PARAMETER_TYPES; placeholders(text)]
+        }
+        "image" => vec![&["image"]],
+        "video" => vec![&["video"]],
+        "document" => vec![&["document"]],
+        "location" => vec![&["location"]],
+        _ => Vec::new(),
+    }
+}
+
+// Parameters given for a component of a template message
+fn parameters<'a>(components: Option<&'a JsonValue>, component: &str) -> &'a [JsonValue] {
+    components
+        .and_then(|v| v.as_array())
+        .and_then(|parts| parts.iter().find(|part| is_type(part, component)))
+        .and_then(|part| part.get("parameters"))
+        .and_then(|v| v.as_array())
+        .map(|parameters| parameters.as_slice())
+        .unwrap_or_default()
+}
+
+// Whether a component is of a type, which definitions spell in upper case
+fn is_type(part: &JsonValue, component: &str) -> bool {
+    part.get("type")
+        .and_then(|v| v.as_str())
+        .is_some_and(|kind| kind.eq_ignore_ascii_case(component))
+}
+
+// Number of distinct placeholders of a text, a placeholder repeated in the
+// text being filled by the same variable
+fn placeholders(text: &str) -> usize {
+    let mut names: Vec<&str> = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let Some(end) = rest.find("}}") else {
+            break;
+        };
+        let name = rest[..end].trim();
+        if !name.is_empty() && !names.contains(&name) {
+            names.push(name);
+        }
+        rest = &rest[end + 2..];
+    }
+    names.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn definition() -> JsonValue {
+        serde_json::json!({
+            "name": "order_update",
+            "language": "en_US",
+            "components": [
+                {"type": "HEADER", "format": "IMAGE"},
+                {"type": "BODY", "text": "Hi {{1}}, order {{2}} ships on {{3}}. Thanks {{1}}!"},
+                {"type": "FOOTER", "text": "Reply STOP to opt out"},
+            ],
+        })
+    }
+
+    #[test]
+    fn checks_the_variables_of_template_messages() {
+        let text = |s: &str| serde_json::json!({"type": "text", "text": s});
+        let header =
+            serde_json::json!({"type": "header", "parameters": [{"type": "image", "image": {"link": "https://a"}}]});
+        let components = serde_json::json!([
+            header,
+            {"type": "body", "parameters": [text("Jane"), text("42"), {"type": "date_time", "date_time": {"fallback_value": "May 1"}}]},
+        ]);
+        assert!(check_variables(&definition(), Some(&components)).is_ok());
+
+        let components = serde_json::json!([header, {"type": "body", "parameters": [text("Jane"), text("42")]}]);
+        assert_eq!(
+            check_variables(&definition(), Some(&components)).unwrap_err(),
+            "Template 'order_update' expects 3 body variables, got 2"
+        );
+        assert_eq!(
+            check_variables(&definition(), None).unwrap_err(),
+            "Template 'order_update' expects 1 header variable, got 0"
+        );
+
+        let components = serde_json::json!([
+            {"type": "header", "parameters": [text("Jane")]},
+            {"type": "body", "parameters": [text("Jane"), text("42"), text("May 1")]},
+        ]);
+        assert_eq!(
+            check_variables(&definition(), Some(&components)).unwrap_err(),
+            "Template 'order_update' expects header variable 1 of type image, got 'text'"
+        );
+    }
+
+    #[test]
+    fn counts_distinct_placeholders() {
+        assert_eq!(placeholders("Hi {{1}}, order {{2}} for {{1}}"), 2);
+        assert_eq!(placeholders("Hi {{first_name}}"), 1);
+        assert_eq!(placeholders("No variables, {{ unclosed"), 0);
+    }
+}
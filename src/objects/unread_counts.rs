@@ -0,0 +1,21 @@
+use serde_json::Value as JsonValue;
+
+use super::Object;
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError};
+use crate::mapping;
+
+// Columns whose equality quals the providers apply, reading a single chat
+pub(crate) const CHAT_QUALS: &[&str] = &["chat_id"];
+
+// Unread messages of each chat of the WhatsApp number
+pub(crate) struct UnreadCounts;
+
+impl Object for UnreadCounts {
+    fn name(&self) -> &'static str {
+        "unread_counts"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::unread_count_cell(src_row, tgt_col_name)
+    }
+}
@@ -0,0 +1,90 @@
+use serde_json::Value as JsonValue;
+
+use super::{Object, ObjectCapabilities, Pagination};
+use crate::bindings::supabase::wrappers::types::{Cell, FdwError, FdwResult};
+use crate::config::TableConfig;
+use crate::mapping;
+
+// Webhooks registered at the provider, pushing its events to a callback
+// URL such as a Supabase Edge Function. Inserting a row registers the
+// table's `webhook_url`.
+pub(crate) struct WebhookBridge;
+
+impl Object for WebhookBridge {
+    fn name(&self) -> &'static str {
+        "webhook_bridge"
+    }
+
+    fn map_cell(&self, src_row: &JsonValue, tgt_col_name: &str) -> Result<Option<Cell>, FdwError> {
+        mapping::webhook_bridge_cell(src_row, tgt_col_name)
+    }
+}
+
+// Webhooks are listed and registered, unregistering being left to the
+// provider dashboards
+pub(crate) const CAPABILITIES: ObjectCapabilities = ObjectCapabilities {
+    name: "webhook_bridge",
+    scan: true,
+    insert: true,
+    update: false,
+    delete: false,
+    rowid: None,
+    quals: &[],
+    pagination: Pagination::None,
+};
+
+// Flag the registrations pointing at the table's webhook URL, `registered`
+// staying null without one
+pub(crate) fn check_registration(rows: &mut [JsonValue], webhook_url: Option<&str>) {
+    let Some(webhook_url) = webhook_url else { return };
+    for row in rows {
+        let registered = row.get("url").and_then(|v| v.as_str()) == Some(webhook_url);
+        row["registered"] = registered.into();
+    }
+}
+
+// Complete an inserted registration with the table options, the row's own
+// `url` and `verify_token` taking precedence
+pub(crate) fn fill_registration(columns: &mut JsonValue, table: &TableConfig) -> FdwResult {
+    for (column, option) in [("url", &table.webhook_url), ("verify_token", &table.verify_token)] {
+        if columns.get(column).filter(|v| !v.is_null()).is_none() {
+            if let Some(value) = option {
+                columns[column] = value.as_str().into();
+            }
+        }
+    }
+    match columns.get("url").and_then(|v| v.as_str()) {
+        Some(url) if url.starts_with("https://") => Ok(()),
+        Some(url) => Err(format!("Invalid webhook URL '{}', expected an https URL", url)),
+        None => Err("Column 'url' or table option 'webhook_url' is required to register a webhook".to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registrations_default_to_the_table_options() {
+        let table = TableConfig {
+            object: "webhook_bridge".to_owned(),
+            webhook_url: Some("https://abc.supabase.co/functions/v1/whatsapp".to_owned()),
+            verify_token: Some("s3cret".to_owned()),
+            ..Default::default()
+        };
+        let mut columns = serde_json::json!({"url": null, "events": null});
+        fill_registration(&mut columns, &table).unwrap();
+        assert_eq!(columns["url"], "https://abc.supabase.co/functions/v1/whatsapp");
+        assert_eq!(columns["verify_token"], "s3cret");
+
+        let mut rows = vec![columns, serde_json::json!({"url": "https://old.example.com/hook"})];
+        check_registration(&mut rows, table.webhook_url.as_deref());
+        assert_eq!(rows[0]["registered"], true);
+        assert_eq!(rows[1]["registered"], false);
+
+        let mut plain = serde_json::json!({"url": "http://abc.supabase.co/hook"});
+        assert!(fill_registration(&mut plain, &TableConfig::default()).is_err());
+        let mut empty = serde_json::json!({});
+        assert!(fill_registration(&mut empty, &TableConfig::default()).is_err());
+    }
+}
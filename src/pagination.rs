@@ -0,0 +1,392 @@
+use serde_json::Value as JsonValue;
+use std::collections::HashSet;
+
+use crate::bindings::supabase::wrappers::types::FdwError;
+use crate::timestamp::Period;
+
+// A page of source rows and the cursor of the following page, if any
+#[derive(Debug, Default)]
+pub(crate) struct Page {
+    pub(crate) rows: Vec<JsonValue>,
+    pub(crate) next: Option<String>,
+    // Number of rows of the whole resource, if the provider reports it
+    pub(crate) total: Option<i64>,
+}
+
+// Position in a paged resource read page by page: the cursor of the next
+// page, and the cursors followed so far. A cursor seen twice means the
+// provider is looping, which would duplicate rows, so it is an error.
+#[derive(Debug, Default)]
+pub(crate) struct Pager {
+    next: Option<String>,
+    seen: HashSet<String>,
+    rows: usize,
+    pages: usize,
+}
+
+impl Pager {
+    // Cursor of the next page, None before the first page and after the
+    // last one
+    pub(crate) fn next(&self) -> Option<&str> {
+        self.next.as_deref()
+    }
+
+    // Whether the last page was fetched
+    pub(crate) fn is_done(&self) -> bool {
+        self.pages > 0 && self.next.is_none()
+    }
+
+    // Move past a fetched page, returning its rows
+    pub(crate) fn advance(&mut self, page: Page) -> Result<Vec<JsonValue>, FdwError> {
+        self.pages += 1;
+        self.rows += page.rows.len();
+        if let Some(next) = &page.next {
+            if !self.seen.insert(next.clone()) {
+                return Err(format!(
+                    "Pagination cursor '{}' was returned twice after {} row(s), stopping to avoid duplicate rows",
+                    next, self.rows
+                ));
+            }
+        }
+        self.next = page.next;
+        Ok(page.rows)
+    }
+}
+
+// Fetch all the rows of a paged resource, starting from the first page and
+// following the cursors until the last page
+pub(crate) fn fetch_all(
+    mut fetch_page: impl FnMut(Option<&str>) -> Result<Page, FdwError>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let mut pager = Pager::default();
+    let mut rows = Vec::new();
+    loop {
+        let page = fetch_page(pager.next())?;
+        rows.extend(pager.advance(page)?);
+        if pager.is_done() {
+            return Ok(rows);
+        }
+    }
+}
+
+// Fetch all the rows of a paged resource as of a single version of it. The
+// resource changed between pages if the total it reports changed, or if a
+// page after the first was refused for its cursor, as a provider no longer
+// accepts the cursors of a changed resource. The read then starts over
+// once, and fails if the resource changed again. Other errors fail the read
+// as they are.
+pub(crate) fn fetch_snapshot(
+    mut fetch_page: impl FnMut(Option<&str>) -> Result<Page, FdwError>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    match read_snapshot(&mut fetch_page)? {
+        Ok(rows) => Ok(rows),
+        Err(_) => read_snapshot(&mut fetch_page)?.map_err(|change| {
+            format!(
+                "Rows changed while being read page by page, twice in a row ({}), retry later or use the table option consistency 'best_effort'",
+                change
+            )
+        }),
+    }
+}
+
+// Read all the pages of a paged resource, or tell how it changed in between
+fn read_snapshot(
+    fetch_page: &mut impl FnMut(Option<&str>) -> Result<Page, FdwError>,
+) -> Result<Result<Vec<JsonValue>, String>, FdwError> {
+    let mut pager = Pager::default();
+    let mut rows = Vec::new();
+    let mut total = None;
+    loop {
+        let page = match fetch_page(pager.next()) {
+            Ok(page) => page,
+            Err(err) if pager.pages > 0 && is_cursor_rejection(&err) => {
+                return Ok(Err(format!("page {} failed: {}", pager.pages + 1, err)));
+            }
+            Err(err) => return Err(err),
+        };
+        match (total, page.total) {
+            (Some(before), Some(after)) if before != after => {
+                return Ok(Err(format!("total went from {} to {} rows", before, after)));
+            }
+            (None, _) => total = page.total,
+            _ => {}
+        }
+        rows.extend(pager.advance(page)?);
+        if pager.is_done() {
+            return Ok(Ok(rows));
+        }
+    }
+}
+
+// Prefix of the error of a page whose cursor the provider refused
+const CURSOR_REJECTED: &str = "Cursor rejected: ";
+
+// Error of a page whose cursor the provider refused, as told by the client
+// from the response
+pub(crate) fn cursor_rejected(err: &str) -> FdwError {
+    format!("{}{}", CURSOR_REJECTED, err)
+}
+
+// Whether a page failed because the provider refused its cursor
+fn is_cursor_rejection(err: &str) -> bool {
+    err.starts_with(CURSOR_REJECTED)
+}
+
+// Fetch the rows of a paged resource listed newest first within a period,
+// walking back through the pages until one reaches past the start of the
+// period rather than reading the whole history. `time` gives the time of a
+// row, rows outside the period being dropped.
+pub(crate) fn fetch_period(
+    period: Period,
+    time: impl Fn(&JsonValue) -> Option<i64>,
+    mut fetch_page: impl FnMut(Option<&str>) -> Result<Page, FdwError>,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let rows = fetch_all(|cursor| {
+        let mut page = fetch_page(cursor)?;
+        let past_start = period
+            .since
+            .is_some_and(|since| page.rows.iter().filter_map(&time).any(|at| at < since));
+        if past_start {
+            page.next = None;
+        }
+        Ok(page)
+    })?;
+    Ok(rows
+        .into_iter()
+        .filter(|row| time(row).is_none_or(|at| period.contains(at)))
+        .collect())
+}
+
+// Cursor of the next page of a Graph API response, or of a provider
+// relaying Graph API paging, where a `next` link is only present if there
+// are more pages. The link itself is not followed, it may point to another
+// host than the provider, and the request is rebuilt from the cursor.
+pub(crate) fn graph_cursor(resp_json: &JsonValue) -> Option<String> {
+    resp_json.pointer("/paging/next")?;
+    resp_json
+        .pointer("/paging/cursors/after")
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned())
+}
+
+// Total number of nodes of a Graph API edge, reported when requested with
+// `summary=total_count`
+pub(crate) fn graph_total(resp_json: &JsonValue) -> Option<i64> {
+    resp_json.pointer("/summary/total_count").and_then(|v| v.as_i64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::Rng;
+
+    // A paged resource of `total` numbered rows, split into pages of
+    // random sizes (possibly empty) linked by random opaque cursors
+    struct PagedSource {
+        pages: Vec<(String, Vec<JsonValue>)>,
+    }
+
+    impl PagedSource {
+        fn generate(rng: &mut Rng) -> Self {
+            let total = rng.below(200) as usize;
+            let mut pages = Vec::new();
+            let mut row = 0;
+            loop {
+                let size = (rng.below(25) as usize).min(total - row);
+                let cursor = format!("c{:x}-{}", rng.next(), pages.len());
+                pages.push((cursor, (row..row + size).map(|i| serde_json::json!(i)).collect()));
+                row += size;
+                // Providers may also end with an empty page
+                if row == total && rng.below(3) > 0 {
+                    break;
+                }
+            }
+            Self { pages }
+        }
+
+        fn total(&self) -> usize {
+            self.pages.iter().map(|(_, rows)| rows.len()).sum()
+        }
+
+        // Serve the page a cursor points to, the first page without one
+        fn page(&self, cursor: Option<&str>) -> Result<Page, FdwError> {
+            let idx = match cursor {
+                None => 0,
+                Some(cursor) => self
+                    .pages
+                    .iter()
+                    .position(|(c, _)| c == cursor)
+                    .ok_or_else(|| format!("unknown cursor {}", cursor))?,
+            };
+            Ok(Page {
+                rows: self.pages[idx].1.clone(),
+                next: self.pages.get(idx + 1).map(|(c, _)| c.clone()),
+                total: None,
+            })
+        }
+    }
+
+    #[test]
+    fn never_drops_duplicates_or_reorders_rows() {
+        for seed in 1..=500u64 {
+            let source = PagedSource::generate(&mut Rng::new(seed));
+            let mut calls = 0;
+            let rows = fetch_all(|cursor| {
+                calls += 1;
+                source.page(cursor)
+            })
+            .unwrap();
+
+            let expected: Vec<JsonValue> = (0..source.total()).map(|i| serde_json::json!(i)).collect();
+            assert_eq!(rows, expected, "seed {}", seed);
+            assert_eq!(calls, source.pages.len(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn stops_at_the_page_reaching_past_the_period_start() {
+        for seed in 1..=500u64 {
+            let mut rng = Rng::new(seed);
+            let source = PagedSource::generate(&mut rng);
+            // Rows listed newest first, row i having time total - i
+            let total = source.total() as i64;
+            let time = |row: &JsonValue| row.as_i64().map(|i| total - i);
+            let since = rng.below(total as u64 + 2) as i64;
+            let period = Period {
+                since: Some(since),
+                until: None,
+            };
+            let mut calls = 0;
+            let rows = fetch_period(period, time, |cursor| {
+                calls += 1;
+                source.page(cursor)
+            })
+            .unwrap();
+
+            let expected: Vec<JsonValue> = (0..total)
+                .filter(|i| total - i >= since)
+                .map(|i| serde_json::json!(i))
+                .collect();
+            assert_eq!(rows, expected, "seed {}", seed);
+            let last_page = source
+                .pages
+                .iter()
+                .position(|(_, rows)| rows.iter().filter_map(time).any(|at| at < since))
+                .unwrap_or(source.pages.len() - 1);
+            assert_eq!(calls, last_page + 1, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn looping_cursor_is_an_error() {
+        for seed in 1..=200u64 {
+            let mut rng = Rng::new(seed);
+            let mut source = PagedSource::generate(&mut rng);
+            if source.pages.len() < 2 {
+                continue;
+            }
+            // Point a later page back to an earlier cursor
+            let from = 1 + rng.below(source.pages.len() as u64 - 1) as usize;
+            let to = 1 + rng.below(from as u64) as usize;
+            let looped = source.pages[to].0.clone();
+            source.pages.truncate(from + 1);
+            source.pages.push((looped, Vec::new()));
+
+            let result = fetch_all(|cursor| source.page(cursor));
+            assert!(result.is_err(), "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn page_failure_is_propagated() {
+        for seed in 1..=200u64 {
+            let mut rng = Rng::new(seed);
+            let source = PagedSource::generate(&mut rng);
+            let fail_at = rng.below(source.pages.len() as u64) as usize;
+            let mut calls = 0;
+            let result = fetch_all(|cursor| {
+                calls += 1;
+                if calls > fail_at {
+                    return Err("connection reset".to_owned());
+                }
+                source.page(cursor)
+            });
+            assert_eq!(result.unwrap_err(), "connection reset", "seed {}", seed);
+            assert_eq!(calls, fail_at + 1, "seed {}", seed);
+        }
+    }
+
+    // A catalog of two pages of two rows reporting its total, a product
+    // being added before the second page of the reads listed in `changes`
+    fn changing_catalog(changes: &[usize]) -> impl FnMut(Option<&str>) -> Result<Page, FdwError> + '_ {
+        let mut reads = 0;
+        move |cursor| {
+            if cursor.is_none() {
+                reads += 1;
+            }
+            let total = if cursor.is_some() && changes.contains(&reads) { 5 } else { 4 };
+            Ok(Page {
+                rows: vec![serde_json::json!({"page": cursor}); 2],
+                next: cursor.is_none().then(|| "p2".to_owned()),
+                total: Some(total),
+            })
+        }
+    }
+
+    #[test]
+    fn snapshot_starts_over_once_when_the_total_changes() {
+        assert_eq!(fetch_snapshot(changing_catalog(&[])).unwrap().len(), 4);
+        assert_eq!(fetch_snapshot(changing_catalog(&[1])).unwrap().len(), 4);
+
+        let err = fetch_snapshot(changing_catalog(&[1, 2])).unwrap_err();
+        assert_eq!(
+            err,
+            "Rows changed while being read page by page, twice in a row (total went from 4 to 5 rows), retry later or use the table option consistency 'best_effort'"
+        );
+    }
+
+    #[test]
+    fn snapshot_starts_over_when_a_cursor_is_rejected() {
+        let mut calls = 0;
+        let rows = fetch_snapshot(|cursor| {
+            calls += 1;
+            if calls == 2 {
+                return Err(cursor_rejected("Meta error 100, invalid parameter: Invalid cursor"));
+            }
+            Ok(Page {
+                rows: vec![JsonValue::Null],
+                next: cursor.is_none().then(|| "p2".to_owned()),
+                total: None,
+            })
+        });
+        assert_eq!(rows.unwrap().len(), 2);
+        assert_eq!(calls, 4);
+
+        // A failing first page is not a change
+        let mut calls = 0;
+        let result = fetch_snapshot(|_| {
+            calls += 1;
+            Err("connection reset".to_owned())
+        });
+        assert_eq!(result.unwrap_err(), "connection reset");
+        assert_eq!(calls, 1);
+
+        // Nor is a later page failing for another reason, even when its
+        // error mentions the cursor
+        let mut calls = 0;
+        let result = fetch_snapshot(|cursor| {
+            calls += 1;
+            if cursor.is_some() {
+                return Err("Invalid OAuth access token for cursor p2".to_owned());
+            }
+            Ok(Page {
+                rows: vec![JsonValue::Null],
+                next: Some("p2".to_owned()),
+                total: None,
+            })
+        });
+        assert_eq!(result.unwrap_err(), "Invalid OAuth access token for cursor p2");
+        assert_eq!(calls, 2);
+    }
+}
@@ -0,0 +1,289 @@
+use serde_json::Value as JsonValue;
+use std::fmt;
+
+use crate::bindings::supabase::wrappers::types::{FdwError, FdwResult};
+use crate::client::Client;
+use crate::config::{Opts, TableConfig};
+use crate::log;
+use crate::objects::{self, ObjectCapabilities, Operation};
+use crate::pagination::Page;
+
+mod bird;
+mod dialog360;
+mod generic;
+mod graph;
+mod meta_cloud;
+mod meta_commerce;
+mod twilio;
+pub(crate) mod two_chat;
+mod union;
+mod vonage;
+mod wati;
+
+// One row of a modify statement
+#[derive(Debug)]
+pub(crate) struct WriteRow {
+    pub(crate) operation: Operation,
+    // Value of the `rowid_column` of an updated or deleted row
+    pub(crate) rowid: Option<JsonValue>,
+    // Columns of an inserted or updated row, as a JSON object
+    pub(crate) columns: JsonValue,
+}
+
+// A WhatsApp number of a server, and the source serving it on a server
+// with several sources
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Line {
+    pub(crate) source: Option<String>,
+    // The number, or the provider id of the number
+    pub(crate) number: String,
+}
+
+// A WhatsApp API backend, selected by the `provider` server option. The
+// provider knows the endpoints and authentication of its API, and which
+// objects it serves with which operations.
+pub(crate) trait Provider: fmt::Debug {
+    // Name of the provider, as given in the `provider` option
+    fn name(&self) -> &'static str;
+
+    // Objects served by the provider
+    fn objects(&self) -> &[ObjectCapabilities];
+
+    // Point the client at the provider API, with its credentials
+    fn connect(&self, client: &mut Client);
+
+    // Accounts and numbers the provider acts as, reported at init with the
+    // phone numbers masked
+    fn identities(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    // The WhatsApp number the provider sends from, or its id, if the
+    // provider is tied to one
+    fn number(&self) -> Option<&str> {
+        None
+    }
+
+    // The WhatsApp numbers served, one per line
+    fn lines(&self) -> Vec<Line> {
+        self.number()
+            .map(|number| Line {
+                source: None,
+                number: number.to_owned(),
+            })
+            .into_iter()
+            .collect()
+    }
+
+    // Check the table options read by the provider, when a table is opened
+    fn open_table(&mut self, table: &TableConfig) -> FdwResult {
+        match table.sources {
+            Some(_) => Err("Table option 'sources' requires a server with the 'sources' option".to_owned()),
+            None => Ok(()),
+        }
+    }
+
+    // Fetch the source rows of an object
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError>;
+
+    // Whether scans read an object one page at a time with `fetch_page`, as
+    // the rows are consumed, instead of whole with `fetch`
+    fn pages_lazily(&self, _object: &str) -> bool {
+        false
+    }
+
+    // Fetch the page of an object a cursor points to, the first page
+    // without one
+    fn fetch_page(&self, _client: &mut Client, object: &str, _cursor: Option<&str>) -> Result<Page, FdwError> {
+        Err(not_implemented(object, Operation::Scan))
+    }
+
+    // Send one row of a modify statement, or buffer it until `end_modify`
+    fn write_row(&mut self, _client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        Err(not_implemented(object, row.operation))
+    }
+
+    // Whether template messages are checked against their template before
+    // being sent, the API checking them otherwise
+    fn checks_templates(&self) -> bool {
+        false
+    }
+
+    // Look up a sent message by the id the provider gave it, in the shape of
+    // the messages object, if the provider can
+    fn message_status(&self, _client: &mut Client, _id: &str) -> Result<Option<JsonValue>, FdwError> {
+        Ok(None)
+    }
+
+    // Send the rows buffered by the modify statement, at its end
+    fn end_modify(&mut self, _client: &mut Client, _object: &str) -> FdwResult {
+        Ok(())
+    }
+
+    // Capabilities of an object, failing if the provider doesn't serve it
+    fn capabilities(&self, object: &str) -> Result<&ObjectCapabilities, FdwError> {
+        self.objects()
+            .iter()
+            .find(|caps| caps.name == object)
+            .ok_or_else(|| {
+                let names: Vec<&str> = self.objects().iter().map(|caps| caps.name).collect();
+                format!(
+                    "Object '{}' is not supported by provider '{}', supported objects: {}",
+                    object,
+                    self.name(),
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+// Error for an operation a provider doesn't implement for an object
+pub(crate) fn not_implemented(object: &str, operation: Operation) -> FdwError {
+    format!(
+        "Operation '{}' is not implemented for object '{}'",
+        operation.as_str(),
+        object
+    )
+}
+
+// Parse the server options of a provider
+type ParseProvider = fn(&Opts) -> Result<Box<dyn Provider>, FdwError>;
+
+// Names of the providers, as given in the `provider` option
+const PROVIDERS: &[(&str, ParseProvider)] = &[
+    ("2chat", two_chat::TwoChat::parse),
+    ("360dialog", dialog360::Dialog360::parse),
+    ("bird", bird::Bird::parse),
+    ("generic", generic::Generic::parse),
+    ("meta_cloud", meta_cloud::MetaCloud::parse),
+    ("meta_commerce", meta_commerce::MetaCommerce::parse),
+    ("twilio", twilio::Twilio::parse),
+    ("vonage", vonage::Vonage::parse),
+    ("wati", wati::Wati::parse),
+];
+
+// Create the provider selected by the server options, 2Chat by default, or
+// the union of the providers of the `sources` option
+pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+    match opts.list("sources") {
+        Some(names) => union::Union::parse(opts, &names),
+        None => parse_provider(opts),
+    }
+}
+
+// Create the provider selected by the `provider` option
+fn parse_provider(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+    let parse = opts.choice("provider", PROVIDERS[0].1, PROVIDERS)?;
+    parse(opts)
+}
+
+// Phone number identity of a provider, masked, or None if it isn't set
+pub(crate) fn masked_number(name: &str, number: &str) -> Option<(String, String)> {
+    (!number.is_empty()).then(|| (name.to_owned(), log::mask_number(number)))
+}
+
+// Report of the provider resolved at init, connected to `base_url`, with
+// its identities and the features of the build, e.g. `Using provider
+// 'twilio' at https://api.twilio.com, from_number +141*****886, features:
+// messages`
+pub(crate) fn startup_report(provider: &dyn Provider, base_url: &str) -> String {
+    let mut report = format!("Using provider '{}' at {}", provider.name(), base_url);
+    for (name, value) in provider.identities() {
+        report.push_str(&format!(", {} {}", name, value));
+    }
+    let features = objects::enabled_features();
+    report.push_str(&format!(
+        ", features: {}",
+        if features.is_empty() { "none".to_owned() } else { features.join(", ") }
+    ));
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn provider(options: &[(&'static str, &'static str)]) -> Result<Box<dyn Provider>, FdwError> {
+        let source: HashMap<&str, &str> = options.iter().copied().collect();
+        parse(&Opts::new(&source))
+    }
+
+    #[test]
+    fn selects_provider_by_name() {
+        let two_chat = provider(&[
+            ("phone_number", "+4915123456789"),
+            ("from_number", "+4915198765432"),
+            ("api_key", "key"),
+        ])
+        .unwrap();
+        assert_eq!(two_chat.name(), "2chat");
+
+        let meta_cloud = provider(&[
+            ("provider", "meta_cloud"),
+            ("access_token", "token"),
+            ("phone_number_id", "106540352242922"),
+        ])
+        .unwrap();
+        assert_eq!(meta_cloud.name(), "meta_cloud");
+
+        assert_eq!(
+            provider(&[("provider", "gupshup")]).unwrap_err(),
+            "Invalid value 'gupshup' for option 'provider', expected one of 2chat, 360dialog, bird, generic, meta_cloud, meta_commerce, twilio, vonage, wati"
+        );
+    }
+
+    #[test]
+    fn reports_masked_identities_of_each_source() {
+        let union = provider(&[
+            ("sources", "eu,us"),
+            ("eu.provider", "twilio"),
+            ("eu.account_sid", "AC123"),
+            ("eu.auth_token", "token"),
+            ("eu.from_number", "+4915123456789"),
+            ("us.phone_number", "+14155238886"),
+            ("us.from_number", "+14155238886"),
+            ("us.api_key", "key"),
+        ])
+        .unwrap();
+        let report = startup_report(union.as_ref(), "https://api.twilio.com/2010-04-01/Accounts/AC123");
+        assert!(
+            report.starts_with(
+                "Using provider 'union' at https://api.twilio.com/2010-04-01/Accounts/AC123, \
+                 eu.provider twilio, eu.account_sid AC123, eu.from_number +491*******789, \
+                 us.provider 2chat, us.phone_number +141*****886, us.from_number +141*****886, features: "
+            ),
+            "{}",
+            report
+        );
+        assert!(!report.contains("token"), "{}", report);
+
+        let lines: Vec<(Option<String>, String)> =
+            union.lines().into_iter().map(|line| (line.source, line.number)).collect();
+        assert_eq!(
+            lines,
+            [
+                (Some("eu".to_owned()), "+4915123456789".to_owned()),
+                (Some("us".to_owned()), "+14155238886".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unsupported_object_lists_the_provider_objects() {
+        let meta_cloud = provider(&[
+            ("provider", "meta_cloud"),
+            ("access_token", "token"),
+            ("phone_number_id", "106540352242922"),
+        ])
+        .unwrap();
+        let err = meta_cloud.capabilities("products").unwrap_err();
+        assert!(
+            err.starts_with("Object 'products' is not supported by provider 'meta_cloud', supported objects: "),
+            "{}",
+            err
+        );
+        assert!(err.contains("health"), "{}", err);
+    }
+}
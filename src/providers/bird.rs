@@ -0,0 +1,601 @@
+use serde_json::Value as JsonValue;
+
+use super::{not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{url_encode, Client, KeyScheme};
+use crate::config::Opts;
+use crate::log::info;
+use crate::model::{self, Envelope};
+#[cfg(feature = "messages")]
+use crate::objects::{broadcast_sends, broadcasts, button_replies, flow_responses, messages};
+use crate::objects::{health, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+use crate::timestamp;
+
+const API_URL: &str = "https://api.bird.com";
+
+// Messages and conversations per page, the maximum accepted by Bird
+const PAGE_SIZE: usize = 100;
+
+// Objects served by Bird
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: true,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: messages::TIME_QUALS,
+        pagination: Pagination::Cursor,
+    },
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("button_replies").paged(Pagination::Cursor),
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("flow_responses").paged(Pagination::Cursor),
+    #[cfg(feature = "messages")]
+    broadcasts::CAPABILITIES,
+    #[cfg(feature = "messages")]
+    broadcast_sends::CAPABILITIES,
+    ObjectCapabilities::read_only("contacts").paged(Pagination::Cursor),
+    ObjectCapabilities::read_only("health"),
+];
+
+// Bird (formerly MessageBird), whose WhatsApp numbers are channels of a
+// workspace. Contacts are the participants of the channel conversations.
+#[derive(Debug)]
+pub(crate) struct Bird {
+    access_key: String,
+    workspace_id: String,
+    // The WhatsApp channel of the workspace
+    channel_id: String,
+}
+
+impl Bird {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        Ok(Box::new(Self {
+            access_key: opts.require("access_key")?,
+            workspace_id: opts.require("workspace_id")?,
+            channel_id: opts.require("channel_id")?,
+        }))
+    }
+
+    // URL of the channel
+    fn channel_url(&self, client: &Client) -> String {
+        format!("{}/channels/{}", client.base_url, url_encode(&self.channel_id))
+    }
+
+    // Fetch the messages of the channel, most recent first
+    fn fetch_messages(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        // Bird lists the newest messages first, so a time range reads the
+        // pages back to its start only
+        let url = paged_url(format!("{}/messages", self.channel_url(client)));
+        let period = client.state.period;
+        pagination::fetch_period(period, model::sent_at, |page_token| {
+            fetch_page(client, &url, page_token, message_row)
+        })
+    }
+
+    // Fetch the rows `row` makes of the incoming messages of the channel,
+    // given the message id, the message, its sender and its time
+    #[cfg(feature = "messages")]
+    fn fetch_incoming(
+        &self,
+        client: &mut Client,
+        row: fn(&JsonValue, &JsonValue, Option<&str>, Option<i64>) -> Option<JsonValue>,
+    ) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!("{}/messages", self.channel_url(client));
+        let messages = fetch_pages(client, url, JsonValue::clone)?;
+        Ok(messages
+            .iter()
+            .filter(|message| message.get("direction").and_then(|v| v.as_str()) == Some("incoming"))
+            .filter_map(|message| {
+                let sent_at = message
+                    .get("createdAt")
+                    .and_then(|v| v.as_str())
+                    .and_then(timestamp::parse_rfc3339);
+                let contact = phone_number(message.pointer("/sender/contact"));
+                let id = message.get("id").cloned().unwrap_or_default();
+                row(&id, message, contact, sent_at)
+            })
+            .collect())
+    }
+
+    // Fetch the conversations of the channel, as contacts
+    fn fetch_contacts(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!(
+            "{}/conversations?channelId={}",
+            client.base_url,
+            url_encode(&self.channel_id)
+        );
+        fetch_pages(client, url, conversation_row)
+    }
+
+    // Fetch the contact lists of the workspace, as broadcast lists
+    #[cfg(feature = "messages")]
+    fn fetch_lists(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!("{}/lists", client.base_url);
+        fetch_pages(client, url, |list| {
+            serde_json::json!({
+                "id": list.get("id"),
+                "name": list.get("name"),
+                "recipient_count": list.get("contactCount"),
+                "created_at": list.get("createdAt").and_then(|v| v.as_str()).and_then(timestamp::parse_rfc3339),
+            })
+        })
+    }
+
+    // Probe the channel, reading its status
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = self.channel_url(client);
+        health::probe(client, url, |resp_json| resp_json.get("status").cloned())
+    }
+
+    // Send a message on the channel
+    fn send_message(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let url = format!("{}/messages", self.channel_url(client));
+        client.send_json(http::Method::Post, url, &message_body(row)?)?;
+        Ok(())
+    }
+
+    // Create a contact list of the workspace with its recipients
+    #[cfg(feature = "messages")]
+    fn create_list(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let name = row
+            .get("name")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'name' is required to create a broadcast list")?;
+        let recipients = broadcasts::recipients(row)?;
+
+        let url = format!("{}/lists", client.base_url);
+        let list = client.send_json(http::Method::Post, url, &serde_json::json!({ "name": name }))?;
+        let list_id = list
+            .get("id")
+            .and_then(|v| v.as_str())
+            .ok_or("Cannot get the id of the created list from Bird response")?;
+        if recipients.is_empty() {
+            return Ok(());
+        }
+        let identifiers: Vec<JsonValue> = recipients
+            .iter()
+            .map(|number| serde_json::json!({"identifierKey": "phonenumber", "identifierValue": number}))
+            .collect();
+        let url = format!("{}/lists/{}/contacts", client.base_url, url_encode(list_id));
+        client.send_json(http::Method::Post, url, &serde_json::json!({ "identifiers": identifiers }))?;
+        Ok(())
+    }
+
+    // Send the message of the row to each member of its list, stopping at
+    // the first failure
+    #[cfg(feature = "messages")]
+    fn send_to_list(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let list_id = row
+            .get("broadcast_id")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'broadcast_id' is required to send to a broadcast list")?;
+        let url = format!("{}/lists/{}/contacts", client.base_url, url_encode(list_id));
+        let members = fetch_pages(client, url, |contact| {
+            let identifiers = contact.get("featuredIdentifiers").and_then(|v| v.as_array());
+            identifiers
+                .into_iter()
+                .flatten()
+                .find(|identifier| identifier.get("key").and_then(|v| v.as_str()) == Some("phonenumber"))
+                .and_then(|identifier| identifier.get("value"))
+                .cloned()
+                .unwrap_or_default()
+        })?;
+
+        let mut sent = 0;
+        for to in members.iter().filter_map(|member| member.as_str()) {
+            self.send_message(client, &broadcast_sends::member_message(row, to))
+                .map_err(|err| format!("Broadcast stopped after {} of {} member(s): {}", sent, members.len(), err))?;
+            sent += 1;
+        }
+        info!("modify", "Sent broadcast to {} member(s) of list '{}'", sent, list_id);
+        Ok(())
+    }
+}
+
+impl Provider for Bird {
+    fn name(&self) -> &'static str {
+        "bird"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = format!("{}/workspaces/{}", API_URL, url_encode(&self.workspace_id));
+        client.envelope = Envelope::Status;
+        client.state.api_key = self.access_key.clone();
+        client.state.key_scheme = KeyScheme::Template {
+            name: "authorization".to_owned(),
+            value: "AccessKey {api_key}".to_owned(),
+        };
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        vec![
+            ("workspace_id".to_owned(), self.workspace_id.clone()),
+            ("channel_id".to_owned(), self.channel_id.clone()),
+        ]
+    }
+
+    fn number(&self) -> Option<&str> {
+        Some(&self.channel_id)
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "messages" => self.fetch_messages(client),
+            #[cfg(feature = "messages")]
+            "button_replies" => self.fetch_incoming(client, button_replies::reply_row),
+            #[cfg(feature = "messages")]
+            "flow_responses" => self.fetch_incoming(client, flow_responses::response_row),
+            #[cfg(feature = "messages")]
+            "broadcasts" => self.fetch_lists(client),
+            "contacts" => self.fetch_contacts(client),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            #[cfg(feature = "messages")]
+            ("broadcasts", Operation::Insert) => self.create_list(client, &row.columns),
+            #[cfg(feature = "messages")]
+            ("broadcast_sends", Operation::Insert) => self.send_to_list(client, &row.columns),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Fetch all pages of a Bird list, following `nextPageToken`
+fn fetch_pages(
+    client: &mut Client,
+    url: String,
+    to_row: fn(&JsonValue) -> JsonValue,
+) -> Result<Vec<JsonValue>, FdwError> {
+    let url = paged_url(url);
+    pagination::fetch_all(|page_token| fetch_page(client, &url, page_token, to_row))
+}
+
+// URL of a listing with the page size
+fn paged_url(url: String) -> String {
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{}{}limit={}", url, separator, PAGE_SIZE)
+}
+
+// Fetch the page of a listing a page token points to, the first page
+// without one
+fn fetch_page(
+    client: &mut Client,
+    url: &str,
+    page_token: Option<&str>,
+    to_row: fn(&JsonValue) -> JsonValue,
+) -> Result<Page, FdwError> {
+    let url = match page_token {
+        Some(token) => format!("{}&pageToken={}", url, url_encode(token)),
+        None => url.to_owned(),
+    };
+    let resp_json = client.get_json(url)?;
+    let results = resp_json
+        .get("results")
+        .and_then(|v| v.as_array())
+        .ok_or("Cannot get 'results' from Bird response")?;
+    Ok(Page {
+        rows: results.iter().map(to_row).collect(),
+        next: resp_json
+            .get("nextPageToken")
+            .and_then(|v| v.as_str())
+            .filter(|v| !v.is_empty())
+            .map(|v| v.to_owned()),
+        total: None,
+    })
+}
+
+// Phone number of a Bird contact reference, e.g.
+// `{"identifierKey": "phonenumber", "identifierValue": "+4915123456789"}`
+fn phone_number(contact: Option<&JsonValue>) -> Option<&str> {
+    contact?.get("identifierValue").and_then(|v| v.as_str())
+}
+
+// Convert a Bird message to the shape of the messages object
+fn message_row(message: &JsonValue) -> JsonValue {
+    let field = |pointer: &str| message.pointer(pointer).cloned().unwrap_or_default();
+    let date = |name: &str| {
+        message
+            .get(name)
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339)
+    };
+    let receiver = phone_number(message.pointer("/receiver/contacts/0"));
+    let sender = phone_number(message.pointer("/sender/contact"));
+
+    let mut row = serde_json::json!({
+        "id": field("/id"),
+        "from": sender,
+        "to": receiver,
+        "type": field("/body/type"),
+        "body": field("/body/text/text"),
+        "template_name": field("/template/projectId"),
+        "language": field("/template/locale"),
+        "status": field("/status"),
+        "direction": field("/direction"),
+        "error_code": field("/failure/code"),
+        "error_message": field("/failure/description"),
+        "quoted_message_id": field("/replyTo/id"),
+        "reply_to_message_id": field("/replyTo/id"),
+        "context": field("/replyTo"),
+        "sent_at": date("createdAt"),
+        "updated_at": date("updatedAt"),
+    });
+    model::add_forwarding(&mut row, message);
+    row
+}
+
+// Convert a Bird conversation to the shape of the contacts object, the
+// contact being its featured participant
+fn conversation_row(conversation: &JsonValue) -> JsonValue {
+    let participant = conversation
+        .get("featuredParticipants")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .find(|p| p.get("type").and_then(|v| v.as_str()) == Some("contact"));
+    let contact = participant.and_then(|p| p.get("contact"));
+    let phone = phone_number(contact);
+
+    serde_json::json!({
+        "id": contact.and_then(|c| c.get("id")),
+        "wa_id": phone.map(|p| p.trim_start_matches('+')),
+        "name": participant.and_then(|p| p.get("displayName")),
+        "phone": phone,
+        "source": "conversation",
+        "status": conversation.get("status"),
+        "attributes": {"conversation_id": conversation.get("id")},
+        "created_at": conversation
+            .get("createdAt")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    })
+}
+
+// Build the request body of a message row: a template message if
+// `template_name` is set, as the template project id optionally followed by
+// `@version`, with its parameters in `components`, a text message with
+// `body` otherwise
+fn message_body(row: &JsonValue) -> Result<JsonValue, FdwError> {
+    let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+    let to = column("to")
+        .and_then(|v| v.as_str())
+        .ok_or("Column 'to' is required to send a message")?;
+
+    let mut body = serde_json::json!({
+        "receiver": {"contacts": [{"identifierKey": "phonenumber", "identifierValue": to}]},
+    });
+    if let Some(name) = column("template_name").and_then(|v| v.as_str()) {
+        let (project_id, version) = match name.split_once('@') {
+            Some((project_id, version)) => (project_id, Some(version)),
+            None => (name, None),
+        };
+        let mut template = serde_json::json!({
+            "projectId": project_id,
+            "locale": column("language").cloned().unwrap_or("en".into()),
+        });
+        if let Some(version) = version {
+            template["version"] = version.into();
+        }
+        if let Some(parameters) = column("components") {
+            template["parameters"] = parameters.clone();
+        }
+        body["template"] = template;
+    } else if let Some(text) = column("body") {
+        body["body"] = serde_json::json!({ "type": "text", "text": { "text": text } });
+    } else {
+        return Err("Either column 'body' or 'template_name' is required to send a message".to_owned());
+    }
+    if let Some(message_id) = column("reply_to_message_id") {
+        body["replyTo"] = serde_json::json!({ "id": message_id, "type": "message" });
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    fn provider() -> Bird {
+        Bird {
+            access_key: "bird-key".to_owned(),
+            workspace_id: "ws-1".to_owned(),
+            channel_id: "ch-1".to_owned(),
+        }
+    }
+
+    #[test]
+    fn maps_messages_and_conversations() {
+        let message = message_row(&serde_json::json!({
+            "id": "msg-1",
+            "sender": {"connector": {"id": "c-1"}},
+            "receiver": {"contacts": [{"id": "ct-1", "identifierKey": "phonenumber", "identifierValue": "+4915123456789"}]},
+            "body": {"type": "text", "text": {"text": "Hi"}},
+            "status": "delivered",
+            "direction": "outgoing",
+            "replyTo": {"id": "msg-0", "order": 0, "type": "message"},
+            "createdAt": "2024-01-02T03:04:05Z",
+        }));
+        assert_eq!(message["to"], "+4915123456789");
+        assert!(message["from"].is_null());
+        assert_eq!(message["body"], "Hi");
+        assert_eq!(message["quoted_message_id"], "msg-0");
+        assert_eq!(message["context"]["type"], "message");
+        assert_eq!(message["is_forwarded"], JsonValue::Null);
+        assert_eq!(message["sent_at"], 1_704_164_645_000_000i64);
+
+        let reply = message_body(&serde_json::json!({
+            "to": "+4915123456789",
+            "body": "Hi",
+            "reply_to_message_id": "msg-0",
+        }));
+        assert_eq!(reply.unwrap()["replyTo"], serde_json::json!({"id": "msg-0", "type": "message"}));
+
+        // Forwarding metadata relayed in the Cloud API shape, or as the
+        // score of WhatsApp Web gateways
+        let forwarded = message_row(&serde_json::json!({
+            "context": {"forwarded": true, "frequently_forwarded": true},
+        }));
+        assert_eq!(forwarded["is_forwarded"], true);
+        assert_eq!(forwarded["frequently_forwarded"], true);
+        assert!(forwarded["forward_count"].is_null());
+        let forwarded = message_row(&serde_json::json!({
+            "forwardingScore": 2,
+            "forwardedNewsletterMessageInfo": {"newsletterName": "Daily News"},
+        }));
+        assert_eq!(forwarded["is_forwarded"], true);
+        assert_eq!(forwarded["forward_count"], 2);
+        assert_eq!(forwarded["frequently_forwarded"], false);
+        assert_eq!(forwarded["forwarded_from"], "Daily News");
+
+        let contact = conversation_row(&serde_json::json!({
+            "id": "conv-1",
+            "status": "active",
+            "featuredParticipants": [
+                {"type": "flow", "displayName": "Bot"},
+                {"type": "contact", "displayName": "Jane", "contact": {"id": "ct-1", "identifierValue": "+4915123456789"}},
+            ],
+        }));
+        assert_eq!(contact["name"], "Jane");
+        assert_eq!(contact["wa_id"], "4915123456789");
+        assert_eq!(contact["attributes"]["conversation_id"], "conv-1");
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn reads_button_replies_of_incoming_messages() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"results": [
+                {"id": "msg-2", "direction": "incoming", "createdAt": "2024-01-02T03:04:05Z",
+                 "sender": {"contact": {"identifierValue": "+4915123456789"}},
+                 "replyTo": {"id": "msg-1"},
+                 "body": {"type": "text", "text": {"text": "Yes", "actions": [{"type": "postback", "postback": {"text": "Yes", "payload": "confirm"}}]}}},
+                {"id": "msg-1", "direction": "outgoing", "body": {"type": "text", "text": {"text": "Confirm?"}}}
+            ]}"#,
+        )]);
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider();
+        provider.connect(&mut client);
+
+        let replies = provider.fetch(&mut client, "button_replies").unwrap();
+        assert_eq!(
+            replies,
+            [serde_json::json!({
+                "message_id": "msg-2",
+                "replied_message_id": "msg-1",
+                "reply_type": "button",
+                "button_id": "confirm",
+                "button_title": "Yes",
+                "contact": "+4915123456789",
+                "replied_at": 1_704_164_645_000_000i64,
+            })]
+        );
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn creates_lists_and_sends_to_their_members() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"id": "list-1", "name": "VIP"}"#),
+            response(200, r#"{}"#),
+            response(
+                200,
+                r#"{"results": [
+                    {"id": "ct-1", "featuredIdentifiers": [{"key": "phonenumber", "value": "+4915123456789"}]},
+                    {"id": "ct-2", "featuredIdentifiers": [{"key": "phonenumber", "value": "+4915100000000"}]}
+                ]}"#,
+            ),
+            response(202, r#"{"id": "msg-1"}"#),
+            response(202, r#"{"id": "msg-2"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let list = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({
+                "name": "VIP",
+                "recipients": ["+49 151 23456789", {"phone": "+4915100000000"}],
+            }),
+        };
+        provider.write_row(&mut client, "broadcasts", &list).unwrap();
+        let send = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"broadcast_id": "list-1", "body": "Sale starts today"}),
+        };
+        provider.write_row(&mut client, "broadcast_sends", &send).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(requests[1].url, "https://api.bird.com/workspaces/ws-1/lists/list-1/contacts");
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(body["identifiers"][0]["identifierValue"], "+4915123456789");
+        assert_eq!(requests[2].url, "https://api.bird.com/workspaces/ws-1/lists/list-1/contacts?limit=100");
+        let body: JsonValue = serde_json::from_str(&requests[4].body).unwrap();
+        assert_eq!(body["receiver"]["contacts"][0]["identifierValue"], "+4915100000000");
+    }
+
+    #[test]
+    fn pages_messages_and_sends_templates() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"results": [{"id": "msg-1"}], "nextPageToken": "p2"}"#),
+            response(200, r#"{"results": [{"id": "msg-2"}]}"#),
+            response(202, r#"{"id": "msg-3", "status": "accepted"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let messages = provider.fetch(&mut client, "messages").unwrap();
+        assert_eq!(messages.len(), 2);
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({
+                "to": "+4915123456789",
+                "template_name": "order_update@v2",
+                "language": "de",
+            }),
+        };
+        provider.write_row(&mut client, "messages", &row).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[1].url,
+            "https://api.bird.com/workspaces/ws-1/channels/ch-1/messages?limit=100&pageToken=p2"
+        );
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("AccessKey bird-key")
+        );
+        let body: JsonValue = serde_json::from_str(&requests[2].body).unwrap();
+        assert_eq!(
+            body["template"],
+            serde_json::json!({"projectId": "order_update", "locale": "de", "version": "v2"})
+        );
+    }
+}
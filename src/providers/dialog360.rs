@@ -0,0 +1,265 @@
+use serde_json::Value as JsonValue;
+
+use super::meta_cloud::message_body;
+use super::{not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{Client, KeyScheme};
+use crate::config::Opts;
+use crate::model::Envelope;
+#[cfg(feature = "messages")]
+use crate::objects::templates;
+use crate::objects::{health, webhook_bridge, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+
+// 360dialog API used unless the `api_url` option is set, e.g. to the
+// sandbox at `https://waba-sandbox.360dialog.io`
+const DEFAULT_API_URL: &str = "https://waba-v2.360dialog.io";
+
+// Templates per page, the maximum accepted by 360dialog
+const TEMPLATES_PAGE_SIZE: usize = 1000;
+
+// Objects served by 360dialog
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: false,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: &[],
+        pagination: Pagination::None,
+    },
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("templates").paged(Pagination::Offset),
+    webhook_bridge::CAPABILITIES,
+    ObjectCapabilities::read_only("health"),
+];
+
+// 360dialog's hosted WhatsApp Business API. Messages take the Cloud API
+// format, relayed for the number the API key was issued for.
+#[derive(Debug)]
+pub(crate) struct Dialog360 {
+    api_key: String,
+    api_url: String,
+    // Definitions of the templates sent, to check the messages
+    #[cfg(feature = "messages")]
+    templates: templates::Definitions,
+}
+
+impl Dialog360 {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        Ok(Box::new(Self {
+            api_key: opts.require("api_key")?,
+            api_url: opts.url_or("api_url", DEFAULT_API_URL),
+            #[cfg(feature = "messages")]
+            templates: templates::Definitions::default(),
+        }))
+    }
+
+    // Probe the webhook configuration, which any valid API key can read
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!("{}/v1/configs/webhook", client.base_url);
+        health::probe(client, url, |_| None)
+    }
+
+    // Read the webhook of the number, a single URL
+    fn fetch_webhook(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!("{}/v1/configs/webhook", client.base_url);
+        let resp_json = client.get_json(url)?;
+        Ok(vec![serde_json::json!({
+            "url": resp_json.get("url").filter(|v| v.as_str() != Some("")),
+            "details": resp_json,
+        })])
+    }
+
+    // Point the webhook of the number at the row's URL
+    fn register_webhook(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let url = format!("{}/v1/configs/webhook", client.base_url);
+        client.send_json(http::Method::Post, url, &serde_json::json!({ "url": row["url"] }))?;
+        Ok(())
+    }
+
+    // Send a message from the number of the API key
+    fn send_message(&mut self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let body = message_body(row)?;
+        // The templates take the Cloud API format too, read from all the
+        // templates of the account as 360dialog doesn't filter them by name
+        #[cfg(feature = "messages")]
+        if let Some(template) = body.get("template") {
+            let now = client.state.clock.now_secs();
+            self.templates.check(now, template, |name| {
+                let mut definitions = fetch_templates(client)?;
+                definitions.retain(|definition| definition["name"].as_str() == Some(name));
+                Ok(definitions)
+            })?;
+        }
+        let url = format!("{}/messages", client.base_url);
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+}
+
+impl Provider for Dialog360 {
+    fn name(&self) -> &'static str {
+        "360dialog"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = self.api_url.clone();
+        client.envelope = Envelope::Graph;
+        client.state.api_key = self.api_key.clone();
+        client.state.key_scheme = KeyScheme::Header("D360-API-KEY");
+    }
+
+    fn checks_templates(&self) -> bool {
+        true
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "templates" => fetch_templates(client),
+            "webhook_bridge" => self.fetch_webhook(client),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            ("webhook_bridge", Operation::Insert) => self.register_webhook(client, &row.columns),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Fetch the message templates of the account, paging by offset
+fn fetch_templates(client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+    pagination::fetch_all(|offset| {
+        let offset = offset.unwrap_or("0");
+        let url = format!(
+            "{}/v1/configs/templates?limit={}&offset={}",
+            client.base_url, TEMPLATES_PAGE_SIZE, offset
+        );
+        let resp_json = client.get_json(url)?;
+        parse_templates(&resp_json, offset.parse().unwrap_or(0))
+    })
+}
+
+// Parse a page of templates, the next offset being given while the
+// `total` count isn't reached
+fn parse_templates(resp_json: &JsonValue, offset: usize) -> Result<Page, FdwError> {
+    let templates = resp_json
+        .get("waba_templates")
+        .and_then(|v| v.as_array())
+        .ok_or("Cannot get 'waba_templates' from 360dialog response")?;
+    let total = resp_json.get("total").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+    let end = offset + templates.len();
+    Ok(Page {
+        rows: templates.to_owned(),
+        next: (!templates.is_empty() && end < total).then(|| end.to_string()),
+        total: Some(total as i64),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    fn provider() -> Dialog360 {
+        Dialog360 {
+            api_key: "d360-key".to_owned(),
+            api_url: DEFAULT_API_URL.to_owned(),
+            #[cfg(feature = "messages")]
+            templates: templates::Definitions::default(),
+        }
+    }
+
+    #[test]
+    fn pages_templates_by_offset() {
+        let page = |offset| {
+            let resp_json = serde_json::json!({
+                "waba_templates": [{"name": "welcome"}, {"name": "order_update"}],
+                "total": 3,
+            });
+            parse_templates(&resp_json, offset).unwrap().next
+        };
+        assert_eq!(page(0), Some("2".to_owned()));
+        assert_eq!(page(1), None);
+        assert!(parse_templates(&serde_json::json!({"templates": []}), 0).is_err());
+    }
+
+    #[test]
+    fn sends_messages_with_d360_api_key() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"messaging_product": "whatsapp", "messages": [{"id": "wamid.HBgM"}]}"#,
+            ),
+            response(
+                401,
+                r#"{"meta": {"success": false, "http_code": 401, "developer_message": "Invalid api key"}}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915123456789", "body": "Hi"}),
+        };
+        provider.write_row(&mut client, "messages", &row).unwrap();
+        assert!(provider.write_row(&mut client, "messages", &row).is_err());
+
+        let requests = requests.borrow();
+        assert_eq!(requests[0].url, "https://waba-v2.360dialog.io/messages");
+        assert_eq!(
+            header_value(&requests[0].headers, "d360-api-key"),
+            Some("d360-key")
+        );
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn checks_template_variables_before_sending() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"waba_templates": [
+                {"name": "welcome", "language": "en_US", "components": [{"type": "BODY", "text": "Welcome"}]},
+                {"name": "order_update", "language": "en_US", "components": [{"type": "BODY", "text": "Order {{1}}"}]}
+            ], "total": 2}"#,
+        )]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(crate::clock::mock::FakeClock::at(1_700_000_000));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915123456789", "template_name": "order_update"}),
+        };
+        let err = provider.write_row(&mut client, "messages", &row).unwrap_err();
+        assert_eq!(err, "Template 'order_update' expects 1 body variable, got 0");
+        assert_eq!(
+            requests.borrow()[0].url,
+            "https://waba-v2.360dialog.io/v1/configs/templates?limit=1000&offset=0"
+        );
+        assert_eq!(requests.borrow().len(), 1);
+    }
+}
@@ -0,0 +1,314 @@
+use serde_json::{Map, Value as JsonValue};
+
+use super::{not_implemented, Provider};
+use crate::bindings::supabase::wrappers::{http, types::FdwError};
+use crate::client::{join_url, normalize_url, Client, KeyScheme};
+use crate::config::Opts;
+use crate::model::Envelope;
+use crate::objects::{self, health, ObjectCapabilities, Operation};
+use crate::timestamp;
+
+// How a column value is read from a source row
+#[derive(Debug)]
+struct ColumnSpec {
+    name: String,
+    // JSON pointer into the source row, e.g. `/key/id`
+    path: String,
+    format: Format,
+}
+
+// Conversion of a column value, for timestamps not already in
+// microseconds since Unix epoch
+#[derive(Debug, Clone, Copy)]
+enum Format {
+    Raw,
+    EpochSecs,
+    EpochMillis,
+    Rfc3339,
+    Rfc2822,
+}
+
+// How the rows of an object are fetched
+#[derive(Debug)]
+struct ObjectSpec {
+    method: http::Method,
+    // Path of the endpoint, relative to the base URL
+    path: String,
+    // JSON body of a POST request
+    body: Option<JsonValue>,
+    // JSON pointer to the array of rows in the response, the response
+    // itself by default
+    rows: String,
+    columns: Vec<ColumnSpec>,
+}
+
+// Any REST API returning JSON, e.g. a self-hosted gateway such as
+// Evolution API, configured by the declarative spec of the `spec` server
+// option. Objects are scanned from a single request each, without
+// pagination.
+#[derive(Debug)]
+pub(crate) struct Generic {
+    base_url: String,
+    api_key: String,
+    // Header carrying the API key, e.g. `("apikey", "{api_key}")`
+    auth_header: Option<(String, String)>,
+    health_path: String,
+    objects: Vec<(&'static str, ObjectSpec)>,
+    capabilities: Vec<ObjectCapabilities>,
+}
+
+impl Generic {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        let spec = opts.require("spec")?;
+        let spec: JsonValue = serde_json::from_str(&spec).map_err(|e| invalid_spec(&e.to_string()))?;
+        let text = |key: &str| spec.get(key).and_then(|v| v.as_str());
+
+        let base_url = text("base_url").ok_or_else(|| invalid_spec("'base_url' is required"))?;
+        let auth_header = match text("auth_header") {
+            Some(template) => {
+                let (name, value) = template
+                    .split_once(':')
+                    .ok_or_else(|| invalid_spec("'auth_header' must be 'Name: value'"))?;
+                Some((name.trim().to_owned(), value.trim().to_owned()))
+            }
+            None => None,
+        };
+
+        let mut objects = Vec::new();
+        let specs = spec.get("objects").and_then(|v| v.as_object());
+        for (name, object) in specs.into_iter().flatten() {
+            // Only the objects of this build can be described
+            let name = objects::lookup(name)?.name();
+            objects.push((name, parse_object(name, object)?));
+        }
+        let mut capabilities: Vec<ObjectCapabilities> =
+            objects.iter().map(|(name, _)| ObjectCapabilities::read_only(name)).collect();
+        capabilities.push(ObjectCapabilities::read_only("health"));
+
+        Ok(Box::new(Self {
+            base_url: normalize_url(base_url),
+            api_key: opts.string_or("api_key", ""),
+            auth_header,
+            health_path: text("health_path").unwrap_or_default().to_owned(),
+            objects,
+            capabilities,
+        }))
+    }
+
+    // Fetch the rows of an object with the request of its spec
+    fn fetch_object(&self, client: &mut Client, object: &ObjectSpec) -> Result<Vec<JsonValue>, FdwError> {
+        let url = join_url(&client.base_url, &object.path);
+        let resp_json = match object.method {
+            http::Method::Get => client.get_json(url)?,
+            method => client.send_json(method, url, object.body.as_ref().unwrap_or(&JsonValue::Null))?,
+        };
+        let rows = resp_json
+            .pointer(&object.rows)
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| format!("Cannot get rows at '{}' from the response", object.rows))?;
+        Ok(rows.iter().map(|row| map_row(row, &object.columns)).collect())
+    }
+}
+
+impl Provider for Generic {
+    fn name(&self) -> &'static str {
+        "generic"
+    }
+
+    fn objects(&self) -> &[ObjectCapabilities] {
+        &self.capabilities
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = self.base_url.clone();
+        client.envelope = Envelope::Status;
+        client.state.api_key = self.api_key.clone();
+        if let Some((name, value)) = &self.auth_header {
+            client.state.key_scheme = KeyScheme::Template {
+                name: name.clone(),
+                value: value.clone(),
+            };
+        }
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        if object == "health" {
+            let url = join_url(&client.base_url, &self.health_path);
+            return Ok(health::probe(client, url, |_| None));
+        }
+        match self.objects.iter().find(|(name, _)| *name == object) {
+            Some((_, spec)) => self.fetch_object(client, spec),
+            None => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+}
+
+fn invalid_spec(reason: &str) -> FdwError {
+    format!("Invalid 'spec' option of provider 'generic': {}", reason)
+}
+
+// Parse the spec of an object, e.g.
+// `{"path": "/messages", "rows": "/data", "columns": {"id": "/key/id"}}`
+fn parse_object(name: &str, object: &JsonValue) -> Result<ObjectSpec, FdwError> {
+    let invalid = |reason: &str| invalid_spec(&format!("object '{}': {}", name, reason));
+    let text = |key: &str| object.get(key).and_then(|v| v.as_str());
+
+    let method = match text("method").unwrap_or("GET") {
+        "GET" => http::Method::Get,
+        "POST" => http::Method::Post,
+        other => return Err(invalid(&format!("unsupported method '{}', expected GET or POST", other))),
+    };
+    let path = text("path").ok_or_else(|| invalid("'path' is required"))?;
+
+    let mut columns = Vec::new();
+    let column_specs = object.get("columns").and_then(|v| v.as_object());
+    for (column, spec) in column_specs.into_iter().flatten() {
+        let (path, format) = match spec {
+            JsonValue::String(path) => (path.as_str(), "raw"),
+            JsonValue::Object(spec) => (
+                spec.get("path").and_then(|v| v.as_str()).unwrap_or_default(),
+                spec.get("format").and_then(|v| v.as_str()).unwrap_or("raw"),
+            ),
+            _ => return Err(invalid(&format!("column '{}' must be a path or an object", column))),
+        };
+        if !path.is_empty() && !path.starts_with('/') {
+            return Err(invalid(&format!("path '{}' of column '{}' must start with '/'", path, column)));
+        }
+        let format = match format {
+            "raw" => Format::Raw,
+            "epoch_secs" => Format::EpochSecs,
+            "epoch_millis" => Format::EpochMillis,
+            "rfc3339" => Format::Rfc3339,
+            "rfc2822" => Format::Rfc2822,
+            other => {
+                return Err(invalid(&format!(
+                    "unknown format '{}' of column '{}', expected one of raw, epoch_secs, epoch_millis, rfc3339, rfc2822",
+                    other, column
+                )))
+            }
+        };
+        columns.push(ColumnSpec {
+            name: column.clone(),
+            path: path.to_owned(),
+            format,
+        });
+    }
+
+    Ok(ObjectSpec {
+        method,
+        path: path.to_owned(),
+        body: object.get("body").cloned(),
+        rows: text("rows").unwrap_or_default().to_owned(),
+        columns,
+    })
+}
+
+// Build a row of the object from a source row: the fields of the source row
+// are kept, and the columns of the spec read from their path
+fn map_row(src_row: &JsonValue, columns: &[ColumnSpec]) -> JsonValue {
+    let mut row = match src_row {
+        JsonValue::Object(fields) => fields.clone(),
+        _ => Map::new(),
+    };
+    for column in columns {
+        let value = src_row.pointer(&column.path).map_or(JsonValue::Null, |v| convert(v, column.format));
+        row.insert(column.name.clone(), value);
+    }
+    JsonValue::Object(row)
+}
+
+// Convert a value to the format of the object columns, timestamps in
+// microseconds since Unix epoch
+fn convert(value: &JsonValue, format: Format) -> JsonValue {
+    let number = || match value {
+        JsonValue::String(s) => s.trim().parse::<i64>().ok(),
+        _ => value.as_i64(),
+    };
+    let text = || value.as_str();
+    let micros = match format {
+        Format::Raw => return value.clone(),
+        Format::EpochSecs => number().and_then(|secs| secs.checked_mul(1_000_000)),
+        Format::EpochMillis => number().and_then(|millis| millis.checked_mul(1_000)),
+        Format::Rfc3339 => text().and_then(timestamp::parse_rfc3339),
+        Format::Rfc2822 => text().and_then(timestamp::parse_rfc2822),
+    };
+    micros.map_or(JsonValue::Null, JsonValue::from)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    // An Evolution API instance, listing its chats
+    const SPEC: &str = r#"{
+        "base_url": "http://evolution:8080/",
+        "auth_header": "apikey: {api_key}",
+        "objects": {
+            "contacts": {
+                "method": "POST",
+                "path": "/chat/findContacts/main",
+                "body": {"where": {}},
+                "columns": {
+                    "id": "/remoteJid",
+                    "name": "/pushName",
+                    "created_at": {"path": "/createdAt", "format": "rfc3339"}
+                }
+            }
+        }
+    }"#;
+
+    fn provider(spec: &str) -> Result<Box<dyn Provider>, FdwError> {
+        let options = HashMap::from([("spec", spec), ("api_key", "evolution-key")]);
+        Generic::parse(&Opts::new(&options))
+    }
+
+    #[test]
+    fn scans_objects_of_the_spec() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"[{"remoteJid": "4915123456789@s.whatsapp.net", "pushName": "Jane", "createdAt": "2024-01-02T03:04:05Z", "profilePicUrl": null}]"#,
+        )]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider(SPEC).unwrap();
+        provider.connect(&mut client);
+
+        assert_eq!(provider.capabilities("contacts").unwrap().operations(), ["scan"]);
+        let contacts = provider.fetch(&mut client, "contacts").unwrap();
+        assert_eq!(contacts[0]["id"], "4915123456789@s.whatsapp.net");
+        assert_eq!(contacts[0]["name"], "Jane");
+        assert_eq!(contacts[0]["created_at"], 1_704_164_645_000_000i64);
+        assert!(contacts[0]["profilePicUrl"].is_null());
+
+        let requests = requests.borrow();
+        assert_eq!(requests[0].url, "http://evolution:8080/chat/findContacts/main");
+        assert_eq!(requests[0].body, r#"{"where":{}}"#);
+        assert_eq!(header_value(&requests[0].headers, "apikey"), Some("evolution-key"));
+    }
+
+    #[test]
+    fn converts_timestamp_formats() {
+        let value = |v: JsonValue, format| convert(&v, format);
+        assert_eq!(value(1_700_000_000.into(), Format::EpochSecs), 1_700_000_000_000_000i64);
+        assert_eq!(value("1700000000123".into(), Format::EpochMillis), 1_700_000_000_123_000i64);
+        assert!(value("yesterday".into(), Format::Rfc3339).is_null());
+        assert_eq!(value("raw".into(), Format::Raw), "raw");
+    }
+
+    #[test]
+    fn rejects_invalid_specs() {
+        let err = |spec: &str| provider(spec).unwrap_err();
+        assert!(err("{").starts_with("Invalid 'spec' option of provider 'generic': "));
+        assert!(err(r#"{"objects": {}}"#).ends_with("'base_url' is required"));
+        assert!(err(r#"{"base_url": "http://x", "auth_header": "Bearer {api_key}"}"#).contains("'Name: value'"));
+        assert!(
+            err(r#"{"base_url": "http://x", "objects": {"contacts": {"path": "/c", "columns": {"id": "id"}}}}"#)
+                .contains("path 'id' of column 'id' must start with '/'")
+        );
+        assert!(err(r#"{"base_url": "http://x", "objects": {"chats": {"path": "/c"}}}"#).contains("'chats'"));
+    }
+}
@@ -0,0 +1,150 @@
+use serde_json::Value as JsonValue;
+
+use crate::bindings::supabase::wrappers::{http, types::FdwError};
+use crate::client::{url_encode, Client, KeyScheme};
+use crate::config::Opts;
+use crate::log;
+use crate::model::Envelope;
+use crate::pagination::{self, Page};
+
+// Graph API version used unless the `api_version` option is set
+pub(crate) const DEFAULT_API_VERSION: &str = "v21.0";
+
+// Meta app owning the system user, whose credentials renew its access
+// token
+#[derive(Debug)]
+struct App {
+    id: String,
+    secret: String,
+}
+
+// Access to the Meta Graph API, shared by the Meta providers
+#[derive(Debug)]
+pub(crate) struct Graph {
+    access_token: String,
+    api_version: String,
+    app: Option<App>,
+}
+
+impl Graph {
+    pub(crate) fn parse(opts: &Opts) -> Result<Self, FdwError> {
+        // With OAuth the access token is obtained from the token endpoint
+        let access_token = match opts.string("token_url") {
+            Some(_) => opts.secret("access_token")?.unwrap_or_default(),
+            None => opts.require_secret("access_token")?,
+        };
+        let app = match opts.string("app_id") {
+            Some(id) => Some(App {
+                id,
+                secret: opts.require_secret("app_secret")?,
+            }),
+            None => None,
+        };
+        Ok(Self {
+            access_token,
+            api_version: opts.string_or("api_version", DEFAULT_API_VERSION),
+            app,
+        })
+    }
+
+    // Exchange the access token for a new long-lived one, system-user
+    // tokens being renewed for 60 days. The exchanged token is returned as
+    // the single row of the `access_token` object, as the FDW can read but
+    // not update the Vault secret holding the token. The credentials go in
+    // a POST body rather than the URL, which the cache keys on and the
+    // fixtures record.
+    pub(crate) fn exchange_token(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let app = self
+            .app
+            .as_ref()
+            .ok_or("Object 'access_token' requires the server options 'app_id' and 'app_secret' or 'app_secret_id'")?;
+        let url = format!("{}/oauth/access_token", client.base_url);
+        let form = [
+            ("grant_type", "fb_exchange_token"),
+            ("client_id", app.id.as_str()),
+            ("client_secret", app.secret.as_str()),
+            ("fb_exchange_token", self.access_token.as_str()),
+            ("set_token_expires_in_60_days", "true"),
+        ];
+        let resp_json = client.send_form(http::Method::Post, url, &form)?;
+        let access_token = resp_json
+            .get("access_token")
+            .and_then(|v| v.as_str())
+            .ok_or("Cannot get 'access_token' from token exchange response")?;
+        log::add_secret(access_token);
+
+        // A token without `expires_in` never expires
+        let now = client.state.clock.now_secs();
+        let expires_at = resp_json
+            .get("expires_in")
+            .and_then(|v| v.as_i64())
+            .and_then(|expires_in| now.checked_add(expires_in)?.checked_mul(1_000_000));
+        Ok(vec![serde_json::json!({
+            "access_token": access_token,
+            "token_type": resp_json.get("token_type"),
+            "expires_at": expires_at,
+            "exchanged_at": now * 1_000_000,
+        })])
+    }
+
+    // Point the client at the Graph API, with the access token as a bearer
+    // token
+    pub(crate) fn connect(&self, client: &mut Client) {
+        client.base_url = format!("https://graph.facebook.com/{}", self.api_version);
+        client.envelope = Envelope::Graph;
+        client.state.api_key = self.access_token.clone();
+        client.state.key_scheme = KeyScheme::Bearer;
+        if let Some(app) = &self.app {
+            log::add_secret(&app.secret);
+        }
+    }
+}
+
+// Fetch all the nodes of an edge, e.g. `{waba_id}/phone_numbers`, following
+// the paging cursors
+pub(crate) fn fetch_edge(
+    client: &mut Client,
+    edge: &str,
+    fields: &str,
+) -> Result<Vec<JsonValue>, FdwError> {
+    pagination::fetch_all(|cursor| fetch_edge_page(client, edge, fields, None, cursor))
+}
+
+// Fetch the page of the nodes of an edge a cursor points to, the first page
+// without one, only the nodes matching `filter` if set, e.g.
+// `{"retailer_id":{"eq":"SKU-001"}}` on the products of a catalog. The total
+// count of the edge comes with each page for a strict consistency scan, to
+// tell when it changed.
+pub(crate) fn fetch_edge_page(
+    client: &mut Client,
+    edge: &str,
+    fields: &str,
+    filter: Option<&str>,
+    cursor: Option<&str>,
+) -> Result<Page, FdwError> {
+    let mut url = format!("{}/{}?fields={}&limit=100", client.base_url, edge, fields);
+    if client.state.count_totals {
+        url.push_str("&summary=total_count");
+    }
+    if let Some(filter) = filter {
+        url = format!("{}&filter={}", url, url_encode(filter));
+    }
+    let resp_json = match cursor {
+        Some(cursor) => client.get_next_page_json(format!("{}&after={}", url, url_encode(cursor)))?,
+        None => client.get_json(url)?,
+    };
+    Ok(Page {
+        rows: parse_data(&resp_json)?,
+        next: pagination::graph_cursor(&resp_json),
+        total: pagination::graph_total(&resp_json),
+    })
+}
+
+// Extract the `data` array of a response, a missing array being an empty
+// edge
+pub(crate) fn parse_data(resp_json: &JsonValue) -> Result<Vec<JsonValue>, FdwError> {
+    match resp_json.pointer("/data") {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(data) => Ok(data.as_array().ok_or("'data' is not an array")?.to_owned()),
+    }
+}
@@ -0,0 +1,436 @@
+use serde_json::Value as JsonValue;
+
+use super::graph::{self, Graph};
+use super::{not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+#[cfg(feature = "messages")]
+use crate::client::url_encode;
+use crate::client::Client;
+use crate::config::Opts;
+#[cfg(feature = "messages")]
+use crate::objects::templates;
+use crate::objects::{health, webhook_bridge, ObjectCapabilities, Operation, Pagination};
+
+// Objects served by the Cloud API
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: false,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: &[],
+        pagination: Pagination::None,
+    },
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("templates").paged(Pagination::Cursor),
+    ObjectCapabilities::read_only("phone_numbers").paged(Pagination::Cursor),
+    ObjectCapabilities::read_only("business_profile").paged(Pagination::Cursor),
+    webhook_bridge::CAPABILITIES,
+    ObjectCapabilities::read_only("access_token"),
+    ObjectCapabilities::read_only("health"),
+];
+
+// Meta's WhatsApp Cloud API, called directly on the Graph API with a
+// system-user access token
+#[derive(Debug)]
+pub(crate) struct MetaCloud {
+    graph: Graph,
+    // Id of the business phone number messages are sent from
+    phone_number_id: String,
+    // Id of the WhatsApp Business Account, owning templates and numbers
+    waba_id: Option<String>,
+    // Definitions of the templates sent, to check the messages
+    #[cfg(feature = "messages")]
+    templates: templates::Definitions,
+}
+
+impl MetaCloud {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        Ok(Box::new(Self {
+            graph: Graph::parse(opts)?,
+            phone_number_id: opts.require("phone_number_id")?,
+            waba_id: opts.string("waba_id"),
+            #[cfg(feature = "messages")]
+            templates: templates::Definitions::default(),
+        }))
+    }
+
+    // Id of the WhatsApp Business Account, required by the account objects
+    fn waba_id(&self, object: &str) -> Result<&str, FdwError> {
+        self.waba_id
+            .as_deref()
+            .ok_or_else(|| format!("Object '{}' requires the 'waba_id' server option", object))
+    }
+
+    // Probe the business phone number, reading its status
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!(
+            "{}/{}?fields=display_phone_number,status",
+            client.base_url, self.phone_number_id
+        );
+        health::probe(client, url, |resp_json| resp_json.get("status").cloned())
+    }
+
+    // Subscribe the app to the webhooks of the account, overriding its
+    // callback URL for this account only
+    fn register_webhook(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let verify_token = row
+            .get("verify_token")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'verify_token' or table option 'verify_token' is required to register a Meta webhook")?;
+        let url = format!("{}/{}/subscribed_apps", client.base_url, self.waba_id("webhook_bridge")?);
+        let body = serde_json::json!({
+            "override_callback_uri": row["url"],
+            "verify_token": verify_token,
+        });
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+
+    // Send a message from the business phone number
+    fn send_message(&mut self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let body = message_body(row)?;
+        #[cfg(feature = "messages")]
+        self.check_template(client, &body)?;
+        let url = format!("{}/{}/messages", client.base_url, self.phone_number_id);
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+
+    // Check the variables of a template message against the definition of
+    // its template, read from the account unless read recently. Without
+    // `waba_id` the templates can't be read and the API checks the message.
+    #[cfg(feature = "messages")]
+    fn check_template(&mut self, client: &mut Client, body: &JsonValue) -> FdwResult {
+        let Some(template) = body.get("template") else {
+            return Ok(());
+        };
+        let Some(waba_id) = &self.waba_id else {
+            return Ok(());
+        };
+        let now = client.state.clock.now_secs();
+        self.templates.check(now, template, |name| {
+            let url = format!(
+                "{}/{}/message_templates?fields=name,language,components&name={}",
+                client.base_url,
+                waba_id,
+                url_encode(name)
+            );
+            graph::parse_data(&client.get_json(url)?)
+        })
+    }
+}
+
+impl Provider for MetaCloud {
+    fn name(&self) -> &'static str {
+        "meta_cloud"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        self.graph.connect(client);
+    }
+
+    fn checks_templates(&self) -> bool {
+        self.waba_id.is_some()
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        let mut identities = vec![("phone_number_id".to_owned(), self.phone_number_id.clone())];
+        if let Some(waba_id) = &self.waba_id {
+            identities.push(("waba_id".to_owned(), waba_id.clone()));
+        }
+        identities
+    }
+
+    fn number(&self) -> Option<&str> {
+        Some(&self.phone_number_id)
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "templates" => graph::fetch_edge(
+                client,
+                &format!("{}/message_templates", self.waba_id(object)?),
+                "id,name,language,status,category,components",
+            ),
+            "phone_numbers" => graph::fetch_edge(
+                client,
+                &format!("{}/phone_numbers", self.waba_id(object)?),
+                "id,display_phone_number,verified_name,quality_rating,code_verification_status,name_status,messaging_limit_tier",
+            ),
+            "business_profile" => graph::fetch_edge(
+                client,
+                &format!("{}/whatsapp_business_profile", self.phone_number_id),
+                "about,address,description,email,profile_picture_url,websites,vertical",
+            ),
+            "access_token" => self.graph.exchange_token(client),
+            "webhook_bridge" => {
+                let apps = graph::fetch_edge(
+                    client,
+                    &format!("{}/subscribed_apps", self.waba_id(object)?),
+                    "whatsapp_business_api_data,override_callback_uri",
+                )?;
+                Ok(apps.into_iter().map(subscribed_app_row).collect())
+            }
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            ("webhook_bridge", Operation::Insert) => self.register_webhook(client, &row.columns),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Convert an app subscribed to the account to a webhook registration, its
+// URL being the app's own callback unless overridden
+fn subscribed_app_row(app: JsonValue) -> JsonValue {
+    serde_json::json!({
+        "url": app.get("override_callback_uri"),
+        "details": app,
+    })
+}
+
+// Build the Cloud API request body of a message row: a template message if
+// `template_name` is set, a text message with `body` otherwise
+pub(super) fn message_body(row: &JsonValue) -> Result<JsonValue, FdwError> {
+    let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+    let to = column("to").ok_or("Column 'to' is required to send a message")?;
+
+    let mut body = serde_json::json!({
+        "messaging_product": "whatsapp",
+        "recipient_type": "individual",
+        "to": to,
+    });
+    if let Some(name) = column("template_name") {
+        let mut template = serde_json::json!({
+            "name": name,
+            "language": { "code": column("language").cloned().unwrap_or("en_US".into()) },
+        });
+        if let Some(components) = column("components") {
+            template["components"] = components.clone();
+        }
+        body["type"] = "template".into();
+        body["template"] = template;
+    } else if let Some(text) = column("body") {
+        body["type"] = "text".into();
+        body["text"] = serde_json::json!({ "body": text });
+    } else {
+        return Err("Either column 'body' or 'template_name' is required to send a message".to_owned());
+    }
+    if let Some(message_id) = column("reply_to_message_id") {
+        body["context"] = serde_json::json!({ "message_id": message_id });
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::{header_value, method_name};
+    use crate::client::mock::{response, MockTransport};
+    use crate::clock::mock::FakeClock;
+
+    fn provider(waba_id: Option<&str>) -> MetaCloud {
+        let options = HashMap::from([("access_token", "system-user-token")]);
+        MetaCloud {
+            graph: Graph::parse(&Opts::new(&options)).unwrap(),
+            phone_number_id: "106540352242922".to_owned(),
+            waba_id: waba_id.map(|id| id.to_owned()),
+            #[cfg(feature = "messages")]
+            templates: templates::Definitions::default(),
+        }
+    }
+
+    #[test]
+    fn builds_text_and_template_messages() {
+        let text = message_body(&serde_json::json!({"to": "+4915123456789", "body": "Hi"})).unwrap();
+        assert_eq!(text["type"], "text");
+        assert_eq!(text["text"]["body"], "Hi");
+
+        let template = message_body(&serde_json::json!({
+            "to": "+4915123456789",
+            "template_name": "order_update",
+            "language": "de",
+            "components": [{"type": "body", "parameters": [{"type": "text", "text": "42"}]}],
+            "body": null,
+        }))
+        .unwrap();
+        assert_eq!(template["type"], "template");
+        assert_eq!(template["template"]["language"]["code"], "de");
+        assert_eq!(template["template"]["components"][0]["type"], "body");
+
+        assert!(message_body(&serde_json::json!({"body": "Hi"})).is_err());
+        assert!(message_body(&serde_json::json!({"to": "+4915123456789"})).is_err());
+    }
+
+    #[test]
+    fn replies_in_the_context_of_a_message() {
+        let reply = message_body(&serde_json::json!({
+            "to": "+4915123456789",
+            "body": "Shipped yesterday",
+            "reply_to_message_id": "wamid.HBgL",
+        }))
+        .unwrap();
+        assert_eq!(reply["context"], serde_json::json!({"message_id": "wamid.HBgL"}));
+    }
+
+    #[test]
+    fn fetches_graph_edges_with_bearer_token() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"data": [{"id": "1", "name": "welcome"}], "paging": {"cursors": {"after": "MQ"}, "next": "https://graph.facebook.com/v21.0/x"}}"#,
+            ),
+            response(200, r#"{"data": [{"id": "2", "name": "order_update"}], "paging": {"cursors": {"after": "Mg"}}}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider(Some("102290129340398"));
+        provider.connect(&mut client);
+
+        let templates = provider.fetch(&mut client, "templates").unwrap();
+        assert_eq!(templates.len(), 2);
+
+        let requests = requests.borrow();
+        assert!(requests[1].url.starts_with(
+            "https://graph.facebook.com/v21.0/102290129340398/message_templates?fields="
+        ));
+        assert!(requests[1].url.ends_with("&limit=100&after=MQ"));
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("Bearer system-user-token")
+        );
+    }
+
+    #[test]
+    fn account_objects_require_waba_id() {
+        let mut client = Client::with_transport(Box::new(MockTransport::new(vec![])));
+        let err = provider(None).fetch(&mut client, "phone_numbers").unwrap_err();
+        assert_eq!(err, "Object 'phone_numbers' requires the 'waba_id' server option");
+    }
+
+    #[test]
+    fn exchanges_the_access_token_with_the_app_credentials() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"access_token": "renewed-token", "token_type": "bearer", "expires_in": 5184000}"#,
+        )]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        let options = HashMap::from([
+            ("access_token", "system-user-token"),
+            ("app_id", "1234567890"),
+            ("app_secret", "app-secret"),
+        ]);
+        let graph = Graph::parse(&Opts::new(&options)).unwrap();
+        graph.connect(&mut client);
+
+        let rows = graph.exchange_token(&mut client).unwrap();
+        assert_eq!(rows[0]["access_token"], "renewed-token");
+        assert_eq!(rows[0]["expires_at"], 1_705_184_000_000_000i64);
+        let request = &requests.borrow()[0];
+        assert_eq!(method_name(&request.method), "POST");
+        assert!(request.url.ends_with("/oauth/access_token"));
+        assert_eq!(
+            request.body,
+            "grant_type=fb_exchange_token&client_id=1234567890&client_secret=app-secret&fb_exchange_token=system-user-token&set_token_expires_in_60_days=true"
+        );
+
+        let err = provider(None).fetch(&mut client, "access_token").unwrap_err();
+        assert!(err.contains("'app_id'"), "{}", err);
+    }
+
+    #[test]
+    fn graph_errors_fail_the_send() {
+        let transport = MockTransport::new(vec![response(
+            400,
+            r#"{"error": {"message": "(#131047) Re-engagement message", "code": 131047, "fbtrace_id": "AbC"}}"#,
+        )]);
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider(None);
+        provider.connect(&mut client);
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915123456789", "body": "Hi"}),
+        };
+        let err = provider.write_row(&mut client, "messages", &row).unwrap_err();
+        assert!(err.contains("Meta error 131047"), "{}", err);
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn checks_template_variables_before_sending() {
+        let definitions = r#"{"data": [
+            {"name": "order_update", "language": "de", "components": [{"type": "BODY", "text": "Bestellung {{1}}"}]},
+            {"name": "order_update", "language": "en_US", "components": [{"type": "BODY", "text": "Hi {{1}}, order {{2}} ships {{3}}"}]}
+        ]}"#;
+        let transport = MockTransport::new(vec![
+            response(200, definitions),
+            response(200, r#"{"messages": [{"id": "wamid.1"}]}"#),
+            response(200, definitions),
+            response(200, definitions),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let clock = FakeClock::at(1_700_000_000);
+        client.state.clock = Box::new(clock.clone());
+        let mut provider = provider(Some("102290129340398"));
+        provider.connect(&mut client);
+
+        let send = |parameters: &[&str]| {
+            let parameters: Vec<JsonValue> =
+                parameters.iter().map(|p| serde_json::json!({"type": "text", "text": p})).collect();
+            WriteRow {
+                operation: Operation::Insert,
+                rowid: None,
+                columns: serde_json::json!({
+                    "to": "+4915123456789",
+                    "template_name": "order_update",
+                    "components": [{"type": "body", "parameters": parameters}],
+                }),
+            }
+        };
+        provider.write_row(&mut client, "messages", &send(&["Jane", "42", "today"])).unwrap();
+        assert!(requests.borrow()[0].url.ends_with(
+            "/102290129340398/message_templates?fields=name,language,components&name=order_update"
+        ));
+
+        // The definition is reused, the message failing without a request
+        let err = provider.write_row(&mut client, "messages", &send(&["Jane", "42"])).unwrap_err();
+        assert_eq!(err, "Template 'order_update' expects 3 body variables, got 2");
+        assert_eq!(requests.borrow().len(), 2);
+
+        // Until it expires
+        clock.advance(templates::DEFINITION_TTL_SECS);
+        assert!(provider.write_row(&mut client, "messages", &send(&["Jane", "42"])).is_err());
+        assert_eq!(requests.borrow().len(), 3);
+
+        let row = WriteRow {
+            columns: serde_json::json!({"to": "+4915123456789", "template_name": "order_update", "language": "fr"}),
+            ..send(&[])
+        };
+        let err = provider.write_row(&mut client, "messages", &row).unwrap_err();
+        assert_eq!(err, "Template 'order_update' in language 'fr' doesn't exist in the account");
+        assert_eq!(requests.borrow().len(), 4);
+    }
+}
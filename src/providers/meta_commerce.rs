@@ -0,0 +1,823 @@
+use serde_json::Value as JsonValue;
+
+use super::graph::{self, Graph};
+use super::{not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{url_encode, Client};
+use crate::config::Opts;
+use crate::mapping;
+#[cfg(feature = "commerce")]
+use crate::mapping::OBJECT_COLUMNS;
+#[cfg(feature = "commerce")]
+use crate::objects::import_products;
+#[cfg(feature = "commerce")]
+use crate::objects::product_images::{self, ImageChange};
+use crate::objects::{health, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+
+// Product fields read from the catalog
+const PRODUCT_FIELDS: &str = "id,retailer_id,name,description,url,currency,price,availability,visibility,inventory,review_status,image_url,additional_image_urls";
+
+// Product fields read for their images
+const IMAGE_FIELDS: &str = "id,retailer_id,image_url,additional_image_urls";
+
+// Product changes sent per request of the batch endpoint, which accepts
+// up to 5000
+const BATCH_SIZE: usize = 1000;
+
+// Bytes of product changes sent per request of the batch endpoint, long
+// descriptions and image lists making a full batch too large for the API
+const BATCH_BYTES: usize = 1_000_000;
+
+// Objects served by the Commerce Catalog API
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "commerce")]
+    ObjectCapabilities {
+        name: "products",
+        scan: true,
+        insert: true,
+        update: true,
+        delete: true,
+        rowid: Some("retailer_id"),
+        quals: &["retailer_id"],
+        pagination: Pagination::Cursor,
+    },
+    #[cfg(feature = "commerce")]
+    product_images::CAPABILITIES,
+    #[cfg(feature = "commerce")]
+    import_products::CAPABILITIES,
+    ObjectCapabilities::read_only("access_token"),
+    ObjectCapabilities::read_only("health"),
+];
+
+// Meta's Commerce Catalog API, reading and writing the products of a
+// catalog directly instead of through a gateway. Writes are buffered and
+// sent in batches through the `items_batch` endpoint.
+#[derive(Debug)]
+pub(crate) struct MetaCommerce {
+    graph: Graph,
+    catalog_id: String,
+    // Requests of the `items_batch` endpoint not sent yet
+    batch: Vec<JsonValue>,
+    // Image changes of the statement by retailer id, applied to the
+    // current images of their products when it ends
+    #[cfg(feature = "commerce")]
+    image_changes: Vec<(String, ImageChange)>,
+}
+
+impl MetaCommerce {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        Ok(Box::new(Self {
+            graph: Graph::parse(opts)?,
+            catalog_id: opts.require("catalog_id")?,
+            batch: Vec::new(),
+            #[cfg(feature = "commerce")]
+            image_changes: Vec::new(),
+        }))
+    }
+
+    // Fetch all products of the catalog, in the shape of the products object
+    fn fetch_products(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        pagination::fetch_all(|cursor| self.fetch_products_page(client, cursor))
+    }
+
+    // Fetch the page of the products of the catalog a cursor points to, or
+    // the product of the retailer id the scan is filtered on
+    fn fetch_products_page(&self, client: &mut Client, cursor: Option<&str>) -> Result<Page, FdwError> {
+        let edge = format!("{}/products", self.catalog_id);
+        let filter = client
+            .state
+            .equal("retailer_id")
+            .map(|id| serde_json::json!({"retailer_id": {"eq": id}}).to_string());
+        let page = graph::fetch_edge_page(client, &edge, PRODUCT_FIELDS, filter.as_deref(), cursor)?;
+        Ok(Page {
+            rows: page.rows.iter().map(product_row).collect(),
+            next: page.next,
+            total: page.total,
+        })
+    }
+
+    // Fetch the images of all products of the catalog
+    #[cfg(feature = "commerce")]
+    fn fetch_images(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let edge = format!("{}/products", self.catalog_id);
+        let products = graph::fetch_edge(client, &edge, IMAGE_FIELDS)?;
+        Ok(products.iter().flat_map(product_images::image_rows).collect())
+    }
+
+    // Turn the image changes of the statement into product updates, each
+    // product being read once for its current images. The catalog takes
+    // the full list of images of a product, the first as its main image.
+    #[cfg(feature = "commerce")]
+    fn push_image_changes(&mut self, client: &mut Client) -> FdwResult {
+        let mut changes = std::mem::take(&mut self.image_changes);
+        while let Some((retailer_id, _)) = changes.first() {
+            let retailer_id = retailer_id.clone();
+            let (product_changes, rest): (Vec<_>, Vec<_>) = changes.into_iter().partition(|(id, _)| *id == retailer_id);
+            changes = rest;
+
+            let filter = serde_json::json!({"retailer_id": {"eq": retailer_id}}).to_string();
+            let url = format!(
+                "{}/{}/products?fields={}&filter={}",
+                client.base_url,
+                self.catalog_id,
+                IMAGE_FIELDS,
+                url_encode(&filter)
+            );
+            let resp_json = client.get_json(url)?;
+            let product = resp_json
+                .pointer("/data/0")
+                .ok_or_else(|| format!("Product '{}' not found in catalog", retailer_id))?;
+            let product_changes: Vec<ImageChange> = product_changes.into_iter().map(|(_, change)| change).collect();
+            let urls = product_images::apply(&retailer_id, product_images::image_urls(product), &product_changes)?;
+
+            self.push_change(serde_json::json!({
+                "method": "UPDATE",
+                "data": {
+                    "id": retailer_id,
+                    "image_link": urls[0],
+                    "additional_image_link": urls[1..].join(","),
+                },
+            }));
+        }
+        Ok(())
+    }
+
+    // Probe the catalog, which has no session to report
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!("{}/{}?fields=name", client.base_url, self.catalog_id);
+        health::probe(client, url, |_| None)
+    }
+
+    // Buffer the change of a product, merged into a pending change of the
+    // same product: set-based statements such as `update ... from` can
+    // reach a product once per joined row, and a batch should change it
+    // once
+    fn push_change(&mut self, request: JsonValue) {
+        let id = request.pointer("/data/id").cloned();
+        let pending = self
+            .batch
+            .iter_mut()
+            .rev()
+            .find(|pending| pending.pointer("/data/id") == id.as_ref());
+        let Some(pending) = pending else {
+            self.batch.push(request);
+            return;
+        };
+        match (pending["method"].as_str(), request["method"].as_str()) {
+            // An update completes a pending creation or update
+            (Some("CREATE" | "UPDATE"), Some("UPDATE")) => {
+                if let (Some(data), Some(changes)) = (pending["data"].as_object_mut(), request["data"].as_object()) {
+                    data.extend(changes.clone());
+                }
+            }
+            // A deletion supersedes any pending change
+            (_, Some("DELETE")) => *pending = request,
+            _ => self.batch.push(request),
+        }
+    }
+
+    // Send the buffered product changes, in as many requests as their size
+    // requires, failing if any of them is rejected. A failed request stops
+    // the batch, reported with the changes the earlier requests rejected.
+    fn send_batch(&mut self, client: &mut Client) -> FdwResult {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let requests = std::mem::take(&mut self.batch);
+        let count = requests.len();
+
+        let mut rejected = Vec::new();
+        let mut result = Ok(());
+        for chunk in chunks(requests, BATCH_BYTES) {
+            result = self.send_chunk(client, chunk, &mut rejected);
+            if result.is_err() {
+                break;
+            }
+        }
+        if rejected.is_empty() {
+            return result;
+        }
+        let summary = format!(
+            "rejected {} of {} product change(s): {}",
+            rejected.len(),
+            count,
+            rejected.join("; ")
+        );
+        Err(client.sanitize_error(&match result {
+            Ok(()) => format!("Catalog batch {}", summary),
+            Err(err) => format!("{}. The earlier requests of the catalog batch {}", err, summary),
+        }))
+    }
+
+    // Send product changes in one request of the batch endpoint, collecting
+    // the rejected ones. A request the API refuses as too large is split in
+    // two and sent again.
+    fn send_chunk(&self, client: &mut Client, mut requests: Vec<JsonValue>, rejected: &mut Vec<String>) -> FdwResult {
+        let url = format!("{}/{}/items_batch", client.base_url, self.catalog_id);
+        let body = serde_json::json!({
+            "item_type": "PRODUCT_ITEM",
+            "requests": requests,
+        });
+        match client.try_send_json(http::Method::Post, url, &body)? {
+            Some(resp_json) => rejected.extend(rejected_items(&resp_json)),
+            None if requests.len() > 1 => {
+                let rest = requests.split_off(requests.len() / 2);
+                self.send_chunk(client, requests, rejected)?;
+                self.send_chunk(client, rest, rejected)?;
+            }
+            None => {
+                let id = requests[0].pointer("/data/id").and_then(|v| v.as_str()).unwrap_or("?");
+                return Err(format!("Catalog batch refused the change of product '{}' as too large", id));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Provider for MetaCommerce {
+    fn name(&self) -> &'static str {
+        "meta_commerce"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        self.graph.connect(client);
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        vec![("catalog_id".to_owned(), self.catalog_id.clone())]
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "products" => self.fetch_products(client),
+            #[cfg(feature = "commerce")]
+            "product_images" => self.fetch_images(client),
+            "access_token" => self.graph.exchange_token(client),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    // Catalogs may hold thousands of products, read page by page
+    fn pages_lazily(&self, object: &str) -> bool {
+        object == "products"
+    }
+
+    fn fetch_page(&self, client: &mut Client, object: &str, cursor: Option<&str>) -> Result<Page, FdwError> {
+        match object {
+            "products" => self.fetch_products_page(client, cursor),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        #[cfg(feature = "commerce")]
+        if object == "product_images" {
+            let change = match row.operation {
+                Operation::Delete => ImageChange::removed(row.rowid.as_ref())?,
+                _ => ImageChange::added(&row.columns)?,
+            };
+            self.image_changes.push(change);
+            return Ok(());
+        }
+        let request = match object {
+            "products" => batch_request(row)?,
+            #[cfg(feature = "commerce")]
+            "import_products" => import_request(&import_products::document(&row.columns)?),
+            _ => return Err(not_implemented(object, row.operation)),
+        };
+        self.push_change(request);
+        if self.batch.len() >= BATCH_SIZE {
+            self.send_batch(client)?;
+        }
+        Ok(())
+    }
+
+    fn end_modify(&mut self, client: &mut Client, _object: &str) -> FdwResult {
+        #[cfg(feature = "commerce")]
+        self.push_image_changes(client)?;
+        self.send_batch(client)
+    }
+}
+
+// Convert a catalog product to the shape of the products object
+fn product_row(product: &JsonValue) -> JsonValue {
+    let field = |name: &str| product.get(name).cloned().unwrap_or_default();
+    let review_status = product.get("review_status").and_then(|v| v.as_str());
+
+    let mut images: Vec<JsonValue> = Vec::new();
+    if let Some(url) = product.get("image_url").and_then(|v| v.as_str()) {
+        images.push(serde_json::json!({ "url": url }));
+    }
+    if let Some(urls) = product.get("additional_image_urls").and_then(|v| v.as_array()) {
+        images.extend(urls.iter().map(|url| serde_json::json!({ "url": url })));
+    }
+
+    serde_json::json!({
+        "id": field("id"),
+        "retailer_id": field("retailer_id"),
+        "name": field("name"),
+        "description": field("description"),
+        "url": field("url"),
+        "currency": field("currency"),
+        "price": field("price"),
+        "availability": field("availability"),
+        "is_hidden": product.get("visibility").and_then(|v| v.as_str()).map(|v| v == "hidden"),
+        "max_available": field("inventory"),
+        "approval_status": review_status,
+        "is_approved": review_status.map(|v| v == "approved"),
+        "images": images,
+    })
+}
+
+// Build the `items_batch` request of a modified product row. Updated and
+// deleted products are identified by their retailer id, the table's
+// `rowid_column`.
+fn batch_request(row: &WriteRow) -> Result<JsonValue, FdwError> {
+    let rowid = || {
+        row.rowid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or("Updating or deleting products requires the table option rowid_column 'retailer_id'")
+    };
+
+    let (method, data) = match row.operation {
+        Operation::Insert => {
+            let data = product_item(&row.columns);
+            if data.get("id").is_none() {
+                return Err("Column 'retailer_id' is required to create a product".to_owned());
+            }
+            ("CREATE", data)
+        }
+        Operation::Update => {
+            let mut data = product_item(&row.columns);
+            data["id"] = rowid()?.into();
+            ("UPDATE", data)
+        }
+        Operation::Delete => ("DELETE", serde_json::json!({ "id": rowid()? })),
+        Operation::Scan => return Err(not_implemented("products", row.operation)),
+    };
+    Ok(serde_json::json!({ "method": method, "data": data }))
+}
+
+// Build the `items_batch` request creating an imported product. Images
+// may be listed as in scanned products, and fields of the document that
+// are not products columns are catalog item fields, e.g. `brand` or
+// `condition`, sent as they are.
+#[cfg(feature = "commerce")]
+fn import_request(document: &JsonValue) -> JsonValue {
+    let is_column = |name: &str| {
+        OBJECT_COLUMNS
+            .iter()
+            .any(|(object, columns)| *object == "products" && columns.iter().any(|(column, _)| *column == name))
+    };
+    let mut data = product_item(document);
+    if let (Some(item), Some(fields)) = (data.as_object_mut(), document.as_object()) {
+        for (name, value) in fields {
+            if !value.is_null() && !is_column(name) {
+                item.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+    }
+    serde_json::json!({ "method": "CREATE", "data": data })
+}
+
+// Convert the writable columns of a product row to catalog item fields,
+// skipping null and read-only columns
+fn product_item(columns: &JsonValue) -> JsonValue {
+    let column = |name: &str| columns.get(name).filter(|v| !v.is_null());
+    let mut item = serde_json::Map::new();
+
+    for (col, field) in [
+        ("retailer_id", "id"),
+        ("name", "title"),
+        ("description", "description"),
+        ("url", "link"),
+        ("availability", "availability"),
+    ] {
+        if let Some(value) = column(col) {
+            item.insert(field.to_owned(), value.clone());
+        }
+    }
+    // The catalog expects the price with its currency, e.g. `12.00 EUR`,
+    // from a text or numeric column
+    let price = match column("price") {
+        Some(JsonValue::Number(n)) => n.as_f64().map(|n| format!("{:.2}", n)),
+        price => price.and_then(|v| v.as_str()).map(str::to_owned),
+    };
+    if let Some(price) = price {
+        let price = match column("currency").and_then(|v| v.as_str()) {
+            Some(currency) => format!("{} {}", catalog_amount(&price), currency),
+            None => price,
+        };
+        item.insert("price".to_owned(), price.into());
+    }
+    if let Some(hidden) = column("is_hidden").and_then(|v| v.as_bool()) {
+        let visibility = if hidden { "hidden" } else { "published" };
+        item.insert("visibility".to_owned(), visibility.into());
+    }
+    // Images are read as a comma-separated list of URLs or as an array
+    if let Some(images) = column("images") {
+        let urls = mapping::url_list(images);
+        let mut urls = urls.iter().map(String::as_str);
+        if let Some(first) = urls.next() {
+            item.insert("image_link".to_owned(), first.into());
+        }
+        let additional: Vec<&str> = urls.collect();
+        if !additional.is_empty() {
+            item.insert("additional_image_link".to_owned(), additional.join(",").into());
+        }
+    }
+    JsonValue::Object(item)
+}
+
+// Amount of a price, the catalog reading prices back formatted with their
+// currency, e.g. `€12.00`, which the unchanged columns of an updated row
+// hold. Prices without digits are left to the catalog to reject.
+fn catalog_amount(price: &str) -> String {
+    if !price.chars().any(|c| c.is_ascii_digit()) {
+        return price.to_owned();
+    }
+    price.chars().filter(|c| c.is_ascii_digit() || matches!(c, '.' | ',')).collect()
+}
+
+// Split product changes into requests of the batch endpoint of at most
+// `max_bytes` each, a larger change going alone
+fn chunks(requests: Vec<JsonValue>, max_bytes: usize) -> Vec<Vec<JsonValue>> {
+    let mut chunks: Vec<Vec<JsonValue>> = Vec::new();
+    let mut bytes = 0;
+    for request in requests {
+        let size = request.to_string().len() + 1;
+        match chunks.last_mut() {
+            Some(chunk) if bytes + size <= max_bytes => chunk.push(request),
+            _ => {
+                chunks.push(vec![request]);
+                bytes = 0;
+            }
+        }
+        bytes += size;
+    }
+    chunks
+}
+
+// Describe the items of a batch response rejected with errors
+fn rejected_items(resp_json: &JsonValue) -> Vec<String> {
+    let Some(statuses) = resp_json.get("validation_status").and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    statuses
+        .iter()
+        .filter_map(|status| {
+            let errors = status.get("errors").and_then(|v| v.as_array())?;
+            let messages: Vec<&str> = errors
+                .iter()
+                .filter_map(|err| err.get("message").and_then(|v| v.as_str()))
+                .collect();
+            if messages.is_empty() {
+                return None;
+            }
+            let retailer_id = status.get("retailer_id").and_then(|v| v.as_str()).unwrap_or("?");
+            Some(format!("{}: {}", retailer_id, messages.join(", ")))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::mock::{response, MockTransport};
+
+    fn provider() -> MetaCommerce {
+        let options = HashMap::from([("access_token", "token")]);
+        MetaCommerce {
+            graph: Graph::parse(&Opts::new(&options)).unwrap(),
+            catalog_id: "884412345".to_owned(),
+            batch: Vec::new(),
+            #[cfg(feature = "commerce")]
+            image_changes: Vec::new(),
+        }
+    }
+
+    fn write(operation: Operation, rowid: Option<&str>, columns: JsonValue) -> WriteRow {
+        WriteRow {
+            operation,
+            rowid: rowid.map(|id| id.into()),
+            columns,
+        }
+    }
+
+    #[test]
+    fn maps_catalog_products_to_product_rows() {
+        let row = product_row(&serde_json::json!({
+            "id": "7418529630",
+            "retailer_id": "SKU-001",
+            "price": "€12.00",
+            "visibility": "hidden",
+            "inventory": 7,
+            "review_status": "approved",
+            "image_url": "https://a",
+            "additional_image_urls": ["https://b"],
+        }));
+        assert_eq!(row["retailer_id"], "SKU-001");
+        assert_eq!(row["is_hidden"], true);
+        assert_eq!(row["max_available"], 7);
+        assert_eq!(row["is_approved"], true);
+        assert_eq!(row["images"], serde_json::json!([{"url": "https://a"}, {"url": "https://b"}]));
+        assert!(row["description"].is_null());
+    }
+
+    #[test]
+    fn builds_batch_requests() {
+        let create = batch_request(&write(
+            Operation::Insert,
+            None,
+            serde_json::json!({"retailer_id": "SKU-9", "name": "Mug", "price": "12.00", "currency": "EUR", "images": "https://a, https://b", "max_available": 3}),
+        ))
+        .unwrap();
+        assert_eq!(
+            create,
+            serde_json::json!({"method": "CREATE", "data": {
+                "id": "SKU-9",
+                "title": "Mug",
+                "price": "12.00 EUR",
+                "image_link": "https://a",
+                "additional_image_link": "https://b",
+            }})
+        );
+
+        let update = batch_request(&write(
+            Operation::Update,
+            Some("SKU-9"),
+            serde_json::json!({"is_hidden": true}),
+        ))
+        .unwrap();
+        assert_eq!(
+            update,
+            serde_json::json!({"method": "UPDATE", "data": {"id": "SKU-9", "visibility": "hidden"}})
+        );
+
+        // A numeric price column
+        let update = batch_request(&write(
+            Operation::Update,
+            Some("SKU-9"),
+            serde_json::json!({"price": 12.5, "currency": "EUR"}),
+        ))
+        .unwrap();
+        assert_eq!(update["data"]["price"], "12.50 EUR");
+
+        let delete = batch_request(&write(Operation::Delete, Some("SKU-9"), JsonValue::Null)).unwrap();
+        assert_eq!(delete, serde_json::json!({"method": "DELETE", "data": {"id": "SKU-9"}}));
+
+        assert!(batch_request(&write(Operation::Delete, None, JsonValue::Null)).is_err());
+        assert!(batch_request(&write(Operation::Insert, None, serde_json::json!({"name": "Mug"}))).is_err());
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn imports_product_documents() {
+        let document = serde_json::json!({
+            "retailer_id": "SKU-1",
+            "name": "Mug",
+            "price": "12.00",
+            "currency": "EUR",
+            "images": [{"url": "https://a"}, {"url": "https://b"}],
+            "brand": "Acme",
+            "id": "7418529630",
+        });
+        assert_eq!(
+            import_request(&document),
+            serde_json::json!({"method": "CREATE", "data": {
+                "id": "SKU-1",
+                "title": "Mug",
+                "price": "12.00 EUR",
+                "image_link": "https://a",
+                "additional_image_link": "https://b",
+                "brand": "Acme",
+            }})
+        );
+    }
+
+    #[test]
+    fn filters_products_on_their_retailer_id() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"data": [{"id": "7418529630", "retailer_id": "SKU-001", "name": "Mug"}]}"#,
+        )]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider();
+        provider.connect(&mut client);
+
+        client.state.equals = vec![("retailer_id".to_owned(), "SKU-001".to_owned())];
+        let page = provider.fetch_page(&mut client, "products", None).unwrap();
+        assert_eq!(page.rows[0]["retailer_id"], "SKU-001");
+        assert!(requests.borrow()[0].url.ends_with(
+            "&limit=100&filter=%7B%22retailer_id%22%3A%7B%22eq%22%3A%22SKU-001%22%7D%7D"
+        ));
+    }
+
+    #[test]
+    fn asks_for_the_total_count_for_strict_scans_only() {
+        let page = r#"{"data": [{"id": "7418529630"}], "summary": {"total_count": 1}}"#;
+        let transport = MockTransport::new(vec![response(200, r#"{"data": []}"#), response(200, page)]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider();
+        provider.connect(&mut client);
+
+        assert_eq!(provider.fetch_page(&mut client, "products", None).unwrap().total, None);
+        client.state.count_totals = true;
+        assert_eq!(provider.fetch_page(&mut client, "products", None).unwrap().total, Some(1));
+        let requests = requests.borrow();
+        assert!(!requests[0].url.contains("summary"));
+        assert!(requests[1].url.contains("&summary=total_count"));
+    }
+
+    #[test]
+    fn sends_buffered_writes_in_one_batch() {
+        let transport = MockTransport::new(vec![response(200, r#"{"handles": ["AcX"]}"#)]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        for sku in ["SKU-1", "SKU-2"] {
+            let row = write(Operation::Delete, Some(sku), JsonValue::Null);
+            provider.write_row(&mut client, "products", &row).unwrap();
+        }
+        assert!(requests.borrow().is_empty());
+        provider.end_modify(&mut client, "products").unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].url, "https://graph.facebook.com/v21.0/884412345/items_batch");
+        let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body["requests"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn merges_changes_of_the_same_product() {
+        let transport = MockTransport::new(vec![response(200, r#"{"handles": ["AcX"]}"#)]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        // Rows of `update wa_products p set availability = s.availability
+        // from stock s where p.retailer_id = s.sku`, with the columns read
+        // back from the catalog
+        for (sku, availability) in [("SKU-1", "in stock"), ("SKU-2", "out of stock"), ("SKU-1", "out of stock")] {
+            let columns = serde_json::json!({
+                "retailer_id": sku,
+                "price": "€12.00",
+                "currency": "EUR",
+                "availability": availability,
+            });
+            let row = write(Operation::Update, Some(sku), columns);
+            provider.write_row(&mut client, "products", &row).unwrap();
+        }
+        provider.end_modify(&mut client, "products").unwrap();
+
+        let body: JsonValue = serde_json::from_str(&requests.borrow()[0].body).unwrap();
+        assert_eq!(
+            body["requests"],
+            serde_json::json!([
+                {"method": "UPDATE", "data": {"id": "SKU-1", "price": "12.00 EUR", "availability": "out of stock"}},
+                {"method": "UPDATE", "data": {"id": "SKU-2", "price": "12.00 EUR", "availability": "out of stock"}},
+            ])
+        );
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn adds_and_removes_images_of_products() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"data": [{"id": "7418529630", "retailer_id": "SKU-1",
+                              "image_url": "https://a", "additional_image_urls": ["https://b"]}]}"#,
+            ),
+            response(200, r#"{"handles": ["AcX"]}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let insert = write(Operation::Insert, None, serde_json::json!({"retailer_id": "SKU-1", "url": "https://c"}));
+        provider.write_row(&mut client, "product_images", &insert).unwrap();
+        let delete = write(Operation::Delete, Some("SKU-1|https://a"), JsonValue::Null);
+        provider.write_row(&mut client, "product_images", &delete).unwrap();
+        provider.end_modify(&mut client, "product_images").unwrap();
+
+        let requests = requests.borrow();
+        assert!(requests[0].url.ends_with("&filter=%7B%22retailer_id%22%3A%7B%22eq%22%3A%22SKU-1%22%7D%7D"));
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(
+            body["requests"],
+            serde_json::json!([{"method": "UPDATE", "data": {
+                "id": "SKU-1",
+                "image_link": "https://b",
+                "additional_image_link": "https://c",
+            }}])
+        );
+    }
+
+    #[test]
+    fn rejected_batch_items_fail_the_statement() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"handles": ["AcX"], "validation_status": [{"retailer_id": "SKU-1", "errors": [{"message": "Invalid price"}]}, {"retailer_id": "SKU-2", "errors": []}]}"#,
+        )]);
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        for sku in ["SKU-1", "SKU-2"] {
+            let row = write(Operation::Update, Some(sku), serde_json::json!({"price": "x"}));
+            provider.write_row(&mut client, "products", &row).unwrap();
+        }
+        let err = provider.end_modify(&mut client, "products").unwrap_err();
+        assert_eq!(err, "Catalog batch rejected 1 of 2 product change(s): SKU-1: Invalid price");
+    }
+
+    #[test]
+    fn splits_batches_by_size() {
+        let change = |sku: &str| {
+            serde_json::json!({"method": "UPDATE", "data": {"id": sku, "description": "x".repeat(400)}})
+        };
+        let requests: Vec<JsonValue> = ["SKU-1", "SKU-2", "SKU-3"].iter().map(|sku| change(sku)).collect();
+        let sizes: Vec<usize> = chunks(requests.clone(), 1000).iter().map(Vec::len).collect();
+        assert_eq!(sizes, [2, 1]);
+        // A change larger than the limit goes alone
+        let sizes: Vec<usize> = chunks(requests, 100).iter().map(Vec::len).collect();
+        assert_eq!(sizes, [1, 1, 1]);
+    }
+
+    #[test]
+    fn halves_batches_refused_as_too_large() {
+        let transport = MockTransport::new(vec![
+            response(413, "Request Entity Too Large"),
+            response(
+                200,
+                r#"{"handles": ["AcX"], "validation_status": [{"retailer_id": "SKU-1", "errors": [{"message": "Invalid price"}]}]}"#,
+            ),
+            response(200, r#"{"handles": ["AcY"]}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        for sku in ["SKU-1", "SKU-2", "SKU-3"] {
+            let row = write(Operation::Update, Some(sku), serde_json::json!({"price": "x"}));
+            provider.write_row(&mut client, "products", &row).unwrap();
+        }
+        let err = provider.end_modify(&mut client, "products").unwrap_err();
+        assert_eq!(err, "Catalog batch rejected 1 of 3 product change(s): SKU-1: Invalid price");
+
+        let requests = requests.borrow();
+        let items = |i: usize| {
+            let body: JsonValue = serde_json::from_str(&requests[i].body).unwrap();
+            body["requests"].as_array().unwrap().len()
+        };
+        assert_eq!((items(0), items(1), items(2)), (3, 1, 2));
+    }
+
+    #[test]
+    fn reports_earlier_rejections_with_a_failed_request() {
+        let transport = MockTransport::new(vec![
+            response(413, "Request Entity Too Large"),
+            response(
+                200,
+                r#"{"handles": ["AcX"], "validation_status": [{"retailer_id": "SKU-1", "errors": [{"message": "Invalid price"}]}]}"#,
+            ),
+            response(400, r#"{"error": {"message": "Invalid catalog", "code": 100}}"#),
+        ]);
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        for sku in ["SKU-1", "SKU-2"] {
+            let row = write(Operation::Update, Some(sku), serde_json::json!({"price": "x"}));
+            provider.write_row(&mut client, "products", &row).unwrap();
+        }
+        let err = provider.end_modify(&mut client, "products").unwrap_err();
+        assert!(err.contains("Invalid catalog"), "{}", err);
+        assert!(
+            err.ends_with(". The earlier requests of the catalog batch rejected 1 of 2 product change(s): SKU-1: Invalid price"),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,454 @@
+use serde_json::Value as JsonValue;
+
+use super::{masked_number, not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{url_encode, Client, KeyScheme};
+use crate::config::Opts;
+use crate::model::{self, Envelope};
+#[cfg(feature = "messages")]
+use crate::objects::messages;
+use crate::objects::{health, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+use crate::timestamp;
+
+const API_HOST: &str = "https://api.twilio.com";
+
+// Objects served by Twilio
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: true,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: messages::TIME_QUALS,
+        pagination: Pagination::NextLink,
+    },
+    ObjectCapabilities::read_only("health"),
+];
+
+// Twilio's Programmable Messaging API, for WhatsApp senders of a Twilio
+// account. Delivery status is polled by scanning messages, rather than
+// received through status callbacks.
+#[derive(Debug)]
+pub(crate) struct Twilio {
+    account_sid: String,
+    auth_token: String,
+    // The WhatsApp sender of the account, e.g. `+14155238886`
+    from_number: String,
+}
+
+impl Twilio {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        Ok(Box::new(Self {
+            account_sid: opts.require("account_sid")?,
+            auth_token: opts.require("auth_token")?,
+            // The sender is prefixed with `whatsapp:` in requests
+            from_number: opts
+                .require_phone_number("from_number")?
+                .trim_start_matches("whatsapp:")
+                .to_owned(),
+        }))
+    }
+
+    // WhatsApp address of the sender
+    fn sender(&self) -> String {
+        format!("whatsapp:{}", self.from_number)
+    }
+
+    // Fetch the messages sent from and to the sender, most recent first
+    fn fetch_messages(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let sender = url_encode(&self.sender());
+        let mut messages = Vec::new();
+        // Each listing is newest first, and read back to the start of the
+        // time range only
+        for filter in ["From", "To"] {
+            let url = format!("{}/Messages.json?{}={}&PageSize=1000", client.base_url, filter, sender);
+            messages.extend(fetch_pages(client, url)?);
+        }
+        messages.sort_by_key(|m| std::cmp::Reverse(m.get("sent_at").and_then(|v| v.as_i64())));
+        Ok(messages)
+    }
+
+    // Probe the account, reading its status
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!("{}.json", client.base_url);
+        health::probe(client, url, |resp_json| resp_json.get("status").cloned())
+    }
+
+    // Send a message from the sender: a content template if
+    // `template_name` is set, with its variables in `components`, a text
+    // message with `body` otherwise
+    fn send_message(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+        let to = column("to")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'to' is required to send a message")?;
+        // The Messages API has no reply context, only received messages
+        // carrying the one they reply to
+        if column("reply_to_message_id").is_some() {
+            return Err("Column 'reply_to_message_id' is not supported by provider 'twilio'".to_owned());
+        }
+        let to = format!("whatsapp:{}", to);
+        let from = self.sender();
+
+        let mut form = vec![("To", to.as_str()), ("From", from.as_str())];
+        let variables;
+        if let Some(content_sid) = column("template_name").and_then(|v| v.as_str()) {
+            form.push(("ContentSid", content_sid));
+            if let Some(components) = column("components") {
+                variables = components.to_string();
+                form.push(("ContentVariables", variables.as_str()));
+            }
+        } else if let Some(body) = column("body").and_then(|v| v.as_str()) {
+            form.push(("Body", body));
+        } else {
+            return Err("Either column 'body' or 'template_name' is required to send a message".to_owned());
+        }
+
+        let url = format!("{}/Messages.json", client.base_url);
+        client.send_form(http::Method::Post, url, &form)?;
+        Ok(())
+    }
+}
+
+impl Provider for Twilio {
+    fn name(&self) -> &'static str {
+        "twilio"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = format!("{}/2010-04-01/Accounts/{}", API_HOST, self.account_sid);
+        client.envelope = Envelope::Status;
+        client.state.api_key = format!("{}:{}", self.account_sid, self.auth_token);
+        client.state.key_scheme = KeyScheme::Basic;
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        let mut identities = vec![("account_sid".to_owned(), self.account_sid.clone())];
+        identities.extend(masked_number("from_number", &self.from_number));
+        identities
+    }
+
+    fn number(&self) -> Option<&str> {
+        Some(&self.from_number)
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "messages" => self.fetch_messages(client),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    // Twilio queues the messages it accepts, and may drop them later, e.g.
+    // as `undelivered`
+    fn message_status(&self, client: &mut Client, id: &str) -> Result<Option<JsonValue>, FdwError> {
+        let url = format!("{}/Messages/{}.json", client.base_url, url_encode(id));
+        let resp_json = client.get_json(url)?;
+        Ok(Some(message_row(&resp_json)))
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Fetch all pages of a message list, following `next_page_uri`
+fn fetch_pages(client: &mut Client, url: String) -> Result<Vec<JsonValue>, FdwError> {
+    let period = client.state.period;
+    pagination::fetch_period(period, model::sent_at, |next_page_uri| {
+        let url = match next_page_uri {
+            Some(uri) => format!("{}{}", API_HOST, uri),
+            None => url.clone(),
+        };
+        let resp_json = client.get_json(url)?;
+        let messages = resp_json
+            .get("messages")
+            .and_then(|v| v.as_array())
+            .ok_or("Cannot get 'messages' from Twilio response")?;
+        Ok(Page {
+            rows: messages.iter().map(message_row).collect(),
+            // A relative URI, so the request stays on the Twilio API
+            next: resp_json
+                .get("next_page_uri")
+                .and_then(|v| v.as_str())
+                .filter(|uri| uri.starts_with('/'))
+                .map(|uri| uri.to_owned()),
+            total: None,
+        })
+    })
+}
+
+// Convert a Twilio message to the shape of the messages object
+fn message_row(message: &JsonValue) -> JsonValue {
+    let field = |name: &str| message.get(name).cloned().unwrap_or_default();
+    let address = |name: &str| {
+        message
+            .get(name)
+            .and_then(|v| v.as_str())
+            .map(|v| v.trim_start_matches("whatsapp:").to_owned())
+    };
+    let date = |name: &str| {
+        message
+            .get(name)
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc2822)
+    };
+    let has_media = message
+        .get("num_media")
+        .and_then(|v| v.as_str())
+        .is_some_and(|n| n != "0");
+
+    serde_json::json!({
+        "id": field("sid"),
+        "from": address("from"),
+        "to": address("to"),
+        "type": if has_media { "media" } else { "text" },
+        "body": field("body"),
+        "status": field("status"),
+        "direction": field("direction"),
+        "error_code": field("error_code"),
+        "error_message": field("error_message"),
+        "sent_at": date("date_sent"),
+        "updated_at": date("date_updated"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+    use crate::config::TableConfig;
+    use crate::state::FdwState;
+
+    fn provider() -> Twilio {
+        Twilio {
+            account_sid: "AC123".to_owned(),
+            auth_token: "secret".to_owned(),
+            from_number: "+14155238886".to_owned(),
+        }
+    }
+
+    #[test]
+    fn maps_twilio_messages() {
+        let row = message_row(&serde_json::json!({
+            "sid": "SM1",
+            "from": "whatsapp:+14155238886",
+            "to": "whatsapp:+4915123456789",
+            "body": "Hi",
+            "num_media": "0",
+            "status": "delivered",
+            "direction": "outbound-api",
+            "error_code": null,
+            "date_sent": "Thu, 30 Jul 2015 20:12:31 +0000",
+        }));
+        assert_eq!(row["from"], "+14155238886");
+        assert_eq!(row["to"], "+4915123456789");
+        assert_eq!(row["type"], "text");
+        assert_eq!(row["status"], "delivered");
+        assert_eq!(row["sent_at"], 1_438_287_151_000_000i64);
+        assert!(row["updated_at"].is_null());
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn confirms_sends_with_a_second_request() {
+        let transport = MockTransport::new(vec![
+            response(201, r#"{"sid": "SM1", "status": "queued"}"#),
+            response(
+                200,
+                r#"{"sid": "SM1", "status": "undelivered", "error_message": "Unreachable destination"}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(crate::clock::mock::FakeClock::at(1_700_000_000));
+        provider().connect(&mut client);
+        let mut state = FdwState {
+            client,
+            provider: Some(Box::new(provider())),
+            object: "messages".to_owned(),
+            table: TableConfig {
+                confirm_sends: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915123456789", "body": "Hi"}),
+        };
+        assert_eq!(
+            state.write_row(row).unwrap_err(),
+            "Message 'SM1' was sent but rejected with status 'undelivered': Unreachable destination"
+        );
+        assert_eq!(
+            requests.borrow()[1].url,
+            "https://api.twilio.com/2010-04-01/Accounts/AC123/Messages/SM1.json"
+        );
+        let entry = &crate::audit::rows()[0];
+        assert_eq!(entry["provider_id"], "SM1");
+        assert_eq!(entry["outcome"], "failed");
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn counts_sends_whose_confirmation_fails_against_the_cap() {
+        let transport = MockTransport::new(vec![
+            response(201, r#"{"sid": "SM1", "status": "queued"}"#),
+            Err("connection reset".to_owned()),
+            Err("connection reset".to_owned()),
+            Err("connection reset".to_owned()),
+            Err("connection reset".to_owned()),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(crate::clock::mock::FakeClock::at(1_700_000_000));
+        provider().connect(&mut client);
+        let mut state = FdwState {
+            client,
+            provider: Some(Box::new(provider())),
+            object: "messages".to_owned(),
+            table: TableConfig {
+                confirm_sends: true,
+                ..Default::default()
+            },
+            config: crate::config::ServerConfig {
+                max_messages_per_contact_per_day: 1,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let row = || WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915111122233", "body": "Hi"}),
+        };
+        let err = state.write_row(row()).unwrap_err();
+        assert!(err.starts_with("Message 'SM1' was sent but its status could not be confirmed"), "{}", err);
+
+        // The message went out, so a retry of the insert is capped
+        let err = state.write_row(row()).unwrap_err();
+        assert!(err.contains("max_messages_per_contact_per_day"), "{}", err);
+        assert_eq!(requests.borrow().len(), 5);
+    }
+
+    #[test]
+    fn polls_messages_from_and_to_the_sender() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"messages": [{"sid": "SM1", "date_sent": "Thu, 30 Jul 2015 20:12:31 +0000"}], "next_page_uri": "/2010-04-01/Accounts/AC123/Messages.json?Page=1&PageToken=PA1"}"#,
+            ),
+            response(200, r#"{"messages": [], "next_page_uri": null}"#),
+            response(
+                200,
+                r#"{"messages": [{"sid": "SM2", "date_sent": "Fri, 31 Jul 2015 08:00:00 +0000"}], "next_page_uri": null}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider();
+        provider.connect(&mut client);
+
+        let messages = provider.fetch(&mut client, "messages").unwrap();
+        let ids: Vec<&str> = messages.iter().map(|m| m["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["SM2", "SM1"]);
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[0].url,
+            "https://api.twilio.com/2010-04-01/Accounts/AC123/Messages.json?From=whatsapp%3A%2B14155238886&PageSize=1000"
+        );
+        assert_eq!(
+            requests[1].url,
+            "https://api.twilio.com/2010-04-01/Accounts/AC123/Messages.json?Page=1&PageToken=PA1"
+        );
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("Basic QUMxMjM6c2VjcmV0")
+        );
+    }
+
+    #[test]
+    fn reads_history_back_to_the_start_of_the_time_range() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"messages": [{"sid": "SM3", "date_sent": "Fri, 31 Jul 2015 08:00:00 +0000"}, {"sid": "SM2", "date_sent": "Thu, 30 Jul 2015 20:12:31 +0000"}], "next_page_uri": "/2010-04-01/Accounts/AC123/Messages.json?Page=1&PageToken=PA1"}"#,
+            ),
+            response(
+                200,
+                r#"{"messages": [{"sid": "SM1", "date_sent": "Wed, 29 Jul 2015 09:00:00 +0000"}], "next_page_uri": "/2010-04-01/Accounts/AC123/Messages.json?Page=2&PageToken=PA2"}"#,
+            ),
+            response(200, r#"{"messages": [], "next_page_uri": null}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.period.since = timestamp::parse_rfc3339("2015-07-30T00:00:00Z");
+        client.state.period.until = timestamp::parse_rfc3339("2015-07-31T00:00:00Z");
+        let provider = provider();
+        provider.connect(&mut client);
+
+        let messages = provider.fetch(&mut client, "messages").unwrap();
+        let ids: Vec<&str> = messages.iter().map(|m| m["id"].as_str().unwrap()).collect();
+        assert_eq!(ids, ["SM2"]);
+        // The second page of sent messages reaches past the start of the
+        // range, the third is never read
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[2].url.contains("To=whatsapp"));
+    }
+
+    #[test]
+    fn sends_form_encoded_messages() {
+        let transport = MockTransport::new(vec![
+            response(201, r#"{"sid": "SM3", "status": "queued"}"#),
+            response(400, r#"{"code": 21211, "message": "Invalid 'To' Phone Number", "status": 400}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let mut send = |columns: JsonValue| {
+            let row = WriteRow {
+                operation: Operation::Insert,
+                rowid: None,
+                columns,
+            };
+            provider.write_row(&mut client, "messages", &row)
+        };
+        send(serde_json::json!({"to": "+4915123456789", "body": "Hi there"})).unwrap();
+        let err = send(serde_json::json!({"to": "0", "body": "Hi"})).unwrap_err();
+        assert!(err.contains("21211"), "{}", err);
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[0].body,
+            "To=whatsapp%3A%2B4915123456789&From=whatsapp%3A%2B14155238886&Body=Hi%20there"
+        );
+        assert_eq!(
+            header_value(&requests[0].headers, "content-type"),
+            Some("application/x-www-form-urlencoded")
+        );
+    }
+}
@@ -0,0 +1,1313 @@
+use serde_json::Value as JsonValue;
+
+use super::{masked_number, not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{url_encode, Client, KeyScheme};
+use crate::config::Opts;
+#[cfg(feature = "commerce")]
+use crate::mapping;
+use crate::model::{self, Envelope};
+#[cfg(feature = "groups")]
+use crate::objects::group_stats::{self, GroupMessage};
+#[cfg(feature = "messages")]
+use crate::objects::{messages, notes, unread_counts};
+use crate::objects::{health, webhook_bridge, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+#[cfg(any(feature = "groups", feature = "messages"))]
+use crate::timestamp;
+
+// Objects served by 2Chat
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "commerce")]
+    ObjectCapabilities {
+        name: "products",
+        scan: true,
+        insert: true,
+        update: true,
+        delete: true,
+        rowid: Some("id"),
+        quals: &["id"],
+        pagination: Pagination::Cursor,
+    },
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: true,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: messages::TIME_QUALS,
+        pagination: Pagination::PageNumber,
+    },
+    #[cfg(feature = "groups")]
+    ObjectCapabilities::read_only("groups"),
+    #[cfg(feature = "groups")]
+    ObjectCapabilities::read_only("group_stats")
+        .paged(Pagination::PageNumber)
+        .filtered(group_stats::PERIOD_QUALS),
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("unread_counts").filtered(unread_counts::CHAT_QUALS),
+    #[cfg(feature = "messages")]
+    notes::CAPABILITIES,
+    ObjectCapabilities::read_only("contacts"),
+    ObjectCapabilities::read_only("phone_numbers"),
+    webhook_bridge::CAPABILITIES,
+    ObjectCapabilities::read_only("health"),
+];
+
+// Events a webhook is subscribed to unless the inserted row lists `events`
+const DEFAULT_WEBHOOK_EVENTS: &[&str] = &["whatsapp.message.received"];
+
+// Generation of the 2Chat API, the next one changing the error format and
+// the pagination of lists
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ApiVersion {
+    // Detected from each response, the current generation carrying a
+    // `success` flag
+    Auto,
+    V1,
+    V2,
+}
+
+impl ApiVersion {
+    const CHOICES: &'static [(&'static str, ApiVersion)] =
+        &[("auto", ApiVersion::Auto), ("v1", ApiVersion::V1), ("v2", ApiVersion::V2)];
+
+    // Whether a response is of the next-gen API
+    fn is_next_gen(&self, resp_json: &JsonValue) -> bool {
+        match self {
+            ApiVersion::Auto => resp_json.get("success").is_none(),
+            ApiVersion::V1 => false,
+            ApiVersion::V2 => true,
+        }
+    }
+}
+
+// 2Chat, a gateway to WhatsApp numbers connected to a 2Chat account
+#[derive(Debug)]
+pub(crate) struct TwoChat {
+    // The WhatsApp number whose resources are read
+    phone_number: String,
+    // The WhatsApp number connected to the 2Chat account
+    from_number: String,
+    api_key: String,
+    api_version: ApiVersion,
+}
+
+impl TwoChat {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        let provider = Self {
+            phone_number: opts.phone_number("phone_number").unwrap_or_default(),
+            from_number: opts.phone_number("from_number").unwrap_or_default(),
+            api_key: opts.string_or("api_key", ""),
+            api_version: opts.choice("api_version", ApiVersion::Auto, ApiVersion::CHOICES)?,
+        };
+
+        // Validate that all required options are provided, OAuth replacing
+        // the API key if a token endpoint is set
+        if provider.phone_number.is_empty()
+            || provider.from_number.is_empty()
+            || (provider.api_key.is_empty() && opts.string("token_url").is_none())
+        {
+            return Err("Missing required options: phone_number, from_number, api_key".to_string());
+        }
+
+        Ok(Box::new(provider))
+    }
+
+    // Fetch all catalog products of phone_number
+    fn fetch_products(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        // Make GET requests to the WhatsApp Catalog API, one per page
+        pagination::fetch_all(|cursor| self.fetch_products_page(client, cursor))
+    }
+
+    // Create a catalog product of phone_number from an inserted row. 2Chat
+    // has no batch endpoint, so each row is sent on its own.
+    #[cfg(feature = "commerce")]
+    fn create_product(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let body = product_body(row)?;
+        let Some(name) = body.get("name").and_then(|v| v.as_str()) else {
+            return Err("Column 'name' is required to create a product".to_owned());
+        };
+        let url = self.products_url(client, None);
+        client
+            .send_json(http::Method::Post, url, &body)
+            .map_err(|err| format!("Cannot create product '{}': {}", name, err))?;
+        Ok(())
+    }
+
+    // Change or delete the catalog product of an updated or deleted row,
+    // identified by its product id, the table's `rowid_column`
+    #[cfg(feature = "commerce")]
+    fn modify_product(&self, client: &mut Client, row: &WriteRow) -> FdwResult {
+        let id = row
+            .rowid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or("Updating or deleting products requires the table option rowid_column 'id'")?;
+        let url = self.products_url(client, Some(id));
+        let result = match row.operation {
+            Operation::Delete => client.delete(url),
+            _ => client.send_json(http::Method::Patch, url, &product_body(&row.columns)?),
+        };
+        result.map_err(|err| format!("Cannot {} product '{}': {}", row.operation.as_str(), id, err))?;
+        Ok(())
+    }
+
+    // URL of the catalog of phone_number, or of one of its products, the
+    // `+` of from_number reading as a space in the query unless encoded
+    fn products_url(&self, client: &Client, id: Option<&str>) -> String {
+        let product = id.map(|id| format!("/{}", url_encode(id))).unwrap_or_default();
+        format!(
+            "{}/catalog/products/{}{}?from_number={}",
+            client.base_url,
+            self.phone_number,
+            product,
+            url_encode(&self.from_number)
+        )
+    }
+
+    // Fetch the page of the catalog products a cursor points to. The
+    // cursor is the query parameter of the next page, which follows the
+    // generation of the page linking to it.
+    fn fetch_products_page(&self, client: &mut Client, cursor: Option<&str>) -> Result<Page, FdwError> {
+        if let Some(id) = client.state.equal("id").map(|id| id.to_owned()) {
+            return Ok(Page {
+                rows: self.fetch_product(client, &id)?,
+                next: None,
+                total: None,
+            });
+        }
+        let url = self.products_url(client, None);
+        let resp_json = match cursor {
+            Some(cursor) => client.get_next_page_json(format!("{}&{}", url, cursor))?,
+            None => client.get_json(url)?,
+        };
+        let (page, parameter) = match self.api_version.is_next_gen(&resp_json) {
+            true => (parse_next_gen_page(&resp_json)?, "cursor"),
+            false => (
+                Page {
+                    rows: parse_products(&resp_json)?,
+                    next: pagination::graph_cursor(&resp_json),
+                    total: pagination::graph_total(&resp_json),
+                },
+                "after",
+            ),
+        };
+        Ok(Page {
+            rows: page.rows,
+            next: page.next.map(|next| format!("{}={}", parameter, url_encode(&next))),
+            total: page.total,
+        })
+    }
+
+    // Read the one product the scan is filtered on by its id, no rows if
+    // the catalog has no such product
+    fn fetch_product(&self, client: &mut Client, id: &str) -> Result<Vec<JsonValue>, FdwError> {
+        let url = self.products_url(client, Some(id));
+        let Some(resp_json) = client.find_json(url)? else {
+            return Ok(Vec::new());
+        };
+        match resp_json.get("data").or(resp_json.get("product")) {
+            Some(product @ JsonValue::Object(_)) => Ok(vec![product.clone()]),
+            _ => parse_products(&resp_json),
+        }
+    }
+
+    // Aggregate the messages of each group of from_number over the period
+    // of the scan, reading the history of a group, most recent first, page
+    // by page until the start of the period
+    #[cfg(feature = "groups")]
+    fn fetch_group_stats(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let groups = self.fetch_groups(client)?;
+        let period = client.state.period;
+
+        let mut rows = Vec::new();
+        for group in &groups {
+            let Some(group_id) = group.get("uuid").and_then(|v| v.as_str()) else { continue };
+            let url = format!("{}/groups/messages/{}", client.base_url, url_encode(group_id));
+            let messages = pagination::fetch_all(|page| {
+                let page_number: u64 = page.and_then(|page| page.parse().ok()).unwrap_or_default();
+                let resp_json = client.get_json(format!("{}?page_number={}", url, page_number))?;
+                let rows = resp_json
+                    .get("messages")
+                    .and_then(|v| v.as_array())
+                    .cloned()
+                    .unwrap_or_default();
+                let oldest = rows.last().and_then(|message| group_message(message).sent_at);
+                let before_period = matches!((oldest, period.since), (Some(oldest), Some(since)) if oldest < since);
+                let next = match rows.is_empty() || before_period {
+                    true => None,
+                    false => Some((page_number + 1).to_string()),
+                };
+                Ok(Page { rows, next, total: None })
+            })?;
+            let messages: Vec<GroupMessage> = messages.iter().map(group_message).collect();
+            let name = group.get("wa_group_name").and_then(|v| v.as_str());
+            rows.push(group_stats::stats_row(group_id, name, &messages, period));
+        }
+        Ok(rows)
+    }
+
+    // List the WhatsApp groups of from_number
+    #[cfg(feature = "groups")]
+    fn fetch_groups(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!("{}/groups/{}", client.base_url, url_encode(&self.from_number));
+        let resp_json = client.get_json(url)?;
+        Ok(resp_json.get("data").and_then(|v| v.as_array()).cloned().unwrap_or_default())
+    }
+
+    // Read the chats of from_number, or the one chat the scan is filtered
+    // on, in a single request
+    fn fetch_chats(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let mut url = format!("{}/chats/{}", client.base_url, url_encode(&self.from_number));
+        if let Some(chat_id) = client.state.equal("chat_id") {
+            url = format!("{}/{}", url, url_encode(chat_id));
+        }
+        let resp_json = client.get_json(url)?;
+        Ok(match resp_json.get("data") {
+            Some(JsonValue::Array(chats)) => chats.clone(),
+            Some(chat @ JsonValue::Object(_)) => vec![chat.clone()],
+            _ => Vec::new(),
+        })
+    }
+
+    // Read the unread messages of the chats of from_number
+    #[cfg(feature = "messages")]
+    fn fetch_unread_counts(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        Ok(self.fetch_chats(client)?.iter().map(chat_row).collect())
+    }
+
+    // Read the messages of the chats of from_number, group chats aside,
+    // most recent first. The history of each chat is read page by page back
+    // to the start of the time range only.
+    #[cfg(feature = "messages")]
+    fn fetch_messages(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let period = client.state.period;
+        let mut rows = Vec::new();
+        for chat in self.fetch_chats(client)?.iter().filter(|chat| !is_group(chat)) {
+            let Some(chat_id) = chat.get("id").and_then(|v| v.as_str()) else { continue };
+            let url = format!("{}/messages", self.chat_url(client, chat_id));
+            let contact = chat_phone(chat_id);
+            let messages = pagination::fetch_period(period, model::sent_at, |page| {
+                let page_number: u64 = page.and_then(|page| page.parse().ok()).unwrap_or_default();
+                let resp_json = client.get_json(format!("{}?page_number={}", url, page_number))?;
+                let messages = resp_json.get("messages").and_then(|v| v.as_array()).cloned().unwrap_or_default();
+                Ok(Page {
+                    rows: messages
+                        .iter()
+                        .map(|message| message_row(&self.from_number, &contact, message))
+                        .collect(),
+                    next: (!messages.is_empty()).then(|| (page_number + 1).to_string()),
+                    total: None,
+                })
+            })?;
+            rows.extend(messages);
+        }
+        rows.sort_by_key(|row| std::cmp::Reverse(model::sent_at(row)));
+        Ok(rows)
+    }
+
+    // Read the chats of from_number as contacts, group chats aside
+    fn fetch_contacts(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let chats = self.fetch_chats(client)?;
+        Ok(chats.iter().filter(|chat| !is_group(chat)).map(contact_row).collect())
+    }
+
+    // Read the numbers connected to the account, the channels of 2Chat
+    fn fetch_phone_numbers(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let resp_json = client.get_json(format!("{}/get-numbers", client.base_url))?;
+        let numbers = resp_json.get("numbers").and_then(|v| v.as_array()).into_iter().flatten();
+        Ok(numbers
+            .map(|number| {
+                serde_json::json!({
+                    "id": number.get("uuid").or_else(|| number.get("id")),
+                    "display_phone_number": number.get("phone_number"),
+                    "verified_name": number.get("friendly_name"),
+                })
+            })
+            .collect())
+    }
+
+    // Read the notes of the chat the scan is filtered on, or of every chat
+    // of from_number, one request per chat
+    #[cfg(feature = "messages")]
+    fn fetch_notes(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let chat_ids: Vec<String> = match client.state.equal("chat_id") {
+            Some(chat_id) => vec![chat_id.to_owned()],
+            None => self
+                .fetch_unread_counts(client)?
+                .iter()
+                .filter_map(|chat| chat.get("chat_id").and_then(|v| v.as_str()).map(str::to_owned))
+                .collect(),
+        };
+        let mut rows = Vec::new();
+        for chat_id in chat_ids {
+            let resp_json = client.get_json(self.notes_url(client, &chat_id))?;
+            let chat_notes = resp_json.get("data").and_then(|v| v.as_array()).into_iter().flatten();
+            rows.extend(chat_notes.map(|note| notes::note_row(&chat_id, note)));
+        }
+        Ok(rows)
+    }
+
+    // Send a text message from from_number to the row's `to`. 2Chat sends
+    // through a WhatsApp Web session, which has no templates nor reply
+    // context.
+    #[cfg(feature = "messages")]
+    fn send_message(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+        for unsupported in ["template_name", "reply_to_message_id"] {
+            if column(unsupported).is_some() {
+                return Err(format!("Column '{}' is not supported by provider '2chat'", unsupported));
+            }
+        }
+        let to = column("to")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'to' is required to send a message")?;
+        let text = column("body")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'body' is required to send a message")?;
+        let url = format!("{}/send-message", client.base_url);
+        let body = serde_json::json!({
+            "from_number": self.from_number,
+            "to_number": to,
+            "text": text,
+        });
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+
+    // Add a note to a chat of from_number
+    #[cfg(feature = "messages")]
+    fn add_note(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let (chat_id, text) = notes::inserted_note(row)?;
+        let url = self.notes_url(client, chat_id);
+        client.send_json(http::Method::Post, url, &serde_json::json!({ "text": text }))?;
+        Ok(())
+    }
+
+    #[cfg(feature = "messages")]
+    fn notes_url(&self, client: &Client, chat_id: &str) -> String {
+        format!("{}/notes", self.chat_url(client, chat_id))
+    }
+
+    #[cfg(feature = "messages")]
+    fn chat_url(&self, client: &Client, chat_id: &str) -> String {
+        format!(
+            "{}/chats/{}/{}",
+            client.base_url,
+            url_encode(&self.from_number),
+            url_encode(chat_id)
+        )
+    }
+
+    // List the webhooks of from_number, one row per subscribed event
+    fn fetch_webhooks(&self, client: &mut Client) -> Result<Vec<JsonValue>, FdwError> {
+        let url = format!("{}/webhooks", webhooks_url(client));
+        let resp_json = client.get_json(url)?;
+        let webhooks = resp_json.get("data").and_then(|v| v.as_array()).into_iter().flatten();
+        Ok(webhooks
+            .filter(|webhook| {
+                webhook.get("on_number").and_then(|v| v.as_str()) == Some(self.from_number.as_str())
+            })
+            .map(|webhook| {
+                serde_json::json!({
+                    "url": webhook.get("hook_url"),
+                    "events": webhook.get("event_name").map(|event| vec![event]),
+                    "details": webhook,
+                })
+            })
+            .collect())
+    }
+
+    // Subscribe the row's URL to each of its events on from_number
+    fn register_webhook(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let events: Vec<String> = match row.get("events").and_then(|v| v.as_array()) {
+            Some(events) => events.iter().filter_map(|v| v.as_str()).map(str::to_owned).collect(),
+            None => DEFAULT_WEBHOOK_EVENTS.iter().map(|event| event.to_string()).collect(),
+        };
+        for event in events {
+            let url = format!("{}/webhooks/subscribe/{}", webhooks_url(client), url_encode(&event));
+            let body = serde_json::json!({ "hook_url": row["url"], "on_number": self.from_number });
+            client.send_json(http::Method::Post, url, &body)?;
+        }
+        Ok(())
+    }
+
+    // Probe the numbers of the account, reading the session state of
+    // from_number
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!("{}/get-numbers", client.base_url);
+        health::probe(client, url, |resp_json| {
+            resp_json
+                .pointer("/numbers")
+                .and_then(|v| v.as_array())
+                .and_then(|numbers| {
+                    numbers.iter().find(|n| {
+                        n.get("phone_number").and_then(|v| v.as_str()) == Some(self.from_number.as_str())
+                    })
+                })
+                .map(|number| number.get("connection_status").cloned().unwrap_or_default())
+        })
+    }
+}
+
+impl Provider for TwoChat {
+    fn name(&self) -> &'static str {
+        "2chat"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = "https://api.p.2chat.io/open/whatsapp".to_owned();
+        client.envelope = match self.api_version {
+            ApiVersion::Auto => Envelope::TwoChatAny,
+            ApiVersion::V1 => Envelope::Success,
+            ApiVersion::V2 => Envelope::TwoChatNext,
+        };
+        client.state.api_key = self.api_key.clone();
+        client.state.key_scheme = KeyScheme::UserApiKey;
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        [
+            masked_number("phone_number", &self.phone_number),
+            masked_number("from_number", &self.from_number),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn number(&self) -> Option<&str> {
+        Some(self.from_number.as_str()).filter(|number| !number.is_empty())
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "products" => self.fetch_products(client),
+            #[cfg(feature = "messages")]
+            "messages" => self.fetch_messages(client),
+            #[cfg(feature = "groups")]
+            "groups" => Ok(self.fetch_groups(client)?.iter().map(group_row).collect()),
+            #[cfg(feature = "groups")]
+            "group_stats" => self.fetch_group_stats(client),
+            #[cfg(feature = "messages")]
+            "unread_counts" => self.fetch_unread_counts(client),
+            #[cfg(feature = "messages")]
+            "notes" => self.fetch_notes(client),
+            "contacts" => self.fetch_contacts(client),
+            "phone_numbers" => self.fetch_phone_numbers(client),
+            "webhook_bridge" => self.fetch_webhooks(client),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    // Catalogs may hold thousands of products, read page by page
+    fn pages_lazily(&self, object: &str) -> bool {
+        object == "products"
+    }
+
+    fn fetch_page(&self, client: &mut Client, object: &str, cursor: Option<&str>) -> Result<Page, FdwError> {
+        match object {
+            "products" => self.fetch_products_page(client, cursor),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("webhook_bridge", Operation::Insert) => self.register_webhook(client, &row.columns),
+            #[cfg(feature = "commerce")]
+            ("products", Operation::Insert) => self.create_product(client, &row.columns),
+            #[cfg(feature = "commerce")]
+            ("products", Operation::Update | Operation::Delete) => self.modify_product(client, row),
+            #[cfg(feature = "messages")]
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            #[cfg(feature = "messages")]
+            ("notes", Operation::Insert) => self.add_note(client, &row.columns),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Webhooks are managed outside of the WhatsApp API, at the root of the
+// 2Chat open API
+fn webhooks_url(client: &Client) -> &str {
+    client.base_url.trim_end_matches("/whatsapp")
+}
+
+// Sender and time of a message of a group history
+#[cfg(feature = "groups")]
+fn group_message(message: &JsonValue) -> GroupMessage {
+    GroupMessage {
+        sender: message
+            .pointer("/participant/phone_number")
+            .and_then(|v| v.as_str())
+            .map(str::to_owned),
+        sent_at: message
+            .get("created_at")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    }
+}
+
+// Convert a 2Chat group to the shape of the groups object
+#[cfg(feature = "groups")]
+fn group_row(group: &JsonValue) -> JsonValue {
+    let participant_count = match group.get("participants") {
+        Some(JsonValue::Array(participants)) => Some(participants.len() as i64),
+        _ => group.get("participant_count").and_then(|v| v.as_i64()),
+    };
+    serde_json::json!({
+        "group_id": group.get("uuid"),
+        "wa_group_id": group.get("wa_group_id"),
+        "name": group.get("wa_group_name"),
+        "participant_count": participant_count,
+        "created_at": group
+            .get("created_at")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    })
+}
+
+// Whether a chat is a group chat
+fn is_group(chat: &JsonValue) -> bool {
+    chat.get("is_group").and_then(|v| v.as_bool()) == Some(true)
+}
+
+// Phone number of a chat with a contact, e.g. `+4915123456789` for the
+// chat id `4915123456789@c.us`, the chat id itself for other chats
+fn chat_phone(chat_id: &str) -> String {
+    match chat_id.strip_suffix("@c.us") {
+        Some(number) => format!("+{}", number),
+        None => chat_id.to_owned(),
+    }
+}
+
+// Convert a 2Chat chat to the shape of the contacts object
+fn contact_row(chat: &JsonValue) -> JsonValue {
+    let chat_id = chat.get("id").and_then(|v| v.as_str()).unwrap_or_default();
+    let phone = chat_phone(chat_id);
+    serde_json::json!({
+        "id": chat_id,
+        "wa_id": phone.trim_start_matches('+'),
+        "name": chat.get("name"),
+        "phone": phone,
+        "source": "chat",
+    })
+}
+
+// Convert a message of a 2Chat chat to the shape of the messages object,
+// the messages `sent_by` the contact being received by from_number, the
+// others being sent from it by an agent or through the API
+#[cfg(feature = "messages")]
+fn message_row(from_number: &str, contact: &str, message: &JsonValue) -> JsonValue {
+    let incoming = message.get("sent_by").and_then(|v| v.as_str()) == Some("contact");
+    let (from, to) = if incoming { (contact, from_number) } else { (from_number, contact) };
+    let body = message.pointer("/message/text");
+    let kind = message
+        .pointer("/message/type")
+        .and_then(|v| v.as_str())
+        .or(body.map(|_| "text"));
+    let mut row = serde_json::json!({
+        "id": message.get("uuid").or_else(|| message.get("id")),
+        "from": from,
+        "to": to,
+        "type": kind,
+        "body": body,
+        "quoted_message_id": message.pointer("/quoted_message/uuid"),
+        "status": message.get("status"),
+        "direction": if incoming { "incoming" } else { "outgoing" },
+        "sent_at": message
+            .get("created_at")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    });
+    model::add_forwarding(&mut row, message);
+    row
+}
+
+// Convert a 2Chat chat to the shape of the unread_counts object
+#[cfg(feature = "messages")]
+fn chat_row(chat: &JsonValue) -> JsonValue {
+    let field = |name: &str| chat.get(name).cloned().unwrap_or_default();
+    serde_json::json!({
+        "chat_id": field("id"),
+        "name": field("name"),
+        "is_group": field("is_group"),
+        "unread_count": chat.get("unread_count").and_then(|v| v.as_i64()).unwrap_or(0),
+        "last_message_at": chat
+            .get("last_message_at")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    })
+}
+
+// Body of a product creation or change in the shape 2Chat lists products
+// in, from the non-null writable columns of a row. Images are read as a
+// comma-separated list of URLs or as an array, the first being the main
+// image.
+#[cfg(feature = "commerce")]
+fn product_body(columns: &JsonValue) -> Result<JsonValue, FdwError> {
+    let column = |name: &str| columns.get(name).filter(|v| !v.is_null());
+    if column("price").is_some() && column("currency").is_none() {
+        return Err("Column 'currency' is required with column 'price'".to_owned());
+    }
+    let mut body = serde_json::Map::new();
+    for name in ["retailer_id", "name", "description", "url", "currency", "price", "is_hidden"] {
+        if let Some(value) = column(name) {
+            body.insert(name.to_owned(), value.clone());
+        }
+    }
+    if let Some(images) = column("images") {
+        let images: Vec<JsonValue> = mapping::url_list(images)
+            .iter()
+            .map(|url| serde_json::json!({ "url": url }))
+            .collect();
+        body.insert("images".to_owned(), images.into());
+    }
+    Ok(JsonValue::Object(body))
+}
+
+// Extract the 'products' array from a catalog response. New numbers
+// legitimately have no catalog yet, so a missing or null array is
+// treated as an empty catalog.
+pub(crate) fn parse_products(resp_json: &JsonValue) -> Result<Vec<JsonValue>, FdwError> {
+    match resp_json.pointer("/products") {
+        None | Some(JsonValue::Null) => Ok(Vec::new()),
+        Some(products) => Ok(products
+            .as_array()
+            .ok_or("'products' is not an array")?
+            .to_owned()),
+    }
+}
+
+// Parse a page of a next-gen list, its rows in `data` and the cursor of
+// the next page in `pagination`, null on the last page
+fn parse_next_gen_page(resp_json: &JsonValue) -> Result<Page, FdwError> {
+    let rows = match resp_json.get("data") {
+        None | Some(JsonValue::Null) => Vec::new(),
+        Some(data) => data.as_array().ok_or("'data' is not an array")?.to_owned(),
+    };
+    let next = resp_json
+        .pointer("/pagination/next_cursor")
+        .and_then(|v| v.as_str())
+        .filter(|v| !v.is_empty())
+        .map(|v| v.to_owned());
+    let total = resp_json.pointer("/pagination/total").and_then(|v| v.as_i64());
+    Ok(Page { rows, next, total })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::method_name;
+    use crate::client::mock::{response, MockTransport};
+
+    #[test]
+    fn parses_products_array() {
+        let resp_json: JsonValue = serde_json::from_str(include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/catalog_products.json"
+        )))
+        .unwrap();
+        let products = parse_products(&resp_json).unwrap();
+        assert_eq!(products.len(), 2);
+        assert_eq!(products[0]["retailer_id"], "SKU-001");
+    }
+
+    #[test]
+    fn empty_catalog_is_zero_rows() {
+        for body in [
+            r#"{"success": true, "products": []}"#,
+            r#"{"success": true, "products": null}"#,
+            r#"{"success": true}"#,
+        ] {
+            let resp_json: JsonValue = serde_json::from_str(body).unwrap();
+            assert!(parse_products(&resp_json).unwrap().is_empty(), "{}", body);
+        }
+    }
+
+    #[test]
+    fn non_array_products_is_an_error() {
+        let resp_json = serde_json::json!({"success": true, "products": "none"});
+        assert!(parse_products(&resp_json).is_err());
+    }
+
+    #[test]
+    fn follows_relayed_paging_cursors() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "products": [{"id": "1"}], "paging": {"cursors": {"after": "QVFI=="}, "next": "https://graph.facebook.com/x"}}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "products": [{"id": "2"}], "paging": {"cursors": {"after": "QVFJ"}}}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        client.base_url = "https://api.example.com".to_owned();
+
+        let provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        let products = provider.fetch_products(&mut client).unwrap();
+        assert_eq!(products, vec![serde_json::json!({"id": "1"}), serde_json::json!({"id": "2"})]);
+
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(
+            requests[1].url,
+            "https://api.example.com/catalog/products/+1?from_number=%2B2&after=QVFI%3D%3D"
+        );
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn creates_products_from_inserted_rows() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"success": true, "product": {"id": "7418529630"}}"#),
+            response(400, r#"{"success": false, "message": "price must be a positive number"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+        client.base_url = "https://api.example.com".to_owned();
+
+        let insert = |columns: JsonValue| WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns,
+        };
+        let row = insert(serde_json::json!({
+            "id": null,
+            "retailer_id": "SKU-1",
+            "name": "Mug",
+            "price": "12.00",
+            "currency": "EUR",
+            "images": "https://a, https://b",
+        }));
+        provider.write_row(&mut client, "products", &row).unwrap();
+        {
+            let requests = requests.borrow();
+            assert_eq!(requests[0].url, "https://api.example.com/catalog/products/+1?from_number=%2B2");
+            let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+            assert_eq!(
+                body,
+                serde_json::json!({
+                    "retailer_id": "SKU-1",
+                    "name": "Mug",
+                    "price": "12.00",
+                    "currency": "EUR",
+                    "images": [{"url": "https://a"}, {"url": "https://b"}],
+                })
+            );
+        }
+
+        let row = insert(serde_json::json!({"name": "Mug", "price": "-1", "currency": "EUR"}));
+        let err = provider.write_row(&mut client, "products", &row).unwrap_err();
+        assert!(err.starts_with("Cannot create product 'Mug': "), "{}", err);
+        assert!(err.contains("price must be a positive number"), "{}", err);
+
+        let row = insert(serde_json::json!({"name": "Mug", "price": "12.00"}));
+        assert!(provider.write_row(&mut client, "products", &row).is_err());
+        assert_eq!(requests.borrow().len(), 2);
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn sends_text_messages() {
+        let transport = MockTransport::new(vec![response(
+            200,
+            r#"{"success": true, "message_uuid": "MSG7c1a", "to_number": "+4915123456789"}"#,
+        )]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let send = |columns: JsonValue| WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns,
+        };
+        let row = send(serde_json::json!({"to": "+4915123456789", "body": "Your order shipped", "template_name": null}));
+        provider.write_row(&mut client, "messages", &row).unwrap();
+        {
+            let requests = requests.borrow();
+            assert_eq!(requests[0].url, "https://api.p.2chat.io/open/whatsapp/send-message");
+            let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+            assert_eq!(
+                body,
+                serde_json::json!({"from_number": "+2", "to_number": "+4915123456789", "text": "Your order shipped"})
+            );
+        }
+        assert_eq!(client.state.last_write.as_ref().unwrap()["message_uuid"], "MSG7c1a");
+
+        let row = send(serde_json::json!({"to": "+4915123456789", "template_name": "order_update"}));
+        assert_eq!(
+            provider.write_row(&mut client, "messages", &row).unwrap_err(),
+            "Column 'template_name' is not supported by provider '2chat'"
+        );
+        assert_eq!(requests.borrow().len(), 1);
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn updates_and_deletes_products_by_id() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"success": true}"#),
+            response(200, r#"{"success": true}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+        client.base_url = "https://api.example.com".to_owned();
+
+        // The unchanged and read-only columns of the row come along
+        let update = WriteRow {
+            operation: Operation::Update,
+            rowid: Some("7418529630".into()),
+            columns: serde_json::json!({"id": "7418529630", "name": "Mug", "is_hidden": true, "is_approved": true}),
+        };
+        provider.write_row(&mut client, "products", &update).unwrap();
+        let delete = WriteRow {
+            operation: Operation::Delete,
+            rowid: Some("7418529630".into()),
+            columns: JsonValue::Null,
+        };
+        provider.write_row(&mut client, "products", &delete).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(method_name(&requests[0].method), "PATCH");
+        assert_eq!(
+            requests[0].url,
+            "https://api.example.com/catalog/products/+1/7418529630?from_number=%2B2"
+        );
+        let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(body, serde_json::json!({"name": "Mug", "is_hidden": true}));
+        assert_eq!(method_name(&requests[1].method), "DELETE");
+        assert_eq!(requests[1].url, requests[0].url);
+        assert!(requests[1].body.is_empty());
+
+        let err = provider
+            .write_row(&mut client, "products", &WriteRow { rowid: None, ..delete })
+            .unwrap_err();
+        assert_eq!(err, "Updating or deleting products requires the table option rowid_column 'id'");
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn reads_a_single_product_when_filtered_on_its_id() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"success": true, "data": {"id": "7418529630", "name": "Mug"}}"#),
+            response(404, r#"{"success": false, "message": "Product not found"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+        client.base_url = "https://api.example.com".to_owned();
+
+        client.state.equals = vec![("id".to_owned(), "7418529630".to_owned())];
+        let page = provider.fetch_page(&mut client, "products", None).unwrap();
+        assert_eq!(page.rows, vec![serde_json::json!({"id": "7418529630", "name": "Mug"})]);
+        assert!(page.next.is_none());
+        assert_eq!(
+            requests.borrow()[0].url,
+            "https://api.example.com/catalog/products/+1/7418529630?from_number=%2B2"
+        );
+
+        // An unknown id is no rows rather than an error
+        client.state.equals = vec![("id".to_owned(), "404".to_owned())];
+        assert!(provider.fetch(&mut client, "products").unwrap().is_empty());
+    }
+
+    #[test]
+    fn detects_next_gen_pagination_and_errors() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"data": [{"id": "1"}], "pagination": {"next_cursor": "c2"}}"#),
+            response(200, r#"{"data": [{"id": "2"}], "pagination": {"next_cursor": null}}"#),
+            response(
+                401,
+                r#"{"error": {"code": "invalid_api_key", "message": "Invalid API key"}}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+1".to_owned(),
+            from_number: "+2".to_owned(),
+            api_key: "2chat-api-key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let products = provider.fetch_products(&mut client).unwrap();
+        assert_eq!(products.len(), 2);
+        assert!(requests.borrow()[1].url.ends_with("?from_number=%2B2&cursor=c2"));
+
+        let err = provider.fetch_products(&mut client).unwrap_err();
+        assert!(err.ends_with("Invalid API key (invalid_api_key)"), "{}", err);
+    }
+
+    #[test]
+    fn lists_and_registers_webhooks_of_from_number() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "data": [
+                    {"uuid": "WHK1", "event_name": "whatsapp.message.received", "hook_url": "https://a.example.com", "on_number": "+4915198765432"},
+                    {"uuid": "WHK2", "event_name": "whatsapp.message.received", "hook_url": "https://b.example.com", "on_number": "+4915100000000"}
+                ]}"#,
+            ),
+            response(200, r#"{"success": true, "data": {"uuid": "WHK3"}}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let webhooks = provider.fetch(&mut client, "webhook_bridge").unwrap();
+        assert_eq!(webhooks.len(), 1);
+        assert_eq!(webhooks[0]["url"], "https://a.example.com");
+        assert_eq!(webhooks[0]["events"], serde_json::json!(["whatsapp.message.received"]));
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"url": "https://abc.supabase.co/functions/v1/whatsapp", "events": null}),
+        };
+        provider.write_row(&mut client, "webhook_bridge", &row).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(requests[0].url, "https://api.p.2chat.io/open/webhooks");
+        assert_eq!(
+            requests[1].url,
+            "https://api.p.2chat.io/open/webhooks/subscribe/whatsapp.message.received"
+        );
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(body["on_number"], "+4915198765432");
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn aggregates_group_history_until_the_period_start() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "data": [{"uuid": "WAG1", "wa_group_name": "Team"}]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "messages": [
+                    {"participant": {"phone_number": "+1"}, "created_at": "2024-01-03T10:00:00Z"},
+                    {"participant": {"phone_number": "+2"}, "created_at": "2024-01-02T10:00:00Z"}
+                ]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "messages": [
+                    {"participant": {"phone_number": "+1"}, "created_at": "2024-01-01T10:00:00Z"},
+                    {"participant": {"phone_number": "+3"}, "created_at": "2023-12-31T10:00:00Z"}
+                ]}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+        client.state.period.since = timestamp::parse_rfc3339("2024-01-01T00:00:00Z");
+
+        let rows = provider.fetch(&mut client, "group_stats").unwrap();
+        assert_eq!(rows[0]["group_name"], "Team");
+        assert_eq!(rows[0]["message_count"], 3);
+        assert_eq!(rows[0]["active_members"], 2);
+        assert_eq!(rows[0]["last_activity_at"], 1_704_276_000_000_000i64);
+
+        // The second page reaches past the start of the period
+        let requests = requests.borrow();
+        assert_eq!(requests.len(), 3);
+        assert_eq!(requests[0].url, "https://api.p.2chat.io/open/whatsapp/groups/%2B4915198765432");
+        assert!(requests[2].url.ends_with("/groups/messages/WAG1?page_number=1"));
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn reads_a_single_chat_when_filtered_on_its_id() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "data": [
+                    {"id": "4915123456789@c.us", "name": "Jane", "is_group": false, "unread_count": 2, "last_message_at": "2024-01-02T03:04:05Z"},
+                    {"id": "120363041234567890@g.us", "name": "Team", "is_group": true}
+                ]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "data": {"id": "4915123456789@c.us", "unread_count": 3}}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let chats = provider.fetch(&mut client, "unread_counts").unwrap();
+        assert_eq!(chats[0]["unread_count"], 2);
+        assert_eq!(chats[0]["last_message_at"], 1_704_164_645_000_000i64);
+        assert_eq!(chats[1]["unread_count"], 0);
+
+        client.state.equals = vec![("chat_id".to_owned(), "4915123456789@c.us".to_owned())];
+        let chats = provider.fetch(&mut client, "unread_counts").unwrap();
+        assert_eq!(chats.len(), 1);
+        assert_eq!(chats[0]["unread_count"], 3);
+        assert_eq!(
+            requests.borrow()[1].url,
+            "https://api.p.2chat.io/open/whatsapp/chats/%2B4915198765432/4915123456789%40c.us"
+        );
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn reads_chat_messages_back_to_the_start_of_the_time_range() {
+        let chats = r#"{"success": true, "data": [
+            {"id": "4915123456789@c.us", "name": "Jane", "is_group": false},
+            {"id": "120363041234567890@g.us", "name": "Team", "is_group": true}
+        ]}"#;
+        let transport = MockTransport::new(vec![
+            response(200, chats),
+            response(
+                200,
+                r#"{"success": true, "messages": [
+                    {"uuid": "MSG3", "sent_by": "contact", "message": {"text": "Thanks!"}, "created_at": "2024-01-03T10:00:00Z"},
+                    {"uuid": "MSG2", "sent_by": "api", "message": {"text": "Shipped"}, "created_at": "2024-01-02T10:00:00Z"}
+                ]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "messages": [
+                    {"uuid": "MSG1", "sent_by": "contact", "message": {"text": "Where is my order?"}, "created_at": "2023-12-31T10:00:00Z"}
+                ]}"#,
+            ),
+            response(200, chats),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+        client.state.period.since = timestamp::parse_rfc3339("2024-01-01T00:00:00Z");
+
+        let rows = provider.fetch(&mut client, "messages").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["id"], "MSG3");
+        assert_eq!(rows[0]["from"], "+4915123456789");
+        assert_eq!(rows[0]["to"], "+4915198765432");
+        assert_eq!(rows[0]["direction"], "incoming");
+        assert_eq!(rows[1]["body"], "Shipped");
+        assert_eq!(rows[1]["direction"], "outgoing");
+        // The second page reaches past the start of the range, the group
+        // chat is not read
+        assert_eq!(
+            requests.borrow()[2].url,
+            "https://api.p.2chat.io/open/whatsapp/chats/%2B4915198765432/4915123456789%40c.us/messages?page_number=1"
+        );
+
+        let contacts = provider.fetch(&mut client, "contacts").unwrap();
+        assert_eq!(
+            contacts,
+            [serde_json::json!({
+                "id": "4915123456789@c.us",
+                "wa_id": "4915123456789",
+                "name": "Jane",
+                "phone": "+4915123456789",
+                "source": "chat",
+            })]
+        );
+        assert_eq!(requests.borrow().len(), 4);
+    }
+
+    #[cfg(feature = "groups")]
+    #[test]
+    fn lists_groups_and_connected_numbers() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "data": [{"uuid": "WAG1", "wa_group_id": "120363041234567890@g.us", "wa_group_name": "Team", "participants": [{}, {}, {}], "created_at": "2024-01-02T03:04:05Z"}]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "numbers": [{"uuid": "WPN1", "phone_number": "+4915198765432", "friendly_name": "Support", "connection_status": "C"}]}"#,
+            ),
+        ]);
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let groups = provider.fetch(&mut client, "groups").unwrap();
+        assert_eq!(groups[0]["group_id"], "WAG1");
+        assert_eq!(groups[0]["name"], "Team");
+        assert_eq!(groups[0]["participant_count"], 3);
+        assert_eq!(groups[0]["created_at"], 1_704_164_645_000_000i64);
+
+        let numbers = provider.fetch(&mut client, "phone_numbers").unwrap();
+        assert_eq!(
+            numbers,
+            [serde_json::json!({"id": "WPN1", "display_phone_number": "+4915198765432", "verified_name": "Support"})]
+        );
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn reads_and_adds_chat_notes() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"success": true, "data": [{"id": "4915123456789@c.us"}, {"id": "4915100000000@c.us"}]}"#,
+            ),
+            response(
+                200,
+                r#"{"success": true, "data": [
+                    {"uuid": "NTE1", "text": "Prefers calls after 5pm", "author": {"name": "Ana"}, "created_at": "2024-01-02T03:04:05Z"}
+                ]}"#,
+            ),
+            response(200, r#"{"success": true, "data": []}"#),
+            response(200, r#"{"success": true, "data": {"uuid": "NTE2"}}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        let rows = provider.fetch(&mut client, "notes").unwrap();
+        assert_eq!(
+            rows,
+            [serde_json::json!({
+                "id": "NTE1",
+                "chat_id": "4915123456789@c.us",
+                "text": "Prefers calls after 5pm",
+                "author": "Ana",
+                "created_at": 1_704_164_645_000_000i64,
+            })]
+        );
+
+        let row = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"chat_id": "4915123456789@c.us", "text": "Refund approved"}),
+        };
+        provider.write_row(&mut client, "notes", &row).unwrap();
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[3].url,
+            "https://api.p.2chat.io/open/whatsapp/chats/%2B4915198765432/4915123456789%40c.us/notes"
+        );
+        assert_eq!(requests[3].body, r#"{"text":"Refund approved"}"#);
+    }
+
+    #[cfg(feature = "commerce")]
+    #[test]
+    fn replays_products_scan() {
+        use crate::bindings::supabase::wrappers::types::Cell;
+        use crate::client::mock::ReplayTransport;
+        use crate::objects;
+
+        let fixture = include_str!(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/tests/fixtures/recorded/products_scan.jsonl"
+        ));
+        let mut client = Client::with_transport(Box::new(ReplayTransport::new(fixture)));
+        let provider = TwoChat {
+            phone_number: "+4915123456789".to_owned(),
+            from_number: "+4915198765432".to_owned(),
+            api_key: "key".to_owned(),
+            api_version: ApiVersion::Auto,
+        };
+        provider.connect(&mut client);
+
+        // Run a full scan against the recorded exchange, mapping every
+        // column of every row
+        let object = objects::lookup("products").unwrap();
+        let rows: Vec<Vec<Option<Cell>>> = provider
+            .fetch(&mut client, "products")
+            .unwrap()
+            .iter()
+            .map(|row| {
+                ["id", "name", "price", "max_available", "images"]
+                    .iter()
+                    .map(|col| object.map_cell(row, col).unwrap())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert!(matches!(&rows[0][0], Some(Cell::String(id)) if id == "7418529630"));
+        assert!(matches!(&rows[0][3], Some(Cell::I64(120))));
+        assert!(rows[1][2].is_none());
+    }
+}
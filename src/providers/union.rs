@@ -0,0 +1,237 @@
+use serde_json::Value as JsonValue;
+
+use super::{parse_provider, Line, Provider};
+use crate::bindings::supabase::wrappers::types::{FdwError, FdwResult};
+use crate::client::Client;
+use crate::config::{Opts, TableConfig};
+use crate::mapping::SOURCE_COLUMN;
+use crate::objects::ObjectCapabilities;
+
+// Several providers or numbers read as one, e.g. while migrating from one
+// gateway to another. The `sources` server option names the sources, each
+// configured by the server options prefixed with its name, e.g.
+// `old.provider` and `old.api_key`. Rows carry the name of their source in
+// the `source` column.
+#[derive(Debug)]
+pub(crate) struct Union {
+    sources: Vec<(String, Box<dyn Provider>)>,
+    // Indexes of the sources read by the current table
+    selected: Vec<usize>,
+    // Objects scanned by every source, read-only as a row written to a
+    // union would have no single destination
+    capabilities: Vec<ObjectCapabilities>,
+}
+
+impl Union {
+    pub(crate) fn parse(opts: &Opts, names: &[String]) -> Result<Box<dyn Provider>, FdwError> {
+        if names.is_empty() {
+            return Err("Server option 'sources' must name at least one source".to_owned());
+        }
+        if opts.string("provider").is_some() {
+            return Err(format!(
+                "Server option 'provider' doesn't apply with 'sources', set it per source, e.g. '{}.provider'",
+                names[0]
+            ));
+        }
+        // The token endpoint would replace the credentials of all sources
+        if opts.string("token_url").is_some() {
+            return Err("Server option 'token_url' is not supported with 'sources'".to_owned());
+        }
+
+        let mut sources: Vec<(String, Box<dyn Provider>)> = Vec::new();
+        for name in names {
+            if sources.iter().any(|(source, _)| source == name) {
+                return Err(format!("Source '{}' is listed twice in server option 'sources'", name));
+            }
+            let provider = parse_provider(&Opts::new(&opts.scoped(name)))
+                .map_err(|e| format!("Source '{}': {}", name, e))?;
+            sources.push((name.clone(), provider));
+        }
+
+        let (_, first) = &sources[0];
+        let capabilities = first
+            .objects()
+            .iter()
+            .filter(|caps| {
+                sources.iter().all(|(_, provider)| {
+                    provider.capabilities(caps.name).is_ok_and(|other| other.scan)
+                })
+            })
+            .map(|caps| ObjectCapabilities::read_only(caps.name).paged(caps.pagination))
+            .collect();
+
+        Ok(Box::new(Self {
+            selected: (0..sources.len()).collect(),
+            sources,
+            capabilities,
+        }))
+    }
+
+    fn names(&self) -> Vec<&str> {
+        self.sources.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+impl Provider for Union {
+    fn name(&self) -> &'static str {
+        "union"
+    }
+
+    fn objects(&self) -> &[ObjectCapabilities] {
+        &self.capabilities
+    }
+
+    // Register the credentials of every source for redaction, leaving the
+    // client connected to the first source
+    fn connect(&self, client: &mut Client) {
+        for (_, provider) in self.sources.iter().rev() {
+            provider.connect(client);
+            client.state.register_secrets();
+        }
+    }
+
+    // Identities of every source, prefixed with the source name
+    fn identities(&self) -> Vec<(String, String)> {
+        self.sources
+            .iter()
+            .flat_map(|(source, provider)| {
+                let mut identities = vec![("provider".to_owned(), provider.name().to_owned())];
+                identities.extend(provider.identities());
+                identities
+                    .into_iter()
+                    .map(move |(name, value)| (format!("{}.{}", source, name), value))
+            })
+            .collect()
+    }
+
+    // The numbers of every source, with its name
+    fn lines(&self) -> Vec<Line> {
+        self.sources
+            .iter()
+            .flat_map(|(source, provider)| {
+                provider.lines().into_iter().map(move |line| Line {
+                    source: Some(source.clone()),
+                    ..line
+                })
+            })
+            .collect()
+    }
+
+    // Select the sources named by the `sources` table option
+    fn open_table(&mut self, table: &TableConfig) -> FdwResult {
+        self.selected = match &table.sources {
+            Some(names) => names
+                .iter()
+                .map(|name| {
+                    self.sources.iter().position(|(source, _)| source == name).ok_or_else(|| {
+                        format!(
+                            "Unknown source '{}' in table option 'sources', expected one of {}",
+                            name,
+                            self.names().join(", ")
+                        )
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+            None => (0..self.sources.len()).collect(),
+        };
+        Ok(())
+    }
+
+    // Fetch the rows of each selected source in turn, tagged with its name
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        let mut rows = Vec::new();
+        for &i in &self.selected {
+            let (name, provider) = &self.sources[i];
+            provider.connect(client);
+            let source_rows = provider
+                .fetch(client, object)
+                .map_err(|e| format!("Source '{}': {}", name, e))?;
+            rows.extend(source_rows.into_iter().map(|mut row| {
+                if let JsonValue::Object(fields) = &mut row {
+                    fields.insert(SOURCE_COLUMN.to_owned(), JsonValue::String(name.clone()));
+                }
+                row
+            }));
+        }
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    const OPTIONS: &[(&str, &str)] = &[
+        ("sources", "old, new"),
+        ("old.provider", "wati"),
+        ("old.api_endpoint", "https://live-mt-server.wati.io/111"),
+        ("old.access_token", "old-token"),
+        ("new.provider", "wati"),
+        ("new.api_endpoint", "https://live-mt-server.wati.io/222"),
+        ("new.access_token", "new-token"),
+    ];
+
+    fn union(extra: &[(&'static str, &'static str)]) -> Result<Box<dyn Provider>, FdwError> {
+        let mut options: HashMap<&str, &str> = OPTIONS.iter().copied().collect();
+        options.extend(extra.iter().copied());
+        super::super::parse(&Opts::new(&options))
+    }
+
+    fn table(sources: Option<&str>) -> TableConfig {
+        TableConfig {
+            object: "contacts".to_owned(),
+            sources: sources.map(|names| names.split(',').map(str::to_owned).collect()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn scans_every_source_with_its_name() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"result": "success", "contact_list": [{"id": "1"}]}"#),
+            response(200, r#"{"result": "success", "contact_list": [{"id": "2"}, {"id": "3"}]}"#),
+            response(200, r#"{"result": "success", "contact_list": [{"id": "2"}]}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = union(&[]).unwrap();
+        provider.connect(&mut client);
+        assert_eq!(provider.capabilities("contacts").unwrap().operations(), ["scan"]);
+
+        provider.open_table(&table(None)).unwrap();
+        let rows = provider.fetch(&mut client, "contacts").unwrap();
+        let sources: Vec<&str> = rows.iter().map(|row| row["source"].as_str().unwrap()).collect();
+        assert_eq!(sources, ["old", "new", "new"]);
+
+        provider.open_table(&table(Some("new"))).unwrap();
+        assert_eq!(provider.fetch(&mut client, "contacts").unwrap().len(), 1);
+
+        let requests = requests.borrow();
+        assert!(requests[1].url.starts_with("https://live-mt-server.wati.io/222/"));
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("Bearer old-token")
+        );
+        assert_eq!(requests.len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_sources() {
+        let mut provider = union(&[]).unwrap();
+        assert_eq!(
+            provider.open_table(&table(Some("legacy"))).unwrap_err(),
+            "Unknown source 'legacy' in table option 'sources', expected one of old, new"
+        );
+        assert!(provider.capabilities("messages").is_err());
+
+        let err = union(&[("new.access_token", "")]).unwrap_err();
+        assert_eq!(err, "Source 'new': Missing required option 'access_token'");
+        let err = union(&[("provider", "wati")]).unwrap_err();
+        assert!(err.contains("'old.provider'"), "{}", err);
+        assert!(union(&[("sources", "old, old")]).is_err());
+    }
+}
@@ -0,0 +1,254 @@
+use serde_json::Value as JsonValue;
+
+use super::{masked_number, not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http, jwt, time,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{url_encode, Client, KeyScheme};
+use crate::config::Opts;
+use crate::model::Envelope;
+use crate::objects::{ObjectCapabilities, Operation, Pagination};
+
+// Vonage API used unless the `api_url` option is set, e.g. to
+// `https://api-eu.vonage.com` for the EU region
+const DEFAULT_API_URL: &str = "https://api.nexmo.com";
+
+// Lifetime of the application JWT, longer than any statement
+const JWT_TTL_HOURS: u32 = 1;
+
+// Objects served by Vonage. The Messages API only sends messages and
+// updates their status, it has no read endpoint a health probe could use.
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: false,
+        insert: true,
+        update: true,
+        delete: false,
+        rowid: Some("id"),
+        quals: &[],
+        pagination: Pagination::None,
+    },
+];
+
+// Vonage's (formerly Nexmo) Messages API, authenticated with a JWT signed
+// by the private key of a Vonage application
+#[derive(Debug)]
+pub(crate) struct Vonage {
+    api_url: String,
+    // The WhatsApp number of the application, e.g. `14157386102`
+    from_number: String,
+    // Application JWT, signed when the provider is created
+    token: String,
+}
+
+impl Vonage {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        let application_id = opts.require("application_id")?;
+        let private_key = opts.require("private_key")?;
+        Ok(Box::new(Self {
+            api_url: opts.url_or("api_url", DEFAULT_API_URL),
+            from_number: opts.require_phone_number("from_number")?,
+            token: sign_token(&application_id, &private_key)?,
+        }))
+    }
+
+    // Send a message from the application number
+    fn send_message(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let url = format!("{}/v1/messages", client.base_url);
+        client.send_json(http::Method::Post, url, &message_body(&self.from_number, row)?)?;
+        Ok(())
+    }
+
+    // Update the status of a received message, only marking it as read
+    // being supported by WhatsApp
+    fn update_status(&self, client: &mut Client, row: &WriteRow) -> FdwResult {
+        let uuid = row
+            .rowid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or("Updating messages requires the table option rowid_column 'id'")?;
+        match row.columns.get("status").and_then(|v| v.as_str()) {
+            Some("read") => {}
+            _ => return Err("Only setting column 'status' to 'read' is supported on messages".to_owned()),
+        }
+        let url = format!("{}/v1/messages/{}", client.base_url, url_encode(uuid));
+        client.send_json(http::Method::Patch, url, &serde_json::json!({ "status": "read" }))?;
+        Ok(())
+    }
+}
+
+impl Provider for Vonage {
+    fn name(&self) -> &'static str {
+        "vonage"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = self.api_url.clone();
+        client.envelope = Envelope::Status;
+        client.state.api_key = self.token.clone();
+        client.state.key_scheme = KeyScheme::Bearer;
+    }
+
+    fn identities(&self) -> Vec<(String, String)> {
+        masked_number("from_number", &self.from_number).into_iter().collect()
+    }
+
+    fn number(&self) -> Option<&str> {
+        Some(&self.from_number)
+    }
+
+    fn fetch(&self, _client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        Err(not_implemented(object, Operation::Scan))
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            ("messages", Operation::Update) => self.update_status(client, row),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Sign the application JWT with the host, the `jti` claim making each
+// token unique as Vonage requires
+fn sign_token(application_id: &str, private_key: &str) -> Result<String, FdwError> {
+    let payload = vec![
+        ("application_id".to_owned(), application_id.to_owned()),
+        ("jti".to_owned(), format!("{}-{}", application_id, time::epoch_secs())),
+    ];
+    jwt::encode(&payload, "RS256", private_key, JWT_TTL_HOURS)
+        .map_err(|e| format!("Cannot sign the Vonage application JWT: {}", e))
+}
+
+// Build the request body of a message row: a WhatsApp template if
+// `template_name` is set, with the Cloud API `components`, a text message
+// with `body` otherwise. Vonage numbers have no leading `+`.
+fn message_body(from_number: &str, row: &JsonValue) -> Result<JsonValue, FdwError> {
+    let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+    let to = column("to")
+        .and_then(|v| v.as_str())
+        .ok_or("Column 'to' is required to send a message")?;
+
+    let mut body = serde_json::json!({
+        "channel": "whatsapp",
+        "to": to.trim_start_matches('+'),
+        "from": from_number.trim_start_matches('+'),
+    });
+    if let Some(name) = column("template_name") {
+        let mut template = serde_json::json!({
+            "name": name,
+            "language": {
+                "policy": "deterministic",
+                "code": column("language").cloned().unwrap_or("en_US".into()),
+            },
+        });
+        if let Some(components) = column("components") {
+            template["components"] = components.clone();
+        }
+        body["message_type"] = "custom".into();
+        body["custom"] = serde_json::json!({ "type": "template", "template": template });
+    } else if let Some(text) = column("body") {
+        body["message_type"] = "text".into();
+        body["text"] = text.clone();
+    } else {
+        return Err("Either column 'body' or 'template_name' is required to send a message".to_owned());
+    }
+    if let Some(message_uuid) = column("reply_to_message_id") {
+        body["context"] = serde_json::json!({ "message_uuid": message_uuid });
+    }
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    fn provider() -> Vonage {
+        Vonage {
+            api_url: DEFAULT_API_URL.to_owned(),
+            from_number: "+14157386102".to_owned(),
+            token: "signed.jwt.token".to_owned(),
+        }
+    }
+
+    #[test]
+    fn builds_text_and_template_messages() {
+        let text = message_body("14157386102", &serde_json::json!({"to": "+4915123456789", "body": "Hi"})).unwrap();
+        assert_eq!(
+            text,
+            serde_json::json!({
+                "channel": "whatsapp",
+                "to": "4915123456789",
+                "from": "14157386102",
+                "message_type": "text",
+                "text": "Hi",
+            })
+        );
+
+        let template = message_body(
+            "14157386102",
+            &serde_json::json!({"to": "4915123456789", "template_name": "order_update"}),
+        )
+        .unwrap();
+        assert_eq!(template["message_type"], "custom");
+        assert_eq!(template["custom"]["template"]["language"]["code"], "en_US");
+
+        assert!(message_body("14157386102", &serde_json::json!({"to": "4915123456789"})).is_err());
+    }
+
+    #[test]
+    fn sends_and_marks_messages_read() {
+        let transport = MockTransport::new(vec![
+            response(202, r#"{"message_uuid": "aaaaaaaa-bbbb-cccc-dddd-0123456789ab"}"#),
+            response(200, ""),
+            response(
+                401,
+                r#"{"type": "https://developer.nexmo.com/api-errors#unauthorized", "title": "Invalid credentials"}"#,
+            ),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let insert = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({"to": "+4915123456789", "body": "Hi"}),
+        };
+        provider.write_row(&mut client, "messages", &insert).unwrap();
+
+        let mut update = WriteRow {
+            operation: Operation::Update,
+            rowid: Some("aaaaaaaa-bbbb-cccc-dddd-0123456789ab".into()),
+            columns: serde_json::json!({"status": "read"}),
+        };
+        provider.write_row(&mut client, "messages", &update).unwrap();
+        let err = provider.write_row(&mut client, "messages", &update).unwrap_err();
+        assert!(err.contains("Invalid credentials"), "{}", err);
+
+        update.columns = serde_json::json!({"status": "delivered"});
+        assert!(provider.write_row(&mut client, "messages", &update).is_err());
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[1].url,
+            "https://api.nexmo.com/v1/messages/aaaaaaaa-bbbb-cccc-dddd-0123456789ab"
+        );
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("Bearer signed.jwt.token")
+        );
+        assert_eq!(requests.len(), 3);
+    }
+}
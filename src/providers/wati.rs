@@ -0,0 +1,508 @@
+use serde_json::Value as JsonValue;
+
+use super::{not_implemented, Provider, WriteRow};
+use crate::bindings::supabase::wrappers::{
+    http,
+    types::{FdwError, FdwResult},
+};
+use crate::client::{normalize_number, normalize_url, url_encode, Client, KeyScheme};
+use crate::config::Opts;
+use crate::model::Envelope;
+use crate::objects::contacts::{self, ContactChange};
+use crate::objects::{consents, health, ObjectCapabilities, Operation, Pagination};
+use crate::pagination::{self, Page};
+use crate::timestamp;
+
+// Contacts and templates per page
+const PAGE_SIZE: usize = 100;
+
+// Objects served by WATI
+const OBJECTS: &[ObjectCapabilities] = &[
+    #[cfg(feature = "messages")]
+    ObjectCapabilities {
+        name: "messages",
+        scan: false,
+        insert: true,
+        update: false,
+        delete: false,
+        rowid: None,
+        quals: &[],
+        pagination: Pagination::None,
+    },
+    #[cfg(feature = "messages")]
+    ObjectCapabilities::read_only("templates").paged(Pagination::PageNumber),
+    contacts::WRITABLE_CAPABILITIES,
+    consents::CAPABILITIES,
+    ObjectCapabilities::read_only("health"),
+];
+
+// WATI's WhatsApp team inbox API, on the tenant server of the account.
+// WATI has no account-wide message history, so messages can only be sent.
+#[derive(Debug)]
+pub(crate) struct Wati {
+    // Tenant API endpoint, e.g. `https://live-mt-server.wati.io/123456`
+    api_endpoint: String,
+    access_token: String,
+}
+
+impl Wati {
+    pub(crate) fn parse(opts: &Opts) -> Result<Box<dyn Provider>, FdwError> {
+        let access_token = opts.require("access_token")?;
+        Ok(Box::new(Self {
+            api_endpoint: normalize_url(&opts.require("api_endpoint")?),
+            // The dashboard shows the token with its `Bearer ` prefix
+            access_token: access_token.trim_start_matches("Bearer ").to_owned(),
+        }))
+    }
+
+    // Probe the contacts endpoint with a single row page
+    fn fetch_health(&self, client: &mut Client) -> Vec<JsonValue> {
+        let url = format!("{}/api/v1/getContacts?pageSize=1&pageNumber=1", client.base_url);
+        health::probe(client, url, |_| None)
+    }
+
+    // Send a message: a template message if `template_name` is set, with
+    // its parameters in `components`, a session text message with `body`
+    // otherwise
+    fn send_message(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let column = |name: &str| row.get(name).filter(|v| !v.is_null());
+        let to = column("to")
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'to' is required to send a message")?;
+        // Session and template messages are sent without reply context
+        if column("reply_to_message_id").is_some() {
+            return Err("Column 'reply_to_message_id' is not supported by provider 'wati'".to_owned());
+        }
+        // WATI numbers have no leading `+`
+        let to = url_encode(normalize_number(to).trim_start_matches('+'));
+
+        if let Some(name) = column("template_name") {
+            let url = format!("{}/api/v1/sendTemplateMessage?whatsappNumber={}", client.base_url, to);
+            let body = serde_json::json!({
+                "template_name": name,
+                "broadcast_name": name,
+                "parameters": template_parameters(column("components")),
+            });
+            client.send_json(http::Method::Post, url, &body)?;
+        } else if let Some(text) = column("body").and_then(|v| v.as_str()) {
+            let url = format!(
+                "{}/api/v1/sendSessionMessage/{}?messageText={}",
+                client.base_url,
+                to,
+                url_encode(text)
+            );
+            client.send_json(http::Method::Post, url, &serde_json::json!({}))?;
+        } else {
+            return Err("Either column 'body' or 'template_name' is required to send a message".to_owned());
+        }
+        Ok(())
+    }
+
+    // Add a contact to the contact book, with its name and attributes
+    fn add_contact(&self, client: &mut Client, row: &JsonValue) -> FdwResult {
+        let phone = row
+            .get("phone")
+            .or(row.get("wa_id"))
+            .and_then(|v| v.as_str())
+            .ok_or("Column 'phone' is required to add a contact")?;
+        let change = ContactChange::of(row)?;
+        let url = format!(
+            "{}/api/v1/addContact/{}",
+            client.base_url,
+            url_encode(normalize_number(phone).trim_start_matches('+'))
+        );
+        let body = serde_json::json!({
+            "name": change.name.unwrap_or_default(),
+            "customParams": custom_params(&change.attributes),
+        });
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+
+    // Update the attributes of the contact of a phone number. WATI has no
+    // endpoint renaming a contact, so the name is only set when adding it.
+    fn update_contact(&self, client: &mut Client, row: &WriteRow) -> FdwResult {
+        let phone = row
+            .rowid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or("Table option rowid_column 'phone' is required to update a contact")?;
+        let change = ContactChange::of(&row.columns)?;
+        if change.attributes.is_empty() {
+            return Ok(());
+        }
+        let url = format!(
+            "{}/api/v1/updateContactAttributes/{}",
+            client.base_url,
+            url_encode(normalize_number(phone).trim_start_matches('+'))
+        );
+        let body = serde_json::json!({ "customParams": custom_params(&change.attributes) });
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+
+    // Record the consent of the contact of a phone number in its consent
+    // attribute
+    fn record_consent(&self, client: &mut Client, row: &WriteRow) -> FdwResult {
+        let consent = consents::updated_consent(&row.columns)?;
+        let phone = row
+            .rowid
+            .as_ref()
+            .and_then(|v| v.as_str())
+            .ok_or("Table option rowid_column 'phone' is required to update a consent")?;
+        let url = format!(
+            "{}/api/v1/updateContactAttributes/{}",
+            client.base_url,
+            url_encode(normalize_number(phone).trim_start_matches('+'))
+        );
+        let body = serde_json::json!({
+            "customParams": [{"name": consents::CONSENT_ATTRIBUTE, "value": consent}],
+        });
+        client.send_json(http::Method::Post, url, &body)?;
+        Ok(())
+    }
+}
+
+impl Provider for Wati {
+    fn name(&self) -> &'static str {
+        "wati"
+    }
+
+    fn objects(&self) -> &'static [ObjectCapabilities] {
+        OBJECTS
+    }
+
+    fn connect(&self, client: &mut Client) {
+        client.base_url = self.api_endpoint.clone();
+        client.envelope = Envelope::ResultFlag;
+        client.state.api_key = self.access_token.clone();
+        client.state.key_scheme = KeyScheme::Bearer;
+    }
+
+    fn fetch(&self, client: &mut Client, object: &str) -> Result<Vec<JsonValue>, FdwError> {
+        match object {
+            "contacts" => fetch_pages(client, "getContacts", "contact_list", contact_row),
+            "consents" => fetch_pages(client, "getContacts", "contact_list", |contact| {
+                consents::consent_row(&contact_row(contact))
+            }),
+            "templates" => fetch_pages(client, "getMessageTemplates", "messageTemplates", template_row),
+            "health" => Ok(self.fetch_health(client)),
+            _ => Err(not_implemented(object, Operation::Scan)),
+        }
+    }
+
+    fn write_row(&mut self, client: &mut Client, object: &str, row: &WriteRow) -> FdwResult {
+        match (object, row.operation) {
+            ("messages", Operation::Insert) => self.send_message(client, &row.columns),
+            ("contacts", Operation::Insert) => self.add_contact(client, &row.columns),
+            ("contacts", Operation::Update) => self.update_contact(client, row),
+            ("consents", Operation::Update) => self.record_consent(client, row),
+            _ => Err(not_implemented(object, row.operation)),
+        }
+    }
+}
+
+// Fetch all pages of a WATI list, numbered from 1, until a page is not full
+fn fetch_pages(
+    client: &mut Client,
+    endpoint: &str,
+    list: &str,
+    to_row: fn(&JsonValue) -> JsonValue,
+) -> Result<Vec<JsonValue>, FdwError> {
+    pagination::fetch_all(|page_number| {
+        let page_number: usize = page_number.and_then(|n| n.parse().ok()).unwrap_or(1);
+        let url = format!(
+            "{}/api/v1/{}?pageSize={}&pageNumber={}",
+            client.base_url, endpoint, PAGE_SIZE, page_number
+        );
+        let resp_json = client.get_json(url)?;
+        let items = resp_json
+            .get(list)
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| format!("Cannot get '{}' from WATI response", list))?;
+        Ok(Page {
+            rows: items.iter().map(to_row).collect(),
+            next: (items.len() == PAGE_SIZE).then(|| (page_number + 1).to_string()),
+            total: None,
+        })
+    })
+}
+
+// Convert a WATI contact to the shape of the contacts object
+fn contact_row(contact: &JsonValue) -> JsonValue {
+    let field = |name: &str| contact.get(name).cloned().unwrap_or_default();
+    // Custom parameters, as an object of their values by name
+    let attributes: serde_json::Map<String, JsonValue> = contact
+        .get("customParams")
+        .and_then(|v| v.as_array())
+        .into_iter()
+        .flatten()
+        .filter_map(|param| {
+            let name = param.get("name")?.as_str()?;
+            Some((name.to_owned(), param.get("value").cloned().unwrap_or_default()))
+        })
+        .collect();
+
+    serde_json::json!({
+        "id": field("id"),
+        "wa_id": field("wAid"),
+        "name": field("fullName"),
+        "first_name": field("firstName"),
+        "phone": field("phone"),
+        "source": field("source"),
+        "status": field("contactStatus"),
+        "opted_in": field("optedIn"),
+        "attributes": attributes,
+        "created_at": contact
+            .get("created")
+            .and_then(|v| v.as_str())
+            .and_then(timestamp::parse_rfc3339),
+    })
+}
+
+// Convert a WATI template to the shape of the templates object, its
+// header, body, footer and buttons becoming the components
+fn template_row(template: &JsonValue) -> JsonValue {
+    let field = |name: &str| template.get(name).cloned().unwrap_or_default();
+    let language = match template.get("language") {
+        // A language object, e.g. `{"key": "en", "value": "English"}`
+        Some(JsonValue::Object(language)) => language.get("key").cloned().unwrap_or_default(),
+        language => language.cloned().unwrap_or_default(),
+    };
+    let components: Vec<JsonValue> = ["header", "body", "footer", "buttons"]
+        .into_iter()
+        .filter_map(|part| {
+            let value = template.get(part).filter(|v| !v.is_null())?;
+            Some(serde_json::json!({ "type": part.to_uppercase(), part: value }))
+        })
+        .collect();
+
+    serde_json::json!({
+        "id": field("id"),
+        "name": field("elementName"),
+        "language": language,
+        "status": field("status"),
+        "category": field("category"),
+        "components": components,
+    })
+}
+
+// Custom parameters of a contact, from its attributes
+fn custom_params(attributes: &[(String, String)]) -> JsonValue {
+    attributes
+        .iter()
+        .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+        .collect()
+}
+
+// Template parameters, given as an array of `{"name", "value"}` objects or
+// as an object of values by name
+fn template_parameters(components: Option<&JsonValue>) -> JsonValue {
+    match components {
+        Some(JsonValue::Object(params)) => params
+            .iter()
+            .map(|(name, value)| {
+                let value = value.as_str().map_or_else(|| value.to_string(), |v| v.to_owned());
+                serde_json::json!({ "name": name, "value": value })
+            })
+            .collect(),
+        Some(params) => params.clone(),
+        None => JsonValue::Array(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::header_value;
+    use crate::client::mock::{response, MockTransport};
+
+    fn provider() -> Wati {
+        Wati {
+            api_endpoint: "https://live-mt-server.wati.io/123456".to_owned(),
+            access_token: "wati-token".to_owned(),
+        }
+    }
+
+    #[test]
+    fn maps_contacts_and_templates() {
+        let contact = contact_row(&serde_json::json!({
+            "id": "61f0",
+            "wAid": "4915123456789",
+            "fullName": "Jane Doe",
+            "contactStatus": "VALID",
+            "customParams": [{"name": "city", "value": "Berlin"}],
+            "created": "2021-08-17T07:22:51.133Z",
+        }));
+        assert_eq!(contact["wa_id"], "4915123456789");
+        assert_eq!(contact["attributes"], serde_json::json!({"city": "Berlin"}));
+        assert_eq!(contact["created_at"], 1_629_184_971_133_000i64);
+
+        let template = template_row(&serde_json::json!({
+            "id": "6200",
+            "elementName": "order_update",
+            "language": {"key": "de", "value": "German"},
+            "status": "APPROVED",
+            "body": "Your order {{1}} has shipped",
+            "footer": null,
+        }));
+        assert_eq!(template["name"], "order_update");
+        assert_eq!(template["language"], "de");
+        assert_eq!(
+            template["components"],
+            serde_json::json!([{"type": "BODY", "body": "Your order {{1}} has shipped"}])
+        );
+    }
+
+    #[test]
+    fn pages_contacts_until_a_partial_page() {
+        let full_page = serde_json::json!({
+            "result": "success",
+            "contact_list": vec![serde_json::json!({"id": "1"}); PAGE_SIZE],
+        });
+        let transport = MockTransport::new(vec![
+            response(200, &full_page.to_string()),
+            response(200, r#"{"result": "success", "contact_list": [{"id": "2"}]}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let provider = provider();
+        provider.connect(&mut client);
+
+        let contacts = provider.fetch(&mut client, "contacts").unwrap();
+        assert_eq!(contacts.len(), PAGE_SIZE + 1);
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[1].url,
+            "https://live-mt-server.wati.io/123456/api/v1/getContacts?pageSize=100&pageNumber=2"
+        );
+        assert_eq!(
+            header_value(&requests[0].headers, "authorization"),
+            Some("Bearer wati-token")
+        );
+    }
+
+    #[test]
+    fn adds_contacts_and_updates_their_attributes() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"result": true, "contact": {"id": "61f0"}}"#),
+            response(200, r#"{"result": true}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let insert = WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns: serde_json::json!({
+                "phone": "+49 151 23456789",
+                "name": "Jane Doe",
+                "attributes": {"city": "Berlin"},
+            }),
+        };
+        provider.write_row(&mut client, "contacts", &insert).unwrap();
+        // An update passes the unchanged read-only columns along
+        let update = WriteRow {
+            operation: Operation::Update,
+            rowid: Some("4915123456789".into()),
+            columns: serde_json::json!({"name": "Jane Doe", "status": "VALID", "attributes": {"tier": "vip"}}),
+        };
+        provider.write_row(&mut client, "contacts", &update).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[0].url,
+            "https://live-mt-server.wati.io/123456/api/v1/addContact/4915123456789"
+        );
+        let body: JsonValue = serde_json::from_str(&requests[0].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"name": "Jane Doe", "customParams": [{"name": "city", "value": "Berlin"}]})
+        );
+        assert!(requests[1].url.ends_with("/api/v1/updateContactAttributes/4915123456789"));
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(body, serde_json::json!({"customParams": [{"name": "tier", "value": "vip"}]}));
+    }
+
+    #[test]
+    fn sends_session_and_template_messages() {
+        let transport = MockTransport::new(vec![
+            response(200, r#"{"ok": true, "result": "success"}"#),
+            response(200, r#"{"result": true, "receivers": []}"#),
+            response(200, r#"{"result": false, "info": "Template not found"}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let mut send = |columns: JsonValue| {
+            let row = WriteRow {
+                operation: Operation::Insert,
+                rowid: None,
+                columns,
+            };
+            provider.write_row(&mut client, "messages", &row)
+        };
+        send(serde_json::json!({"to": "+4915123456789", "body": "Hi there"})).unwrap();
+        let template = serde_json::json!({
+            "to": "+4915123456789",
+            "template_name": "order_update",
+            "components": {"1": "42"},
+        });
+        send(template.clone()).unwrap();
+        let err = send(template).unwrap_err();
+        assert!(err.contains("Template not found"), "{}", err);
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[0].url,
+            "https://live-mt-server.wati.io/123456/api/v1/sendSessionMessage/4915123456789?messageText=Hi%20there"
+        );
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(body["parameters"], serde_json::json!([{"name": "1", "value": "42"}]));
+    }
+
+    #[test]
+    fn reads_and_records_consents() {
+        let transport = MockTransport::new(vec![
+            response(
+                200,
+                r#"{"result": "success", "contact_list": [
+                    {"id": "1", "phone": "4915123456789", "optedIn": true, "customParams": [{"name": "marketing_consent", "value": "opted_out"}]},
+                    {"id": "2", "phone": "4915100000000", "optedIn": true, "customParams": []}
+                ]}"#,
+            ),
+            response(200, r#"{"result": true}"#),
+        ]);
+        let requests = transport.requests.clone();
+        let mut client = Client::with_transport(Box::new(transport));
+        let mut provider = provider();
+        provider.connect(&mut client);
+
+        let consents = provider.fetch(&mut client, "consents").unwrap();
+        assert_eq!(consents[0]["consent"], "opted_out");
+        assert_eq!(consents[1]["consent"], "opted_in");
+
+        let row = WriteRow {
+            operation: Operation::Update,
+            rowid: Some("+49 151 00000000".into()),
+            columns: serde_json::json!({"consent": "opted_out"}),
+        };
+        provider.write_row(&mut client, "consents", &row).unwrap();
+
+        let requests = requests.borrow();
+        assert_eq!(
+            requests[1].url,
+            "https://live-mt-server.wati.io/123456/api/v1/updateContactAttributes/4915100000000"
+        );
+        let body: JsonValue = serde_json::from_str(&requests[1].body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"customParams": [{"name": "marketing_consent", "value": "opted_out"}]})
+        );
+    }
+}
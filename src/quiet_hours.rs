@@ -0,0 +1,163 @@
+use crate::bindings::supabase::wrappers::types::FdwError;
+use crate::log::warning;
+
+// Objects whose inserts send messages to customers
+pub(crate) const SEND_OBJECTS: &[&str] = &["messages", "campaign_sends", "broadcast_sends"];
+
+// What inserts into a send object do during quiet hours, with the
+// `quiet_hours_policy` option
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Policy {
+    // The insert fails before anything is sent
+    Fail,
+    // The rows are sent, with a warning once per statement
+    Warn,
+}
+
+impl Policy {
+    pub(crate) const CHOICES: &'static [(&'static str, Policy)] = &[("fail", Policy::Fail), ("warn", Policy::Warn)];
+}
+
+// Local time range during which automated jobs should not message
+// customers, e.g. `22:00-08:00`, with the `quiet_hours` and `timezone`
+// options. The time zone is a fixed UTC offset, as the Wasm guest has no
+// time zone database, so it doesn't follow daylight saving time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct QuietHours {
+    // Minutes since local midnight, the range wrapping past midnight if
+    // it ends before it starts
+    pub(crate) start: i64,
+    pub(crate) end: i64,
+    // seconds east of UTC
+    pub(crate) utc_offset: i64,
+    pub(crate) policy: Policy,
+}
+
+impl QuietHours {
+    // The local time, as `HH:MM`, if it is within quiet hours
+    fn local_time(&self, now_secs: i64) -> Option<String> {
+        let minute = (now_secs + self.utc_offset).rem_euclid(86400) / 60;
+        let quiet = match self.start <= self.end {
+            true => self.start <= minute && minute < self.end,
+            false => minute >= self.start || minute < self.end,
+        };
+        quiet.then(|| format_minute(minute))
+    }
+
+    // Check an insert into `object` at `now_secs`, failing or warning per
+    // the policy if it sends messages during quiet hours. Returns whether
+    // a warning was reported.
+    pub(crate) fn check(&self, object: &str, now_secs: i64, warned: bool) -> Result<bool, FdwError> {
+        if !SEND_OBJECTS.contains(&object) {
+            return Ok(false);
+        }
+        let Some(local_time) = self.local_time(now_secs) else {
+            return Ok(false);
+        };
+        let range = format!("{}-{}", format_minute(self.start), format_minute(self.end));
+        match self.policy {
+            Policy::Fail => Err(format!(
+                "Not sending to '{}' at {} local time, within the quiet hours {}. Set the server option quiet_hours_policy 'warn' to send anyway",
+                object, local_time, range
+            )),
+            Policy::Warn => {
+                if !warned {
+                    warning!(
+                        "modify",
+                        "Sending to '{}' at {} local time, within the quiet hours {}",
+                        object,
+                        local_time,
+                        range
+                    );
+                }
+                Ok(true)
+            }
+        }
+    }
+}
+
+fn format_minute(minute: i64) -> String {
+    format!("{:02}:{:02}", minute / 60, minute % 60)
+}
+
+// Parse a local time range such as `22:00-08:00` as minutes since local
+// midnight
+pub(crate) fn parse_range(s: &str) -> Option<(i64, i64)> {
+    let (start, end) = s.split_once('-')?;
+    let (start, end) = (parse_minute(start.trim())?, parse_minute(end.trim())?);
+    (start != end).then_some((start, end))
+}
+
+fn parse_minute(s: &str) -> Option<i64> {
+    let (hours, minutes) = s.split_once(':')?;
+    if minutes.len() != 2 {
+        return None;
+    }
+    let (hours, minutes): (i64, i64) = (hours.parse().ok()?, minutes.parse().ok()?);
+    ((0..24).contains(&hours) && (0..60).contains(&minutes)).then_some(hours * 60 + minutes)
+}
+
+// Parse a UTC offset such as `UTC`, `+02:00` or `-05:30` as seconds east
+// of UTC
+pub(crate) fn parse_utc_offset(s: &str) -> Option<i64> {
+    if s.eq_ignore_ascii_case("utc") || s == "Z" {
+        return Some(0);
+    }
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    let minutes = parse_minute(&s[1..]).filter(|minutes| *minutes <= 14 * 60)?;
+    Some(sign * minutes * 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2023-11-14T22:13:20Z
+    const NOW: i64 = 1_700_000_000;
+
+    fn quiet_hours(range: &str, timezone: &str, policy: Policy) -> QuietHours {
+        let (start, end) = parse_range(range).unwrap();
+        QuietHours {
+            start,
+            end,
+            utc_offset: parse_utc_offset(timezone).unwrap(),
+            policy,
+        }
+    }
+
+    #[test]
+    fn parses_ranges_and_offsets() {
+        assert_eq!(parse_range("22:00-08:00"), Some((1320, 480)));
+        assert_eq!(parse_range(" 9:30 - 17:00 "), Some((570, 1020)));
+        assert_eq!(parse_range("22:00-22:00"), None);
+        assert_eq!(parse_range("22h-8h"), None);
+        assert_eq!(parse_range("24:00-08:00"), None);
+        assert_eq!(parse_utc_offset("UTC"), Some(0));
+        assert_eq!(parse_utc_offset("+02:00"), Some(7200));
+        assert_eq!(parse_utc_offset("-05:30"), Some(-19800));
+        assert_eq!(parse_utc_offset("Europe/Berlin"), None);
+    }
+
+    #[test]
+    fn holds_sends_back_within_local_quiet_hours() {
+        // 23:13 in Berlin in winter, within the range wrapping past midnight
+        let berlin = quiet_hours("22:00-08:00", "+01:00", Policy::Fail);
+        let err = berlin.check("messages", NOW, false).unwrap_err();
+        assert!(err.starts_with("Not sending to 'messages' at 23:13 local time, within the quiet hours 22:00-08:00"));
+        assert!(berlin.check("campaign_sends", NOW + 9 * 3600, false).is_ok());
+        // Only sends are held back
+        assert_eq!(berlin.check("products", NOW, false), Ok(false));
+
+        // 17:13 in New York, outside a range within the day
+        let new_york = quiet_hours("20:00-23:00", "-05:00", Policy::Fail);
+        assert_eq!(new_york.check("messages", NOW, false), Ok(false));
+        assert!(new_york.check("messages", NOW + 3 * 3600, false).is_err());
+
+        let warn = quiet_hours("22:00-08:00", "UTC", Policy::Warn);
+        assert_eq!(warn.check("broadcast_sends", NOW, false), Ok(true));
+    }
+}
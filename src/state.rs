@@ -0,0 +1,646 @@
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+
+use crate::bindings::supabase::wrappers::types::{Context, FdwError, FdwResult};
+use crate::audit;
+use crate::boundary;
+use crate::client::Client;
+use crate::config::{ServerConfig, TableConfig};
+use crate::frequency_cap;
+use crate::log::{self, debug, notice};
+use crate::mapping;
+#[cfg(feature = "messages")]
+use crate::objects::campaign_sends::{self, Campaigns};
+#[cfg(feature = "messages")]
+use crate::objects::{export_messages, sla};
+#[cfg(feature = "commerce")]
+use crate::objects::{catalog_diff, product_duplicates, product_feed, products};
+use crate::objects::{self, audit_log, columns, ddl, discover, fdw_stats, webhook_bridge, ObjectCapabilities, Operation};
+use crate::pagination::{self, Pager};
+use crate::providers::{Provider, WriteRow};
+use crate::quiet_hours::SEND_OBJECTS;
+use crate::stats;
+
+// Outcome of the rows written by the current modify statement. Requests
+// already sent to the provider can't be rolled back with the transaction,
+// so a failure reports how far the statement got.
+#[derive(Debug, Default)]
+pub(crate) struct WriteReport {
+    pub(crate) sent: usize,
+    failed: usize,
+    first_failure: Option<String>,
+    // Whether sending during quiet hours was already reported
+    warned_quiet_hours: bool,
+    // Whether the template messages sent unchecked were already reported
+    noticed_unchecked_templates: bool,
+}
+
+impl WriteReport {
+    // Record the result of writing one row, turning a failure into an
+    // error summarizing the whole statement
+    pub(crate) fn record(&mut self, result: FdwResult) -> FdwResult {
+        match result {
+            Ok(()) => {
+                self.sent += 1;
+                stats::record(|stats| stats.rows_written += 1);
+                Ok(())
+            }
+            Err(err) => {
+                self.failed += 1;
+                let first_failure = self.first_failure.get_or_insert(err);
+                Err(format!(
+                    "Write failed after {} row(s) were sent, {} row(s) failed (already sent rows are not rolled back), first failure: {}",
+                    self.sent, self.failed, first_failure
+                ))
+            }
+        }
+    }
+}
+
+// Lifecycle phase of the FDW instance. The host calls the routines in a
+// fixed order, anything else is a host or FDW bug, which is reported as
+// an error rather than producing wrong rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) enum Phase {
+    #[default]
+    Uninitialized,
+    Ready,
+    Scanning,
+    Modifying,
+}
+
+impl Phase {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Uninitialized => "uninitialized",
+            Phase::Ready => "ready",
+            Phase::Scanning => "scanning",
+            Phase::Modifying => "modifying",
+        }
+    }
+}
+
+// State of the FDW instance, kept across host calls
+#[derive(Debug, Default)]
+pub(crate) struct FdwState {
+    pub(crate) phase: Phase,
+    pub(crate) client: Client,
+    pub(crate) config: ServerConfig,
+    pub(crate) provider: Option<Box<dyn Provider>>,
+    pub(crate) object: String,
+    // Options of the current table
+    pub(crate) table: TableConfig,
+    pub(crate) write_report: WriteReport,
+    // Progress of the current insert into campaign_sends
+    #[cfg(feature = "messages")]
+    pub(crate) campaigns: Campaigns,
+    pub(crate) src_rows: Vec<JsonValue>,
+    pub(crate) src_idx: usize,
+    // Position of a scan reading its object page by page, the rows of the
+    // current page being in `src_rows`
+    pub(crate) pager: Option<Pager>,
+    // Start of the current scan, microseconds since Unix epoch
+    pub(crate) scanned_at: i64,
+}
+
+thread_local! {
+    // The FDW instance state. Wasm guests are single-threaded, so a thread
+    // local cell gives safe mutable access without a leaked static pointer.
+    static INSTANCE: RefCell<FdwState> = RefCell::new(FdwState::default());
+}
+
+impl FdwState {
+    // Reset the instance state, at the start of the FDW lifecycle
+    pub(crate) fn reset() {
+        INSTANCE.with(|instance| *instance.borrow_mut() = Self::default());
+    }
+
+    // Run a guest entrypoint with mutable access to the instance state,
+    // turning a panic into an error naming the entrypoint. Errors carry
+    // the correlation id of the statement.
+    pub(crate) fn with<R>(
+        entrypoint: &'static str,
+        f: impl FnOnce(&mut Self) -> Result<R, FdwError>,
+    ) -> Result<R, FdwError> {
+        boundary::guard(entrypoint, || {
+            INSTANCE.with(|instance| f(&mut instance.borrow_mut()))
+        })
+        .map_err(log::trace_error)
+    }
+
+    // Move to the next lifecycle phase, failing if the routine is called
+    // out of order
+    pub(crate) fn transition(&mut self, routine: &str, from: Phase, to: Phase) -> FdwResult {
+        if self.phase != from {
+            return Err(format!(
+                "Internal state error: {} called while {}, expected {}",
+                routine,
+                self.phase.as_str(),
+                from.as_str()
+            ));
+        }
+        self.phase = to;
+        Ok(())
+    }
+
+    // The provider selected at init
+    pub(crate) fn provider(&self) -> Result<&dyn Provider, FdwError> {
+        self.provider
+            .as_deref()
+            .ok_or_else(|| "Provider is not configured".to_owned())
+    }
+
+    // Open a foreign table at the start of a scan or modify, checking that
+    // the object, the provider and the table options fit together before
+    // any request is sent
+    pub(crate) fn open_table(&mut self, table: TableConfig) -> FdwResult {
+        let provider = self.provider.as_deref_mut().ok_or("Provider is not configured")?;
+        provider.open_table(&table)?;
+        self.object = table.object.clone();
+        self.client.state.object = table.object.clone();
+        self.client.state.count_totals = table.strict_consistency;
+        self.table = table;
+        let caps = self.capabilities()?;
+        caps.check_rowid(self.table.rowid_column.as_deref())?;
+        debug!(
+            "table",
+            "Object '{}' supports {} with {} pagination",
+            caps.name,
+            caps.operations().join(", "),
+            caps.pagination.as_str()
+        );
+        Ok(())
+    }
+
+    // Capabilities of the current object on the provider, failing if the
+    // object doesn't exist in this build or the provider doesn't serve it
+    pub(crate) fn capabilities(&self) -> Result<&ObjectCapabilities, FdwError> {
+        self.object_capabilities(&self.object)
+    }
+
+    // Capabilities of an object on the provider
+    pub(crate) fn object_capabilities(&self, object: &str) -> Result<&ObjectCapabilities, FdwError> {
+        objects::lookup(object)?;
+        match object {
+            "fdw_stats" => return Ok(&fdw_stats::CAPABILITIES),
+            "audit_log" => return Ok(&audit_log::CAPABILITIES),
+            "discover" => return Ok(&discover::CAPABILITIES),
+            "columns" => return Ok(&columns::CAPABILITIES),
+            "ddl" => return Ok(&ddl::CAPABILITIES),
+            _ => {}
+        }
+        #[cfg(feature = "messages")]
+        match object {
+            "campaign_sends" => return Ok(&campaign_sends::CAPABILITIES),
+            "export_messages" => return Ok(&export_messages::CAPABILITIES),
+            "sla" => return Ok(&sla::CAPABILITIES),
+            _ => {}
+        }
+        #[cfg(feature = "commerce")]
+        match object {
+            "product_feed" => return Ok(&product_feed::CAPABILITIES),
+            "catalog_diff" => return Ok(&catalog_diff::CAPABILITIES),
+            "product_duplicates" => return Ok(&product_duplicates::CAPABILITIES),
+            _ => {}
+        }
+        self.provider()?.capabilities(object)
+    }
+
+    // Fetch the source rows of the current object from the provider, from
+    // its feed file, or from the instance counters, audit log, campaign
+    // cursors, column metadata and generated DDL. Message exports and the
+    // SLA of chats are computed from the provider's messages, and product
+    // duplicates from its products. Webhook registrations are compared to
+    // the table's webhook URL, and discovery samples the objects it names.
+    // Objects the provider pages lazily only return their first rows, the
+    // next ones being read by `next_page`, unless the table asks for a
+    // consistent snapshot of them.
+    pub(crate) fn fetch(&mut self) -> Result<Vec<JsonValue>, FdwError> {
+        self.capabilities()?.check(Operation::Scan)?;
+        self.scanned_at = self.client.state.clock.now_secs() * 1_000_000;
+        self.pager = None;
+        #[cfg(feature = "messages")]
+        if self.object == "campaign_sends" {
+            return Ok(self.campaigns.rows());
+        }
+        #[cfg(feature = "commerce")]
+        match self.object.as_str() {
+            "product_feed" => return product_feed::fetch(&mut self.client, self.table.feed_url.as_deref()),
+            "catalog_diff" => {
+                let provider = self.provider.as_deref().ok_or("Provider is not configured")?;
+                return catalog_diff::fetch(&mut self.client, provider, self.table.feed_url.as_deref());
+            }
+            "product_duplicates" => {
+                let provider = self.provider.as_deref().ok_or("Provider is not configured")?;
+                return product_duplicates::fetch(&mut self.client, provider);
+            }
+            _ => {}
+        }
+        match self.object.as_str() {
+            "fdw_stats" => return Ok(fdw_stats::fetch(self.client.state.clock.as_ref())),
+            "audit_log" => return Ok(audit::rows()),
+            "columns" => return Ok(columns::fetch(|object| self.object_capabilities(object).ok())),
+            "ddl" => {
+                let lines = match self.table.per_number {
+                    true => Some(self.provider()?.lines()),
+                    false => None,
+                };
+                let server_name = self.table.server_name.as_deref();
+                return ddl::fetch(|object| self.object_capabilities(object).ok(), server_name, lines.as_deref());
+            }
+            _ => {}
+        }
+        let provider = self.provider.as_deref().ok_or("Provider is not configured")?;
+        #[cfg(feature = "messages")]
+        match self.object.as_str() {
+            "export_messages" => return export_messages::fetch(&mut self.client, provider),
+            "sla" => return sla::fetch(&mut self.client, provider, self.table.sla_threshold),
+            _ => {}
+        }
+        if self.object == "discover" {
+            return discover::fetch(&mut self.client, provider, self.table.sample_objects.as_deref());
+        }
+        if provider.pages_lazily(&self.object) && self.table.strict_consistency {
+            let rows =
+                pagination::fetch_snapshot(|cursor| provider.fetch_page(&mut self.client, &self.object, cursor))?;
+            return Ok(self.complete_rows(rows));
+        }
+        if provider.pages_lazily(&self.object) {
+            self.pager = Some(Pager::default());
+            return self.next_page();
+        }
+        let rows = provider.fetch(&mut self.client, &self.object)?;
+        Ok(self.complete_rows(rows))
+    }
+
+    // Fetch the rows of the next page of a scan reading its object page by
+    // page, skipping empty pages. No rows are left once the last page was
+    // read, or if the object is not paged lazily.
+    pub(crate) fn next_page(&mut self) -> Result<Vec<JsonValue>, FdwError> {
+        let Some(pager) = self.pager.as_mut() else {
+            return Ok(Vec::new());
+        };
+        let provider = self.provider.as_deref().ok_or("Provider is not configured")?;
+        while !pager.is_done() {
+            let page = provider.fetch_page(&mut self.client, &self.object, pager.next())?;
+            let rows = pager.advance(page)?;
+            if !rows.is_empty() {
+                return Ok(self.complete_rows(rows));
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    // Complete the rows fetched from the provider with what the FDW knows:
+    // the registration state of webhooks, and the capture time of products,
+    // the same for all the pages of a scan
+    fn complete_rows(&self, mut rows: Vec<JsonValue>) -> Vec<JsonValue> {
+        if self.object == "webhook_bridge" {
+            webhook_bridge::check_registration(&mut rows, self.table.webhook_url.as_deref());
+        }
+        #[cfg(feature = "commerce")]
+        if self.object == "products" {
+            products::stamp_snapshot(&mut rows, self.table.as_of.unwrap_or(self.scanned_at));
+        }
+        rows
+    }
+
+    // Send one row of the current modify statement to the provider, and
+    // record it in the audit log. Campaign rows are sent as messages, and
+    // sent messages are confirmed with the `confirm_sends` table option.
+    pub(crate) fn write_row(&mut self, mut row: WriteRow) -> FdwResult {
+        self.capabilities()?.check(row.operation)?;
+        if let (Operation::Insert, Some(quiet_hours)) = (row.operation, &self.config.quiet_hours) {
+            let now = self.client.state.clock.now_secs();
+            let warned = self.write_report.warned_quiet_hours;
+            self.write_report.warned_quiet_hours |= quiet_hours.check(&self.object, now, warned)?;
+        }
+        self.notice_unchecked_template(&row)?;
+        let capped_recipient = self.capped_recipient(&row);
+        let now = self.client.state.clock.now_secs();
+        if let Some(to) = &capped_recipient {
+            frequency_cap::check(self.config.max_messages_per_contact_per_day, to, now)?;
+        }
+        if self.object == "webhook_bridge" {
+            webhook_bridge::fill_registration(&mut row.columns, &self.table)?;
+        }
+        #[cfg(feature = "messages")]
+        if self.object == "campaign_sends" {
+            self.object_capabilities("messages")?.check(Operation::Insert)?;
+        }
+        let provider = self.provider.as_deref_mut().ok_or("Provider is not configured")?;
+        self.client.state.last_write = None;
+        let result = match self.object.as_str() {
+            #[cfg(feature = "messages")]
+            "campaign_sends" => self.campaigns.send(&mut self.client, provider, &self.table, &row.columns),
+            _ => provider.write_row(&mut self.client, &self.object, &row),
+        };
+        // A message the provider accepted counts against the cap, even if
+        // its status can't be confirmed afterwards
+        if let (Ok(()), Some(to)) = (&result, &capped_recipient) {
+            frequency_cap::record(to, now);
+        }
+        let confirmed = match result {
+            Ok(()) => self.confirm_send(),
+            Err(_) => Ok(None),
+        };
+        let (result, status) = match confirmed {
+            Ok(status) => (result, status),
+            Err(err) => (Err(err), None),
+        };
+        self.audit(row.operation.as_str(), row.rowid.as_ref(), &result, status.as_deref());
+        result
+    }
+
+    // Report once per statement that template messages go out without
+    // being checked against their template, by providers that can't read
+    // the definitions or without the options to
+    fn notice_unchecked_template(&mut self, row: &WriteRow) -> FdwResult {
+        let sends = matches!(row.operation, Operation::Insert) && SEND_OBJECTS.contains(&self.object.as_str());
+        let template = row.columns.get("template_name").is_some_and(|v| !v.is_null());
+        if !sends || !template || self.write_report.noticed_unchecked_templates {
+            return Ok(());
+        }
+        let provider = self.provider()?;
+        if !provider.checks_templates() {
+            notice!(
+                "modify",
+                "Template messages to '{}' are sent unchecked, provider '{}' can't read the template definitions (meta_cloud reads them with the waba_id server option)",
+                self.object,
+                provider.name()
+            );
+        }
+        self.write_report.noticed_unchecked_templates = true;
+        Ok(())
+    }
+
+    // Recipient of an inserted row sending a message, counted against the
+    // `max_messages_per_contact_per_day` cap. Campaign rows sent by an
+    // earlier statement are skipped, so they are not counted again.
+    fn capped_recipient(&self, row: &WriteRow) -> Option<String> {
+        let capped = frequency_cap::CAPPED_OBJECTS.contains(&self.object.as_str());
+        if !matches!(row.operation, Operation::Insert) || !capped || self.config.max_messages_per_contact_per_day == 0 {
+            return None;
+        }
+        #[cfg(feature = "messages")]
+        if self.object == "campaign_sends" && self.campaigns.is_sent(&row.columns) {
+            return None;
+        }
+        row.columns.get("to").and_then(|v| v.as_str()).map(|to| to.to_owned())
+    }
+
+    // Look up the message just sent by the id in the provider response, a
+    // second request telling apart the messages the provider queued from
+    // those it rejected after accepting the send
+    fn confirm_send(&mut self) -> Result<Option<String>, FdwError> {
+        if self.object != "messages" || !self.table.confirm_sends {
+            return Ok(None);
+        }
+        let Some(id) = self.client.state.last_write.as_ref().and_then(audit::provider_id) else {
+            return Ok(None);
+        };
+        let provider = self.provider.as_deref().ok_or("Provider is not configured")?;
+        let message = provider
+            .message_status(&mut self.client, &id)
+            .map_err(|err| format!("Message '{}' was sent but its status could not be confirmed: {}", id, err))?;
+        match message {
+            Some(message) => audit::confirmed_status(&id, &message),
+            None => Ok(None),
+        }
+    }
+
+    // Send the rows the provider buffered for the current modify statement,
+    // recording the batch in the audit log if there was one
+    pub(crate) fn end_modify(&mut self) -> FdwResult {
+        let provider = self.provider.as_deref_mut().ok_or("Provider is not configured")?;
+        self.client.state.last_write = None;
+        let result = provider.end_modify(&mut self.client, &self.object);
+        if result.is_err() || self.client.state.last_write.is_some() {
+            self.audit("batch", None, &result, None);
+        }
+        result
+    }
+
+    fn audit(&mut self, operation: &str, rowid: Option<&JsonValue>, result: &FdwResult, status: Option<&str>) {
+        let now = self.client.state.clock.now_secs();
+        let response = self.client.state.last_write.take();
+        audit::record(now, &self.object, operation, rowid, result, response.as_ref(), status);
+    }
+
+    // Report selected columns whose key is absent from every fetched row,
+    // as this usually points to a typo in the foreign table definition
+    pub(crate) fn report_missing_columns(&self, ctx: &Context) {
+        if self.src_rows.is_empty() {
+            return;
+        }
+
+        let missing: Vec<String> = ctx
+            .get_columns()
+            .iter()
+            .map(|col| col.name())
+            .filter(|name| {
+                self.src_rows.iter().all(|row| match self.table.auto_columns {
+                    true => mapping::field(row, name).is_none(),
+                    false => row.get(name).is_none(),
+                })
+            })
+            .collect();
+
+        if !missing.is_empty() {
+            notice!(
+                "scan",
+                "Columns not found in any row returned for object '{}': {}",
+                self.object,
+                missing.join(", ")
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::mock::FakeClock;
+    use crate::pagination::Page;
+
+    #[test]
+    fn follows_scan_and_modify_lifecycle() {
+        let mut state = FdwState::default();
+        state.transition("init", Phase::Uninitialized, Phase::Ready).unwrap();
+        state.transition("begin_scan", Phase::Ready, Phase::Scanning).unwrap();
+        state.transition("iter_scan", Phase::Scanning, Phase::Scanning).unwrap();
+        state.transition("end_scan", Phase::Scanning, Phase::Ready).unwrap();
+        state.transition("begin_modify", Phase::Ready, Phase::Modifying).unwrap();
+        state.transition("end_modify", Phase::Modifying, Phase::Ready).unwrap();
+        assert_eq!(state.phase, Phase::Ready);
+    }
+
+    #[test]
+    fn rejects_out_of_order_routines() {
+        let mut state = FdwState::default();
+        let err = state
+            .transition("iter_scan", Phase::Scanning, Phase::Scanning)
+            .unwrap_err();
+        assert_eq!(
+            err,
+            "Internal state error: iter_scan called while uninitialized, expected scanning"
+        );
+        assert_eq!(state.phase, Phase::Uninitialized);
+
+        state.phase = Phase::Modifying;
+        assert!(state.transition("begin_scan", Phase::Ready, Phase::Scanning).is_err());
+    }
+
+    // Provider listing contacts in pages of the given sizes, the cursor
+    // being the index of the page
+    #[derive(Debug)]
+    struct PagedContacts {
+        pages: Vec<usize>,
+        fetched: std::rc::Rc<RefCell<Vec<Option<String>>>>,
+    }
+
+    impl Provider for PagedContacts {
+        fn name(&self) -> &'static str {
+            "paged"
+        }
+
+        fn objects(&self) -> &[ObjectCapabilities] {
+            const OBJECTS: &[ObjectCapabilities] = &[ObjectCapabilities::read_only("contacts")];
+            OBJECTS
+        }
+
+        fn connect(&self, _client: &mut Client) {}
+
+        fn fetch(&self, _client: &mut Client, _object: &str) -> Result<Vec<JsonValue>, FdwError> {
+            unreachable!("contacts are paged lazily")
+        }
+
+        fn pages_lazily(&self, _object: &str) -> bool {
+            true
+        }
+
+        fn fetch_page(&self, _client: &mut Client, _object: &str, cursor: Option<&str>) -> Result<Page, FdwError> {
+            self.fetched.borrow_mut().push(cursor.map(|c| c.to_owned()));
+            let index: usize = cursor.map_or(0, |c| c.parse().unwrap());
+            Ok(Page {
+                rows: (0..self.pages[index]).map(|i| serde_json::json!({"id": i})).collect(),
+                next: Some(index + 1).filter(|&next| next < self.pages.len()).map(|next| next.to_string()),
+                total: None,
+            })
+        }
+    }
+
+    #[test]
+    fn reads_the_next_page_once_the_rows_are_consumed() {
+        let fetched = std::rc::Rc::default();
+        let mut state = FdwState {
+            object: "contacts".to_owned(),
+            provider: Some(Box::new(PagedContacts {
+                pages: vec![2, 0, 0, 3],
+                fetched: std::rc::Rc::clone(&fetched),
+            })),
+            ..Default::default()
+        };
+        state.client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        assert_eq!(state.fetch().unwrap().len(), 2);
+        assert_eq!(*fetched.borrow(), [None]);
+
+        // Empty pages are skipped
+        assert_eq!(state.next_page().unwrap().len(), 3);
+        assert_eq!(fetched.borrow().len(), 4);
+        assert!(state.next_page().unwrap().is_empty());
+        assert_eq!(fetched.borrow().len(), 4);
+    }
+
+    #[test]
+    fn strict_consistency_reads_all_pages_up_front() {
+        let fetched = std::rc::Rc::default();
+        let mut state = FdwState {
+            object: "contacts".to_owned(),
+            provider: Some(Box::new(PagedContacts {
+                pages: vec![2, 0, 3],
+                fetched: std::rc::Rc::clone(&fetched),
+            })),
+            ..Default::default()
+        };
+        state.table.strict_consistency = true;
+        state.client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        assert_eq!(state.fetch().unwrap().len(), 5);
+        assert_eq!(fetched.borrow().len(), 3);
+        assert!(state.next_page().unwrap().is_empty());
+    }
+
+    // Provider sending messages, checking their templates or not
+    #[cfg(feature = "messages")]
+    #[derive(Debug)]
+    struct Sender {
+        checks_templates: bool,
+        sent: std::rc::Rc<RefCell<usize>>,
+    }
+
+    #[cfg(feature = "messages")]
+    impl Provider for Sender {
+        fn name(&self) -> &'static str {
+            "sender"
+        }
+
+        fn objects(&self) -> &[ObjectCapabilities] {
+            const OBJECTS: &[ObjectCapabilities] = &[ObjectCapabilities {
+                name: "messages",
+                scan: false,
+                insert: true,
+                update: false,
+                delete: false,
+                rowid: None,
+                quals: &[],
+                pagination: objects::Pagination::None,
+            }];
+            OBJECTS
+        }
+
+        fn connect(&self, _client: &mut Client) {}
+
+        fn fetch(&self, _client: &mut Client, _object: &str) -> Result<Vec<JsonValue>, FdwError> {
+            unreachable!("messages are only sent")
+        }
+
+        fn checks_templates(&self) -> bool {
+            self.checks_templates
+        }
+
+        fn write_row(&mut self, _client: &mut Client, _object: &str, _row: &WriteRow) -> FdwResult {
+            *self.sent.borrow_mut() += 1;
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "messages")]
+    #[test]
+    fn notices_template_messages_sent_unchecked_once_per_statement() {
+        let row = |columns: JsonValue| WriteRow {
+            operation: Operation::Insert,
+            rowid: None,
+            columns,
+        };
+        let sent = std::rc::Rc::default();
+        let mut state = FdwState {
+            object: "messages".to_owned(),
+            provider: Some(Box::new(Sender {
+                checks_templates: false,
+                sent: std::rc::Rc::clone(&sent),
+            })),
+            ..Default::default()
+        };
+        state.client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+
+        state.write_row(row(serde_json::json!({"to": "+4915123456789", "body": "Hi"}))).unwrap();
+        assert!(!state.write_report.noticed_unchecked_templates);
+
+        // The template messages still go out
+        let template = serde_json::json!({"to": "+4915123456789", "template_name": "order_update"});
+        state.write_row(row(template.clone())).unwrap();
+        state.write_row(row(template)).unwrap();
+        assert!(state.write_report.noticed_unchecked_templates);
+        assert_eq!(*sent.borrow(), 3);
+    }
+}
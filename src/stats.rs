@@ -0,0 +1,180 @@
+use serde_json::Value as JsonValue;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+// Cumulative counters of the Wasm instance, exposed by the `fdw_stats`
+// object. Unlike the FDW state they are not reset at init, so they cover
+// every statement run by the instance since it was instantiated.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Stats {
+    // seconds since Unix epoch
+    pub(crate) started_at: Option<i64>,
+    pub(crate) scans: u64,
+    pub(crate) rows_scanned: u64,
+    pub(crate) rows_written: u64,
+    // Requests that reached the provider, retries included
+    pub(crate) api_calls: u64,
+    pub(crate) retries: u64,
+    pub(crate) cache_hits: u64,
+    // API requests slower than the `slow_request_threshold` option
+    pub(crate) slow_requests: u64,
+    // API credits consumed, and the balance last reported by the provider
+    pub(crate) credits_used: i64,
+    pub(crate) credits_remaining: Option<i64>,
+    // Failed API requests by kind, e.g. `auth` or `server`
+    pub(crate) errors: BTreeMap<&'static str, u64>,
+    // Latency histogram of the API requests of each object, as request
+    // counts by whole seconds, the resolution of the host clock
+    pub(crate) latency: BTreeMap<String, BTreeMap<i64, u64>>,
+}
+
+// Latency percentile of a histogram, by nearest rank
+fn percentile(histogram: &BTreeMap<i64, u64>, percent: u64) -> i64 {
+    let total: u64 = histogram.values().sum();
+    let rank = (total * percent).div_ceil(100).max(1);
+    let mut seen = 0;
+    for (secs, count) in histogram {
+        seen += count;
+        if seen >= rank {
+            return *secs;
+        }
+    }
+    0
+}
+
+impl Stats {
+    // The `fdw_stats` row of the counters, collected at `now` in seconds
+    // since Unix epoch
+    pub(crate) fn to_row(&self, now: i64) -> JsonValue {
+        serde_json::json!({
+            "started_at": self.started_at.map(|secs| secs * 1_000_000),
+            "collected_at": now * 1_000_000,
+            "scans": self.scans,
+            "rows_scanned": self.rows_scanned,
+            "rows_written": self.rows_written,
+            "api_calls": self.api_calls,
+            "retries": self.retries,
+            "cache_hits": self.cache_hits,
+            "slow_requests": self.slow_requests,
+            "credits_used": self.credits_used,
+            "credits_remaining": self.credits_remaining,
+            "errors": self.errors.values().sum::<u64>(),
+            "errors_by_kind": self.errors,
+            "latency_by_object": self.latency_summary(),
+        })
+    }
+
+    // Request count, p50, p95 and max latency in seconds of each object
+    fn latency_summary(&self) -> JsonValue {
+        self.latency
+            .iter()
+            .map(|(object, histogram)| {
+                let summary = serde_json::json!({
+                    "requests": histogram.values().sum::<u64>(),
+                    "p50_secs": percentile(histogram, 50),
+                    "p95_secs": percentile(histogram, 95),
+                    "max_secs": histogram.keys().next_back().copied().unwrap_or_default(),
+                });
+                (object.clone(), summary)
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into()
+    }
+}
+
+thread_local! {
+    static STATS: RefCell<Stats> = RefCell::new(Stats::default());
+}
+
+// Update the counters
+pub(crate) fn record(f: impl FnOnce(&mut Stats)) {
+    STATS.with(|stats| f(&mut stats.borrow_mut()));
+}
+
+// Count a failed API request
+pub(crate) fn record_error(kind: &'static str) {
+    record(|stats| *stats.errors.entry(kind).or_default() += 1);
+}
+
+// Note the time the instance started, at its first init
+pub(crate) fn start(now: i64) {
+    record(|stats| {
+        stats.started_at.get_or_insert(now);
+    });
+}
+
+// A copy of the current counters
+pub(crate) fn snapshot() -> Stats {
+    STATS.with(|stats| stats.borrow().clone())
+}
+
+// Count an API request of an object taking `secs` seconds
+pub(crate) fn record_latency(object: &str, secs: i64) {
+    record(|stats| {
+        let histogram = stats.latency.entry(object.to_owned()).or_default();
+        *histogram.entry(secs.max(0)).or_default() += 1;
+    });
+}
+
+// Kind of error of an API response status, None for a success
+pub(crate) fn status_error_kind(status_code: u16) -> Option<&'static str> {
+    match status_code {
+        401 | 403 => Some("auth"),
+        429 => Some("rate_limit"),
+        400..=499 => Some("client"),
+        500.. => Some("server"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::mock::{response, MockTransport};
+    use crate::client::Client;
+    use crate::clock::mock::FakeClock;
+
+    #[test]
+    fn counts_calls_retries_cache_hits_and_errors() {
+        // Tests run on their own thread, so the counters start from zero
+        let transport = MockTransport::new(vec![
+            Err("connection reset".to_owned()),
+            response(200, r#"{"success": true}"#),
+            response(401, r#"{"success": false, "message": "Unauthorized"}"#),
+            response(200, r#"{"success": false, "message": "Unknown number"}"#),
+        ]);
+        let mut client = Client::with_transport(Box::new(transport));
+        client.state.clock = Box::new(FakeClock::at(1_700_000_000));
+        client.enable_cache(60);
+
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        client.get_json("https://api.example.com/x".to_owned()).unwrap();
+        assert!(client.get_json("https://api.example.com/y".to_owned()).is_err());
+        assert!(client.get_json("https://api.example.com/z".to_owned()).is_err());
+
+        start(1_700_000_000);
+        let row = snapshot().to_row(1_700_000_060);
+        assert_eq!(row["api_calls"], 4);
+        assert_eq!(row["retries"], 1);
+        assert_eq!(row["cache_hits"], 1);
+        assert_eq!(row["errors"], 2);
+        assert_eq!(row["errors_by_kind"], serde_json::json!({"auth": 1, "provider": 1}));
+        assert_eq!(row["started_at"], 1_700_000_000_000_000i64);
+    }
+
+    #[test]
+    fn summarizes_latency_by_object() {
+        for secs in [0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 2, 2, 7] {
+            record_latency("messages", secs);
+        }
+        record_latency("health", 3);
+        let row = snapshot().to_row(0);
+        assert_eq!(
+            row["latency_by_object"],
+            serde_json::json!({
+                "health": {"requests": 1, "p50_secs": 3, "p95_secs": 3, "max_secs": 3},
+                "messages": {"requests": 20, "p50_secs": 1, "p95_secs": 2, "max_secs": 7},
+            })
+        );
+    }
+}
@@ -0,0 +1,260 @@
+// Parsing of the date formats of the provider APIs into Postgres
+// timestamps, in microseconds since Unix epoch
+
+use crate::bindings::supabase::wrappers::types::{Cell, Value};
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+// Days since Unix epoch of a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+// Proleptic Gregorian date of a number of days since Unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+// Seconds of a `hh:mm:ss` time of day
+fn parse_time(s: &str) -> Option<i64> {
+    let mut parts = s.split(':').map(|p| p.parse::<i64>().ok());
+    let (h, m, sec) = (parts.next()??, parts.next()??, parts.next().unwrap_or(Some(0))?);
+    if parts.next().is_some() || h > 23 || m > 59 || sec > 60 {
+        return None;
+    }
+    Some(h * 3600 + m * 60 + sec)
+}
+
+// Seconds east of UTC of a `+hhmm` zone offset, or of `GMT`/`UT`/`Z`
+fn parse_offset(s: &str) -> Option<i64> {
+    match s {
+        "GMT" | "UT" | "UTC" | "Z" => Some(0),
+        _ => {
+            let sign = match s.as_bytes().first()? {
+                b'+' => 1,
+                b'-' => -1,
+                _ => return None,
+            };
+            let digits = s[1..].replace(':', "");
+            if digits.len() != 4 {
+                return None;
+            }
+            let hhmm = digits.parse::<i64>().ok()?;
+            Some(sign * ((hhmm / 100) * 3600 + (hhmm % 100) * 60))
+        }
+    }
+}
+
+// RFC 2822 date, e.g. `Thu, 30 Jul 2015 20:12:31 +0000`
+pub(crate) fn parse_rfc2822(s: &str) -> Option<i64> {
+    let s = s.trim();
+    // The day of the week is optional
+    let s = s.split_once(',').map_or(s, |(_, rest)| rest);
+    let parts: Vec<&str> = s.split_whitespace().collect();
+    let [day, month, year, time, offset] = parts[..] else {
+        return None;
+    };
+
+    let day = day.parse::<i64>().ok().filter(|d| (1..=31).contains(d))?;
+    let month = MONTHS
+        .iter()
+        .position(|m| m.eq_ignore_ascii_case(month))? as i64
+        + 1;
+    let year = year.parse::<i64>().ok()?;
+    let secs = days_from_civil(year, month, day) * 86400 + parse_time(time)? - parse_offset(offset)?;
+    Some(secs * 1_000_000)
+}
+
+// RFC 3339 timestamp, e.g. `2021-08-17T07:22:51.133Z`, a missing zone
+// offset being UTC
+pub(crate) fn parse_rfc3339(s: &str) -> Option<i64> {
+    let s = s.trim();
+    let (date, rest) = s.split_once(['T', 't', ' '])?;
+    let mut date_parts = date.split('-').map(|p| p.parse::<i64>().ok());
+    let (year, month, day) = (date_parts.next()??, date_parts.next()??, date_parts.next()??);
+    if date_parts.next().is_some() || !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // The zone offset starts at the first `Z`, `+` or `-` after the time
+    let zone_at = rest.find(['Z', 'z', '+', '-']).unwrap_or(rest.len());
+    let (time, offset) = rest.split_at(zone_at);
+    let offset = match offset {
+        "" | "z" => 0,
+        _ => parse_offset(offset)?,
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let micros = format!("{:0<6}", &fraction[..fraction.len().min(6)])
+        .parse::<i64>()
+        .ok()?;
+
+    let secs = days_from_civil(year, month, day) * 86400 + parse_time(time)? - offset;
+    Some(secs * 1_000_000 + micros)
+}
+
+// Format a timestamp as RFC 3339 in UTC, e.g. `2015-07-30T20:12:31.5Z`,
+// with the fraction of a second only if there is one
+pub(crate) fn format_rfc3339(micros: i64) -> String {
+    let secs = micros.div_euclid(1_000_000);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let time = secs.rem_euclid(86400);
+    let mut s = format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    );
+    let fraction = micros.rem_euclid(1_000_000);
+    if fraction > 0 {
+        s.push_str(format!(".{:06}", fraction).trim_end_matches('0'));
+    }
+    s.push('Z');
+    s
+}
+
+// Time range of the rows of a scan, from the quals on its `since` and
+// `until` columns, in microseconds since Unix epoch. Only the quals the
+// rows satisfy by echoing the bound are taken, as Postgres checks them
+// again on the fetched rows.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(crate) struct Period {
+    pub(crate) since: Option<i64>,
+    pub(crate) until: Option<i64>,
+}
+
+impl Period {
+    // Narrow the period with a qual, ignoring other columns and operators
+    pub(crate) fn add_qual(&mut self, field: &str, operator: &str, value: &Value) {
+        let Value::Cell(cell) = value else { return };
+        let at = match cell {
+            Cell::Timestamptz(micros) | Cell::Timestamp(micros) => *micros,
+            Cell::Date(secs) => secs * 1_000_000,
+            Cell::String(s) => match parse_rfc3339(s) {
+                Some(micros) => micros,
+                None => return,
+            },
+            _ => return,
+        };
+        match (field, operator) {
+            ("since", "=" | ">=") => self.since = Some(at),
+            ("until", "=" | "<=") => self.until = Some(at),
+            // A column of the rows rather than an echo of the qual, so
+            // strict comparisons bound the period too, Postgres rechecking
+            // the rows at the bound
+            ("sent_at", ">=" | ">") => self.since = Some(at),
+            ("sent_at", "<=" | "<") => self.until = Some(at),
+            ("sent_at", "=") => {
+                self.since = Some(at);
+                self.until = Some(at);
+            }
+            _ => {}
+        }
+    }
+
+    // Whether a time falls in the period, bounds included
+    pub(crate) fn contains(&self, at: i64) -> bool {
+        self.since.is_none_or(|since| at >= since) && self.until.is_none_or(|until| at <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rfc2822_dates() {
+        assert_eq!(parse_rfc2822("Thu, 01 Jan 1970 00:00:00 +0000"), Some(0));
+        assert_eq!(
+            parse_rfc2822("Thu, 30 Jul 2015 20:12:31 +0000"),
+            Some(1_438_287_151_000_000)
+        );
+        assert_eq!(
+            parse_rfc2822("30 Jul 2015 22:12:31 +0200"),
+            Some(1_438_287_151_000_000)
+        );
+        assert_eq!(parse_rfc2822("Tue, 29 Feb 2000 12:00:00 GMT"), Some(951_825_600_000_000));
+        for invalid in ["", "yesterday", "Thu, 30 Foo 2015 20:12:31 +0000", "Thu, 30 Jul 2015 25:12:31 +0000"] {
+            assert_eq!(parse_rfc2822(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn parses_rfc3339_timestamps() {
+        assert_eq!(parse_rfc3339("1970-01-01T00:00:00Z"), Some(0));
+        assert_eq!(
+            parse_rfc3339("2015-07-30T20:12:31.5Z"),
+            Some(1_438_287_151_500_000)
+        );
+        assert_eq!(
+            parse_rfc3339("2015-07-30T22:12:31+02:00"),
+            Some(1_438_287_151_000_000)
+        );
+        assert_eq!(parse_rfc3339("2015-07-30 20:12:31"), Some(1_438_287_151_000_000));
+        for invalid in ["", "2015-07-30", "2015-13-30T20:12:31Z", "2015-07-30T20:12:31.x1Z"] {
+            assert_eq!(parse_rfc3339(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn formats_rfc3339_timestamps() {
+        assert_eq!(format_rfc3339(0), "1970-01-01T00:00:00Z");
+        assert_eq!(format_rfc3339(1_438_287_151_500_000), "2015-07-30T20:12:31.5Z");
+        assert_eq!(format_rfc3339(-1), "1969-12-31T23:59:59.999999Z");
+        for micros in [951_782_400_000_000, 1_709_164_800_123_456, -2_208_988_800_000_000] {
+            assert_eq!(parse_rfc3339(&format_rfc3339(micros)), Some(micros));
+        }
+    }
+
+    #[test]
+    fn narrows_the_period_with_quals() {
+        let mut period = Period::default();
+        period.add_qual("since", ">=", &Value::Cell(Cell::Date(86_400)));
+        period.add_qual("until", "=", &Value::Cell(Cell::String("1970-01-03T00:00:00Z".to_owned())));
+        // Postgres would drop rows echoing the bound of a strict comparison
+        period.add_qual("until", "<", &Value::Cell(Cell::Timestamptz(0)));
+        period.add_qual("name", "=", &Value::Cell(Cell::Timestamptz(0)));
+        assert_eq!(
+            period,
+            Period {
+                since: Some(86_400_000_000),
+                until: Some(172_800_000_000),
+            }
+        );
+        assert!(period.contains(86_400_000_000));
+        assert!(!period.contains(0));
+        assert!(Period::default().contains(0));
+
+        let mut window = Period::default();
+        window.add_qual("sent_at", ">", &Value::Cell(Cell::Timestamptz(10)));
+        window.add_qual("sent_at", "<", &Value::Cell(Cell::Timestamptz(20)));
+        assert_eq!(
+            window,
+            Period {
+                since: Some(10),
+                until: Some(20),
+            }
+        );
+    }
+}